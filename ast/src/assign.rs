@@ -1,15 +1,17 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::{formatter::Formatter, RcLocal, SideEffects, Traverse};
+use crate::{formatter::Formatter, BinaryOperation, RcLocal, SideEffects, Span, Traverse};
 
 use super::{LValue, LocalRw, RValue};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Assign {
     pub left: Vec<LValue>,
     pub right: Vec<RValue>,
     pub prefix: bool,
     pub parallel: bool,
+    pub span: Option<Span>,
 }
 
 impl Assign {
@@ -19,6 +21,7 @@ impl Assign {
             right,
             prefix: false,
             parallel: false,
+            span: None,
         }
     }
 }
@@ -75,11 +78,84 @@ impl LocalRw for Assign {
 
 impl fmt::Display for Assign {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Formatter {
-            indentation_level: 0,
-            indentation_mode: Default::default(),
-            output: f,
+        Formatter::new(f, Default::default()).format_assign(self)
+    }
+}
+
+/// A Luau compound assignment, e.g. `a += 1`, see
+/// [`compound_assignments`](crate::compound_assignments::compound_assignments).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompoundAssign {
+    pub left: LValue,
+    pub operation: BinaryOperation,
+    pub right: RValue,
+    pub span: Option<Span>,
+}
+
+impl CompoundAssign {
+    pub fn new(left: LValue, operation: BinaryOperation, right: RValue) -> Self {
+        Self {
+            left,
+            operation,
+            right,
+            span: None,
+        }
+    }
+}
+
+impl Traverse for CompoundAssign {
+    fn lvalues_mut(&mut self) -> Vec<&mut LValue> {
+        vec![&mut self.left]
+    }
+
+    fn rvalues_mut(&mut self) -> Vec<&mut RValue> {
+        vec![&mut self.right]
+    }
+
+    fn rvalues(&self) -> Vec<&RValue> {
+        vec![&self.right]
+    }
+}
+
+impl SideEffects for CompoundAssign {
+    fn has_side_effects(&self) -> bool {
+        self.right.has_side_effects() || self.left.has_side_effects()
+    }
+}
+
+// a local that is assigned to is read too
+impl LocalRw for CompoundAssign {
+    fn values_read(&self) -> Vec<&RcLocal> {
+        self.left
+            .as_local()
+            .into_iter()
+            .chain(self.left.values_read())
+            .chain(self.right.values_read())
+            .collect()
+    }
+
+    fn values_read_mut(&mut self) -> Vec<&mut RcLocal> {
+        let (left, right) = (&mut self.left, &mut self.right);
+        match left {
+            LValue::Local(local) => vec![local],
+            left => left.values_read_mut(),
         }
-        .format_assign(self)
+        .into_iter()
+        .chain(right.values_read_mut())
+        .collect()
+    }
+
+    fn values_written(&self) -> Vec<&RcLocal> {
+        self.left.values_written()
+    }
+
+    fn values_written_mut(&mut self) -> Vec<&mut RcLocal> {
+        self.left.values_written_mut()
+    }
+}
+
+impl fmt::Display for CompoundAssign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Formatter::new(f, Default::default()).format_compound_assign(self)
     }
 }
@@ -1,10 +1,14 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::{Literal, LocalRw, RValue, RcLocal, Reduce, SideEffects, Traverse};
+use crate::{
+    type_system::Infer, Literal, LocalRw, RValue, RcLocal, Reduce, SideEffects, Traverse, Type,
+    TypeSystem,
+};
 
 use super::{Unary, UnaryOperation};
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Copy, Clone, Serialize, Deserialize)]
 pub enum BinaryOperation {
     Add,
     Sub,
@@ -65,7 +69,7 @@ impl fmt::Display for BinaryOperation {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Binary {
     pub left: Box<RValue>,
     pub right: Box<RValue>,
@@ -94,6 +98,34 @@ impl SideEffects for Binary {
     }
 }
 
+impl Infer for Binary {
+    fn infer(&self, system: &TypeSystem) -> Type {
+        let (left, right) = (self.left.infer(system), self.right.infer(system));
+        // numbers and strings are coerced without metamethods, anything else could have one
+        let primitive = |r#type: &Type| r#type.is_never() || r#type.is_primitive();
+        match self.operation {
+            operation if operation.is_comparator() => Type::Boolean,
+            BinaryOperation::Add
+            | BinaryOperation::Sub
+            | BinaryOperation::Mul
+            | BinaryOperation::Div
+            | BinaryOperation::Mod
+            | BinaryOperation::Pow
+            | BinaryOperation::IDiv
+                if primitive(&left) && primitive(&right) =>
+            {
+                Type::Number
+            }
+            BinaryOperation::Concat if primitive(&left) && primitive(&right) => Type::String,
+            BinaryOperation::And if left.is_truthy() => right,
+            BinaryOperation::And => left.union(right),
+            BinaryOperation::Or if left.is_truthy() => left,
+            BinaryOperation::Or => left.without_nil().union(right),
+            _ => Type::Any,
+        }
+    }
+}
+
 impl<'a: 'b, 'b> Reduce for Binary {
     fn reduce(self) -> RValue {
         // TODO: true == true, true == false, etc.
@@ -151,6 +183,27 @@ impl<'a: 'b, 'b> Reduce for Binary {
             {
                 left
             }
+            // `a and b and b` is `a and b`, which is what chains of tests that set the same
+            // register collapse to
+            (
+                RValue::Binary(Binary {
+                    left: inner_left,
+                    right: inner_right,
+                    operation: inner_operation,
+                }),
+                right,
+                operation @ (BinaryOperation::And | BinaryOperation::Or),
+            ) if inner_operation == operation
+                && *inner_right == right
+                && !right.has_side_effects() =>
+            {
+                Self {
+                    left: inner_left,
+                    right: inner_right,
+                    operation,
+                }
+                .into()
+            }
             (
                 RValue::Binary(Binary {
                     left:
@@ -170,9 +223,7 @@ impl<'a: 'b, 'b> Reduce for Binary {
                 RValue::Literal(Literal::String(left)),
                 RValue::Literal(Literal::String(right)),
                 BinaryOperation::Concat,
-            ) => RValue::Literal(Literal::String(
-                left.into_iter().chain(right.into_iter()).collect(),
-            )),
+            ) => RValue::Literal(Literal::String([left, right].concat().into())),
             (left, right, operation) => Self {
                 left: Box::new(left),
                 right: Box::new(right),
@@ -248,14 +299,32 @@ impl<'a: 'b, 'b> Reduce for Binary {
                 BinaryOperation::Or => left.reduce(),
                 _ => unreachable!(),
             },
+            // `a and b and b` is `a and b` here too
+            (
+                RValue::Binary(Binary {
+                    left: inner_left,
+                    right: inner_right,
+                    operation: inner_operation,
+                }),
+                right,
+                operation @ (BinaryOperation::And | BinaryOperation::Or),
+            ) if inner_operation == operation
+                && *inner_right == right
+                && !right.has_side_effects() =>
+            {
+                Self {
+                    left: inner_left,
+                    right: inner_right,
+                    operation,
+                }
+                .into()
+            }
             // TODO: concat numbers
             (
                 RValue::Literal(Literal::String(left)),
                 RValue::Literal(Literal::String(right)),
                 BinaryOperation::Concat,
-            ) => RValue::Literal(Literal::String(
-                left.into_iter().chain(right.into_iter()).collect(),
-            )),
+            ) => RValue::Literal(Literal::String([left, right].concat().into())),
             (left, right, operation) => Self {
                 left: Box::new(left),
                 right: Box::new(right),
@@ -1,13 +1,19 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::{formatter::Formatter, has_side_effects, LocalRw, RcLocal, Traverse};
+use crate::{
+    formatter::Formatter, has_side_effects, type_system::Infer, Environment, Global, Index,
+    Literal, LocalRw, RcLocal, SideEffects, Span, Traverse, Type, TypeSystem,
+};
 
 use super::RValue;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Call {
     pub value: Box<RValue>,
     pub arguments: Vec<RValue>,
+    /// Only set for calls that are statements, see [`Statement::span`](crate::Statement::span)
+    pub span: Option<Span>,
 }
 
 impl Call {
@@ -15,12 +21,84 @@ impl Call {
         Self {
             value: Box::new(value),
             arguments,
+            span: None,
+        }
+    }
+
+    /// The name of the global or library function being called, e.g. `select` or `table.unpack`.
+    /// Builtins are assumed not to be overridden.
+    pub fn builtin(&self) -> Option<&str> {
+        let name = match self.value.as_ref() {
+            RValue::Global(Global(name, Environment::Function)) => name,
+            RValue::Index(Index { left, right }) => match (left.as_ref(), right.as_ref()) {
+                (
+                    RValue::Global(Global(library, Environment::Function)),
+                    RValue::Literal(Literal::String(name)),
+                ) if library == b"table" => {
+                    return match &**name {
+                        b"pack" => Some("table.pack"),
+                        b"unpack" => Some("table.unpack"),
+                        _ => None,
+                    };
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        std::str::from_utf8(name).ok()
+    }
+
+    /// Whether this is `select("#", ...)`
+    pub fn is_vararg_count(&self) -> bool {
+        self.builtin() == Some("select")
+            && matches!(self.arguments.first(), Some(RValue::Literal(Literal::String(s))) if **s == *b"#")
+    }
+
+    /// The number of values returned, if it's known from the arguments of a builtin,
+    /// e.g. `unpack(t, 1, 3)` returns 3 values.
+    pub fn value_count(&self) -> Option<usize> {
+        match self.builtin()? {
+            "select" if self.is_vararg_count() => Some(1),
+            "rawget" | "rawequal" | "rawlen" | "rawset" => Some(1),
+            "unpack" | "table.unpack" => match self.arguments[..] {
+                [_, RValue::Literal(Literal::Number(i)), RValue::Literal(Literal::Number(j))]
+                    if i.fract() == 0.0 && j.fract() == 0.0 =>
+                {
+                    Some((j - i + 1.0).max(0.0) as usize)
+                }
+                _ => None,
+            },
+            _ => None,
         }
     }
 }
 
-// call can error
-has_side_effects!(Call);
+// calls can error, except for `select` with a count or a positive index which only reads the varargs
+impl SideEffects for Call {
+    fn has_side_effects(&self) -> bool {
+        let pure = self.builtin() == Some("select")
+            && match self.arguments.first() {
+                Some(RValue::Literal(Literal::String(s))) => **s == *b"#",
+                Some(&RValue::Literal(Literal::Number(n))) => n >= 1.0 && n.fract() == 0.0,
+                _ => false,
+            };
+        !pure || self.arguments.iter().any(|a| a.has_side_effects())
+    }
+}
+
+impl Infer for Call {
+    fn infer(&self, system: &TypeSystem) -> Type {
+        match self.builtin() {
+            Some("select") if self.is_vararg_count() => Type::Number,
+            Some("rawlen") => Type::Number,
+            Some("rawequal") => Type::Boolean,
+            Some("tostring" | "type" | "typeof") => Type::String,
+            Some("rawset") if !self.arguments.is_empty() => self.arguments[0].infer(system),
+            Some("select" | "unpack" | "table.unpack") => Type::VarArg,
+            _ => Type::Any,
+        }
+    }
+}
 // impl SideEffects for Call {
 //     fn has_side_effects(&self) -> bool {
 //         matches!(self.value, box RValue::Local(_))
@@ -63,21 +141,18 @@ impl LocalRw for Call {
 
 impl fmt::Display for Call {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Formatter {
-            indentation_level: 0,
-            indentation_mode: Default::default(),
-            output: f,
-        }
-        .format_call(self)
+        Formatter::new(f, Default::default()).format_call(self)
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MethodCall {
     // TODO: STYLE: rename to object?
     pub value: Box<RValue>,
     pub method: String,
     pub arguments: Vec<RValue>,
+    /// Only set for calls that are statements, see [`Statement::span`](crate::Statement::span)
+    pub span: Option<Span>,
 }
 
 impl MethodCall {
@@ -86,6 +161,7 @@ impl MethodCall {
             value: Box::new(value),
             method,
             arguments,
+            span: None,
         }
     }
 }
@@ -127,11 +203,6 @@ impl LocalRw for MethodCall {
 
 impl fmt::Display for MethodCall {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Formatter {
-            indentation_level: 0,
-            indentation_mode: Default::default(),
-            output: f,
-        }
-        .format_method_call(self)
+        Formatter::new(f, Default::default()).format_method_call(self)
     }
 }
@@ -1,8 +1,9 @@
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 
 use crate::{LocalRw, RcLocal, SideEffects, Traverse};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Close {
     pub locals: Vec<RcLocal>,
 }
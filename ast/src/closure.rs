@@ -2,6 +2,7 @@ use std::fmt;
 
 use by_address::ByAddress;
 use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use triomphe::Arc;
 
 use crate::{
@@ -10,13 +11,13 @@ use crate::{
     Block, Literal, LocalRw, RcLocal, Reduce, SideEffects, Traverse, Type,
 };
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Upvalue {
     Copy(RcLocal),
     Ref(RcLocal),
 }
 
-#[derive(Default, Debug, PartialEq, Clone)]
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Function {
     pub name: Option<String>,
     pub parameters: Vec<RcLocal>,
@@ -24,8 +25,9 @@ pub struct Function {
     pub body: Block,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Closure {
+    #[serde(with = "crate::serialize::shared_function")]
     pub function: ByAddress<Arc<Mutex<Function>>>,
     pub upvalues: Vec<Upvalue>,
 }
@@ -41,27 +43,25 @@ impl Reduce for Closure {
 }
 
 impl Infer for Closure {
-    fn infer<'a: 'b, 'b>(&'a mut self, _system: &mut TypeSystem<'b>) -> Type {
-        todo!()
-        // let return_values = system.analyze_block(&mut self.body);
-        // let parameters = self
-        //     .parameters
-        //     .iter_mut()
-        //     .map(|l| l.infer(system))
-        //     .collect_vec();
-
-        // Type::Function(parameters, return_values)
+    fn infer(&self, system: &TypeSystem) -> Type {
+        let parameters = self
+            .function
+            .lock()
+            .parameters
+            .iter()
+            .map(|l| l.infer(system))
+            .collect();
+        let return_values = system
+            .return_annotation(&self.function)
+            .map(|types| types.to_vec())
+            .unwrap_or_default();
+        Type::Function(parameters, return_values)
     }
 }
 
 impl fmt::Display for Closure {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Formatter {
-            indentation_level: 0,
-            indentation_mode: Default::default(),
-            output: f,
-        }
-        .format_closure(self)
+        Formatter::new(f, Default::default()).format_closure(self)
     }
 }
 
@@ -0,0 +1,82 @@
+use crate::{Assign, Binary, BinaryOperation, Block, CompoundAssign, LValue, RValue, Statement};
+
+// whether the operand of an index is evaluated the same way twice as it is once
+fn is_stable(rvalue: &RValue) -> bool {
+    matches!(
+        rvalue,
+        RValue::Local(_) | RValue::Global(_) | RValue::Literal(_)
+    )
+}
+
+fn is_same(lvalue: &LValue, rvalue: &RValue) -> bool {
+    match (lvalue, rvalue) {
+        (LValue::Local(a), RValue::Local(b)) => a == b,
+        (LValue::Global(a), RValue::Global(b)) => a == b,
+        // `t[f()] = t[f()] + 1` calls `f` twice, `t[f()] += 1` only once
+        (LValue::Index(a), RValue::Index(b)) => a == b && is_stable(&a.left) && is_stable(&a.right),
+        _ => false,
+    }
+}
+
+// `a = a + b`
+fn compound_assign(assign: &Assign) -> Option<CompoundAssign> {
+    if assign.prefix {
+        return None;
+    }
+    let (
+        [left],
+        [RValue::Binary(Binary {
+            left: box operand,
+            right: box value,
+            operation,
+        })],
+    ) = (&assign.left[..], &assign.right[..])
+    else {
+        return None;
+    };
+    if !matches!(
+        operation,
+        BinaryOperation::Add
+            | BinaryOperation::Sub
+            | BinaryOperation::Mul
+            | BinaryOperation::Div
+            | BinaryOperation::IDiv
+            | BinaryOperation::Mod
+            | BinaryOperation::Pow
+            | BinaryOperation::Concat
+    ) || !is_same(left, operand)
+    {
+        return None;
+    }
+    let mut compound_assign = CompoundAssign::new(left.clone(), *operation, value.clone());
+    compound_assign.span = assign.span;
+    Some(compound_assign)
+}
+
+/// Replaces assignments of a binary operation on the value that is assigned to with a compound
+/// assignment, e.g. `a.b = a.b + 1` becomes `a.b += 1`. Indices are only replaced if the table
+/// and key are locals, globals or literals. The output is only valid Luau.
+pub fn compound_assignments(block: &mut Block) {
+    for statement in &mut block.0 {
+        match statement {
+            Statement::Assign(assign) => {
+                if let Some(compound_assign) = compound_assign(assign) {
+                    *statement = compound_assign.into();
+                }
+            }
+            Statement::If(r#if) => {
+                compound_assignments(&mut r#if.then_block.lock());
+                compound_assignments(&mut r#if.else_block.lock());
+            }
+            Statement::While(r#while) => compound_assignments(&mut r#while.block.lock()),
+            Statement::Repeat(repeat) => compound_assignments(&mut repeat.block.lock()),
+            Statement::NumericFor(numeric_for) => {
+                compound_assignments(&mut numeric_for.block.lock())
+            }
+            Statement::GenericFor(generic_for) => {
+                compound_assignments(&mut generic_for.block.lock())
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{Block, Call, Index, Literal, RValue, Select, Statement, Traverse};
+
+/// The function a [`StringDecryptor`] replaces calls to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callee {
+    /// A global, e.g. `decode`
+    Global(Vec<u8>),
+    /// A field of a global, e.g. `Obfuscator.decode`
+    Field(Vec<u8>, Vec<u8>),
+    /// A local with this name. Locals only have names from debug info or
+    /// [`name_locals`](crate::name_locals::name_locals), so this needs one of them to have run.
+    Local(String),
+}
+
+impl Callee {
+    fn matches(&self, rvalue: &RValue) -> bool {
+        match (self, rvalue) {
+            (Self::Global(name), RValue::Global(global)) => global.0 == *name,
+            (Self::Field(table, field), RValue::Index(Index { left, right })) => {
+                matches!(left.as_ref(), RValue::Global(global) if global.0 == *table)
+                    && matches!(
+                        right.as_ref(),
+                        RValue::Literal(Literal::String(key)) if **key == **field
+                    )
+            }
+            (Self::Local(name), RValue::Local(local)) => local.0 .0.lock().0.as_ref() == Some(name),
+            _ => false,
+        }
+    }
+}
+
+type Decrypt = dyn Fn(&[Literal]) -> Option<Literal> + Send + Sync;
+
+/// Decrypts the constants an obfuscator wrapped in calls to `callee`. `decrypt` is passed the
+/// arguments of calls whose arguments are all literals and returns the value of the call, or
+/// `None` to keep it.
+pub struct StringDecryptor {
+    pub callee: Callee,
+    decrypt: Box<Decrypt>,
+}
+
+impl StringDecryptor {
+    pub fn new(
+        callee: Callee,
+        decrypt: impl Fn(&[Literal]) -> Option<Literal> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            callee,
+            decrypt: Box::new(decrypt),
+        }
+    }
+}
+
+impl std::fmt::Debug for StringDecryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StringDecryptor")
+            .field("callee", &self.callee)
+            .finish_non_exhaustive()
+    }
+}
+
+fn decrypt(call: &Call, decryptors: &[StringDecryptor]) -> Option<Literal> {
+    let arguments = call
+        .arguments
+        .iter()
+        .map(|argument| argument.as_literal().cloned())
+        .collect::<Option<Vec<_>>>()?;
+    decryptors
+        .iter()
+        .filter(|decryptor| decryptor.callee.matches(&call.value))
+        .find_map(|decryptor| (decryptor.decrypt)(&arguments))
+}
+
+/// Replaces calls to the functions of `decryptors` with the value they decrypt to, in `block` and
+/// the functions in it. Nested calls are decrypted innermost first, so `decode(decode("..."))`
+/// is replaced as a whole. A call that could return more than one value is replaced with one.
+pub fn decrypt_strings(block: &mut Block, decryptors: &[StringDecryptor]) {
+    for statement in &mut block.0 {
+        statement.post_traverse_rvalues(&mut |rvalue| -> Option<()> {
+            match rvalue {
+                RValue::Call(call) | RValue::Select(Select::Call(call)) => {
+                    if let Some(literal) = decrypt(call, decryptors) {
+                        *rvalue = literal.into();
+                    }
+                }
+                RValue::Closure(closure) => {
+                    decrypt_strings(&mut closure.function.lock().body, decryptors)
+                }
+                _ => {}
+            }
+            None
+        });
+        match statement {
+            Statement::If(r#if) => {
+                decrypt_strings(&mut r#if.then_block.lock(), decryptors);
+                decrypt_strings(&mut r#if.else_block.lock(), decryptors);
+            }
+            Statement::While(r#while) => decrypt_strings(&mut r#while.block.lock(), decryptors),
+            Statement::Repeat(repeat) => decrypt_strings(&mut repeat.block.lock(), decryptors),
+            Statement::NumericFor(numeric_for) => {
+                decrypt_strings(&mut numeric_for.block.lock(), decryptors)
+            }
+            Statement::GenericFor(generic_for) => {
+                decrypt_strings(&mut generic_for.block.lock(), decryptors)
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use itertools::Itertools;
+use parking_lot::Mutex;
+use triomphe::Arc;
+
+use crate::{Binary, BinaryOperation, Block, If, Literal, RValue, RcLocal, Statement};
+
+// the local and constant of `x == 1`, `1 == x` or `x ~= 1`, and whether the comparison is negated
+fn comparison(condition: &RValue) -> Option<(&RcLocal, &Literal, bool)> {
+    let RValue::Binary(Binary {
+        left,
+        right,
+        operation,
+    }) = condition
+    else {
+        return None;
+    };
+    let negated = match operation {
+        BinaryOperation::Equal => false,
+        BinaryOperation::NotEqual => true,
+        _ => return None,
+    };
+    match (left.as_ref(), right.as_ref()) {
+        (RValue::Local(local), RValue::Literal(literal))
+        | (RValue::Literal(literal), RValue::Local(local)) => Some((local, literal, negated)),
+        _ => None,
+    }
+}
+
+// the constant an if that is part of a chain over `subject` compares it to, and whether the arm
+// is its else block. a negated comparison without an else block ends the chain as the arm would
+// be empty
+fn arm(r#if: &If, subject: &RcLocal) -> Option<(Literal, bool)> {
+    let (local, constant, negated) = comparison(&r#if.condition)?;
+    (local == subject && !(negated && r#if.else_block.lock().is_empty()))
+        .then(|| (constant.clone(), negated))
+}
+
+type Arm = (Literal, Arc<Mutex<Block>>);
+
+// the arms of a chain of ifs comparing the same local to constants and the block that is run
+// when none of them match
+fn chain(r#if: &If) -> Option<(RcLocal, Vec<Arm>, Arc<Mutex<Block>>)> {
+    let subject = comparison(&r#if.condition)?.0.clone();
+    let mut arms = Vec::new();
+    let mut current = r#if.clone();
+    let otherwise = loop {
+        let (constant, negated) = arm(&current, &subject)?;
+        let (block, rest) = if negated {
+            (current.else_block, current.then_block)
+        } else {
+            (current.then_block, current.else_block)
+        };
+        arms.push((constant, block));
+        let next = rest
+            .lock()
+            .iter()
+            .exactly_one()
+            .ok()
+            .and_then(Statement::as_if)
+            .filter(|next| arm(next, &subject).is_some())
+            .cloned();
+        match next {
+            Some(next) => current = next,
+            None => break rest,
+        }
+    };
+    (arms.len() > 1).then_some((subject, arms, otherwise))
+}
+
+// numbers are sorted if there are enough arms for the order to matter and none of them are the
+// same, which would make the later arm unreachable
+fn order(arms: &mut [Arm]) {
+    if arms.len() < 3
+        || !arms
+            .iter()
+            .all(|(constant, _)| constant.as_number().is_some_and(|n| !n.is_nan()))
+    {
+        return;
+    }
+    let mut sorted = arms.to_vec();
+    sorted.sort_by(|(a, _), (b, _)| a.as_number().unwrap().total_cmp(b.as_number().unwrap()));
+    if sorted.windows(2).all(|w| w[0].0 != w[1].0) {
+        arms.clone_from_slice(&sorted);
+    }
+}
+
+fn visit(block: &mut Block) {
+    for statement in block.iter_mut() {
+        match statement {
+            Statement::If(r#if) => match chain(r#if) {
+                Some((subject, mut arms, otherwise)) => {
+                    order(&mut arms);
+                    for (_, block) in &arms {
+                        visit(&mut block.lock());
+                    }
+                    visit(&mut otherwise.lock());
+                    let condition = |constant| {
+                        Binary::new(
+                            subject.clone().into(),
+                            RValue::Literal(constant),
+                            BinaryOperation::Equal,
+                        )
+                        .into()
+                    };
+                    let mut arms = arms.into_iter().rev();
+                    let (constant, then_block) = arms.next().unwrap();
+                    let mut chain = If {
+                        condition: condition(constant),
+                        then_block,
+                        else_block: otherwise,
+                    };
+                    for (constant, then_block) in arms {
+                        chain = If {
+                            condition: condition(constant),
+                            then_block,
+                            else_block: Arc::new(Mutex::new(Block(vec![chain.into()]))),
+                        };
+                    }
+                    *r#if = chain;
+                }
+                None => {
+                    visit(&mut r#if.then_block.lock());
+                    visit(&mut r#if.else_block.lock());
+                }
+            },
+            Statement::While(r#while) => visit(&mut r#while.block.lock()),
+            Statement::Repeat(repeat) => visit(&mut repeat.block.lock()),
+            Statement::NumericFor(numeric_for) => visit(&mut numeric_for.block.lock()),
+            Statement::GenericFor(generic_for) => visit(&mut generic_for.block.lock()),
+            _ => {}
+        }
+    }
+}
+
+/// Normalizes chains of ifs that compare the same local to constants, like the dispatch loops of
+/// virtual machines in obfuscated scripts. Every arm is written as `x == constant`, arms reached
+/// through `x ~= constant` are moved into the chain, and arms comparing to distinct numbers are
+/// sorted, which doesn't change what the chain does as at most one of them can match.
+pub fn normalize_dispatch_chains(block: &mut Block) {
+    visit(block);
+}
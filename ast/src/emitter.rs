@@ -0,0 +1,47 @@
+use crate::{
+    formatter::{FormatOptions, Formatter, SourceMapping},
+    Block,
+};
+
+/// A backend that turns a decompiled block into its final form.
+/// The output doesn't have to be source code, e.g. an emitter could produce a token stream for an
+/// external pretty-printer or a concrete syntax tree.
+pub trait Emitter {
+    type Output;
+
+    fn emit(&mut self, block: &Block) -> Self::Output;
+}
+
+/// The default emitter, produces the same source as the `Display` implementation of [`Block`].
+#[derive(Default, Clone)]
+pub struct DisplayEmitter {
+    pub options: FormatOptions,
+}
+
+impl Emitter for DisplayEmitter {
+    type Output = String;
+
+    fn emit(&mut self, block: &Block) -> String {
+        let mut output = String::new();
+        Formatter::format(block, &mut output, self.options).unwrap();
+        output
+    }
+}
+
+/// Like [`DisplayEmitter`], but also maps the lines of the source to the instructions they were
+/// lifted from, see [`Formatter::format_with_source_map`].
+#[derive(Default, Clone)]
+pub struct SourceMapEmitter {
+    pub options: FormatOptions,
+}
+
+impl Emitter for SourceMapEmitter {
+    type Output = (String, Vec<SourceMapping>);
+
+    fn emit(&mut self, block: &Block) -> Self::Output {
+        let mut output = String::new();
+        let source_map =
+            Formatter::format_with_source_map(block, &mut output, self.options).unwrap();
+        (output, source_map)
+    }
+}
@@ -3,10 +3,11 @@ use crate::{
 };
 use itertools::Itertools;
 use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use triomphe::Arc;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct NumForInit {
     // TODO: REFACTOR: store 3 `Assign`s instead
     // TODO: STYLE: rename to `control`? that's what lua calls it
@@ -95,7 +96,7 @@ impl fmt::Display for NumForInit {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct NumForNext {
     // TODO: REFACTOR: store an `Assign` and an `If` instead?
     // TODO: REFACTOR: this is the worst s$H##()WT ever literally
@@ -173,13 +174,14 @@ impl fmt::Display for NumForNext {
 }
 
 // TODO: STYLE: this should probably be named "NumFor"
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NumericFor {
     pub initial: RValue,
     pub limit: RValue,
     pub step: RValue,
     // TODO: STYLE: rename to `control`? (thats what lua calls it)
     pub counter: RcLocal,
+    #[serde(with = "crate::serialize::locked")]
     pub block: Arc<Mutex<Block>>,
 }
 
@@ -266,7 +268,7 @@ impl fmt::Display for NumericFor {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct GenericForInit(pub Assign);
 
 impl GenericForInit {
@@ -333,7 +335,7 @@ impl fmt::Display for GenericForInit {
 // TODO: STYLE: i think GenericFor is a bad name, lua calls iterators "generators",
 // so maybe uh GenerativeFor? LOL
 // or GenFor?
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct GenericForNext {
     // TODO: REFACTOR: store an `Assign` with a `Call` and an `If` instead?
     pub res_locals: Vec<LValue>,
@@ -415,10 +417,11 @@ impl fmt::Display for GenericForNext {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenericFor {
     pub res_locals: Vec<RcLocal>,
     pub right: Vec<RValue>,
+    #[serde(with = "crate::serialize::locked")]
     pub block: Arc<Mutex<Block>>,
 }
 
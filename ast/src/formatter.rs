@@ -1,17 +1,22 @@
 use std::fmt::Write;
 use std::iter;
+use std::rc::Rc;
+use std::str::FromStr;
 use std::{
     borrow::Cow,
     fmt::{self},
 };
 
 use itertools::Itertools;
+use serde::Serialize;
 
 use crate::{
-    Assign, Binary, BinaryOperation, Block, Call, Closure, GenericFor, If, Index, LValue, Literal,
-    MethodCall, NumericFor, RValue, Repeat, Return, Select, Statement, Table, Unary, While,
+    Assign, Binary, BinaryOperation, Block, Call, Closure, CompoundAssign, GenericFor, If, Index,
+    Interpolation, LValue, Literal, MethodCall, NumericFor, RValue, RcLocal, Repeat, Return,
+    Select, Span, Statement, Table, TypeSystem, Unary, While,
 };
 
+#[derive(Debug, Clone, Copy)]
 pub enum IndentationMode {
     Spaces(u8),
     Tab,
@@ -58,28 +63,199 @@ pub(crate) fn format_arg_list(list: &[RValue]) -> String {
     s
 }
 
+/// How globals are written, for targets that don't give every function its environment as plain
+/// global names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GlobalStyle {
+    /// `x`, as in Lua 5.1 and Luau. Names that aren't identifiers are written as `__FENV["x"]`.
+    #[default]
+    Name,
+    /// `_ENV.x`, as in Lua 5.2 and later
+    Env,
+    /// `getfenv().x`, apart from the globals Roblox gives every script like `game` and `script`
+    Getfenv,
+}
+
+impl FromStr for GlobalStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "env" => Ok(Self::Env),
+            "getfenv" => Ok(Self::Getfenv),
+            _ => Err(format!(
+                "unknown global style {}, expected name, env or getfenv",
+                s
+            )),
+        }
+    }
+}
+
+/// How the formatter lays out the source it emits.
+#[derive(Debug, Clone, Copy)]
+pub struct FormatOptions {
+    pub indentation_mode: IndentationMode,
+    /// Tables and argument lists that would make a line longer than this are split over multiple
+    /// lines. Tabs are counted as 4 columns.
+    pub line_width: usize,
+    /// Annotate locals, parameters and return values with the Luau types that could be inferred
+    pub emit_types: bool,
+    /// End every statement that has a [`Span`] with a comment naming the instructions it was
+    /// lifted from
+    pub emit_spans: bool,
+    /// How globals accessed through the function's environment are written
+    pub global_style: GlobalStyle,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indentation_mode: IndentationMode::default(),
+            line_width: 100,
+            emit_types: false,
+            emit_spans: false,
+            global_style: GlobalStyle::default(),
+        }
+    }
+}
+
+/// A line of the output and the instructions the statement starting on it was lifted from, see
+/// [`Formatter::format_with_source_map`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SourceMapping {
+    /// 1-based
+    pub line: usize,
+    /// The line the function the statement is in starts on, the program counters are relative to
+    /// it. 0 for the main function.
+    pub function_line: usize,
+    #[serde(flatten)]
+    pub span: Span,
+}
+
+/// Keeps track of the line and column the next character is written to
+pub(crate) struct Output<'a, W: fmt::Write> {
+    inner: &'a mut W,
+    line: usize,
+    column: usize,
+}
+
+impl<W: fmt::Write> fmt::Write for Output<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.line += s.matches('\n').count();
+        let line = match s.rfind('\n') {
+            Some(newline) => {
+                self.column = 0;
+                &s[newline + 1..]
+            }
+            None => s,
+        };
+        self.column += line
+            .chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum::<usize>();
+        self.inner.write_str(s)
+    }
+}
+
 pub struct Formatter<'a, W: fmt::Write> {
     pub(crate) indentation_level: usize,
-    pub(crate) indentation_mode: IndentationMode,
-    pub(crate) output: &'a mut W,
+    pub(crate) options: FormatOptions,
+    pub(crate) output: Output<'a, W>,
+    types: Option<Rc<TypeSystem>>,
+    source_map: Option<Vec<SourceMapping>>,
+    function_line: usize,
 }
 
 impl<'a, W: fmt::Write> Formatter<'a, W> {
-    pub fn format(
+    pub(crate) fn new(output: &'a mut W, options: FormatOptions) -> Self {
+        Self {
+            indentation_level: 0,
+            options,
+            output: Output {
+                inner: output,
+                line: 1,
+                column: 0,
+            },
+            types: None,
+            source_map: None,
+            function_line: 0,
+        }
+    }
+
+    pub fn format(main: &Block, output: &'a mut W, options: FormatOptions) -> fmt::Result {
+        let mut formatter = Self::new(output, options);
+        if options.emit_types {
+            formatter.types = Some(Rc::new(TypeSystem::analyze(main)));
+        }
+        formatter.format_block_no_indent(main)
+    }
+
+    /// Like [`Formatter::format`], but also returns the line every statement with a [`Span`]
+    /// starts on. Spans are relative to the function the statement is in.
+    pub fn format_with_source_map(
         main: &Block,
         output: &'a mut W,
-        indentation_mode: IndentationMode,
+        options: FormatOptions,
+    ) -> Result<Vec<SourceMapping>, fmt::Error> {
+        let mut formatter = Self::new(output, options);
+        if options.emit_types {
+            formatter.types = Some(Rc::new(TypeSystem::analyze(main)));
+        }
+        formatter.source_map = Some(Vec::new());
+        formatter.format_block_no_indent(main)?;
+        Ok(formatter.source_map.unwrap())
+    }
+
+    /// Formats a statement of the main block on its own, for writing the output while the rest of
+    /// the chunk is still being decompiled. `next_statement` is the first statement after it that
+    /// isn't a comment, which decides whether a semicolon has to separate them.
+    pub fn format_main_statement(
+        statement: &Statement,
+        next_statement: Option<&Statement>,
+        output: &'a mut W,
+        options: FormatOptions,
     ) -> fmt::Result {
-        let mut formatter = Self {
-            indentation_level: 0,
-            indentation_mode,
-            output,
+        let mut formatter = Self::new(output, options);
+        formatter.format_statement(statement)?;
+        if let Some(next_statement) = next_statement
+            && Self::needs_semicolon(statement, next_statement)
+        {
+            write!(formatter.output, ";")?;
+        }
+        formatter.format_span(statement)
+    }
+
+    // whether `format` would be written on a single line that is longer than the line width,
+    // things that span multiple lines anyway aren't split further
+    fn overflows(&self, format: impl FnOnce(&mut Formatter<String>) -> fmt::Result) -> bool {
+        if self.options.line_width == usize::MAX {
+            return false;
+        }
+        let mut output = String::new();
+        let mut formatter = Formatter {
+            indentation_level: self.indentation_level,
+            options: FormatOptions {
+                line_width: usize::MAX,
+                ..self.options
+            },
+            output: Output {
+                inner: &mut output,
+                line: self.output.line,
+                column: self.output.column,
+            },
+            types: self.types.clone(),
+            source_map: None,
+            function_line: 0,
         };
-        formatter.format_block_no_indent(main)
+        format(&mut formatter).unwrap();
+        let column = formatter.output.column;
+        !output.contains('\n') && column > self.options.line_width
     }
 
     fn indent(&mut self) -> fmt::Result {
-        self.indentation_mode
+        self.options
+            .indentation_mode
             .display(&mut self.output, self.indentation_level)
     }
 
@@ -107,67 +283,96 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
             if i != 0 {
                 writeln!(self.output)?;
             }
+            if let Some(source_map) = &mut self.source_map
+                && let Some(span) = statement.span()
+            {
+                source_map.push(SourceMapping {
+                    line: self.output.line,
+                    function_line: self.function_line,
+                    span,
+                });
+            }
             self.format_statement(statement)?;
             if let Some(next_statement) =
                 block.iter().skip(i + 1).find(|s| s.as_comment().is_none())
+                && Self::needs_semicolon(statement, next_statement)
             {
-                fn is_ambiguous(r: &RValue) -> bool {
-                    match r {
-                        RValue::Local(_)
-                        | RValue::Global(_)
-                        | RValue::Index(_)
-                        | RValue::Call(_)
-                        | RValue::MethodCall(_)
-                        | RValue::Select(Select::Call(_) | Select::MethodCall(_)) => true,
-                        RValue::Binary(binary) => is_ambiguous(&binary.right),
-                        _ => false,
-                    }
-                }
+                write!(self.output, ";")?;
+            }
+            self.format_span(statement)?;
+        }
+        Ok(())
+    }
 
-                let disambiguate = match statement {
-                    Statement::Call(_) | Statement::MethodCall(_) => true,
-                    Statement::Repeat(repeat) => is_ambiguous(&repeat.condition),
-                    Statement::Assign(Assign { right: list, .. })
-                    | Statement::Return(Return { values: list }) => {
-                        if let Some(last) = list.last() {
-                            is_ambiguous(last)
-                        } else {
-                            false
-                        }
+    fn format_span(&mut self, statement: &Statement) -> fmt::Result {
+        if self.options.emit_spans
+            && let Some(span) = statement.span()
+        {
+            write!(self.output, " -- {}", span)?;
+        }
+        Ok(())
+    }
+
+    // whether the next statement would be parsed as a continuation of this one without a
+    // semicolon between them, e.g. `a = b` followed by `(f)()`
+    fn needs_semicolon(statement: &Statement, next_statement: &Statement) -> bool {
+        fn is_ambiguous(r: &RValue) -> bool {
+            match r {
+                RValue::Local(_)
+                | RValue::Global(_)
+                | RValue::Index(_)
+                | RValue::Call(_)
+                | RValue::MethodCall(_)
+                | RValue::Select(Select::Call(_) | Select::MethodCall(_)) => true,
+                RValue::Binary(binary) => is_ambiguous(&binary.right),
+                _ => false,
+            }
+        }
+
+        let disambiguate = match statement {
+            Statement::Call(_) | Statement::MethodCall(_) => true,
+            Statement::Repeat(repeat) => is_ambiguous(&repeat.condition),
+            Statement::CompoundAssign(compound_assign) => is_ambiguous(&compound_assign.right),
+            Statement::Assign(Assign { right: list, .. })
+            | Statement::Return(Return { values: list, .. }) => {
+                if let Some(last) = list.last() {
+                    is_ambiguous(last)
+                } else {
+                    false
+                }
+            }
+            Statement::Goto(_) | Statement::Continue(_) | Statement::Break(_) => true,
+            _ => false,
+        };
+        disambiguate
+            && match next_statement {
+                Statement::Assign(Assign {
+                    left,
+                    prefix: false,
+                    ..
+                }) => {
+                    if let Some(index) = left[0].as_index() {
+                        Self::should_wrap_left_rvalue(&index.left)
+                    } else {
+                        false
                     }
-                    Statement::Goto(_) | Statement::Continue(_) | Statement::Break(_) => true,
-                    _ => false,
-                };
-                let disambiguate = disambiguate
-                    && match next_statement {
-                        Statement::Assign(Assign {
-                            left,
-                            prefix: false,
-                            ..
-                        }) => {
-                            if let Some(index) = left[0].as_index() {
-                                Self::should_wrap_left_rvalue(&index.left)
-                            } else {
-                                false
-                            }
-                        }
-                        Statement::Call(Call { value, .. })
-                        | Statement::MethodCall(MethodCall { value, .. }) => {
-                            Self::should_wrap_left_rvalue(value)
-                        }
-                        Statement::Comment(_) => unimplemented!(),
-                        _ => false,
-                    };
-                if disambiguate {
-                    write!(self.output, ";")?;
                 }
+                Statement::CompoundAssign(CompoundAssign {
+                    left: LValue::Index(index),
+                    ..
+                }) => Self::should_wrap_left_rvalue(&index.left),
+                Statement::Call(Call { value, .. })
+                | Statement::MethodCall(MethodCall { value, .. }) => {
+                    Self::should_wrap_left_rvalue(value)
+                }
+                Statement::Comment(_) => unimplemented!(),
+                _ => false,
             }
-        }
-        Ok(())
     }
 
     fn format_lvalue(&mut self, lvalue: &LValue) -> fmt::Result {
         match lvalue {
+            LValue::Global(global) => global.format(&mut self.output, self.options.global_style),
             LValue::Index(index) => self.format_index(index),
             _ => write!(self.output, "{}", lvalue),
         }
@@ -202,7 +407,8 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
         let sequential_keys = Self::are_table_keys_sequential(table);
         let should_space = !table.0.is_empty();
         let should_format = !table.0.is_empty() && (!sequential_keys || table.0.len() > 3)
-            || Self::contains_table(table);
+            || Self::contains_table(table)
+            || self.overflows(|f| f.format_table(table));
         write!(self.output, "{{")?;
         if should_format {
             writeln!(self.output)?;
@@ -281,25 +487,44 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
 
     fn format_closure_parameters(&mut self, closure: &Closure) -> fmt::Result {
         let function = closure.function.lock();
-        write!(
-            self.output,
-            "{}",
-            if function.is_variadic {
-                function
-                    .parameters
-                    .iter()
-                    .map(|x| x.to_string())
-                    .chain(std::iter::once("...".into()))
-                    .join(", ")
-            } else {
-                function.parameters.iter().join(", ")
+        for (i, parameter) in function.parameters.iter().enumerate() {
+            if i != 0 {
+                write!(self.output, ", ")?;
             }
-        )
+            write!(self.output, "{}", parameter)?;
+            self.format_annotation(parameter)?;
+        }
+        if function.is_variadic {
+            if !function.parameters.is_empty() {
+                write!(self.output, ", ")?;
+            }
+            write!(self.output, "...")?;
+        }
+        write!(self.output, ")")?;
+        if let Some(types) = &self.types
+            && let Some(returns) = types.return_annotation(&closure.function)
+        {
+            match returns {
+                [r#type] if r#type.precedence() == 0 => write!(self.output, ": {}", r#type)?,
+                returns => write!(self.output, ": ({})", returns.iter().join(", "))?,
+            }
+        }
+        Ok(())
+    }
+
+    fn format_annotation(&mut self, local: &RcLocal) -> fmt::Result {
+        if let Some(types) = &self.types
+            && let Some(r#type) = types.annotation(local)
+        {
+            write!(self.output, ": {}", r#type)?;
+        }
+        Ok(())
     }
 
     fn format_closure_body(&mut self, closure: &Closure) -> fmt::Result {
         let function = closure.function.lock();
         if !function.body.is_empty() {
+            let header_line = self.output.line;
             writeln!(self.output)?;
             self.indentation_level += 1;
             // if closure.name.is_some() {
@@ -331,7 +556,9 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
             }
             self.indentation_level -= 1;
 
+            let function_line = std::mem::replace(&mut self.function_line, header_line);
             self.format_block(&function.body)?;
+            self.function_line = function_line;
             writeln!(self.output)?;
             self.indent()
         } else {
@@ -342,15 +569,15 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
     pub(crate) fn format_closure(&mut self, closure: &Closure) -> fmt::Result {
         write!(self.output, "function(")?;
         self.format_closure_parameters(closure)?;
-        write!(self.output, ")")?;
         self.format_closure_body(closure)?;
         write!(self.output, "end")
     }
 
     fn format_named_function(&mut self, name: &LValue, closure: &Closure) -> fmt::Result {
-        write!(self.output, "function {}(", name)?;
+        write!(self.output, "function ")?;
+        self.format_lvalue(name)?;
+        write!(self.output, "(")?;
         self.format_closure_parameters(closure)?;
-        write!(self.output, ")")?;
         self.format_closure_body(closure)?;
         write!(self.output, "end")
     }
@@ -361,11 +588,13 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
             RValue::Select(Select::MethodCall(method_call)) | RValue::MethodCall(method_call) => {
                 self.format_method_call(method_call)
             }
+            RValue::Global(global) => global.format(&mut self.output, self.options.global_style),
             RValue::Table(table) => self.format_table(table),
             RValue::Index(index) => self.format_index(index),
             RValue::Unary(unary) => self.format_unary(unary),
             RValue::Binary(binary) => self.format_binary(binary),
             RValue::Closure(closure) => self.format_closure(closure),
+            RValue::Interpolation(interpolation) => self.format_interpolation(interpolation),
             RValue::Literal(Literal::Number(n)) if n.is_infinite() => {
                 // TODO: only insert parentheses when necessary
                 write!(self.output, "(")?;
@@ -393,7 +622,19 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
     }
 
     fn format_arg_list(&mut self, list: &[RValue]) -> fmt::Result {
+        // one argument per line if they don't fit on the line with the closing parenthesis
+        let split = self.overflows(|f| {
+            f.format_arg_list(list)?;
+            write!(f.output, ")")
+        });
+        if split {
+            self.indentation_level += 1;
+        }
         for (index, rvalue) in list.iter().enumerate() {
+            if split {
+                writeln!(self.output)?;
+                self.indent()?;
+            }
             if index + 1 == list.len() {
                 let wrap = matches!(rvalue, RValue::Select(_));
                 if wrap {
@@ -405,12 +646,18 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
                 }
             } else {
                 self.format_rvalue(rvalue)?;
-                write!(self.output, ", ")?;
+                write!(self.output, "{}", if split { "," } else { ", " })?;
             }
         }
+        if split {
+            self.indentation_level -= 1;
+            writeln!(self.output)?;
+            self.indent()?;
+        }
         Ok(())
     }
-    pub(crate) fn is_valid_name(name: &[u8]) -> bool {
+
+    pub fn is_valid_name(name: &[u8]) -> bool {
         if !(name
             .iter()
             .enumerate()
@@ -480,6 +727,29 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
         }
     }
 
+    pub(crate) fn format_interpolation(&mut self, interpolation: &Interpolation) -> fmt::Result {
+        write!(self.output, "`")?;
+        for (index, string) in interpolation.strings.iter().enumerate() {
+            // braces and backticks are the only characters that need escaping on top of the ones
+            // in a quoted string
+            write!(
+                self.output,
+                "{}",
+                Self::escape_string(string)
+                    .replace('`', r"\`")
+                    .replace('{', r"\{")
+            )?;
+            if let Some(value) = interpolation.values.get(index) {
+                // `{{` isn't allowed, so tables are wrapped
+                let wrap = matches!(value, RValue::Table(_));
+                write!(self.output, "{}", if wrap { "{(" } else { "{" })?;
+                self.format_rvalue(value)?;
+                write!(self.output, "{}", if wrap { ")}" } else { "}" })?;
+            }
+        }
+        write!(self.output, "`")
+    }
+
     pub(crate) fn format_index(&mut self, index: &Index) -> fmt::Result {
         let wrap = Self::should_wrap_left_rvalue(&index.left);
         if wrap {
@@ -537,11 +807,33 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
     pub(crate) fn format_if(&mut self, r#if: &If) -> fmt::Result {
         write!(self.output, "if ")?;
 
-        self.format_rvalue(&r#if.condition)?;
+        // `if a then if b then ... end end` is written as `if a and b then ... end`
+        let mut condition = None;
+        let mut then_block = r#if.then_block.clone();
+        if r#if.else_block.lock().is_empty() {
+            loop {
+                let nested = match then_block.lock().iter().exactly_one() {
+                    Ok(Statement::If(nested)) if nested.else_block.lock().is_empty() => {
+                        (nested.condition.clone(), nested.then_block.clone())
+                    }
+                    _ => break,
+                };
+                condition = Some(
+                    Binary::new(
+                        condition.unwrap_or_else(|| r#if.condition.clone()),
+                        nested.0,
+                        BinaryOperation::And,
+                    )
+                    .into(),
+                );
+                then_block = nested.1;
+            }
+        }
+        self.format_rvalue(condition.as_ref().unwrap_or(&r#if.condition))?;
 
         writeln!(self.output, " then")?;
 
-        let then_block = r#if.then_block.lock();
+        let then_block = then_block.lock();
         if !then_block.is_empty() {
             self.format_block(&then_block)?;
             writeln!(self.output)?;
@@ -573,7 +865,11 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
             && let RValue::Closure(closure) = &assign.right[0]
         {
             let left = &assign.left[0];
-            if assign.prefix || left.as_global().is_some() || {
+            let style = self.options.global_style;
+            let named_global = left
+                .as_global()
+                .is_some_and(|global| global.is_name_path(style));
+            if assign.prefix || named_global || {
                 if let LValue::Index(ref index) = left {
                     let mut index = index;
                     let mut valid = true;
@@ -586,7 +882,8 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
                                     index = i;
                                     continue;
                                 }
-                                box RValue::Global(_) | box RValue::Local(_) => {}
+                                box RValue::Global(ref global) if global.is_name_path(style) => {}
+                                box RValue::Local(_) => {}
                                 _ => valid = false,
                             }
                         } else {
@@ -608,6 +905,11 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
                 write!(self.output, ", ")?;
             }
             self.format_lvalue(lvalue)?;
+            if assign.prefix
+                && let LValue::Local(local) = lvalue
+            {
+                self.format_annotation(local)?;
+            }
         }
 
         if !assign.right.is_empty() {
@@ -631,6 +933,15 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
         Ok(())
     }
 
+    pub(crate) fn format_compound_assign(
+        &mut self,
+        compound_assign: &CompoundAssign,
+    ) -> fmt::Result {
+        self.format_lvalue(&compound_assign.left)?;
+        write!(self.output, " {}= ", compound_assign.operation)?;
+        self.format_rvalue(&compound_assign.right)
+    }
+
     pub(crate) fn format_while(&mut self, r#while: &While) -> fmt::Result {
         write!(self.output, "while ")?;
 
@@ -725,6 +1036,9 @@ impl<'a, W: fmt::Write> Formatter<'a, W> {
 
         match statement {
             Statement::Assign(assign) => self.format_assign(assign),
+            Statement::CompoundAssign(compound_assign) => {
+                self.format_compound_assign(compound_assign)
+            }
             Statement::If(r#if) => self.format_if(r#if),
             Statement::While(r#while) => self.format_while(r#while),
             Statement::Repeat(repeat) => self.format_repeat(repeat),
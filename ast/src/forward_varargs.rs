@@ -0,0 +1,224 @@
+use parking_lot::Mutex;
+use rustc_hash::{FxHashMap, FxHashSet};
+use triomphe::Arc;
+
+use crate::{
+    Block, Call, Global, LValue, Literal, LocalRw, RValue, RcLocal, Statement, Traverse, VarArg,
+};
+
+// `local t = table.pack(...)`, which is forwarded with `unpack(t, 1, t.n)` and counted with `t.n`
+fn packed(statement: &Statement) -> Option<RcLocal> {
+    let Statement::Assign(assign) = statement else {
+        return None;
+    };
+    if !assign.prefix || assign.right.len() != 1 {
+        return None;
+    }
+    let [LValue::Local(local)] = &assign.left[..] else {
+        return None;
+    };
+    match &assign.right[0] {
+        RValue::Call(call)
+            if call.builtin() == Some("table.pack")
+                && matches!(&call.arguments[..], [RValue::VarArg(_)]) =>
+        {
+            Some(local.clone())
+        }
+        _ => None,
+    }
+}
+
+fn is_local(rvalue: &RValue, local: &RcLocal) -> bool {
+    matches!(rvalue, RValue::Local(l) if l == local)
+}
+
+// `t.n`
+fn is_count(rvalue: &RValue, local: &RcLocal) -> bool {
+    matches!(rvalue, RValue::Index(index)
+        if is_local(&index.left, local)
+            && matches!(index.right.as_ref(), RValue::Literal(Literal::String(s)) if **s == *b"n"))
+}
+
+// `unpack(t, 1, t.n)`, which reads `local` twice
+fn is_forwarded(rvalue: &RValue, local: &RcLocal) -> bool {
+    let RValue::Call(call) = rvalue else {
+        return false;
+    };
+    matches!(call.builtin(), Some("unpack" | "table.unpack"))
+        && matches!(&call.arguments[..], [table, RValue::Literal(Literal::Number(start)), count]
+            if is_local(table, local) && *start == 1.0 && is_count(count, local))
+}
+
+fn nested_blocks(statement: &Statement) -> Vec<&Arc<Mutex<Block>>> {
+    match statement {
+        Statement::If(r#if) => vec![&r#if.then_block, &r#if.else_block],
+        Statement::While(r#while) => vec![&r#while.block],
+        Statement::Repeat(repeat) => vec![&repeat.block],
+        Statement::NumericFor(numeric_for) => vec![&numeric_for.block],
+        Statement::GenericFor(generic_for) => vec![&generic_for.block],
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Default)]
+struct Uses {
+    reads: FxHashMap<RcLocal, usize>,
+    writes: FxHashMap<RcLocal, usize>,
+    // reads that are part of a forwarding pattern
+    forwarded: FxHashMap<RcLocal, usize>,
+}
+
+impl Uses {
+    fn visit(&mut self, rvalue: &RValue, candidates: &FxHashSet<RcLocal>) {
+        for local in candidates {
+            let reads = if is_forwarded(rvalue, local) {
+                2
+            } else if is_count(rvalue, local) {
+                1
+            } else {
+                continue;
+            };
+            *self.forwarded.entry(local.clone()).or_default() += reads;
+            return;
+        }
+        for rvalue in rvalue.rvalues() {
+            self.visit(rvalue, candidates);
+        }
+    }
+
+    fn collect(&mut self, block: &Block, candidates: &FxHashSet<RcLocal>) {
+        for statement in block.iter() {
+            // reads in closures are upvalues, `...` can't be forwarded into them
+            for local in statement.values_read() {
+                *self.reads.entry(local.clone()).or_default() += 1;
+            }
+            for local in statement.values_written() {
+                *self.writes.entry(local.clone()).or_default() += 1;
+            }
+            for rvalue in statement.rvalues() {
+                self.visit(rvalue, candidates);
+            }
+            for block in nested_blocks(statement) {
+                self.collect(&block.lock(), candidates);
+            }
+        }
+    }
+}
+
+fn find_candidates(block: &Block, candidates: &mut FxHashSet<RcLocal>) {
+    for statement in block.iter() {
+        if let Some(local) = packed(statement) {
+            candidates.insert(local);
+        }
+        for block in nested_blocks(statement) {
+            find_candidates(&block.lock(), candidates);
+        }
+    }
+}
+
+fn forward(block: &mut Block, locals: &FxHashSet<RcLocal>) {
+    block.retain(|statement| !packed(statement).is_some_and(|l| locals.contains(&l)));
+    for statement in block.iter_mut() {
+        statement.traverse_rvalues(&mut |rvalue| {
+            for local in locals {
+                if is_forwarded(rvalue, local) {
+                    *rvalue = VarArg.into();
+                } else if is_count(rvalue, local) {
+                    *rvalue = Call::new(
+                        Global::from("select").into(),
+                        vec![Literal::from("#").into(), VarArg.into()],
+                    )
+                    .into();
+                }
+            }
+        });
+        for block in nested_blocks(statement) {
+            forward(&mut block.lock(), locals);
+        }
+    }
+}
+
+/// Replaces varargs that are packed into a table only to be unpacked again with `...`,
+/// e.g. `local t = table.pack(...); f(unpack(t, 1, t.n))` becomes `f(...)`, and `t.n` with
+/// `select("#", ...)`. This is only done when the table isn't used in any other way. `{...}`
+/// unpacked with `unpack(t)` is left alone, as `unpack` stops at the border of the table where
+/// `...` keeps trailing nils. Bodies of functions that aren't vararg are left as is, as they
+/// can't pack `...`.
+pub fn forward_varargs(block: &mut Block) {
+    let mut candidates = FxHashSet::default();
+    find_candidates(block, &mut candidates);
+    if candidates.is_empty() {
+        return;
+    }
+    let mut uses = Uses::default();
+    uses.collect(block, &candidates);
+    candidates.retain(|local| {
+        uses.writes.get(local) == Some(&1)
+            && uses.reads.get(local).copied().unwrap_or(0)
+                == uses.forwarded.get(local).copied().unwrap_or(0)
+    });
+    if !candidates.is_empty() {
+        forward(block, &candidates);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Assign, Index, Local, Table};
+
+    // `local t = <pack>; f(<forwarded>)`
+    fn forwarding(
+        pack: impl FnOnce() -> RValue,
+        forwarded: impl FnOnce(&RcLocal) -> RValue,
+    ) -> Block {
+        let t = RcLocal::new(Local::new(Some("t".to_string())));
+        let mut declaration = Assign::new(vec![t.clone().into()], vec![pack()]);
+        declaration.prefix = true;
+        Block(vec![
+            declaration.into(),
+            Call::new(Global::from("f").into(), vec![forwarded(&t)]).into(),
+        ])
+    }
+
+    #[test]
+    fn table_pack_is_forwarded() {
+        let mut block = forwarding(
+            || {
+                Call::new(
+                    Index::new(Global::from("table").into(), Literal::from("pack").into()).into(),
+                    vec![VarArg.into()],
+                )
+                .into()
+            },
+            |t| {
+                let count = Index::new(t.clone().into(), Literal::from("n").into());
+                Call::new(
+                    Global::from("unpack").into(),
+                    vec![t.clone().into(), Literal::Number(1.0).into(), count.into()],
+                )
+                .into()
+            },
+        );
+        forward_varargs(&mut block);
+        assert_eq!(
+            block.0,
+            vec![Statement::from(Call::new(
+                Global::from("f").into(),
+                vec![VarArg.into()]
+            ))]
+        );
+    }
+
+    // `unpack(t)` stops at the border of `t`, `...` doesn't
+    #[test]
+    fn table_constructor_is_left_alone() {
+        let mut block = forwarding(
+            || Table(vec![(None, VarArg.into())]).into(),
+            |t| Call::new(Global::from("unpack").into(), vec![t.clone().into()]).into(),
+        );
+        let expected = block.clone();
+        forward_varargs(&mut block);
+        assert_eq!(block.0, expected.0);
+    }
+}
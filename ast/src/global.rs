@@ -1,18 +1,91 @@
 use derive_more::From;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::{formatter::Formatter, LocalRw, SideEffects, Traverse};
+use crate::{
+    formatter::{Formatter, GlobalStyle},
+    LocalRw, RcLocal, SideEffects, Traverse,
+};
 
-#[derive(Debug, From, PartialEq, Eq, PartialOrd, Clone)]
-pub struct Global(pub Vec<u8>);
+/// The table a global is looked up in.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Clone, Serialize, Deserialize)]
+pub enum Environment {
+    /// The environment of the function the global is accessed in, written the way
+    /// [`FormatOptions::global_style`](crate::formatter::FormatOptions::global_style) says
+    #[default]
+    Function,
+    /// A table the function's environment was replaced with, e.g. with `setfenv`, the global is
+    /// written as an index of the local holding it
+    Local(RcLocal),
+}
+
+// globals Roblox gives every script, which read better by name than through `getfenv()`, along
+// with the functions that access the environment
+const SCRIPT_GLOBALS: &[&[u8]] = &[b"game", b"script", b"workspace", b"getfenv", b"setfenv"];
+
+#[derive(Debug, From, PartialEq, Eq, PartialOrd, Clone, Serialize, Deserialize)]
+pub struct Global(pub Vec<u8>, pub Environment);
 
 impl Global {
     pub fn new(name: Vec<u8>) -> Self {
-        Self(name)
+        Self(name, Environment::Function)
+    }
+
+    // the table the global is written as an index of, if it isn't written by name
+    fn table(&self, style: GlobalStyle) -> Option<String> {
+        match &self.1 {
+            Environment::Local(local) => Some(local.to_string()),
+            Environment::Function => match style {
+                GlobalStyle::Name => (!Formatter::<fmt::Formatter>::is_valid_name(&self.0))
+                    .then(|| "__FENV".to_string()),
+                GlobalStyle::Env => Some("_ENV".to_string()),
+                GlobalStyle::Getfenv if SCRIPT_GLOBALS.contains(&self.0.as_slice()) => None,
+                GlobalStyle::Getfenv => Some("getfenv()".to_string()),
+            },
+        }
+    }
+
+    /// Whether the global is written as a name or a chain of fields starting with one, which is
+    /// what a function statement can be named with.
+    pub fn is_name_path(&self, style: GlobalStyle) -> bool {
+        Formatter::<fmt::Formatter>::is_valid_name(&self.0)
+            && self
+                .table(style)
+                .is_none_or(|table| Formatter::<fmt::Formatter>::is_valid_name(table.as_bytes()))
+    }
+
+    pub(crate) fn format(&self, f: &mut impl fmt::Write, style: GlobalStyle) -> fmt::Result {
+        match self.table(style) {
+            // a global is only written by name if it is a valid one
+            None => write!(f, "{}", std::str::from_utf8(&self.0).unwrap()),
+            Some(table) if Formatter::<fmt::Formatter>::is_valid_name(&self.0) => {
+                write!(f, "{}.{}", table, std::str::from_utf8(&self.0).unwrap())
+            }
+            Some(table) => write!(
+                f,
+                "{}[\"{}\"]",
+                table,
+                Formatter::<fmt::Formatter>::escape_string(&self.0)
+            ),
+        }
     }
 }
 
-impl LocalRw for Global {}
+impl LocalRw for Global {
+    fn values_read(&self) -> Vec<&RcLocal> {
+        match &self.1 {
+            Environment::Function => Vec::new(),
+            Environment::Local(local) => vec![local],
+        }
+    }
+
+    fn values_read_mut(&mut self) -> Vec<&mut RcLocal> {
+        match &mut self.1 {
+            Environment::Function => Vec::new(),
+            Environment::Local(local) => vec![local],
+        }
+    }
+}
 
 impl SideEffects for Global {
     fn has_side_effects(&self) -> bool {
@@ -30,14 +103,6 @@ impl<'a> From<&'a str> for Global {
 
 impl fmt::Display for Global {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if Formatter::<fmt::Formatter>::is_valid_name(&self.0) {
-            write!(f, "{}", std::str::from_utf8(&self.0).unwrap())
-        } else {
-            write!(
-                f,
-                "__FENV[\"{}\"]",
-                Formatter::<fmt::Formatter>::escape_string(&self.0)
-            )
-        }
+        self.format(f, GlobalStyle::Name)
     }
 }
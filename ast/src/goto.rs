@@ -1,9 +1,10 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 use crate::{has_side_effects, LocalRw, SideEffects, Traverse};
 
 // TODO: Rc
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Label(pub String);
 
 impl SideEffects for Label {}
@@ -30,7 +31,7 @@ impl fmt::Display for Label {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Goto(pub Label);
 
 impl Traverse for Goto {}
@@ -1,9 +1,10 @@
 use crate::{formatter::Formatter, has_side_effects, LocalRw, RcLocal, Traverse};
+use serde::{Deserialize, Serialize};
 
 use super::RValue;
 use std::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Index {
     pub left: Box<RValue>,
     pub right: Box<RValue>,
@@ -51,11 +52,6 @@ impl Traverse for Index {
 
 impl fmt::Display for Index {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Formatter {
-            indentation_level: 0,
-            indentation_mode: Default::default(),
-            output: f,
-        }
-        .format_index(self)
+        Formatter::new(f, Default::default()).format_index(self)
     }
 }
@@ -0,0 +1,73 @@
+use crate::{Block, Interpolation, Literal, MethodCall, RValue, Select, Statement, Traverse};
+
+// the text around the `%*` specifiers of a format string, if it has no other specifiers than
+// `%*` and the `%%` that `%` in the text is escaped with
+fn split_format(format: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut strings = vec![Vec::new()];
+    let mut iter = format.iter();
+    while let Some(&c) = iter.next() {
+        if c == b'%' {
+            match iter.next()? {
+                b'*' => strings.push(Vec::new()),
+                b'%' => strings.last_mut().unwrap().push(b'%'),
+                _ => return None,
+            }
+        } else {
+            strings.last_mut().unwrap().push(c);
+        }
+    }
+    Some(strings)
+}
+
+// `("%* and %*"):format(a, b)`, which is what `` `{a} and {b}` `` compiles to
+fn interpolation(method_call: &MethodCall) -> Option<Interpolation> {
+    let RValue::Literal(Literal::String(format)) = method_call.value.as_ref() else {
+        return None;
+    };
+    // every value is truncated to one, so the last argument can't be a call or `...`, which would
+    // be expanded
+    if method_call.method != "format"
+        || matches!(
+            method_call.arguments.last(),
+            Some(RValue::Call(_) | RValue::MethodCall(_) | RValue::VarArg(_))
+        )
+    {
+        return None;
+    }
+    let strings = split_format(format)?;
+    (strings.len() == method_call.arguments.len() + 1)
+        .then(|| Interpolation::new(strings, method_call.arguments.clone()))
+}
+
+/// Replaces the `string.format` calls that Luau compiles interpolated strings to with the
+/// interpolated string, e.g. `("%*, %*"):format(a, b)` becomes `` `{a}, {b}` ``. A format string
+/// qualifies if its only specifiers are `%*` and `%%`, which can't be told apart from a call
+/// written by hand, but has the same result. The output is only valid Luau.
+pub fn interpolate_strings(block: &mut Block) {
+    for statement in &mut block.0 {
+        statement.post_traverse_rvalues(&mut |rvalue| -> Option<()> {
+            if let RValue::MethodCall(method_call)
+            | RValue::Select(Select::MethodCall(method_call)) = rvalue
+                && let Some(interpolation) = interpolation(method_call)
+            {
+                *rvalue = interpolation.into();
+            }
+            None
+        });
+        match statement {
+            Statement::If(r#if) => {
+                interpolate_strings(&mut r#if.then_block.lock());
+                interpolate_strings(&mut r#if.else_block.lock());
+            }
+            Statement::While(r#while) => interpolate_strings(&mut r#while.block.lock()),
+            Statement::Repeat(repeat) => interpolate_strings(&mut repeat.block.lock()),
+            Statement::NumericFor(numeric_for) => {
+                interpolate_strings(&mut numeric_for.block.lock())
+            }
+            Statement::GenericFor(generic_for) => {
+                interpolate_strings(&mut generic_for.block.lock())
+            }
+            _ => {}
+        }
+    }
+}
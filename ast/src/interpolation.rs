@@ -0,0 +1,53 @@
+use crate::{formatter::Formatter, has_side_effects, LocalRw, RcLocal, Traverse};
+use serde::{Deserialize, Serialize};
+
+use super::RValue;
+use std::fmt;
+
+/// A Luau interpolated string, e.g. `` `{a} of {b}` ``, see
+/// [`interpolate_strings`](crate::interpolate_strings::interpolate_strings).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interpolation {
+    /// The text before, between and after the values, there is one more than there are values
+    pub strings: Vec<Vec<u8>>,
+    pub values: Vec<RValue>,
+}
+
+// values are converted with `tostring`, which may call a metamethod
+has_side_effects!(Interpolation);
+
+impl Interpolation {
+    pub fn new(strings: Vec<Vec<u8>>, values: Vec<RValue>) -> Self {
+        debug_assert_eq!(strings.len(), values.len() + 1);
+        Self { strings, values }
+    }
+}
+
+impl LocalRw for Interpolation {
+    fn values_read(&self) -> Vec<&RcLocal> {
+        self.values.iter().flat_map(|v| v.values_read()).collect()
+    }
+
+    fn values_read_mut(&mut self) -> Vec<&mut RcLocal> {
+        self.values
+            .iter_mut()
+            .flat_map(|v| v.values_read_mut())
+            .collect()
+    }
+}
+
+impl Traverse for Interpolation {
+    fn rvalues_mut(&mut self) -> Vec<&mut RValue> {
+        self.values.iter_mut().collect()
+    }
+
+    fn rvalues(&self) -> Vec<&RValue> {
+        self.values.iter().collect()
+    }
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Formatter::new(f, Default::default()).format_interpolation(self)
+    }
+}
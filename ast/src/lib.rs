@@ -6,6 +6,7 @@ use enum_as_inner::EnumAsInner;
 use enum_dispatch::enum_dispatch;
 use formatter::Formatter;
 use itertools::Either;
+use serde::{Deserialize, Serialize};
 
 use std::{
     fmt,
@@ -18,23 +19,36 @@ mod r#break;
 mod call;
 mod close;
 mod closure;
+pub mod compound_assignments;
 mod r#continue;
+pub mod decrypt_strings;
+pub mod dispatch_chains;
+pub mod emitter;
 mod r#for;
 pub mod formatter;
+pub mod forward_varargs;
 mod global;
 mod goto;
 mod r#if;
 mod index;
+pub mod interpolate_strings;
+mod interpolation;
 mod literal;
 mod local;
 //mod name_gen;
 pub mod local_declarations;
+pub mod lower_continue;
 pub mod name_locals;
 mod repeat;
+pub mod replace_globals;
 pub mod replace_locals;
 mod r#return;
+pub mod rewrite;
+pub mod serialize;
 mod set_list;
 mod side_effects;
+pub mod slice;
+mod span;
 mod table;
 mod traverse;
 pub mod type_system;
@@ -50,6 +64,7 @@ pub use closure::*;
 pub use global::*;
 pub use goto::*;
 pub use index::*;
+pub use interpolation::*;
 pub use literal::*;
 pub use local::*;
 pub use r#break::*;
@@ -61,6 +76,7 @@ pub use r#while::*;
 pub use repeat::*;
 pub use set_list::*;
 pub use side_effects::*;
+pub use span::*;
 pub use table::*;
 pub use traverse::*;
 use type_system::{Type, TypeSystem};
@@ -73,7 +89,7 @@ pub trait Reduce {
 }
 
 #[enum_dispatch(LocalRw, SideEffects, Traverse)]
-#[derive(Debug, Clone, PartialEq, EnumAsInner)]
+#[derive(Debug, Clone, PartialEq, EnumAsInner, Serialize, Deserialize)]
 pub enum Select {
     VarArg(VarArg),
     Call(Call),
@@ -91,7 +107,7 @@ impl fmt::Display for Select {
 }
 
 #[enum_dispatch(LocalRw, SideEffects, Traverse)]
-#[derive(Debug, Clone, PartialEq, EnumAsInner)]
+#[derive(Debug, Clone, PartialEq, EnumAsInner, Serialize, Deserialize)]
 pub enum RValue {
     Local(RcLocal),
     Global(Global),
@@ -105,21 +121,24 @@ pub enum RValue {
     Binary(Binary),
     Closure(Closure),
     Select(Select),
+    Interpolation(Interpolation),
 }
 
 impl type_system::Infer for RValue {
-    fn infer<'a: 'b, 'b>(&'a mut self, system: &mut TypeSystem<'b>) -> Type {
+    fn infer(&self, system: &TypeSystem) -> Type {
         match self {
             RValue::Local(local) => local.infer(system),
             RValue::Global(_) => Type::Any,
-            RValue::Call(_) => Type::Any,
-            //RValue::Table(table) => table.infer(system),
+            RValue::Call(call) => call.infer(system),
+            RValue::Table(table) => table.infer(system),
             RValue::Literal(literal) => literal.infer(system),
             RValue::Index(_) => Type::Any,
-            RValue::Unary(_) => Type::Any,
-            RValue::Binary(_) => Type::Any,
+            RValue::Unary(unary) => unary.infer(system),
+            RValue::Binary(binary) => binary.infer(system),
             RValue::Closure(closure) => closure.infer(system),
-            _ => Type::VarArg,
+            RValue::VarArg(_) => Type::VarArg,
+            RValue::Interpolation(_) => Type::String,
+            _ => Type::Any,
         }
     }
 }
@@ -185,12 +204,13 @@ impl fmt::Display for RValue {
             RValue::Binary(binary) => write!(f, "{}", binary),
             RValue::Closure(closure) => write!(f, "{}", closure),
             RValue::Select(select) => write!(f, "{}", select),
+            RValue::Interpolation(interpolation) => write!(f, "{}", interpolation),
         }
     }
 }
 
 #[enum_dispatch(SideEffects, Traverse)]
-#[derive(Debug, Clone, PartialEq, EnumAsInner)]
+#[derive(Debug, Clone, PartialEq, EnumAsInner, Serialize, Deserialize)]
 pub enum LValue {
     Local(RcLocal),
     Global(Global),
@@ -241,7 +261,7 @@ impl fmt::Display for LValue {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Comment {
     pub text: String,
 }
@@ -259,12 +279,13 @@ impl SideEffects for Comment {}
 impl LocalRw for Comment {}
 
 #[enum_dispatch(LocalRw, SideEffects, Traverse)]
-#[derive(Debug, Clone, PartialEq, EnumAsInner)]
+#[derive(Debug, Clone, PartialEq, EnumAsInner, Serialize, Deserialize)]
 pub enum Statement {
     Empty(Empty),
     Call(Call),
     MethodCall(MethodCall),
     Assign(Assign),
+    CompoundAssign(CompoundAssign),
     If(If),
     Goto(Goto),
     Label(Label),
@@ -284,7 +305,7 @@ pub enum Statement {
     Comment(Comment),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Empty {}
 
 impl SideEffects for Empty {}
@@ -312,6 +333,7 @@ impl fmt::Display for Statement {
             Statement::Call(call) => write!(f, "{}", call),
             Statement::MethodCall(method_call) => write!(f, "{}", method_call),
             Statement::Assign(assign) => write!(f, "{}", assign),
+            Statement::CompoundAssign(compound_assign) => write!(f, "{}", compound_assign),
             // TODO: STYLE: replace all `if_` with `r#if`, etc
             Statement::If(if_) => write!(f, "{}", if_),
             Statement::Goto(goto) => write!(f, "{}", goto),
@@ -335,7 +357,7 @@ impl fmt::Display for Statement {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Default, From)]
+#[derive(Debug, PartialEq, Clone, Default, From, Serialize, Deserialize)]
 pub struct Block(pub Vec<Statement>);
 
 // rust-analyzer doesnt like derive_more :/
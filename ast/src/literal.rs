@@ -1,18 +1,20 @@
 use derive_more::From;
 use enum_as_inner::EnumAsInner;
-use std::fmt;
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
 
 use crate::{
     formatter::Formatter, type_system::Infer, LocalRw, Reduce, SideEffects, Traverse, Type,
     TypeSystem,
 };
 
-#[derive(Debug, From, Clone, PartialEq, PartialOrd, EnumAsInner)]
+#[derive(Debug, From, Clone, PartialEq, PartialOrd, EnumAsInner, Serialize, Deserialize)]
 pub enum Literal {
     Nil,
     Boolean(bool),
     Number(f64),
-    String(Vec<u8>),
+    /// Shared so copies of a constant don't copy its contents.
+    String(Arc<[u8]>),
     Vector(f32, f32, f32),
 }
 
@@ -34,7 +36,7 @@ impl Reduce for Literal {
 }
 
 impl Infer for Literal {
-    fn infer<'a: 'b, 'b>(&'a mut self, _: &mut TypeSystem<'b>) -> Type {
+    fn infer(&self, _: &TypeSystem) -> Type {
         match self {
             Literal::Nil => Type::Nil,
             Literal::Boolean(_) => Type::Boolean,
@@ -47,6 +49,18 @@ impl Infer for Literal {
 
 impl From<&str> for Literal {
     fn from(value: &str) -> Self {
+        Self::String(value.as_bytes().into())
+    }
+}
+
+impl From<&[u8]> for Literal {
+    fn from(value: &[u8]) -> Self {
+        Self::String(value.into())
+    }
+}
+
+impl From<Vec<u8>> for Literal {
+    fn from(value: Vec<u8>) -> Self {
         Self::String(value.into())
     }
 }
@@ -2,20 +2,49 @@ use crate::{type_system::Infer, SideEffects, Traverse, Type, TypeSystem};
 use by_address::ByAddress;
 use derive_more::From;
 use enum_dispatch::enum_dispatch;
-use nohash_hasher::NoHashHasher;
 use parking_lot::Mutex;
 use std::{
+    cell::Cell,
+    cmp::Ordering,
     fmt::{self, Display},
-    hash::{Hash, Hasher},
+    sync::atomic::{self, AtomicUsize},
 };
 use triomphe::Arc;
 
-#[derive(Debug, Default, From, Clone, PartialEq, PartialOrd, Ord, Eq, Hash)]
-pub struct Local(pub Option<String>);
+thread_local! {
+    static NEXT_ID: Cell<usize> = const { Cell::new(1) };
+    static SCOPE: Cell<usize> = const { Cell::new(0) };
+}
+
+static NEXT_SERIAL: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs `f` with the locals it creates numbered sequentially from `start` within `scope`, e.g. the
+/// id of the function they belong to, so unnamed locals are displayed the same way every time.
+/// Returns the result of `f` and the next unused number, which can be passed back in to keep
+/// numbering the locals of the same function later on.
+pub fn number_locals<R>(scope: usize, start: usize, f: impl FnOnce() -> R) -> (R, usize) {
+    let previous_scope = SCOPE.replace(scope);
+    let previous = NEXT_ID.replace(start);
+    let result = f();
+    SCOPE.set(previous_scope);
+    (result, NEXT_ID.replace(previous))
+}
+
+/// A local and the number it was created with, see [`number_locals`]
+#[derive(Debug, From, Clone, PartialEq, PartialOrd, Ord, Eq, Hash)]
+pub struct Local(pub Option<String>, pub usize);
 
 impl Local {
     pub fn new(name: Option<String>) -> Self {
-        Self(name)
+        let id = NEXT_ID.get();
+        NEXT_ID.set(id + 1);
+        Self(name, id)
+    }
+}
+
+impl Default for Local {
+    fn default() -> Self {
+        Self::new(None)
     }
 }
 
@@ -23,30 +52,61 @@ impl fmt::Display for Local {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.0 {
             Some(name) => write!(f, "{}", name),
-            None => write!(f, "UNNAMED_LOCAL"),
+            None => write!(f, "UNNAMED_{}", self.1),
         }
     }
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct RcLocal(pub ByAddress<Arc<Mutex<Local>>>);
+/// A shared local, compared and hashed by address. Locals are ordered by the scope they were
+/// numbered in and their number rather than by address, so sorting them gives the same order on
+/// every run.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RcLocal(pub ByAddress<Arc<Mutex<Local>>>, Origin);
+
+// where a local was created. the serial is unique, it only orders locals that were created
+// outside of `number_locals` and share a number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Origin {
+    scope: usize,
+    serial: usize,
+}
+
+impl Default for RcLocal {
+    fn default() -> Self {
+        Self::new(Local::default())
+    }
+}
+
+impl PartialOrd for RcLocal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RcLocal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // locking the same local twice would deadlock
+        if self == other {
+            return Ordering::Equal;
+        }
+        // locals numbered in different scopes can share a number. the locks are taken one at a
+        // time since closures share locals with functions decompiled in parallel, which could be
+        // comparing the same two locals the other way around
+        let id = self.0 .0.lock().1;
+        let other_id = other.0 .0.lock().1;
+        (self.1.scope, id, self.1.serial).cmp(&(other.1.scope, other_id, other.1.serial))
+    }
+}
 
 impl Infer for RcLocal {
-    fn infer<'a: 'b, 'b>(&'a mut self, system: &mut TypeSystem<'b>) -> Type {
-        system.type_of(self).clone()
+    fn infer(&self, system: &TypeSystem) -> Type {
+        system.type_of(self)
     }
 }
 
 impl Display for RcLocal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self.0 .0.lock().0 {
-            Some(name) => write!(f, "{}", name),
-            None => {
-                let mut hasher = NoHashHasher::<u8>::default();
-                self.hash(&mut hasher);
-                write!(f, "UNNAMED_{}", hasher.finish())
-            }
-        }
+        write!(f, "{}", self.0 .0.lock())
     }
 }
 
@@ -55,8 +115,24 @@ impl SideEffects for RcLocal {}
 impl Traverse for RcLocal {}
 
 impl RcLocal {
+    /// A local in the scope [`number_locals`] is numbering
     pub fn new(local: Local) -> Self {
-        Self(ByAddress(Arc::new(Mutex::new(local))))
+        Self::with_scope(local, SCOPE.get())
+    }
+
+    pub fn with_scope(local: Local, scope: usize) -> Self {
+        Self(
+            ByAddress(Arc::new(Mutex::new(local))),
+            Origin {
+                scope,
+                serial: NEXT_SERIAL.fetch_add(1, atomic::Ordering::Relaxed),
+            },
+        )
+    }
+
+    /// The scope the local was numbered in, see [`number_locals`]
+    pub fn scope(&self) -> usize {
+        self.1.scope
     }
 }
 
@@ -116,3 +192,25 @@ pub trait LocalRw {
         self.replace_values_written(old, new);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locals_sharing_a_number_are_ordered_by_scope() {
+        let (later, _) = number_locals(2, 1, RcLocal::default);
+        let (earlier, _) = number_locals(1, 1, RcLocal::default);
+        assert!(earlier < later);
+        assert!(later > earlier);
+        assert_eq!(earlier.cmp(&earlier.clone()), Ordering::Equal);
+    }
+
+    #[test]
+    fn locals_sharing_a_number_and_scope_are_distinct() {
+        let first = RcLocal::new(Local(None, 1));
+        let second = RcLocal::new(Local(None, 1));
+        assert_ne!(first.cmp(&second), Ordering::Equal);
+        assert_eq!(first.cmp(&second), second.cmp(&first).reverse());
+    }
+}
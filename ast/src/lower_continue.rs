@@ -0,0 +1,195 @@
+use parking_lot::Mutex;
+use triomphe::Arc;
+
+use crate::{Assign, Block, Break, If, Literal, LocalRw, RValue, RcLocal, Repeat, Statement};
+
+fn loop_block(statement: &Statement) -> Option<&Arc<Mutex<Block>>> {
+    match statement {
+        Statement::While(r#while) => Some(&r#while.block),
+        Statement::Repeat(repeat) => Some(&repeat.block),
+        Statement::NumericFor(numeric_for) => Some(&numeric_for.block),
+        Statement::GenericFor(generic_for) => Some(&generic_for.block),
+        _ => None,
+    }
+}
+
+// wrapping the body of a `repeat` loop would take the locals it declares out of the scope of its
+// condition, so the declarations of the ones the condition reads become assignments and the
+// locals are returned to be declared before the wrapped body
+fn hoist_condition_locals(condition: &RValue, body: &mut Block) -> Vec<RcLocal> {
+    let read = condition.values_read();
+    let mut hoisted = Vec::new();
+    body.retain_mut(|statement| {
+        if let Statement::Assign(assign) = statement
+            && assign.prefix
+            && assign
+                .left
+                .iter()
+                .any(|l| l.as_local().is_some_and(|l| read.contains(&l)))
+        {
+            hoisted.extend(assign.left.iter().filter_map(|l| l.as_local()).cloned());
+            assign.prefix = false;
+            // the hoisted declaration is made on every iteration too, so it's nil already
+            return !assign.right.is_empty();
+        }
+        true
+    });
+    hoisted
+}
+
+// whether the block continues or breaks out of the loop it's in, nested loops aren't searched
+fn exits(block: &Block) -> (bool, bool) {
+    let (mut continues, mut breaks) = (false, false);
+    for statement in &block.0 {
+        match statement {
+            Statement::Continue(_) => continues = true,
+            Statement::Break(_) => breaks = true,
+            Statement::If(r#if) => {
+                for block in [&r#if.then_block, &r#if.else_block] {
+                    let (c, b) = exits(&block.lock());
+                    continues |= c;
+                    breaks |= b;
+                }
+            }
+            _ => {}
+        }
+    }
+    (continues, breaks)
+}
+
+// `continue` becomes `break` out of the wrapping `repeat` and `break` sets `broke` first
+fn replace_exits(block: &mut Block, broke: Option<&RcLocal>) {
+    let statements = std::mem::take(&mut block.0);
+    for mut statement in statements {
+        match &mut statement {
+            Statement::Continue(_) => {
+                block.push(Break {}.into());
+                continue;
+            }
+            Statement::Break(_) if let Some(broke) = broke => {
+                block.push(
+                    Assign::new(
+                        vec![broke.clone().into()],
+                        vec![Literal::Boolean(true).into()],
+                    )
+                    .into(),
+                );
+            }
+            Statement::If(r#if) => {
+                replace_exits(&mut r#if.then_block.lock(), broke);
+                replace_exits(&mut r#if.else_block.lock(), broke);
+            }
+            _ => {}
+        }
+        block.push(statement);
+    }
+}
+
+/// Rewrites `continue`, which Lua 5.1 doesn't have, into a `break` out of a `repeat ... until
+/// true` around the loop body. Loops that also `break` set a local before leaving the inner
+/// `repeat` and break again after it.
+///
+/// The locals of a `repeat` loop's body that its condition reads are declared before the wrapped
+/// body, so the condition can still see them.
+pub fn lower_continue(block: &mut Block) {
+    for statement in &mut block.0 {
+        if let Statement::If(r#if) = statement {
+            lower_continue(&mut r#if.then_block.lock());
+            lower_continue(&mut r#if.else_block.lock());
+        } else if let Some(loop_block) = loop_block(statement) {
+            let mut body = loop_block.lock();
+            lower_continue(&mut body);
+            let (continues, breaks) = exits(&body);
+            if !continues {
+                continue;
+            }
+            let hoisted = match &*statement {
+                Statement::Repeat(repeat) => hoist_condition_locals(&repeat.condition, &mut body),
+                _ => Vec::new(),
+            };
+            let broke = breaks.then(RcLocal::default);
+            replace_exits(&mut body, broke.as_ref());
+            let inner = Repeat::new(Literal::Boolean(true).into(), std::mem::take(&mut body));
+            match broke {
+                Some(broke) => {
+                    let mut declaration = Assign::new(
+                        vec![broke.clone().into()],
+                        vec![Literal::Boolean(false).into()],
+                    );
+                    declaration.prefix = true;
+                    body.push(declaration.into());
+                    body.push(inner.into());
+                    body.push(
+                        If::new(
+                            RValue::Local(broke),
+                            vec![Break {}.into()].into(),
+                            Block::default(),
+                        )
+                        .into(),
+                    );
+                }
+                None => body.push(inner.into()),
+            }
+            if !hoisted.is_empty() {
+                let mut declaration =
+                    Assign::new(hoisted.into_iter().map(|l| l.into()).collect(), Vec::new());
+                declaration.prefix = true;
+                body.insert(0, declaration.into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Binary, BinaryOperation, Call, Continue, Global, LValue, Local};
+
+    fn local(name: &str) -> RcLocal {
+        RcLocal::new(Local::new(Some(name.to_string())))
+    }
+
+    // repeat
+    //     local x = f()
+    //     if x then continue end
+    //     g()
+    // until x == 1
+    #[test]
+    fn repeat_condition_reads_body_local() {
+        let x = local("x");
+        let mut declaration = Assign::new(
+            vec![x.clone().into()],
+            vec![Call::new(Global::from("f").into(), Vec::new()).into()],
+        );
+        declaration.prefix = true;
+        let body = Block(vec![
+            declaration.into(),
+            If::new(
+                x.clone().into(),
+                Block(vec![Continue {}.into()]),
+                Block::default(),
+            )
+            .into(),
+            Call::new(Global::from("g").into(), Vec::new()).into(),
+        ]);
+        let condition = Binary::new(
+            x.clone().into(),
+            Literal::Number(1.0).into(),
+            BinaryOperation::Equal,
+        );
+        let mut block = Block(vec![Repeat::new(condition.into(), body).into()]);
+        lower_continue(&mut block);
+        assert!(!block.to_string().contains("continue"));
+
+        // the declaration of x is hoisted out of the wrapped body, which only assigns it
+        let outer = block[0].as_repeat().unwrap().block.lock();
+        let hoisted = outer[0].as_assign().unwrap();
+        assert!(hoisted.prefix && hoisted.right.is_empty());
+        assert_eq!(hoisted.left, vec![LValue::Local(x.clone())]);
+        let inner = outer[1].as_repeat().unwrap();
+        assert_eq!(inner.condition, RValue::Literal(Literal::Boolean(true)));
+        let assign = inner.block.lock()[0].as_assign().unwrap().clone();
+        assert!(!assign.prefix);
+        assert_eq!(assign.left, vec![LValue::Local(x)]);
+    }
+}
@@ -1,18 +1,99 @@
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
 use triomphe::Arc;
 
 use crate::{Block, RValue, RcLocal, Statement, Traverse, Upvalue};
 
-struct Namer {
+/// Names locals like [`name_locals_with_overrides`], but a block at a time, so a chunk can be
+/// named as it's decompiled. Generated names are unique across every block named by the same
+/// namer.
+pub struct Namer<'a> {
     rename: bool,
     counter: usize,
     upvalues: FxHashSet<RcLocal>,
+    overrides: &'a FxHashMap<String, String>,
+    local_names: FxHashMap<RcLocal, String>,
+    // existing names that were kept, and the locals they were kept for
+    kept: FxHashMap<String, FxHashSet<RcLocal>>,
+    // existing names that will be kept and names chosen by the user, generated names and suffixes
+    // skip them
+    reserved: FxHashSet<String>,
+    // the kept names and names chosen by the user of the locals in scope, innermost block last
+    scopes: Vec<FxHashSet<String>>,
 }
 
-impl Namer {
+impl<'a> Namer<'a> {
+    pub fn new(rename: bool, overrides: &'a FxHashMap<String, String>) -> Self {
+        Self {
+            rename,
+            counter: 1,
+            upvalues: FxHashSet::default(),
+            overrides,
+            local_names: FxHashMap::default(),
+            kept: FxHashMap::default(),
+            reserved: overrides.values().cloned().collect(),
+            scopes: vec![FxHashSet::default()],
+        }
+    }
+
+    /// Gives the locals in `local_names` the mapped name, whether or not existing names are
+    /// renamed, e.g. locals the user picked out by the instruction that declares them.
+    pub fn with_local_names(mut self, local_names: FxHashMap<RcLocal, String>) -> Self {
+        self.reserved.extend(local_names.values().cloned());
+        self.local_names = local_names;
+        self
+    }
+
+    // a name chosen by the user, with a suffix if a local in scope already has it so neither is
+    // shadowed
+    fn declare_chosen_name(&mut self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut suffix = 1;
+        while self.scopes.iter().any(|scope| scope.contains(&unique))
+            || (suffix != 1 && self.reserved.contains(&unique))
+        {
+            suffix += 1;
+            unique = format!("{}_{}", name, suffix);
+        }
+        self.scopes.last_mut().unwrap().insert(unique.clone());
+        unique
+    }
+
+    // whether `name` can't be kept for `local`, a suffixed name can't be one another local has
+    fn is_taken(&self, name: &str, local: &RcLocal, suffixed: bool) -> bool {
+        match self.kept.get(name) {
+            Some(locals) => !locals.contains(local),
+            None => suffixed && self.reserved.contains(name),
+        }
+    }
+
+    fn reserve_name(&mut self, local: &RcLocal) {
+        if let Some(name) = &local.0 .0.lock().0 {
+            self.reserved.insert(name.clone());
+        }
+    }
+
     fn name_local(&mut self, prefix: &str, local: &RcLocal) {
         let mut lock = local.0 .0.lock();
-        if self.rename || lock.0.is_none() {
+        if let Some(name) = self.local_names.get(local).cloned() {
+            lock.0 = Some(self.declare_chosen_name(&name));
+        } else if !self.rename
+            && let Some(name) = &lock.0
+        {
+            // a name from debug info is shared by every local a variable was split into that
+            // couldn't be coalesced again, so later ones get a suffix
+            let mut unique = name.clone();
+            let mut suffix = 1;
+            while self.is_taken(&unique, local, suffix != 1) {
+                suffix += 1;
+                unique = format!("{}_{}", name, suffix);
+            }
+            self.kept
+                .entry(unique.clone())
+                .or_default()
+                .insert(local.clone());
+            self.scopes.last_mut().unwrap().insert(unique.clone());
+            lock.0 = Some(unique);
+        } else if self.rename || lock.0.is_none() {
             // TODO: hacky and slow
             if Arc::count(&local.0 .0) == 1 {
                 lock.0 = Some("_".to_string());
@@ -23,22 +104,35 @@ impl Namer {
                     } else {
                         ""
                     };
-                lock.0 = Some(format!("{}{}", prefix, self.counter));
+                let mut name = format!("{}{}", prefix, self.counter);
                 self.counter += 1;
+                while self.reserved.contains(&name) {
+                    name = format!("{}{}", prefix, self.counter);
+                    self.counter += 1;
+                }
+                lock.0 = Some(match self.overrides.get(&name) {
+                    Some(chosen) => self.declare_chosen_name(chosen),
+                    None => name,
+                });
             }
         }
     }
 
-    fn name_locals(&mut self, block: &mut Block) {
+    /// Names the locals declared in `block`, which is in the scope of the blocks named before it.
+    /// Locals captured by closures are only named as upvalues if the closures were passed to
+    /// [`Namer::find_upvalues`] first.
+    pub fn name_locals(&mut self, block: &mut Block) {
         for statement in &mut block.0 {
             // TODO: traverse_rvalues
             statement.post_traverse_values(&mut |value| -> Option<()> {
                 if let itertools::Either::Right(RValue::Closure(closure)) = value {
                     let mut function = closure.function.lock();
+                    self.scopes.push(FxHashSet::default());
                     for param in &function.parameters {
                         self.name_local("p", param);
                     }
                     self.name_locals(&mut function.body);
+                    self.scopes.pop();
                 };
                 None
             });
@@ -49,24 +143,87 @@ impl Namer {
                     }
                 }
                 Statement::If(r#if) => {
-                    self.name_locals(&mut r#if.then_block.lock());
-                    self.name_locals(&mut r#if.else_block.lock());
+                    self.name_nested_locals(&[], &mut r#if.then_block.lock());
+                    self.name_nested_locals(&[], &mut r#if.else_block.lock());
                 }
                 Statement::While(r#while) => {
-                    self.name_locals(&mut r#while.block.lock());
+                    self.name_nested_locals(&[], &mut r#while.block.lock());
                 }
                 Statement::Repeat(repeat) => {
-                    self.name_locals(&mut repeat.block.lock());
+                    self.name_nested_locals(&[], &mut repeat.block.lock());
                 }
                 Statement::NumericFor(numeric_for) => {
-                    self.name_local("v", &numeric_for.counter);
-                    self.name_locals(&mut numeric_for.block.lock());
+                    self.name_nested_locals(
+                        &[("i", &numeric_for.counter)],
+                        &mut numeric_for.block.lock(),
+                    );
+                }
+                Statement::GenericFor(generic_for) => {
+                    let res_locals = generic_for
+                        .res_locals
+                        .iter()
+                        .map(|l| ("v", l))
+                        .collect::<Vec<_>>();
+                    self.name_nested_locals(&res_locals, &mut generic_for.block.lock());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // names the locals of a block in a scope of its own, along with `locals` declared by the
+    // statement the block is in
+    fn name_nested_locals(&mut self, locals: &[(&str, &RcLocal)], block: &mut Block) {
+        self.scopes.push(FxHashSet::default());
+        for (prefix, local) in locals {
+            self.name_local(prefix, local);
+        }
+        self.name_locals(block);
+        self.scopes.pop();
+    }
+
+    /// Reserves the names of the locals declared in `block` that will be kept, so no generated name
+    /// or suffix uses them. Does nothing if every local is renamed.
+    pub fn reserve_names(&mut self, block: &mut Block) {
+        if self.rename {
+            return;
+        }
+        for statement in &mut block.0 {
+            statement.post_traverse_values(&mut |value| -> Option<()> {
+                if let itertools::Either::Right(RValue::Closure(closure)) = value {
+                    let mut function = closure.function.lock();
+                    for param in &function.parameters {
+                        self.reserve_name(param);
+                    }
+                    self.reserve_names(&mut function.body);
+                };
+                None
+            });
+            match statement {
+                Statement::Assign(assign) if assign.prefix => {
+                    for lvalue in &assign.left {
+                        self.reserve_name(lvalue.as_local().unwrap());
+                    }
+                }
+                Statement::If(r#if) => {
+                    self.reserve_names(&mut r#if.then_block.lock());
+                    self.reserve_names(&mut r#if.else_block.lock());
+                }
+                Statement::While(r#while) => {
+                    self.reserve_names(&mut r#while.block.lock());
+                }
+                Statement::Repeat(repeat) => {
+                    self.reserve_names(&mut repeat.block.lock());
+                }
+                Statement::NumericFor(numeric_for) => {
+                    self.reserve_name(&numeric_for.counter);
+                    self.reserve_names(&mut numeric_for.block.lock());
                 }
                 Statement::GenericFor(generic_for) => {
                     for res_local in &generic_for.res_locals {
-                        self.name_local("v", res_local);
+                        self.reserve_name(res_local);
                     }
-                    self.name_locals(&mut generic_for.block.lock());
+                    self.reserve_names(&mut generic_for.block.lock());
                 }
                 _ => {}
             }
@@ -74,7 +231,7 @@ impl Namer {
     }
 
     // TODO: does this need to be mut?
-    fn find_upvalues(&mut self, block: &mut Block) {
+    pub fn find_upvalues(&mut self, block: &mut Block) {
         for statement in &mut block.0 {
             // TODO: traverse_values
             // TODO: doesnt need to be mut
@@ -116,12 +273,105 @@ impl Namer {
     }
 }
 
+/// Names the locals declared in `block`. Existing names are replaced if `rename` is set, otherwise
+/// they are kept, with a suffix if they are used by more than one local.
 pub fn name_locals(block: &mut Block, rename: bool) {
-    let mut namer = Namer {
-        rename,
-        counter: 1,
-        upvalues: FxHashSet::default(),
-    };
+    name_locals_with_overrides(block, rename, &FxHashMap::default(), FxHashMap::default());
+}
+
+/// Like [`name_locals`], but a local whose generated name is in `overrides` is given the mapped name instead.
+/// Generated names are unique within a chunk, so labels chosen by the user stay attached to the same local
+/// as long as the output is generated the same way. Locals in `local_names` are given the mapped name
+/// whatever their generated name is. A chosen name that a local in scope already has gets a suffix.
+pub fn name_locals_with_overrides(
+    block: &mut Block,
+    rename: bool,
+    overrides: &FxHashMap<String, String>,
+    local_names: FxHashMap<RcLocal, String>,
+) {
+    let mut namer = Namer::new(rename, overrides).with_local_names(local_names);
+    namer.reserve_names(block);
     namer.find_upvalues(block);
     namer.name_locals(block);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Assign, Call, Global, If, Literal, While};
+
+    fn declare(local: &RcLocal) -> Statement {
+        let mut assign = Assign::new(
+            vec![local.clone().into()],
+            vec![Call::new(Global::from("f").into(), Vec::new()).into()],
+        );
+        assign.prefix = true;
+        assign.into()
+    }
+
+    fn name(local: &RcLocal) -> String {
+        local.0 .0.lock().0.clone().unwrap()
+    }
+
+    // local player = f()
+    // while true do
+    //     local player_2 = f()
+    // end
+    #[test]
+    fn chosen_names_are_not_shadowed() {
+        let (outer, inner) = (RcLocal::default(), RcLocal::default());
+        let mut block = Block(vec![
+            declare(&outer),
+            While::new(Literal::Boolean(true).into(), Block(vec![declare(&inner)])).into(),
+        ]);
+        let local_names = [(outer.clone(), "player"), (inner.clone(), "player")]
+            .into_iter()
+            .map(|(local, name)| (local, name.to_string()))
+            .collect();
+        name_locals_with_overrides(&mut block, true, &FxHashMap::default(), local_names);
+        assert_eq!(name(&outer), "player");
+        assert_eq!(name(&inner), "player_2");
+    }
+
+    // if a then
+    //     local value = f()
+    // else
+    //     local value = f()
+    // end
+    #[test]
+    fn chosen_names_are_reused_out_of_scope() {
+        let (then_local, else_local) = (RcLocal::default(), RcLocal::default());
+        let mut block = Block(vec![If::new(
+            Global::from("a").into(),
+            Block(vec![declare(&then_local)]),
+            Block(vec![declare(&else_local)]),
+        )
+        .into()]);
+        let local_names = [(then_local.clone(), "value"), (else_local.clone(), "value")]
+            .into_iter()
+            .map(|(local, name)| (local, name.to_string()))
+            .collect();
+        name_locals_with_overrides(&mut block, true, &FxHashMap::default(), local_names);
+        assert_eq!(name(&then_local), "value");
+        assert_eq!(name(&else_local), "value");
+    }
+
+    // a generated name that is chosen for another local is skipped, and an override of a
+    // generated name doesn't shadow a chosen name
+    #[test]
+    fn generated_names_skip_chosen_names() {
+        let (chosen, generated, overridden) =
+            (RcLocal::default(), RcLocal::default(), RcLocal::default());
+        let mut block = Block(vec![
+            declare(&chosen),
+            declare(&generated),
+            declare(&overridden),
+        ]);
+        let overrides = [("v3".to_string(), "v1".to_string())].into_iter().collect();
+        let local_names = [(chosen.clone(), "v1".to_string())].into_iter().collect();
+        name_locals_with_overrides(&mut block, true, &overrides, local_names);
+        assert_eq!(name(&chosen), "v1");
+        assert_eq!(name(&generated), "v2");
+        assert_eq!(name(&overridden), "v1_2");
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use itertools::Either;
+
+use crate::{Block, LValue, RValue, Statement, Traverse};
+
+pub fn replace_globals<H: std::hash::BuildHasher>(
+    block: &mut Block,
+    map: &HashMap<Vec<u8>, Vec<u8>, H>,
+) {
+    for statement in &mut block.0 {
+        statement.post_traverse_values(&mut |value| -> Option<()> {
+            match value {
+                Either::Left(LValue::Global(global)) | Either::Right(RValue::Global(global)) => {
+                    if let Some(new_name) = map.get(&global.0) {
+                        global.0 = new_name.clone();
+                    }
+                }
+                Either::Right(RValue::Closure(closure)) => {
+                    replace_globals(&mut closure.function.lock().body, map)
+                }
+                _ => {}
+            }
+            None
+        });
+        match statement {
+            Statement::If(r#if) => {
+                replace_globals(&mut r#if.then_block.lock(), map);
+                replace_globals(&mut r#if.else_block.lock(), map);
+            }
+            Statement::While(r#while) => {
+                replace_globals(&mut r#while.block.lock(), map);
+            }
+            Statement::Repeat(repeat) => {
+                replace_globals(&mut repeat.block.lock(), map);
+            }
+            Statement::NumericFor(numeric_for) => {
+                replace_globals(&mut numeric_for.block.lock(), map);
+            }
+            Statement::GenericFor(generic_for) => {
+                replace_globals(&mut generic_for.block.lock(), map);
+            }
+            _ => {}
+        }
+    }
+}
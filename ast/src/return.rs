@@ -1,19 +1,21 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::{formatter::Formatter, has_side_effects, LocalRw, RcLocal, Traverse};
+use crate::{formatter::Formatter, has_side_effects, LocalRw, RcLocal, Span, Traverse};
 
 use super::RValue;
 
-#[derive(Debug, PartialEq, Clone, Default)]
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
 pub struct Return {
     pub values: Vec<RValue>,
+    pub span: Option<Span>,
 }
 
 has_side_effects!(Return);
 
 impl Return {
     pub fn new(values: Vec<RValue>) -> Self {
-        Self { values }
+        Self { values, span: None }
     }
 }
 
@@ -42,11 +44,6 @@ impl LocalRw for Return {
 
 impl fmt::Display for Return {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Formatter {
-            indentation_level: 0,
-            indentation_mode: Default::default(),
-            output: f,
-        }
-        .format_return(self)
+        Formatter::new(f, Default::default()).format_return(self)
     }
 }
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use crate::{
+    Binary, BinaryOperation, Block, Call, Global, Index, Literal, MethodCall, RValue, Select,
+    SideEffects, Statement, Traverse, Unary, UnaryOperation,
+};
+
+/// The values a [`Pattern`] bound to its metavariables.
+pub type Bindings = HashMap<String, RValue>;
+
+/// The shape of a value, with metavariables in place of the parts that can vary. Used both as the
+/// value a [`Rule`] matches and the value it replaces it with, where metavariables are replaced
+/// with the value they were bound to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// A metavariable matching any value. A metavariable that appears more than once in a pattern
+    /// only matches if the values are equal.
+    Any(String),
+    /// A metavariable matching a local
+    Local(String),
+    /// A metavariable matching a literal
+    Constant(String),
+    Literal(Literal),
+    Global(Vec<u8>),
+    Unary(UnaryOperation, Box<Pattern>),
+    Binary(Box<Pattern>, BinaryOperation, Box<Pattern>),
+    Index(Box<Pattern>, Box<Pattern>),
+    /// Matches calls truncated to one value as well as calls that aren't
+    Call(Box<Pattern>, Vec<Pattern>),
+    MethodCall(Box<Pattern>, String, Vec<Pattern>),
+}
+
+impl Pattern {
+    pub fn any(name: impl Into<String>) -> Self {
+        Self::Any(name.into())
+    }
+
+    pub fn local(name: impl Into<String>) -> Self {
+        Self::Local(name.into())
+    }
+
+    pub fn constant(name: impl Into<String>) -> Self {
+        Self::Constant(name.into())
+    }
+
+    pub fn literal(literal: impl Into<Literal>) -> Self {
+        Self::Literal(literal.into())
+    }
+
+    pub fn global(name: impl Into<Vec<u8>>) -> Self {
+        Self::Global(name.into())
+    }
+
+    pub fn unary(operation: UnaryOperation, value: Pattern) -> Self {
+        Self::Unary(operation, Box::new(value))
+    }
+
+    pub fn binary(left: Pattern, operation: BinaryOperation, right: Pattern) -> Self {
+        Self::Binary(Box::new(left), operation, Box::new(right))
+    }
+
+    pub fn index(left: Pattern, right: Pattern) -> Self {
+        Self::Index(Box::new(left), Box::new(right))
+    }
+
+    pub fn call(value: Pattern, arguments: Vec<Pattern>) -> Self {
+        Self::Call(Box::new(value), arguments)
+    }
+
+    pub fn method_call(value: Pattern, method: impl Into<String>, arguments: Vec<Pattern>) -> Self {
+        Self::MethodCall(Box::new(value), method.into(), arguments)
+    }
+
+    // how many times every metavariable appears
+    fn metavariables<'a>(&'a self, counts: &mut HashMap<&'a str, usize>) {
+        match self {
+            Self::Any(name) | Self::Local(name) | Self::Constant(name) => {
+                *counts.entry(name).or_default() += 1
+            }
+            Self::Literal(_) | Self::Global(_) => {}
+            Self::Unary(_, value) => value.metavariables(counts),
+            Self::Binary(left, _, right) | Self::Index(left, right) => {
+                left.metavariables(counts);
+                right.metavariables(counts);
+            }
+            Self::Call(value, arguments) | Self::MethodCall(value, _, arguments) => {
+                value.metavariables(counts);
+                for argument in arguments {
+                    argument.metavariables(counts);
+                }
+            }
+        }
+    }
+
+    fn matches(&self, rvalue: &RValue, bindings: &mut Bindings) -> bool {
+        let mut bind = |name: &String| match bindings.get(name) {
+            Some(bound) => bound == rvalue,
+            None => {
+                bindings.insert(name.clone(), rvalue.clone());
+                true
+            }
+        };
+        match (self, rvalue) {
+            (Self::Any(name), _)
+            | (Self::Local(name), RValue::Local(_))
+            | (Self::Constant(name), RValue::Literal(_)) => bind(name),
+            (Self::Literal(literal), RValue::Literal(other)) => literal == other,
+            (Self::Global(name), RValue::Global(global)) => global.0 == *name,
+            (Self::Unary(operation, value), RValue::Unary(unary)) => {
+                *operation == unary.operation && value.matches(&unary.value, bindings)
+            }
+            (Self::Binary(left, operation, right), RValue::Binary(binary)) => {
+                *operation == binary.operation
+                    && left.matches(&binary.left, bindings)
+                    && right.matches(&binary.right, bindings)
+            }
+            (Self::Index(left, right), RValue::Index(index)) => {
+                left.matches(&index.left, bindings) && right.matches(&index.right, bindings)
+            }
+            (Self::Call(value, arguments), RValue::Call(call))
+            | (Self::Call(value, arguments), RValue::Select(Select::Call(call))) => {
+                value.matches(&call.value, bindings)
+                    && Self::matches_all(arguments, &call.arguments, bindings)
+            }
+            (Self::MethodCall(value, method, arguments), RValue::MethodCall(call))
+            | (
+                Self::MethodCall(value, method, arguments),
+                RValue::Select(Select::MethodCall(call)),
+            ) => {
+                *method == call.method
+                    && value.matches(&call.value, bindings)
+                    && Self::matches_all(arguments, &call.arguments, bindings)
+            }
+            _ => false,
+        }
+    }
+
+    fn matches_all(patterns: &[Pattern], rvalues: &[RValue], bindings: &mut Bindings) -> bool {
+        patterns.len() == rvalues.len()
+            && patterns
+                .iter()
+                .zip(rvalues)
+                .all(|(pattern, rvalue)| pattern.matches(rvalue, bindings))
+    }
+
+    // calls are truncated to one value, the caller expands the outermost one if it replaces a
+    // call that wasn't
+    fn instantiate(&self, bindings: &Bindings) -> RValue {
+        let instantiate_all = |patterns: &[Pattern]| {
+            patterns
+                .iter()
+                .map(|pattern| pattern.instantiate(bindings))
+                .collect()
+        };
+        match self {
+            Self::Any(name) | Self::Local(name) | Self::Constant(name) => bindings[name].clone(),
+            Self::Literal(literal) => literal.clone().into(),
+            Self::Global(name) => Global::new(name.clone()).into(),
+            Self::Unary(operation, value) => {
+                Unary::new(value.instantiate(bindings), *operation).into()
+            }
+            Self::Binary(left, operation, right) => Binary::new(
+                left.instantiate(bindings),
+                right.instantiate(bindings),
+                *operation,
+            )
+            .into(),
+            Self::Index(left, right) => {
+                Index::new(left.instantiate(bindings), right.instantiate(bindings)).into()
+            }
+            Self::Call(value, arguments) => RValue::Select(Select::Call(Call::new(
+                value.instantiate(bindings),
+                instantiate_all(arguments),
+            ))),
+            Self::MethodCall(value, method, arguments) => {
+                RValue::Select(Select::MethodCall(MethodCall::new(
+                    value.instantiate(bindings),
+                    method.clone(),
+                    instantiate_all(arguments),
+                )))
+            }
+        }
+    }
+}
+
+type Condition = dyn Fn(&Bindings) -> bool + Send + Sync;
+
+/// Replaces values matching a pattern with another, see [`rewrite`].
+pub struct Rule {
+    pattern: Pattern,
+    replacement: Pattern,
+    condition: Option<Box<Condition>>,
+    // the number of times each metavariable appears in the pattern and replacement
+    counts: HashMap<String, (usize, usize)>,
+}
+
+impl Rule {
+    /// # Panics
+    /// Panics if `replacement` has a metavariable that isn't in `pattern`.
+    pub fn new(pattern: Pattern, replacement: Pattern) -> Self {
+        let mut pattern_counts = HashMap::new();
+        pattern.metavariables(&mut pattern_counts);
+        let mut replacement_counts = HashMap::new();
+        replacement.metavariables(&mut replacement_counts);
+        if let Some(name) = replacement_counts
+            .keys()
+            .find(|name| !pattern_counts.contains_key(*name))
+        {
+            panic!("metavariable `{}` isn't bound by the pattern", name);
+        }
+        let counts = pattern_counts
+            .iter()
+            .map(|(&name, &count)| {
+                let replaced = replacement_counts.get(name).copied().unwrap_or_default();
+                (name.to_string(), (count, replaced))
+            })
+            .collect();
+        Self {
+            pattern,
+            replacement,
+            condition: None,
+            counts,
+        }
+    }
+
+    /// Only applies the rule when `condition` holds for the values the pattern bound.
+    pub fn when(mut self, condition: impl Fn(&Bindings) -> bool + Send + Sync + 'static) -> Self {
+        self.condition = Some(Box::new(condition));
+        self
+    }
+
+    // values with side effects have to be evaluated as many times after the rewrite as before
+    fn apply(&self, rvalue: &RValue) -> Option<RValue> {
+        let mut bindings = Bindings::new();
+        if !self.pattern.matches(rvalue, &mut bindings)
+            || !bindings.iter().all(|(name, value)| {
+                let (count, replaced) = self.counts[name];
+                count == replaced || !value.has_side_effects()
+            })
+            || self
+                .condition
+                .as_ref()
+                .is_some_and(|condition| !condition(&bindings))
+        {
+            return None;
+        }
+        Some(match (rvalue, self.replacement.instantiate(&bindings)) {
+            (RValue::Call(_) | RValue::MethodCall(_), RValue::Select(Select::Call(call))) => {
+                call.into()
+            }
+            (
+                RValue::Call(_) | RValue::MethodCall(_),
+                RValue::Select(Select::MethodCall(method_call)),
+            ) => method_call.into(),
+            (_, replacement) => replacement,
+        })
+    }
+}
+
+impl std::fmt::Debug for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Rule")
+            .field("pattern", &self.pattern)
+            .field("replacement", &self.replacement)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The most times [`rewrite`] goes over a block, in case rules keep rewriting each other's output
+const MAX_PASSES: usize = 100;
+
+// goes over the block once, applying the first rule that matches to every value
+fn rewrite_once(block: &mut Block, rules: &[Rule]) -> usize {
+    let mut rewrites = 0;
+    for statement in &mut block.0 {
+        statement.post_traverse_rvalues(&mut |rvalue| -> Option<()> {
+            if let RValue::Closure(closure) = rvalue {
+                rewrites += rewrite_once(&mut closure.function.lock().body, rules);
+            } else if let Some(replacement) = rules.iter().find_map(|rule| rule.apply(rvalue)) {
+                *rvalue = replacement;
+                rewrites += 1;
+            }
+            None
+        });
+        match statement {
+            Statement::If(r#if) => {
+                rewrites += rewrite_once(&mut r#if.then_block.lock(), rules);
+                rewrites += rewrite_once(&mut r#if.else_block.lock(), rules);
+            }
+            Statement::While(r#while) => rewrites += rewrite_once(&mut r#while.block.lock(), rules),
+            Statement::Repeat(repeat) => rewrites += rewrite_once(&mut repeat.block.lock(), rules),
+            Statement::NumericFor(numeric_for) => {
+                rewrites += rewrite_once(&mut numeric_for.block.lock(), rules)
+            }
+            Statement::GenericFor(generic_for) => {
+                rewrites += rewrite_once(&mut generic_for.block.lock(), rules)
+            }
+            _ => {}
+        }
+    }
+    rewrites
+}
+
+/// Applies `rules` to the values in `block` and the functions in it until none of them match,
+/// and returns the number of values that were rewritten. Rules are tried in order and values are
+/// rewritten innermost first. A rule isn't applied if it would change how many times a value with
+/// side effects is evaluated, but it is up to the rule to keep them in the same order.
+pub fn rewrite(block: &mut Block, rules: &[Rule]) -> usize {
+    let mut rewrites = 0;
+    for _ in 0..MAX_PASSES {
+        match rewrite_once(block, rules) {
+            0 => break,
+            count => rewrites += count,
+        }
+    }
+    rewrites
+}
@@ -0,0 +1,119 @@
+//! Serde support for values that are shared by reference in the AST.
+//! Locals and closure functions are compared by address, so they are written as an id that is
+//! unique within a [`scope`] and read back as a single shared value.
+
+use std::cell::RefCell;
+
+use by_address::ByAddress;
+use parking_lot::Mutex;
+use rustc_hash::FxHashMap;
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use triomphe::Arc;
+
+use crate::{Function, Local, RcLocal};
+
+#[derive(Default)]
+struct State {
+    ids: FxHashMap<usize, usize>,
+    locals: FxHashMap<usize, RcLocal>,
+    functions: FxHashMap<usize, ByAddress<Arc<Mutex<Function>>>>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::default();
+}
+
+/// Runs `f` with a fresh set of ids. Everything that refers to the same locals or functions
+/// must be serialized or deserialized within a single scope.
+pub fn scope<R>(f: impl FnOnce() -> R) -> R {
+    STATE.take();
+    let result = f();
+    STATE.take();
+    result
+}
+
+// returns the id of the value at `address` and whether it was seen for the first time
+fn id_of(address: usize) -> (usize, bool) {
+    STATE.with_borrow_mut(|state| {
+        let next_id = state.ids.len();
+        let mut new = false;
+        let id = *state.ids.entry(address).or_insert_with(|| {
+            new = true;
+            next_id
+        });
+        (id, new)
+    })
+}
+
+impl Serialize for RcLocal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (id, _) = id_of(Arc::as_ptr(&self.0 .0) as usize);
+        let local = self.0.lock();
+        (id, &local.0, local.1, self.scope()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RcLocal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (id, name, number, scope) =
+            <(usize, Option<String>, usize, usize)>::deserialize(deserializer)?;
+        Ok(STATE.with_borrow_mut(|state| {
+            state
+                .locals
+                .entry(id)
+                .or_insert_with(|| RcLocal::with_scope(Local(name, number), scope))
+                .clone()
+        }))
+    }
+}
+
+/// Writes the contents of a locked value, e.g. the block of an `If`.
+pub mod locked {
+    use super::*;
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        value: &Arc<Mutex<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.lock().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<Mutex<T>>, D::Error> {
+        Ok(Arc::new(Mutex::new(T::deserialize(deserializer)?)))
+    }
+}
+
+/// Writes a function the first time it is seen and only its id after that.
+pub mod shared_function {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        function: &ByAddress<Arc<Mutex<Function>>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let (id, new) = id_of(Arc::as_ptr(&function.0) as usize);
+        if new {
+            (id, Some(&*function.lock())).serialize(serializer)
+        } else {
+            (id, None::<&Function>).serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ByAddress<Arc<Mutex<Function>>>, D::Error> {
+        let (id, function) = <(usize, Option<Function>)>::deserialize(deserializer)?;
+        STATE.with_borrow_mut(|state| match function {
+            Some(function) => {
+                let function = ByAddress(Arc::new(Mutex::new(function)));
+                state.functions.insert(id, function.clone());
+                Ok(function)
+            }
+            None => state.functions.get(&id).cloned().ok_or_else(|| {
+                D::Error::custom(format!("function {} used before it is defined", id))
+            }),
+        })
+    }
+}
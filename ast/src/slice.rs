@@ -0,0 +1,260 @@
+use rustc_hash::FxHashSet;
+
+use crate::{
+    Block, GenericFor, If, LValue, LocalRw, NumericFor, RValue, RcLocal, Repeat, Statement,
+    Traverse, While,
+};
+
+fn visit_rvalues<'a>(rvalue: &'a RValue, callback: &mut impl FnMut(&'a RValue)) {
+    callback(rvalue);
+    for rvalue in rvalue.rvalues() {
+        visit_rvalues(rvalue, callback);
+    }
+}
+
+fn statement_rvalues(statement: &Statement) -> Vec<&RValue> {
+    let mut rvalues = Vec::new();
+    if let Statement::Assign(assign) = statement {
+        for lvalue in &assign.left {
+            if let LValue::Index(index) = lvalue {
+                visit_rvalues(&index.left, &mut |r| rvalues.push(r));
+                visit_rvalues(&index.right, &mut |r| rvalues.push(r));
+            }
+        }
+    }
+    for rvalue in statement.rvalues() {
+        visit_rvalues(rvalue, &mut |r| rvalues.push(r));
+    }
+    rvalues
+}
+
+fn globals_read(statement: &Statement) -> Vec<Vec<u8>> {
+    statement_rvalues(statement)
+        .into_iter()
+        .filter_map(|rvalue| match rvalue {
+            RValue::Global(global) => Some(global.0.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// the value an index expression like `a.b.c` is rooted at
+fn index_root(lvalue: &LValue) -> Option<&RValue> {
+    let LValue::Index(index) = lvalue else {
+        return None;
+    };
+    let mut value = &*index.left;
+    while let RValue::Index(index) = value {
+        value = &index.left;
+    }
+    Some(value)
+}
+
+struct Slicer {
+    locals: FxHashSet<RcLocal>,
+    globals: FxHashSet<Vec<u8>>,
+    changed: bool,
+}
+
+impl Slicer {
+    fn is_relevant_value(&self, value: &RValue) -> bool {
+        match value {
+            RValue::Local(local) => self.locals.contains(local),
+            RValue::Global(global) => self.globals.contains(&global.0),
+            _ => false,
+        }
+    }
+
+    fn add_reads<'a>(&mut self, locals: impl IntoIterator<Item = &'a RcLocal>) {
+        for local in locals {
+            self.changed |= self.locals.insert(local.clone());
+        }
+    }
+
+    fn add_global_reads(&mut self, statement: &Statement) {
+        for global in globals_read(statement) {
+            self.changed |= self.globals.insert(global);
+        }
+    }
+
+    // a statement is relevant if it may write to a relevant value, including through
+    // calls that are passed one and closures that assign to one
+    fn is_relevant_leaf(&mut self, statement: &Statement) -> bool {
+        let mut relevant = statement
+            .values_written()
+            .iter()
+            .any(|local| self.locals.contains(*local));
+        match statement {
+            Statement::Assign(assign) => {
+                relevant |= assign.left.iter().any(|lvalue| match lvalue {
+                    LValue::Global(global) => self.globals.contains(&global.0),
+                    lvalue => index_root(lvalue).is_some_and(|root| self.is_relevant_value(root)),
+                })
+            }
+            Statement::Call(_) | Statement::MethodCall(_) => {
+                relevant |= statement
+                    .values_read()
+                    .iter()
+                    .any(|local| self.locals.contains(*local))
+                    || globals_read(statement)
+                        .iter()
+                        .any(|global| self.globals.contains(global));
+            }
+            _ => {}
+        }
+        for rvalue in statement_rvalues(statement) {
+            if let RValue::Closure(closure) = rvalue {
+                let function = closure.function.lock();
+                relevant |= self.analyze(&function.body);
+            }
+        }
+        if relevant {
+            self.add_reads(statement.values_read());
+            self.add_global_reads(statement);
+        }
+        relevant
+    }
+
+    fn is_relevant(&mut self, statement: &Statement) -> bool {
+        let blocks = match statement {
+            Statement::If(r#if) => vec![r#if.then_block.clone(), r#if.else_block.clone()],
+            Statement::While(r#while) => vec![r#while.block.clone()],
+            Statement::Repeat(repeat) => vec![repeat.block.clone()],
+            Statement::NumericFor(numeric_for) => vec![numeric_for.block.clone()],
+            Statement::GenericFor(generic_for) => vec![generic_for.block.clone()],
+            _ => return self.is_relevant_leaf(statement),
+        };
+        let mut relevant = statement
+            .values_written()
+            .iter()
+            .any(|local| self.locals.contains(*local));
+        for block in blocks {
+            relevant |= self.analyze(&block.lock());
+        }
+        if relevant {
+            self.add_reads(statement.values_read());
+            self.add_global_reads(statement);
+        }
+        relevant
+    }
+
+    fn analyze(&mut self, block: &Block) -> bool {
+        let mut relevant = false;
+        for statement in block.iter() {
+            relevant |= self.is_relevant(statement);
+        }
+        relevant
+    }
+
+    fn filter(&mut self, block: &Block) -> Block {
+        let mut statements = Vec::new();
+        for statement in block.iter() {
+            let statement = match statement {
+                Statement::Break(_)
+                | Statement::Continue(_)
+                | Statement::Goto(_)
+                | Statement::Label(_) => statement.clone(),
+                _ if !self.is_relevant(statement) => continue,
+                Statement::If(r#if) => If::new(
+                    r#if.condition.clone(),
+                    self.filter(&r#if.then_block.lock()),
+                    self.filter(&r#if.else_block.lock()),
+                )
+                .into(),
+                Statement::While(r#while) => While::new(
+                    r#while.condition.clone(),
+                    self.filter(&r#while.block.lock()),
+                )
+                .into(),
+                Statement::Repeat(repeat) => {
+                    Repeat::new(repeat.condition.clone(), self.filter(&repeat.block.lock())).into()
+                }
+                Statement::NumericFor(numeric_for) => NumericFor::new(
+                    numeric_for.initial.clone(),
+                    numeric_for.limit.clone(),
+                    numeric_for.step.clone(),
+                    numeric_for.counter.clone(),
+                    self.filter(&numeric_for.block.lock()),
+                )
+                .into(),
+                Statement::GenericFor(generic_for) => GenericFor::new(
+                    generic_for.res_locals.clone(),
+                    generic_for.right.clone(),
+                    self.filter(&generic_for.block.lock()),
+                )
+                .into(),
+                statement => statement.clone(),
+            };
+            statements.push(statement);
+        }
+        Block(statements)
+    }
+}
+
+/// Computes the backward slice of `block` with respect to `locals` and `globals`: every statement
+/// that may influence their values, along with the control flow around those statements.
+/// Statements inside closures are followed through upvalues and kept by keeping the whole closure.
+/// To slice with respect to a statement, pass the locals it reads from (`values_read`).
+pub fn backward_slice(
+    block: &Block,
+    locals: FxHashSet<RcLocal>,
+    globals: FxHashSet<Vec<u8>>,
+) -> Block {
+    let mut slicer = Slicer {
+        locals,
+        globals,
+        changed: true,
+    };
+    while slicer.changed {
+        slicer.changed = false;
+        slicer.analyze(block);
+    }
+    slicer.filter(block)
+}
+
+/// Returns every local in `block`, including nested blocks and closures, that is named `name`.
+pub fn locals_named(block: &Block, name: &str) -> FxHashSet<RcLocal> {
+    let mut locals = FxHashSet::default();
+    collect_locals_named(block, name, &mut locals);
+    locals
+}
+
+fn collect_locals_named(block: &Block, name: &str, locals: &mut FxHashSet<RcLocal>) {
+    for statement in block.iter() {
+        for local in statement
+            .values_read()
+            .into_iter()
+            .chain(statement.values_written())
+        {
+            if local.0.lock().0.as_deref() == Some(name) {
+                locals.insert(local.clone());
+            }
+        }
+        for rvalue in statement_rvalues(statement) {
+            if let RValue::Closure(closure) = rvalue {
+                let function = closure.function.lock();
+                for parameter in &function.parameters {
+                    if parameter.0.lock().0.as_deref() == Some(name) {
+                        locals.insert(parameter.clone());
+                    }
+                }
+                collect_locals_named(&function.body, name, locals);
+            }
+        }
+        match statement {
+            Statement::If(r#if) => {
+                collect_locals_named(&r#if.then_block.lock(), name, locals);
+                collect_locals_named(&r#if.else_block.lock(), name, locals);
+            }
+            Statement::While(r#while) => collect_locals_named(&r#while.block.lock(), name, locals),
+            Statement::Repeat(repeat) => collect_locals_named(&repeat.block.lock(), name, locals),
+            Statement::NumericFor(numeric_for) => {
+                collect_locals_named(&numeric_for.block.lock(), name, locals)
+            }
+            Statement::GenericFor(generic_for) => {
+                collect_locals_named(&generic_for.block.lock(), name, locals)
+            }
+            _ => {}
+        }
+    }
+}
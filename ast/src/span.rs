@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::Statement;
+
+/// The instructions a statement was lifted from, as an inclusive range of program counters in the
+/// function the statement is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub pc_start: usize,
+    pub pc_end: usize,
+}
+
+impl Span {
+    pub fn new(pc_start: usize, pc_end: usize) -> Self {
+        Self { pc_start, pc_end }
+    }
+
+    /// The smallest span covering both spans.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            pc_start: self.pc_start.min(other.pc_start),
+            pc_end: self.pc_end.max(other.pc_end),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.pc_start == self.pc_end {
+            write!(f, "pc {}", self.pc_start)
+        } else {
+            write!(f, "pc {}-{}", self.pc_start, self.pc_end)
+        }
+    }
+}
+
+impl Statement {
+    /// The instructions the statement was lifted from. Only assignments, calls and returns have
+    /// one, and only if they were lifted from instructions rather than created by a pass.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Statement::Assign(assign) => assign.span,
+            Statement::CompoundAssign(compound_assign) => compound_assign.span,
+            Statement::Call(call) => call.span,
+            Statement::MethodCall(method_call) => method_call.span,
+            Statement::Return(r#return) => r#return.span,
+            _ => None,
+        }
+    }
+
+    fn span_mut(&mut self) -> Option<&mut Option<Span>> {
+        match self {
+            Statement::Assign(assign) => Some(&mut assign.span),
+            Statement::CompoundAssign(compound_assign) => Some(&mut compound_assign.span),
+            Statement::Call(call) => Some(&mut call.span),
+            Statement::MethodCall(method_call) => Some(&mut method_call.span),
+            Statement::Return(r#return) => Some(&mut r#return.span),
+            _ => None,
+        }
+    }
+
+    /// Extends the span of the statement to cover `span` too, e.g. when an expression lifted from
+    /// other instructions is inlined into it. Does nothing for statements that can't have a span.
+    pub fn extend_span(&mut self, span: Option<Span>) {
+        if let Some(span) = span
+            && let Some(own_span) = self.span_mut()
+        {
+            *own_span = Some(own_span.map_or(span, |own_span| own_span.merge(span)));
+        }
+    }
+}
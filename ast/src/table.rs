@@ -1,10 +1,12 @@
 use crate::{
-    formatter::Formatter, Literal, LocalRw, RValue, RcLocal, Reduce, SideEffects, Traverse,
+    formatter::Formatter, type_system::Infer, Literal, LocalRw, RValue, RcLocal, Reduce,
+    SideEffects, Traverse, Type, TypeSystem,
 };
+use serde::{Deserialize, Serialize};
 
-use std::{fmt, iter};
+use std::{collections::BTreeMap, fmt, iter};
 
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct Table(pub Vec<(Option<RValue>, RValue)>);
 
 impl Reduce for Table {
@@ -22,39 +24,58 @@ impl Reduce for Table {
     }
 }
 
-/*impl Infer for Table {
-    fn infer<'a: 'b, 'b>(&'a mut self, system: &mut TypeSystem<'b>) -> Type {
-        let elements: BTreeSet<_> = self
-            .0
-            .iter_mut()
-            .map(|(f, v)| (f.clone(), v.infer(system)))
-            .collect();
-        let elements: BTreeSet<_> = elements
-            .iter()
-            .filter(|(f, t)| {
-                f.is_some() || !elements.iter().any(|(_, x)| t != x && t.is_subtype_of(x))
-            })
-            .cloned()
-            .collect();
-        let (elements, fields): (BTreeSet<_>, BTreeMap<_, _>) =
-            elements.into_iter().partition_map(|(f, t)| match f {
-                None => Either::Left(t),
-                Some(f) => Either::Right((f, t)),
-            });
-
+impl Infer for Table {
+    // records whose keys are names and arrays of values, anything else is a table of unknown values
+    fn infer(&self, system: &TypeSystem) -> Type {
+        let unknown = || Type::Table {
+            indexer: Box::new((Type::Any, Type::Any)),
+            fields: BTreeMap::new(),
+        };
+        if self.0.is_empty() {
+            // fields are usually assigned later
+            return Type::Any;
+        }
+        if self.0.iter().all(|(k, _)| k.is_none()) {
+            if matches!(
+                self.0.last(),
+                Some((
+                    _,
+                    RValue::Call(_) | RValue::MethodCall(_) | RValue::VarArg(_)
+                ))
+            ) {
+                return unknown();
+            }
+            let element = self
+                .0
+                .iter()
+                .map(|(_, v)| v.infer(system))
+                .reduce(Type::union)
+                .unwrap();
+            return Type::Table {
+                indexer: Box::new((Type::Number, element)),
+                fields: BTreeMap::new(),
+            };
+        }
+        let mut fields = BTreeMap::new();
+        for (key, value) in &self.0 {
+            match key {
+                Some(RValue::Literal(Literal::String(key)))
+                    if Formatter::<String>::is_valid_name(key) =>
+                {
+                    fields.insert(
+                        String::from_utf8_lossy(key).into_owned(),
+                        value.infer(system),
+                    );
+                }
+                _ => return unknown(),
+            }
+        }
         Type::Table {
-            indexer: Box::new((
-                Type::Any,
-                if elements.len() > 1 {
-                    Type::Union(elements)
-                } else {
-                    elements.into_iter().next().unwrap_or(Type::Any)
-                },
-            )),
+            indexer: Box::new((Type::Any, Type::Any)),
             fields,
         }
     }
-}*/
+}
 
 impl LocalRw for Table {
     fn values_read(&self) -> Vec<&RcLocal> {
@@ -117,11 +138,6 @@ impl SideEffects for Table {
 
 impl fmt::Display for Table {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Formatter {
-            indentation_level: 0,
-            indentation_mode: Default::default(),
-            output: f,
-        }
-        .format_table(self)
+        Formatter::new(f, Default::default()).format_table(self)
     }
 }
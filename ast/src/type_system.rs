@@ -1,10 +1,14 @@
-use crate::{Block, RcLocal};
+use crate::{Block, Function, LValue, RValue, RcLocal, Statement, Traverse};
+use by_address::ByAddress;
 use itertools::Itertools;
+use parking_lot::Mutex;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::{
     borrow::Cow,
     collections::{BTreeMap, BTreeSet},
     fmt::{Display, Formatter},
 };
+use triomphe::Arc;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum Type {
@@ -58,6 +62,120 @@ impl Type {
         }
     }
 
+    /// The type of a value that hasn't been assigned anything yet, see [`TypeSystem::analyze`]
+    pub fn never() -> Self {
+        Self::Union(BTreeSet::new())
+    }
+
+    pub fn is_never(&self) -> bool {
+        matches!(self, Self::Union(types) if types.is_empty())
+    }
+
+    /// The type of a value that is either `self` or `other`
+    pub fn union(self, other: Self) -> Self {
+        let mut types = BTreeSet::new();
+        let mut optional = false;
+        for r#type in [self, other] {
+            match r#type {
+                Self::Any => return Self::Any,
+                Self::Nil => optional = true,
+                Self::Optional(box r#type) => {
+                    optional = true;
+                    types.insert(r#type);
+                }
+                Self::Union(union) => {
+                    optional |= union.contains(&Self::Nil);
+                    types.extend(union.into_iter().filter(|t| t != &Self::Nil));
+                }
+                r#type => {
+                    types.insert(r#type);
+                }
+            }
+        }
+        match (types.len(), optional) {
+            (0, true) => Self::Nil,
+            (1, false) => types.into_iter().next().unwrap(),
+            (1, true) => Self::Optional(Box::new(types.into_iter().next().unwrap())),
+            (_, true) => {
+                types.insert(Self::Nil);
+                Self::Union(types)
+            }
+            (_, false) => Self::Union(types),
+        }
+    }
+
+    /// The type without `nil`, e.g. of `a` in `a or b` when `b` is used instead of `nil`
+    pub fn without_nil(self) -> Self {
+        match self {
+            Self::Nil => Self::never(),
+            Self::Optional(box r#type) => r#type,
+            Self::Union(mut types) => {
+                types.remove(&Self::Nil);
+                Self::Union(types)
+            }
+            r#type => r#type,
+        }
+    }
+
+    /// Whether every value of the type is truthy
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Self::Number
+            | Self::String
+            | Self::Table { .. }
+            | Self::Function(..)
+            | Self::Vector => true,
+            Self::Union(types) => !types.is_empty() && types.iter().all(|t| t.is_truthy()),
+            _ => false,
+        }
+    }
+
+    /// Whether the value is a number or a string, which arithmetic and concatenation coerce
+    /// without metamethods
+    pub fn is_primitive(&self) -> bool {
+        match self {
+            Self::Number | Self::String => true,
+            Self::Union(types) => types.iter().all(|t| t.is_primitive()),
+            _ => false,
+        }
+    }
+
+    // whether the type can be written as an annotation that the value will always match
+    fn is_annotation(&self) -> bool {
+        match self {
+            Self::Any | Self::Nil | Self::VarArg | Self::Function(..) | Self::Intersection(_) => {
+                false
+            }
+            Self::Boolean | Self::Number | Self::String | Self::Vector => true,
+            Self::Table { indexer, fields } if fields.is_empty() => {
+                indexer.0.is_annotation() && indexer.1.is_annotation()
+            }
+            // records
+            Self::Table { indexer, fields } => {
+                indexer.0 == Self::Any
+                    && indexer.1 == Self::Any
+                    && fields.values().all(|t| t.is_annotation())
+            }
+            Self::Optional(r#type) => r#type.is_annotation(),
+            // a value that is one of many tables can't be given a single sealed table type
+            Self::Union(types) => {
+                !types.is_empty()
+                    && types
+                        .iter()
+                        .all(|t| t == &Self::Nil || (t.is_annotation() && !t.is_table()))
+            }
+        }
+    }
+
+    fn is_table(&self) -> bool {
+        match self {
+            Self::Table { .. } => true,
+            Self::Optional(r#type) => r#type.is_table(),
+            Self::Union(types) => types.iter().any(|t| t.is_table()),
+            _ => false,
+        }
+    }
+
     pub fn precedence(&self) -> usize {
         match self {
             Self::Any => 0,
@@ -90,14 +208,24 @@ impl Display for Type {
                 Type::Table { indexer, fields } => {
                     let (indexer_type, element_type) = indexer.as_ref();
 
+                    // a table with fields and no known indexer is a record
+                    let is_record = !fields.is_empty()
+                        && indexer_type == &Type::Any
+                        && element_type == &Type::Any;
                     Cow::Owned(format!(
                         "{{{}{}{}}}",
-                        if indexer_type == &Type::Number && fields.is_empty() {
+                        if is_record {
+                            String::new()
+                        } else if indexer_type == &Type::Number && fields.is_empty() {
                             element_type.to_string()
                         } else {
                             format!("[{}]: {}", indexer_type, element_type)
                         },
-                        (!fields.is_empty()).then_some(", ").unwrap_or_default(),
+                        if !fields.is_empty() && !is_record {
+                            ", "
+                        } else {
+                            ""
+                        },
                         fields
                             .iter()
                             .map(|(field, r#type)| { format!("{}: {}", field, r#type) })
@@ -129,78 +257,208 @@ impl Display for Type {
     }
 }
 
-pub struct TypeSystem<'a> {
-    // TODO: use hash map?
-    annotations: BTreeMap<&'a RcLocal, &'a mut Type>,
+/// The types of locals and the values functions return, inferred from the values they are
+/// assigned and returned. Locals are typed optimistically: a local is only assumed to have the
+/// types of the values it is assigned, so the types of locals that depend on each other are found
+/// by iterating until nothing changes.
+#[derive(Debug, Default, PartialEq)]
+pub struct TypeSystem {
+    locals: FxHashMap<RcLocal, Type>,
+    returns: FxHashMap<ByAddress<Arc<Mutex<Function>>>, Vec<Type>>,
+    // locals whose fields are assigned, which a sealed table type wouldn't allow
+    indexed: FxHashSet<RcLocal>,
 }
 
-impl<'a> TypeSystem<'a> {
-    pub fn analyze(block: &'a mut Block) {
-        let mut system = Self {
-            annotations: BTreeMap::new(),
-        };
-
-        system.analyze_block(block);
-    }
-
-    pub fn analyze_block(&mut self, _block: &'a mut Block) -> Vec<Type> {
-        todo!()
-        // let mut return_values = Vec::new();
-
-        // for statement in &mut block.0 {
-        //     match statement {
-        //         /*Statement::Assign(assign) => {
-        //             for ((lvalue, annotation), rvalue) in
-        //                 assign.left.iter_mut().zip(assign.right.iter_mut())
-        //             {
-        //                 let r#type = rvalue.infer(self);
-
-        //                 if let LValue::Local(local) = lvalue {
-        //                     if let Some(annotation) = self.annotations.get_mut(local) {
-        //                         if let Type::Union(types) = annotation {
-        //                             types.insert(r#type);
-        //                         } else {
-        //                             let mut types = BTreeSet::new();
-
-        //                             types.insert(annotation.clone());
-        //                             types.insert(r#type);
-
-        //                             **annotation = Type::Union(types);
-        //                         }
-        //                     } else {
-        //                         *annotation = Some(r#type);
-        //                         self.annotations.insert(local, annotation.as_mut().unwrap());
-        //                     }
-        //                 }
-        //             }
-        //         }*/
-        //         Statement::If(r#if) => {
-        //             self.analyze_block(&mut r#if.then_block);
-        //             self.analyze_block(&mut r#if.else_block);
-        //         }
-        //         // TODO: repeat and for loops
-        //         Statement::While(r#while) => {
-        //             self.analyze_block(&mut r#while.block.lock());
-        //         }
-        //         Statement::Return(r#return) => {
-        //             return_values.extend(r#return.values.iter_mut().map(|v| v.infer(self)));
-        //         }
-        //         // TODO: numericfor, genericfor
-        //         _ => {}
-        //     }
-        // }
-
-        // return_values
-    }
-
-    pub fn type_of(&self, local: &RcLocal) -> &Type {
-        self.annotations
+impl TypeSystem {
+    const MAX_ITERATIONS: usize = 16;
+
+    pub fn analyze(block: &Block) -> Self {
+        let mut system = Self::default();
+        for _ in 0..Self::MAX_ITERATIONS {
+            let mut next = Self::default();
+            system.collect_block(block, &mut next);
+            if next == system {
+                break;
+            }
+            system = next;
+        }
+        system
+    }
+
+    pub fn type_of(&self, local: &RcLocal) -> Type {
+        self.locals.get(local).cloned().unwrap_or_else(Type::never)
+    }
+
+    /// The type to annotate the local with, if it could be inferred.
+    pub fn annotation(&self, local: &RcLocal) -> Option<&Type> {
+        self.locals
             .get(local)
-            .map(|t| t as &Type)
-            .unwrap_or(&Type::Any)
+            .filter(|t| t.is_annotation() && !(t.is_table() && self.indexed.contains(local)))
+    }
+
+    /// The types of the values the function returns, if every return statement returns the same
+    /// number of values and they could be inferred.
+    pub fn return_annotation(&self, function: &ByAddress<Arc<Mutex<Function>>>) -> Option<&[Type]> {
+        self.returns
+            .get(function)
+            .filter(|types| types.iter().all(|t| t.is_annotation() && !t.is_table()))
+            .map(|types| &types[..])
+    }
+
+    fn assign(&mut self, local: &RcLocal, r#type: Type) {
+        let previous = self.locals.remove(local).unwrap_or_else(Type::never);
+        self.locals.insert(local.clone(), previous.union(r#type));
+    }
+
+    // the types of the first `count` values of a list, values past the end of the list are nil
+    // unless the last value is a call or `...`
+    fn list_types(&self, values: &[RValue], count: usize) -> Vec<Type> {
+        let multiple = matches!(
+            values.last(),
+            Some(RValue::Call(_) | RValue::MethodCall(_) | RValue::VarArg(_))
+        );
+        (0..count)
+            .map(|i| match values.get(i) {
+                Some(_) if multiple && i == values.len() - 1 => Type::Any,
+                Some(value) => value.infer(self),
+                None if multiple => Type::Any,
+                None => Type::Nil,
+            })
+            .collect()
+    }
+
+    fn collect_block(&self, block: &Block, next: &mut Self) {
+        for statement in &block.0 {
+            match statement {
+                Statement::Assign(assign) => {
+                    for lvalue in &assign.left {
+                        if let LValue::Index(index) = lvalue
+                            && let RValue::Local(local) = index.left.as_ref()
+                        {
+                            next.indexed.insert(local.clone());
+                        }
+                    }
+                    let types = self.list_types(&assign.right, assign.left.len());
+                    for (lvalue, r#type) in assign.left.iter().zip(types) {
+                        if let LValue::Local(local) = lvalue {
+                            next.assign(local, r#type);
+                        }
+                    }
+                }
+                Statement::If(r#if) => {
+                    self.collect_block(&r#if.then_block.lock(), next);
+                    self.collect_block(&r#if.else_block.lock(), next);
+                }
+                Statement::While(r#while) => self.collect_block(&r#while.block.lock(), next),
+                Statement::Repeat(repeat) => self.collect_block(&repeat.block.lock(), next),
+                Statement::NumericFor(numeric_for) => {
+                    next.assign(&numeric_for.counter, Type::Number);
+                    self.collect_block(&numeric_for.block.lock(), next);
+                }
+                Statement::GenericFor(generic_for) => {
+                    for local in &generic_for.res_locals {
+                        next.assign(local, Type::Any);
+                    }
+                    self.collect_block(&generic_for.block.lock(), next);
+                }
+                _ => {}
+            }
+            let mut rvalues = statement.rvalues();
+            while let Some(rvalue) = rvalues.pop() {
+                if let RValue::Closure(closure) = rvalue {
+                    self.collect_function(&closure.function, next);
+                }
+                rvalues.extend(rvalue.rvalues());
+            }
+        }
+    }
+
+    // the types returned by every return statement in the block, `None` if the number of values
+    // isn't known. nested functions aren't searched.
+    fn collect_returns(&self, block: &Block, returns: &mut Vec<Option<Vec<Type>>>) {
+        for statement in &block.0 {
+            match statement {
+                Statement::Return(r#return) => returns.push(
+                    (!matches!(
+                        r#return.values.last(),
+                        Some(RValue::Call(_) | RValue::MethodCall(_) | RValue::VarArg(_))
+                    ))
+                    .then(|| self.list_types(&r#return.values, r#return.values.len())),
+                ),
+                Statement::If(r#if) => {
+                    self.collect_returns(&r#if.then_block.lock(), returns);
+                    self.collect_returns(&r#if.else_block.lock(), returns);
+                }
+                Statement::While(r#while) => self.collect_returns(&r#while.block.lock(), returns),
+                Statement::Repeat(repeat) => self.collect_returns(&repeat.block.lock(), returns),
+                Statement::NumericFor(numeric_for) => {
+                    self.collect_returns(&numeric_for.block.lock(), returns)
+                }
+                Statement::GenericFor(generic_for) => {
+                    self.collect_returns(&generic_for.block.lock(), returns)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn collect_function(&self, function: &ByAddress<Arc<Mutex<Function>>>, next: &mut Self) {
+        let locked = function.lock();
+        // a numeric for loop that runs before anything can leave the function or assign the
+        // parameters would error if they weren't numbers
+        let mut numbers = FxHashSet::default();
+        for statement in &locked.body.0 {
+            match statement {
+                Statement::Assign(assign)
+                    if assign
+                        .left
+                        .iter()
+                        .all(|l| !l.as_local().is_some_and(|l| locked.parameters.contains(l))) => {}
+                Statement::Call(_) | Statement::MethodCall(_) | Statement::Comment(_) => {}
+                Statement::NumericFor(numeric_for) => {
+                    numbers.extend(
+                        [&numeric_for.initial, &numeric_for.limit, &numeric_for.step]
+                            .into_iter()
+                            .filter_map(|v| v.as_local()),
+                    );
+                    break;
+                }
+                _ => break,
+            }
+        }
+        for parameter in &locked.parameters {
+            next.assign(
+                parameter,
+                if numbers.contains(parameter) {
+                    Type::Number
+                } else {
+                    Type::Any
+                },
+            );
+        }
+
+        if let Some(Statement::Return(_)) = locked.body.last() {
+            let mut returns = Vec::new();
+            self.collect_returns(&locked.body, &mut returns);
+            if let Some(Some(first)) = returns.first()
+                && !first.is_empty()
+                && returns
+                    .iter()
+                    .all(|r| r.as_ref().is_some_and(|r| r.len() == first.len()))
+            {
+                let types = returns
+                    .into_iter()
+                    .flatten()
+                    .reduce(|types, r| types.into_iter().zip(r).map(|(a, b)| a.union(b)).collect())
+                    .unwrap();
+                next.returns.insert(function.clone(), types);
+            }
+        }
+
+        self.collect_block(&locked.body, next);
     }
 }
 
 pub trait Infer {
-    fn infer<'a: 'b, 'b>(&'a mut self, system: &mut TypeSystem<'b>) -> Type;
+    fn infer(&self, system: &TypeSystem) -> Type;
 }
@@ -1,10 +1,14 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::{Literal, LocalRw, RValue, RcLocal, Reduce, SideEffects, Traverse};
+use crate::{
+    type_system::Infer, Literal, LocalRw, RValue, RcLocal, Reduce, SideEffects, Traverse, Type,
+    TypeSystem,
+};
 
 use super::{Binary, BinaryOperation};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum UnaryOperation {
     Not,
     Negate,
@@ -21,7 +25,7 @@ impl fmt::Display for UnaryOperation {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Unary {
     pub value: Box<RValue>,
     pub operation: UnaryOperation,
@@ -47,6 +51,18 @@ impl Traverse for Unary {
     }
 }
 
+impl Infer for Unary {
+    fn infer(&self, system: &TypeSystem) -> Type {
+        let value = self.value.infer(system);
+        match self.operation {
+            UnaryOperation::Not => Type::Boolean,
+            UnaryOperation::Negate if value.is_never() || value.is_primitive() => Type::Number,
+            UnaryOperation::Length if value == Type::String => Type::Number,
+            _ => Type::Any,
+        }
+    }
+}
+
 impl Reduce for Unary {
     fn reduce(self) -> RValue {
         // TODO: unnecessary clone
@@ -1,8 +1,9 @@
 use std::fmt;
 
 use ast::{RValue, RcLocal};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum BranchType {
     #[default]
     Unconditional,
@@ -10,7 +11,7 @@ pub enum BranchType {
     Else,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BlockEdge {
     pub branch_type: BranchType,
     // TODO: why is this not a hash map?
@@ -1,7 +1,10 @@
 use std::{
     borrow::{Borrow, Cow},
     cell::RefCell,
-    io::Write,
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+    process::Command,
 };
 
 use ast::LocalRw;
@@ -131,3 +134,72 @@ pub fn render_to<W: Write>(function: &Function, output: &mut W) -> std::io::Resu
         output,
     )
 }
+
+/// Writes numbered snapshots of control flow graphs to a directory, e.g. one after every
+/// structuring step. Snapshot `n` is written to `n.dot`, and to `n.svg` if an SVG renderer is set.
+pub struct GraphVisualizer {
+    directory: PathBuf,
+    /// A Graphviz compatible command, run as `<command> -Tsvg <n>.dot -o <n>.svg`
+    svg_renderer: Option<String>,
+    counter: usize,
+    // snapshots are skipped after the first one that fails
+    error: Option<io::Error>,
+}
+
+impl GraphVisualizer {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            svg_renderer: None,
+            counter: 0,
+            error: None,
+        }
+    }
+
+    /// Also renders every snapshot to SVG with `command`, e.g. `dot`.
+    pub fn with_svg_renderer(mut self, command: impl Into<String>) -> Self {
+        self.svg_renderer = Some(command.into());
+        self
+    }
+
+    pub fn snapshot(&mut self, function: &Function) {
+        if self.error.is_none()
+            && let Err(error) = self.try_snapshot(function)
+        {
+            self.error = Some(error);
+        }
+    }
+
+    fn try_snapshot(&mut self, function: &Function) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        let dot_path = self.directory.join(format!("{}.dot", self.counter));
+        render_to(function, &mut File::create(&dot_path)?)?;
+        if let Some(command) = &self.svg_renderer {
+            let svg_path = self.directory.join(format!("{}.svg", self.counter));
+            let status = Command::new(command)
+                .arg("-Tsvg")
+                .arg(&dot_path)
+                .arg("-o")
+                .arg(&svg_path)
+                .status()?;
+            if !status.success() {
+                return Err(io::Error::other(format!(
+                    "{} exited with {}",
+                    command, status
+                )));
+            }
+        }
+        self.counter += 1;
+        Ok(())
+    }
+
+    /// The number of snapshots written so far.
+    pub fn snapshots(&self) -> usize {
+        self.counter
+    }
+
+    /// Why snapshots stopped being written, if they did.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
@@ -0,0 +1,80 @@
+//! A snapshot of a control flow graph as plain blocks and edges, for tools that want to analyze
+//! or visualize the intermediate representation without depending on petgraph. Statements are
+//! written in the layout of the AST types and locals as ids, see [`ast::serialize`], so an export
+//! must be serialized within a single [`ast::serialize::scope`] for its locals to match up.
+
+use ast::{RValue, RcLocal};
+use petgraph::visit::EdgeRef;
+use serde::Serialize;
+
+use crate::{block::BranchType, function::Function};
+
+#[derive(Debug, Serialize)]
+pub struct FunctionExport<'a> {
+    pub id: usize,
+    pub name: Option<&'a str>,
+    pub parameters: &'a [RcLocal],
+    pub is_variadic: bool,
+    /// The id of the entry block
+    pub entry: Option<usize>,
+    /// Blocks ordered by id
+    pub blocks: Vec<BlockExport<'a>>,
+    pub edges: Vec<EdgeExport<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockExport<'a> {
+    /// The index of the block in the graph, stable for as long as the block exists
+    pub id: usize,
+    pub statements: &'a ast::Block,
+    /// The statements printed as source code
+    pub source: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EdgeExport<'a> {
+    pub source: usize,
+    pub target: usize,
+    /// `Then` and `Else` edges leave a block that ends with an `If`, whose condition decides
+    /// which one is taken
+    pub branch_type: &'a BranchType,
+    /// The values passed to the parameters of the target block
+    pub arguments: &'a [(RcLocal, RValue)],
+}
+
+impl<'a> FunctionExport<'a> {
+    pub fn new(function: &'a Function) -> Self {
+        let mut nodes = function.graph().node_indices().collect::<Vec<_>>();
+        nodes.sort();
+        let blocks = nodes
+            .iter()
+            .map(|&node| {
+                let block = function.block(node).unwrap();
+                BlockExport {
+                    id: node.index(),
+                    statements: block,
+                    source: block.to_string(),
+                }
+            })
+            .collect();
+        let edges = nodes
+            .iter()
+            .flat_map(|&node| function.edges(node))
+            .map(|edge| EdgeExport {
+                source: edge.source().index(),
+                target: edge.target().index(),
+                branch_type: &edge.weight().branch_type,
+                arguments: &edge.weight().arguments,
+            })
+            .collect();
+        Self {
+            id: function.id,
+            name: function.name.as_deref(),
+            parameters: &function.parameters,
+            is_variadic: function.is_variadic,
+            entry: function.entry().map(|entry| entry.index()),
+            blocks,
+            edges,
+        }
+    }
+}
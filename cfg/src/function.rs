@@ -1,5 +1,6 @@
 use ast::{LocalRw, RcLocal};
 use contracts::requires;
+use serde::{Deserialize, Serialize};
 
 use petgraph::{
     stable_graph::{EdgeReference, Neighbors, NodeIndex, StableDiGraph},
@@ -7,9 +8,12 @@ use petgraph::{
     Direction,
 };
 
-use crate::block::{BlockEdge, BranchType};
+use crate::{
+    block::{BlockEdge, BranchType},
+    view::GraphView,
+};
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Function {
     pub id: usize,
     pub name: Option<String>,
@@ -44,6 +48,11 @@ impl Function {
         self.entry = Some(new_entry);
     }
 
+    /// A view of the control flow graph for use with petgraph algorithms.
+    pub fn view(&self) -> GraphView<'_> {
+        GraphView::new(self)
+    }
+
     pub fn graph(&self) -> &StableDiGraph<ast::Block, BlockEdge> {
         &self.graph
     }
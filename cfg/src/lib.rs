@@ -6,6 +6,11 @@
 
 pub mod block;
 pub mod dot;
+pub mod export;
 pub mod function;
+pub mod passes;
 pub mod pattern;
 pub mod ssa;
+pub mod view;
+
+pub use petgraph;
@@ -0,0 +1,3 @@
+//! Passes over a function in SSA form that the lifters can run, but don't by default.
+
+pub mod dce;
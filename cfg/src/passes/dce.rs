@@ -0,0 +1,101 @@
+use crate::function::Function;
+use ast::{LocalRw, SideEffects};
+use indexmap::IndexMap;
+use petgraph::visit::{Dfs, Walker};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+// an assignment that can be removed if none of the locals it writes are live
+fn removable_assign<'a>(
+    statement: &'a ast::Statement,
+    upvalue_to_group: &IndexMap<ast::RcLocal, ast::RcLocal>,
+) -> Option<impl Iterator<Item = &'a ast::RcLocal>> {
+    let ast::Statement::Assign(assign) = statement else {
+        return None;
+    };
+    if assign.right.iter().any(|r| r.has_side_effects())
+        || !assign.left.iter().all(|l| {
+            l.as_local()
+                .is_some_and(|l| !upvalue_to_group.contains_key(l))
+        })
+    {
+        return None;
+    }
+    Some(assign.left.iter().map(|l| l.as_local().unwrap()))
+}
+
+/// Removes blocks that can't be reached from the entry and assignments and block parameters whose
+/// values are never used, including locals that are only read by their own definitions, e.g. a
+/// counter that is incremented in a loop but never read otherwise. `inline` only removes locals
+/// that aren't read at all. Returns whether anything was removed.
+pub fn eliminate_dead_code(
+    function: &mut Function,
+    upvalue_to_group: &IndexMap<ast::RcLocal, ast::RcLocal>,
+) -> bool {
+    let Some(entry) = *function.entry() else {
+        return false;
+    };
+    let mut changed = false;
+
+    let reachable = Dfs::new(function.graph(), entry)
+        .iter(function.graph())
+        .collect::<FxHashSet<_>>();
+    for node in function.blocks().map(|(n, _)| n).collect::<Vec<_>>() {
+        if !reachable.contains(&node) {
+            function.remove_block(node);
+            changed = true;
+        }
+    }
+
+    // the locals read by the definitions of every local, these are only live if the local is
+    let mut definitions = FxHashMap::<_, Vec<_>>::default();
+    // upvalues can be read by closures at any time
+    let mut worklist = upvalue_to_group.keys().collect::<Vec<_>>();
+    for (node, block) in function.blocks() {
+        for statement in block.iter() {
+            if let Some(written) = removable_assign(statement, upvalue_to_group) {
+                for local in written {
+                    definitions
+                        .entry(local)
+                        .or_default()
+                        .extend(statement.values_read());
+                }
+            } else {
+                worklist.extend(statement.values_read());
+            }
+        }
+        for edge in function.edges(node) {
+            for (parameter, argument) in &edge.weight().arguments {
+                definitions
+                    .entry(parameter)
+                    .or_default()
+                    .extend(argument.values_read());
+            }
+        }
+    }
+
+    let mut live = FxHashSet::default();
+    while let Some(local) = worklist.pop() {
+        if live.insert(local.clone())
+            && let Some(reads) = definitions.get(local)
+        {
+            worklist.extend(reads.iter().copied());
+        }
+    }
+
+    for block in function.blocks_mut() {
+        let len = block.len();
+        block.retain(|statement| {
+            !removable_assign(statement, upvalue_to_group)
+                .is_some_and(|mut written| written.all(|l| !live.contains(l)))
+        });
+        changed |= block.len() != len;
+    }
+    for edge in function.graph_mut().edge_weights_mut() {
+        let len = edge.arguments.len();
+        edge.arguments
+            .retain(|(parameter, _)| live.contains(parameter));
+        changed |= edge.arguments.len() != len;
+    }
+
+    changed
+}
@@ -1,6 +1,7 @@
 pub mod construct;
 mod destruct;
 pub mod inline;
+pub mod listing;
 mod param_dependency_graph;
 pub mod structuring;
 pub mod upvalues;
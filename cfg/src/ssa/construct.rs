@@ -14,10 +14,15 @@ use crate::{function::Function, ssa::param_dependency_graph::ParamDependencyGrap
 
 use super::upvalues::UpvaluesOpen;
 
+// a new definition of `local`, named the same so names from debug info survive
+fn new_version(local: &RcLocal) -> RcLocal {
+    RcLocal::new(ast::Local::new(local.0 .0.lock().0.clone()))
+}
+
 struct SsaConstructor<'a> {
     function: &'a mut Function,
     dfs: IndexSet<NodeIndex>,
-    incomplete_params: FxHashMap<NodeIndex, FxHashMap<RcLocal, RcLocal>>,
+    incomplete_params: FxHashMap<NodeIndex, IndexMap<RcLocal, RcLocal>>,
     filled_blocks: FxHashSet<NodeIndex>,
     sealed_blocks: FxHashSet<NodeIndex>,
     // TODO: combine current/all/old into one map
@@ -40,7 +45,7 @@ pub fn remove_unnecessary_params(
     let mut changed = false;
     for node in function.blocks().map(|(i, _)| i).collect::<Vec<_>>() {
         let mut dependency_graph = ParamDependencyGraph::new(function, node);
-        let mut removable_params = FxHashMap::default();
+        let mut removable_params = IndexMap::new();
         let edges = function
             .graph()
             .edges_directed(node, Direction::Incoming)
@@ -193,6 +198,19 @@ fn apply_local_map_to_values_referenced<T: LocalRw + Traverse>(
 
 // does not replace locals in child closures
 pub fn apply_local_map(function: &mut Function, local_map: FxHashMap<RcLocal, RcLocal>) {
+    // a local replaced by an unnamed one passes its name on, so names from debug info survive
+    for (from, mut to) in &local_map {
+        while let Some(to_to) = local_map.get(to) {
+            to = to_to;
+        }
+        let name = from.0 .0.lock().0.clone();
+        if name.is_some() && from != to {
+            let mut to = to.0 .0.lock();
+            if to.0.is_none() {
+                to.0 = name;
+            }
+        }
+    }
     for param in &mut function.parameters {
         if let Some(mut new_param) = local_map.get(param) {
             // TODO: make sure this doesnt cycle if theres a li -> li entry
@@ -363,7 +381,7 @@ impl<'a> SsaConstructor<'a> {
             // search globally
             if !self.sealed_blocks.contains(&node) {
                 // TODO: this code is repeated multiple times, create new_local function
-                let param_local = RcLocal::default();
+                let param_local = new_version(local);
                 self.old_locals.insert(param_local.clone(), local.clone());
                 if let Some(upvalues) = self.new_upvalues_in.get_mut(local) {
                     upvalues.insert(param_local.clone());
@@ -377,7 +395,7 @@ impl<'a> SsaConstructor<'a> {
             } else if let Ok(pred) = self.function.predecessor_blocks(node).exactly_one() {
                 self.find_local(pred, local)
             } else {
-                let param_local = RcLocal::default();
+                let param_local = new_version(local);
                 self.old_locals.insert(param_local.clone(), local.clone());
                 if let Some(upvalues) = self.new_upvalues_in.get_mut(local) {
                     upvalues.insert(param_local.clone());
@@ -523,7 +541,7 @@ impl<'a> SsaConstructor<'a> {
                     && let Some(local) = assign.left[0].as_local().cloned()
                     && assign.right[0].as_closure().is_some()
                 {
-                    let new_local = RcLocal::default();
+                    let new_local = new_version(&local);
                     self.old_locals.insert(new_local.clone(), local.clone());
                     if let Some(upvalues) = self.new_upvalues_in.get_mut(&local) {
                         upvalues.insert(new_local.clone());
@@ -549,7 +567,7 @@ impl<'a> SsaConstructor<'a> {
                     self.read(node, stat_index);
                     // write
                     for (local_index, local) in written.iter().enumerate() {
-                        let new_local = RcLocal::default();
+                        let new_local = new_version(local);
                         self.old_locals.insert(new_local.clone(), local.clone());
                         if let Some(upvalues) = self.new_upvalues_in.get_mut(local) {
                             upvalues.insert(new_local.clone());
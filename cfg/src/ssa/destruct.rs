@@ -1,7 +1,7 @@
 use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 
 use ast::{LocalRw, RcLocal};
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
 use petgraph::{
     algo::dominators::simple_fast,
@@ -45,7 +45,7 @@ pub struct Destructor<'a> {
     function: &'a mut Function,
     upvalue_to_group: IndexMap<RcLocal, RcLocal>,
     upvalues_in: FxHashSet<RcLocal>,
-    values: FxHashMap<RcLocal, Rc<RefCell<FxHashSet<RcLocal>>>>,
+    values: FxHashMap<RcLocal, Rc<RefCell<IndexSet<RcLocal>>>>,
     // map( local -> rc_map( local -> (pre-order block index, param index) ) )
     // TODO: hash map?
     congruence_classes: FxHashMap<RcLocal, Rc<RefCell<CongruenceClass>>>,
@@ -117,9 +117,9 @@ impl<'a> Destructor<'a> {
             let block = self.function.block_mut(node).unwrap();
             block.insert(
                 0,
-                ast::Comment::new(liveness.live_in.iter().join(", ")).into(),
+                ast::Comment::new(liveness.live_in.iter().sorted().join(", ")).into(),
             );
-            block.push(ast::Comment::new(liveness.live_out.iter().join(", ")).into());
+            block.push(ast::Comment::new(liveness.live_out.iter().sorted().join(", ")).into());
         }
     }
 
@@ -507,7 +507,6 @@ impl<'a> Destructor<'a> {
 
             let con_class_z = self.get_congruence_class(local_c.clone()).clone();
             if con_class_x == con_class_z && con_class_x != con_class_y {
-                println!("WOAH COPY SHARING");
                 return true;
             }
             if con_class_y != con_class_x
@@ -515,7 +514,6 @@ impl<'a> Destructor<'a> {
                 && con_class_x != con_class_z
                 && self.try_coalesce_copy_by_value(local_a.clone(), local_c)
             {
-                println!("WOAH COPY SHARING");
                 return true;
             }
         }
@@ -769,17 +767,38 @@ impl<'a> Destructor<'a> {
         }
     }
 
-    fn get_value_class(&mut self, local: RcLocal) -> &Rc<RefCell<FxHashSet<RcLocal>>> {
+    fn get_value_class(&mut self, local: RcLocal) -> &Rc<RefCell<IndexSet<RcLocal>>> {
         self.values.entry(local.clone()).or_insert_with(|| {
-            let mut value_class = FxHashSet::default();
+            let mut value_class = IndexSet::new();
             value_class.insert(local);
             Rc::new(RefCell::new(value_class))
         })
     }
 
+    // every edge into a block passes its arguments in the order of the first one. locals are
+    // compared by address, so they aren't sorted by them to keep the output the same every run
     fn sort_params(&mut self) {
-        for edge in self.function.graph_mut().edge_weights_mut() {
-            edge.arguments.sort_by(|(p0, _), (p1, _)| p0.cmp(p1));
+        for node in self.function.graph().node_indices().collect::<Vec<_>>() {
+            let edges = self
+                .function
+                .graph()
+                .edges_directed(node, Direction::Incoming)
+                .map(|e| e.id())
+                .collect::<Vec<_>>();
+            let Some((&first, rest)) = edges.split_first() else {
+                continue;
+            };
+            let order = self.function.graph()[first]
+                .arguments
+                .iter()
+                .enumerate()
+                .map(|(index, (param, _))| (param.clone(), index))
+                .collect::<FxHashMap<_, _>>();
+            for &edge in rest {
+                self.function.graph_mut()[edge]
+                    .arguments
+                    .sort_by_key(|(param, _)| order[param]);
+            }
         }
     }
 
@@ -792,7 +811,7 @@ impl<'a> Destructor<'a> {
     // Note that the phi-functions do not have a circular dependency and are ordered accordingly (we have to do this before),
     // i.e., no variable that is defined by a Phi-function is used in a 'later' phi-function.
     fn lift_block_params(&mut self, node: NodeIndex) {
-        let mut param_map = FxHashMap::default();
+        let mut param_map = IndexMap::new();
         if let Some((_, BlockEdge { arguments, .. })) = self.function.edges_to_block(node).next() {
             for param in arguments.iter().map(|(p, _)| p) {
                 let temp_param = RcLocal::default();
@@ -812,6 +831,7 @@ impl<'a> Destructor<'a> {
                     right: param_map.values().map(|v| v.clone().into()).collect(),
                     prefix: false,
                     parallel: true,
+                    span: None,
                 }
                 .into(),
             );
@@ -852,6 +872,7 @@ impl<'a> Destructor<'a> {
                     right: Vec::with_capacity(args.len()),
                     prefix: false,
                     parallel: true,
+                    span: None,
                 };
 
                 for (param, arg) in args {
@@ -867,7 +888,7 @@ impl<'a> Destructor<'a> {
                     parallel_assign
                         .right
                         .push(std::mem::replace(arg, temp_local.into()));
-                    *param = param_map[param].clone();
+                    *param = param_map[&*param].clone();
                 }
 
                 if !parallel_assign.left.is_empty() {
@@ -229,6 +229,8 @@ impl<'a> Inliner<'a> {
                                     }
                                     // we dont need to update local usages because tracking usages for a local
                                     // with no declarations serves no purpose
+                                    let span = block[stat_index].span();
+                                    block[index].extend_span(span);
                                     block[stat_index] = ast::Empty {}.into();
                                     *read = None;
                                     continue 'w;
@@ -296,6 +298,8 @@ impl<'a> Inliner<'a> {
                                     }
                                     // we dont need to update local usages because tracking usages for a local
                                     // with no declarations serves no purpose
+                                    let span = block[stat_index].span();
+                                    block[index].extend_span(span);
                                     block[stat_index] = ast::Empty {}.into();
                                     for old_local in old_locals {
                                         *stat_to_values_read[index]
@@ -565,6 +569,7 @@ pub fn inline(
                         let field_assign = std::mem::replace(&mut block[i], ast::Empty {}.into())
                             .into_assign()
                             .unwrap();
+                        block[table_index].extend_span(field_assign.span);
                         block[table_index].as_assign_mut().unwrap().right[0]
                             .as_table_mut()
                             .unwrap()
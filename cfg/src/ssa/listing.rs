@@ -0,0 +1,106 @@
+use std::fmt::Write;
+
+use ast::{RValue, RcLocal, Statement};
+use indexmap::IndexMap;
+use itertools::Itertools;
+use petgraph::{
+    stable_graph::NodeIndex,
+    visit::{Bfs, EdgeRef, Walker},
+};
+
+use crate::function::Function;
+
+fn label(node: NodeIndex) -> String {
+    format!("block_{}", node.index())
+}
+
+// the values a block's parameters take on each incoming edge
+fn phis(function: &Function, node: NodeIndex) -> IndexMap<RcLocal, Vec<(NodeIndex, &RValue)>> {
+    let mut phis = IndexMap::<_, Vec<_>>::new();
+    let mut edges = function.edges_to_block(node).collect::<Vec<_>>();
+    edges.sort_by_key(|(predecessor, _)| *predecessor);
+    for (predecessor, edge) in edges {
+        for (parameter, value) in &edge.arguments {
+            phis.entry(parameter.clone())
+                .or_default()
+                .push((predecessor, value));
+        }
+    }
+    phis
+}
+
+fn indent(output: &mut String, statement: &Statement) {
+    for line in statement.to_string().lines() {
+        writeln!(output, "\t{}", line).unwrap();
+    }
+}
+
+/// Renders a function in SSA form: every block reachable from the entry with a label, the phi
+/// nodes for its parameters and the edges leaving it. Phi nodes and edges are comments, the
+/// statements are printed as they are.
+pub fn render(function: &Function) -> String {
+    let mut output = String::new();
+    let parameters = function
+        .parameters
+        .iter()
+        .map(|p| p.to_string())
+        .chain(function.is_variadic.then(|| "...".to_string()))
+        .join(", ");
+    match &function.name {
+        Some(name) => writeln!(
+            output,
+            "-- function {} {}({})",
+            function.id, name, parameters
+        ),
+        None => writeln!(output, "-- function {}({})", function.id, parameters),
+    }
+    .unwrap();
+    let Some(entry) = *function.entry() else {
+        return output;
+    };
+    for node in Bfs::new(function.graph(), entry).iter(function.graph()) {
+        let block = function.block(node).unwrap();
+        write!(output, "::{}::", label(node)).unwrap();
+        if node == entry {
+            write!(output, " -- entry").unwrap();
+        }
+        writeln!(output).unwrap();
+        for (parameter, values) in phis(function, node) {
+            writeln!(
+                output,
+                "\t-- phi {} = [{}]",
+                parameter,
+                values
+                    .into_iter()
+                    .map(|(predecessor, value)| format!("{}: {}", label(predecessor), value))
+                    .join(", ")
+            )
+            .unwrap();
+        }
+        if let Some((then_edge, else_edge)) = function.conditional_edges(node) {
+            let (condition, statements) = match block.split_last() {
+                Some((Statement::If(r#if), statements)) => (r#if.condition.to_string(), statements),
+                _ => ("?".to_string(), &block[..]),
+            };
+            for statement in statements {
+                indent(&mut output, statement);
+            }
+            writeln!(
+                output,
+                "\t-- if {} then goto {} else goto {}",
+                condition,
+                label(then_edge.target()),
+                label(else_edge.target())
+            )
+            .unwrap();
+        } else {
+            for statement in block.iter() {
+                indent(&mut output, statement);
+            }
+            if let Some(edge) = function.unconditional_edge(node) {
+                writeln!(output, "\t-- goto {}", label(edge.target())).unwrap();
+            }
+        }
+    }
+    output
+}
@@ -386,6 +386,17 @@ fn make_bool_conditional(
             cond
         };
         Some(cond.reduce())
+    } else if !r#if.condition.has_side_effects()
+        && (r#if.condition == else_value || r#if.condition == then_value)
+    {
+        // `if a then x = b else x = a end` is `x = a and b`, which is what `and` and `or`
+        // compile to when the operand is moved into the result before it's tested
+        let cond = std::mem::replace(&mut r#if.condition, ast::Literal::Nil.into());
+        Some(if cond == else_value {
+            ast::Binary::new(cond, then_value, ast::BinaryOperation::And).reduce()
+        } else {
+            ast::Binary::new(cond, else_value, ast::BinaryOperation::Or).reduce()
+        })
     } else {
         // TODO: `v0 and v1 and v2`, v0, v1 and v2 are truthy, but only v2 is treated as such
         let then_truthy = match is_truthy(then_value.clone()) {
@@ -689,23 +700,29 @@ fn structure_bool_conditional(function: &mut Function, node: NodeIndex) -> bool
             && function.successor_blocks(else_target).next().is_none()
             && let Ok(ast::Statement::Return(ast::Return {
                 values: then_values,
+                span: then_span,
             })) = function.block(then_target).unwrap().iter().exactly_one()
             && let Ok(then_value) = then_values.iter().exactly_one()
             && let Ok(ast::Statement::Return(ast::Return {
                 values: else_values,
+                span: else_span,
             })) = function.block(else_target).unwrap().iter().exactly_one()
             && let Ok(else_value) = else_values.iter().exactly_one()
         {
             // TODO: unnecessary clones
             let then_value = then_value.clone();
             let else_value = else_value.clone();
+            let (then_span, else_span) = (*then_span, *else_span);
 
             if let Some(res) = make_bool_conditional(function, node, then_value, else_value) {
                 function.remove_block(then_target);
                 function.remove_block(else_target);
                 let block = function.block_mut(node).unwrap();
                 block.pop();
-                block.push(ast::Return::new(vec![res]).into());
+                let mut r#return: ast::Statement = ast::Return::new(vec![res]).into();
+                r#return.extend_span(then_span);
+                r#return.extend_span(else_span);
+                block.push(r#return);
                 true
             } else {
                 false
@@ -720,7 +737,6 @@ fn structure_bool_conditional(function: &mut Function, node: NodeIndex) -> bool
 }
 
 fn match_method_call(call: &ast::Call) -> Option<(&ast::RValue, &str)> {
-    // TODO: make sure `a:method with space()` doesnt happen
     if !call.arguments.is_empty()
         && !call.arguments[0].has_side_effects()
         && let Some(ast::Index {
@@ -728,6 +744,8 @@ fn match_method_call(call: &ast::Call) -> Option<(&ast::RValue, &str)> {
             right: box ast::RValue::Literal(ast::Literal::String(index)),
         }) = call.value.as_index()
         && left == &call.arguments[0]
+        // `a:method with space()` isn't valid syntax, so those stay index calls
+        && ast::formatter::Formatter::<String>::is_valid_name(index)
     {
         if let Ok(index) = std::str::from_utf8(index) {
             Some((left, index))
@@ -868,6 +886,7 @@ fn try_remove_unnecessary_condition(function: &mut Function, node: NodeIndex) ->
                     right: vec![cond],
                     prefix: true,
                     parallel: false,
+                    span: None,
                 }
                 .into(),
             ),
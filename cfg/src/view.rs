@@ -0,0 +1,164 @@
+//! A read-only view of a [`Function`]'s control flow graph that implements the petgraph visit
+//! traits, so external analyses can run petgraph algorithms (or their own) on it directly.
+//!
+//! Blocks are identified by [`NodeIndex`]. Indices are stable: removing a block never changes
+//! the index of another block, so an index stays valid for as long as its block exists.
+//! The same indices are used by [`crate::dot`] and the rest of this crate's API.
+//! Use the re-exported [`petgraph`](crate::petgraph) to get matching trait versions.
+
+use petgraph::{
+    stable_graph::{EdgeIndex, NodeIndex, StableDiGraph},
+    visit::{
+        Data, EdgeCount, GraphBase, GraphProp, GraphRef, IntoEdgeReferences, IntoEdges,
+        IntoEdgesDirected, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers,
+        IntoNodeReferences, NodeCount, NodeIndexable, Visitable,
+    },
+    Directed, Direction,
+};
+
+use crate::{block::BlockEdge, function::Function};
+
+type Graph = StableDiGraph<ast::Block, BlockEdge>;
+
+/// See the [module documentation](self).
+#[derive(Debug, Clone, Copy)]
+pub struct GraphView<'a> {
+    graph: &'a Graph,
+    entry: Option<NodeIndex>,
+}
+
+impl<'a> GraphView<'a> {
+    pub fn new(function: &'a Function) -> Self {
+        Self {
+            graph: function.graph(),
+            entry: *function.entry(),
+        }
+    }
+
+    /// The block execution starts at.
+    pub fn entry(&self) -> Option<NodeIndex> {
+        self.entry
+    }
+
+    /// The statements of a block.
+    pub fn block(&self, node: NodeIndex) -> Option<&'a ast::Block> {
+        self.graph.node_weight(node)
+    }
+
+    /// The branch type and block arguments of an edge.
+    pub fn edge(&self, edge: EdgeIndex) -> Option<&'a BlockEdge> {
+        self.graph.edge_weight(edge)
+    }
+}
+
+impl GraphBase for GraphView<'_> {
+    type EdgeId = EdgeIndex;
+    type NodeId = NodeIndex;
+}
+
+impl GraphRef for GraphView<'_> {}
+
+impl Data for GraphView<'_> {
+    type NodeWeight = ast::Block;
+    type EdgeWeight = BlockEdge;
+}
+
+impl GraphProp for GraphView<'_> {
+    type EdgeType = Directed;
+}
+
+impl NodeCount for GraphView<'_> {
+    fn node_count(&self) -> usize {
+        self.graph.node_count()
+    }
+}
+
+impl EdgeCount for GraphView<'_> {
+    fn edge_count(&self) -> usize {
+        self.graph.edge_count()
+    }
+}
+
+impl NodeIndexable for GraphView<'_> {
+    fn node_bound(&self) -> usize {
+        NodeIndexable::node_bound(self.graph)
+    }
+
+    fn to_index(&self, node: NodeIndex) -> usize {
+        NodeIndexable::to_index(self.graph, node)
+    }
+
+    fn from_index(&self, index: usize) -> NodeIndex {
+        NodeIndexable::from_index(self.graph, index)
+    }
+}
+
+impl<'a> Visitable for GraphView<'a> {
+    type Map = <&'a Graph as Visitable>::Map;
+
+    fn visit_map(&self) -> Self::Map {
+        self.graph.visit_map()
+    }
+
+    fn reset_map(&self, map: &mut Self::Map) {
+        self.graph.reset_map(map)
+    }
+}
+
+impl<'a> IntoNeighbors for GraphView<'a> {
+    type Neighbors = <&'a Graph as IntoNeighbors>::Neighbors;
+
+    fn neighbors(self, node: NodeIndex) -> Self::Neighbors {
+        self.graph.neighbors(node)
+    }
+}
+
+impl<'a> IntoNeighborsDirected for GraphView<'a> {
+    type NeighborsDirected = <&'a Graph as IntoNeighborsDirected>::NeighborsDirected;
+
+    fn neighbors_directed(self, node: NodeIndex, direction: Direction) -> Self::NeighborsDirected {
+        self.graph.neighbors_directed(node, direction)
+    }
+}
+
+impl<'a> IntoNodeIdentifiers for GraphView<'a> {
+    type NodeIdentifiers = <&'a Graph as IntoNodeIdentifiers>::NodeIdentifiers;
+
+    fn node_identifiers(self) -> Self::NodeIdentifiers {
+        self.graph.node_identifiers()
+    }
+}
+
+impl<'a> IntoNodeReferences for GraphView<'a> {
+    type NodeRef = <&'a Graph as IntoNodeReferences>::NodeRef;
+    type NodeReferences = <&'a Graph as IntoNodeReferences>::NodeReferences;
+
+    fn node_references(self) -> Self::NodeReferences {
+        self.graph.node_references()
+    }
+}
+
+impl<'a> IntoEdgeReferences for GraphView<'a> {
+    type EdgeRef = <&'a Graph as IntoEdgeReferences>::EdgeRef;
+    type EdgeReferences = <&'a Graph as IntoEdgeReferences>::EdgeReferences;
+
+    fn edge_references(self) -> Self::EdgeReferences {
+        self.graph.edge_references()
+    }
+}
+
+impl<'a> IntoEdges for GraphView<'a> {
+    type Edges = <&'a Graph as IntoEdges>::Edges;
+
+    fn edges(self, node: NodeIndex) -> Self::Edges {
+        self.graph.edges(node)
+    }
+}
+
+impl<'a> IntoEdgesDirected for GraphView<'a> {
+    type EdgesDirected = <&'a Graph as IntoEdgesDirected>::EdgesDirected;
+
+    fn edges_directed(self, node: NodeIndex, direction: Direction) -> Self::EdgesDirected {
+        self.graph.edges_directed(node, direction)
+    }
+}
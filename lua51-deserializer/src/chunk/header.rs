@@ -1,7 +1,12 @@
+use std::fmt;
+
 use nom::{
     bytes::complete::tag,
     error::{Error, ErrorKind, ParseError},
-    number::complete::le_u8,
+    number::{
+        self,
+        complete::{f32, f64, le_u8, u32, u64},
+    },
     Err, IResult,
 };
 
@@ -16,16 +21,45 @@ pub enum Format {
     Official,
 }
 
+/// Why a chunk can't be read, its header describes a build of Lua we don't support.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnsupportedHeader {
+    Version(u8),
+    IntWidth(u8),
+    SizeTWidth(u8),
+    InstructionWidth(u8),
+    NumberWidth(u8),
+    /// `lua_Number` is an integer type (`LUA_NUMBER` was changed to e.g. `long`)
+    IntegralNumbers,
+}
+
+impl fmt::Display for UnsupportedHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Version(version) => write!(f, "unsupported Lua version {:#x}", version),
+            Self::IntWidth(width) => write!(f, "unsupported int size of {} bytes", width),
+            Self::SizeTWidth(width) => write!(f, "unsupported size_t size of {} bytes", width),
+            Self::InstructionWidth(width) => {
+                write!(f, "unsupported instruction size of {} bytes", width)
+            }
+            Self::NumberWidth(width) => write!(f, "unsupported number size of {} bytes", width),
+            Self::IntegralNumbers => write!(f, "chunks with integer numbers aren't supported"),
+        }
+    }
+}
+
+impl std::error::Error for UnsupportedHeader {}
+
 #[derive(Debug)]
 pub struct Header {
-    pub(crate) version_number: u8,
-    pub(crate) format: Format,
-    pub(crate) endianness: Endianness,
-    pub(crate) int_width: u8,
-    pub(crate) size_t_width: u8,
-    pub(crate) instr_width: u8,
-    pub(crate) number_width: u8,
-    pub(crate) number_is_integral: bool,
+    pub version_number: u8,
+    pub format: Format,
+    pub endianness: Endianness,
+    pub int_width: u8,
+    pub size_t_width: u8,
+    pub instr_width: u8,
+    pub number_width: u8,
+    pub number_is_integral: bool,
 }
 
 impl Header {
@@ -75,4 +109,77 @@ impl Header {
             },
         ))
     }
+
+    /// Returns an error if the chunk was dumped by a build of Lua whose layout we can't read.
+    /// Either byte order and 4 or 8 byte ints, `size_t`s and numbers are supported.
+    pub fn check(&self) -> Result<(), UnsupportedHeader> {
+        if self.version_number != 0x51 {
+            Err(UnsupportedHeader::Version(self.version_number))
+        } else if !matches!(self.int_width, 4 | 8) {
+            Err(UnsupportedHeader::IntWidth(self.int_width))
+        } else if !matches!(self.size_t_width, 4 | 8) {
+            Err(UnsupportedHeader::SizeTWidth(self.size_t_width))
+        } else if self.instr_width != 4 {
+            Err(UnsupportedHeader::InstructionWidth(self.instr_width))
+        } else if self.number_is_integral {
+            Err(UnsupportedHeader::IntegralNumbers)
+        } else if !matches!(self.number_width, 4 | 8) {
+            Err(UnsupportedHeader::NumberWidth(self.number_width))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn endianness(&self) -> number::Endianness {
+        match self.endianness {
+            Endianness::Big => number::Endianness::Big,
+            Endianness::Little => number::Endianness::Little,
+        }
+    }
+
+    fn parse_unsigned<'a>(&self, input: &'a [u8], width: u8) -> IResult<&'a [u8], u64> {
+        if width == 8 {
+            u64(self.endianness())(input)
+        } else {
+            let (input, value) = u32(self.endianness())(input)?;
+            Ok((input, value.into()))
+        }
+    }
+
+    /// Parses an `int`, which is used for counts and line numbers.
+    pub(crate) fn parse_int<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], u32> {
+        let (remaining, value) = self.parse_unsigned(input, self.int_width)?;
+        match u32::try_from(value) {
+            Ok(value) => Ok((remaining, value)),
+            Err(_) => Err(Err::Failure(Error::from_error_kind(
+                input,
+                ErrorKind::TooLarge,
+            ))),
+        }
+    }
+
+    /// Parses a `size_t`, which is used for string lengths.
+    pub(crate) fn parse_size_t<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], usize> {
+        let (remaining, value) = self.parse_unsigned(input, self.size_t_width)?;
+        match usize::try_from(value) {
+            Ok(value) => Ok((remaining, value)),
+            Err(_) => Err(Err::Failure(Error::from_error_kind(
+                input,
+                ErrorKind::TooLarge,
+            ))),
+        }
+    }
+
+    pub(crate) fn parse_instruction<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], u32> {
+        u32(self.endianness())(input)
+    }
+
+    pub(crate) fn parse_number<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], f64> {
+        if self.number_width == 4 {
+            let (input, value) = f32(self.endianness())(input)?;
+            Ok((input, value.into()))
+        } else {
+            f64(self.endianness())(input)
+        }
+    }
 }
@@ -0,0 +1,76 @@
+use nom::{combinator::opt, IResult};
+
+use crate::{
+    chunk::Header,
+    instruction::position::Position,
+    local::Local,
+    value::{self, without_terminator},
+};
+
+/// The debug information of a function. Stripped chunks (`luac -s`) have none of it, so every
+/// part can be empty, and nested functions only have a source name if it differs from their
+/// parent's. Names don't include their null terminator.
+#[derive(Debug, Default)]
+pub struct DebugInfo<'a> {
+    /// The name of the chunk the function was compiled from, e.g. `@script.lua`
+    pub source: Option<&'a [u8]>,
+    pub positions: Vec<Position>,
+    /// Ordered by the instruction each local starts at
+    pub locals: Vec<Local<'a>>,
+    pub upvalues: Vec<&'a [u8]>,
+}
+
+impl<'a> DebugInfo<'a> {
+    // the lists are parsed after the rest of the function, some tools leave them out entirely
+    pub(crate) fn parse(
+        input: &'a [u8],
+        header: &Header,
+        source: &'a [u8],
+    ) -> IResult<&'a [u8], Self> {
+        let (input, positions) = opt(|input| Position::parse(input, header))(input)?;
+        let (input, locals) = opt(|input| Local::parse_list(input, header))(input)?;
+        let (input, upvalues) = opt(|input| value::parse_strings(input, header))(input)?;
+
+        Ok((
+            input,
+            Self {
+                source: (!source.is_empty()).then(|| without_terminator(source)),
+                positions: positions.unwrap_or_default(),
+                locals: locals.unwrap_or_default(),
+                upvalues: upvalues
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(without_terminator)
+                    .collect(),
+            },
+        ))
+    }
+
+    pub fn is_stripped(&self) -> bool {
+        self.source.is_none()
+            && self.positions.is_empty()
+            && self.locals.is_empty()
+            && self.upvalues.is_empty()
+    }
+
+    /// Returns the source line of an instruction.
+    pub fn line(&self, pc: usize) -> Option<u32> {
+        self.positions.get(pc).map(|position| position.source)
+    }
+
+    pub fn upvalue_name(&self, upvalue: usize) -> Option<&'a [u8]> {
+        self.upvalues.get(upvalue).copied()
+    }
+
+    /// Returns every local along with the register it's held in. A local is held in the register
+    /// after the ones of the locals still in scope when it starts.
+    pub fn local_registers(&self) -> impl Iterator<Item = (usize, &Local<'a>)> {
+        self.locals.iter().enumerate().map(|(index, local)| {
+            let register = self.locals[..index]
+                .iter()
+                .filter(|l| l.range.contains(&local.range.start))
+                .count();
+            (register, local)
+        })
+    }
+}
@@ -0,0 +1,322 @@
+//! A readable listing of a function's instructions, one per line. Registers are written as `r0`,
+//! upvalues as `u0`, constants by their value and jump targets as labels. The listing keeps
+//! everything but the debug info and the order of the constants, so it can be assembled back into
+//! an equivalent chunk.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use either::Either;
+
+use crate::{
+    argument::{Constant, Register, RegisterOrConstant},
+    Function, Instruction, Value,
+};
+
+// a string as a Lua literal, with every byte that isn't printable ASCII as a 3 digit decimal
+// escape so the listing can be assembled back into the same bytes
+fn escape_string(string: &[u8]) -> String {
+    let mut output = String::from("\"");
+    for &byte in string {
+        match byte {
+            b'"' | b'\\' => {
+                output.push('\\');
+                output.push(byte as char);
+            }
+            b' '..=b'~' => output.push(byte as char),
+            _ => write!(output, "\\{:03}", byte).unwrap(),
+        }
+    }
+    output.push('"');
+    output
+}
+
+fn constant(function: &Function, constant: &Constant) -> String {
+    match function.constants.get(constant.0 as usize) {
+        Some(Value::Nil) => "nil".to_string(),
+        Some(Value::Boolean(value)) => value.to_string(),
+        Some(Value::Number(value)) => value.to_string(),
+        Some(Value::String(value)) => escape_string(value),
+        None => format!("k{}", constant.0),
+    }
+}
+
+fn register_or_constant(function: &Function, operand: &RegisterOrConstant) -> String {
+    match &operand.0 {
+        Either::Left(register) => format!("r{}", register.0),
+        Either::Right(operand) => constant(function, operand),
+    }
+}
+
+fn registers(registers: &[Register]) -> String {
+    match registers {
+        [] => String::new(),
+        [register] => format!("r{}", register.0),
+        [first, .., last] => format!("r{}..r{}", first.0, last.0),
+    }
+}
+
+// the instruction a jump at `pc` goes to, if the instruction is one
+fn jump_target(pc: usize, instruction: &Instruction) -> Option<usize> {
+    let skip = match *instruction {
+        Instruction::Jump(skip)
+        | Instruction::IterateNumericForLoop { skip, .. }
+        | Instruction::InitNumericForLoop { skip, .. } => skip,
+        Instruction::LoadBoolean {
+            skip_next: true, ..
+        } => 1,
+        _ => return None,
+    };
+    (pc + 1).checked_add_signed(skip as isize)
+}
+
+// the mnemonic and operands of an instruction, with the names used by `luac -l`
+fn format_instruction(function: &Function, instruction: &Instruction) -> (&'static str, String) {
+    let rk = |operand| register_or_constant(function, operand);
+    let comparison = |name, lhs, rhs, invert: bool| {
+        (
+            name,
+            format!("{}, {}, {}", u8::from(!invert), rk(lhs), rk(rhs)),
+        )
+    };
+    let arithmetic = |name, destination: &Register, lhs, rhs| {
+        (
+            name,
+            format!("r{}, {}, {}", destination.0, rk(lhs), rk(rhs)),
+        )
+    };
+    match instruction {
+        Instruction::Move {
+            destination,
+            source,
+        } => ("MOVE", format!("r{}, r{}", destination.0, source.0)),
+        Instruction::LoadConstant {
+            destination,
+            source,
+        } => (
+            "LOADK",
+            format!("r{}, {}", destination.0, constant(function, source)),
+        ),
+        Instruction::LoadBoolean {
+            destination,
+            value,
+            skip_next,
+        } => (
+            "LOADBOOL",
+            format!("r{}, {}, {}", destination.0, value, u8::from(*skip_next)),
+        ),
+        Instruction::LoadNil(destinations) => ("LOADNIL", registers(destinations)),
+        Instruction::GetUpvalue {
+            destination,
+            upvalue,
+        } => ("GETUPVAL", format!("r{}, u{}", destination.0, upvalue.0)),
+        Instruction::GetGlobal {
+            destination,
+            global,
+        } => (
+            "GETGLOBAL",
+            format!("r{}, {}", destination.0, constant(function, global)),
+        ),
+        Instruction::GetIndex {
+            destination,
+            object,
+            key,
+        } => (
+            "GETTABLE",
+            format!("r{}, r{}, {}", destination.0, object.0, rk(key)),
+        ),
+        Instruction::SetGlobal { destination, value } => (
+            "SETGLOBAL",
+            format!("r{}, {}", value.0, constant(function, destination)),
+        ),
+        Instruction::SetUpvalue {
+            destination,
+            source,
+        } => ("SETUPVAL", format!("r{}, u{}", source.0, destination.0)),
+        Instruction::SetIndex { object, key, value } => (
+            "SETTABLE",
+            format!("r{}, {}, {}", object.0, rk(key), rk(value)),
+        ),
+        Instruction::NewTable {
+            destination,
+            array_size,
+            hash_size,
+        } => (
+            "NEWTABLE",
+            format!("r{}, {}, {}", destination.0, array_size, hash_size),
+        ),
+        Instruction::PrepMethodCall {
+            destination,
+            object,
+            method,
+            ..
+        } => (
+            "SELF",
+            format!("r{}, r{}, {}", destination.0, object.0, rk(method)),
+        ),
+        Instruction::Add {
+            destination,
+            lhs,
+            rhs,
+        } => arithmetic("ADD", destination, lhs, rhs),
+        Instruction::Sub {
+            destination,
+            lhs,
+            rhs,
+        } => arithmetic("SUB", destination, lhs, rhs),
+        Instruction::Mul {
+            destination,
+            lhs,
+            rhs,
+        } => arithmetic("MUL", destination, lhs, rhs),
+        Instruction::Div {
+            destination,
+            lhs,
+            rhs,
+        } => arithmetic("DIV", destination, lhs, rhs),
+        Instruction::Mod {
+            destination,
+            lhs,
+            rhs,
+        } => arithmetic("MOD", destination, lhs, rhs),
+        Instruction::Pow {
+            destination,
+            lhs,
+            rhs,
+        } => arithmetic("POW", destination, lhs, rhs),
+        Instruction::Minus {
+            destination,
+            operand,
+        } => ("UNM", format!("r{}, r{}", destination.0, operand.0)),
+        Instruction::Not {
+            destination,
+            operand,
+        } => ("NOT", format!("r{}, r{}", destination.0, operand.0)),
+        Instruction::Length {
+            destination,
+            operand,
+        } => ("LEN", format!("r{}, r{}", destination.0, operand.0)),
+        Instruction::Concatenate {
+            destination,
+            operands,
+        } => (
+            "CONCAT",
+            format!("r{}, {}", destination.0, registers(operands)),
+        ),
+        Instruction::Jump(_) => ("JMP", String::new()),
+        Instruction::Equal { lhs, rhs, invert } => comparison("EQ", lhs, rhs, *invert),
+        Instruction::LessThan { lhs, rhs, invert } => comparison("LT", lhs, rhs, *invert),
+        Instruction::LessThanOrEqual { lhs, rhs, invert } => comparison("LE", lhs, rhs, *invert),
+        Instruction::Test { value, invert } => {
+            ("TEST", format!("r{}, {}", value.0, u8::from(!invert)))
+        }
+        Instruction::TestSet {
+            destination,
+            value,
+            invert,
+        } => (
+            "TESTSET",
+            format!("r{}, r{}, {}", destination.0, value.0, u8::from(!invert)),
+        ),
+        Instruction::Call {
+            function,
+            arguments,
+            return_values,
+        } => (
+            "CALL",
+            format!("r{}, {}, {}", function.0, arguments, return_values),
+        ),
+        Instruction::TailCall {
+            function,
+            arguments,
+        } => ("TAILCALL", format!("r{}, {}", function.0, arguments)),
+        Instruction::Return(register, values) => ("RETURN", format!("r{}, {}", register.0, values)),
+        Instruction::IterateNumericForLoop { control, .. } => ("FORLOOP", registers(control)),
+        Instruction::InitNumericForLoop { control, .. } => ("FORPREP", registers(control)),
+        Instruction::IterateGenericForLoop {
+            generator, vars, ..
+        } => ("TFORLOOP", format!("r{}, {}", generator.0, registers(vars))),
+        Instruction::SetList {
+            table,
+            number_of_elements,
+            block_number,
+        } => (
+            "SETLIST",
+            format!("r{}, {}, {}", table.0, number_of_elements, block_number),
+        ),
+        Instruction::Close(register) => ("CLOSE", format!("r{}", register.0)),
+        Instruction::Closure {
+            destination,
+            function,
+        } => ("CLOSURE", format!("r{}, f{}", destination.0, function.0)),
+        Instruction::VarArg(register, values) => ("VARARG", format!("r{}, {}", register.0, values)),
+    }
+}
+
+/// Returns a listing of the function's instructions, one per line, prefixed with their pc.
+pub fn disassemble_function(function: &Function) -> String {
+    let labels = function
+        .code
+        .iter()
+        .enumerate()
+        .filter_map(|(pc, instruction)| jump_target(pc, instruction))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .enumerate()
+        .map(|(label, pc)| (pc, format!("L{}", label)))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut output = String::new();
+    for (pc, instruction) in function.code.iter().enumerate() {
+        if let Some(label) = labels.get(&pc) {
+            writeln!(output, "{}:", label).unwrap();
+        }
+        let (name, operands) = format_instruction(function, instruction);
+        write!(output, "{:>5}  {:<10}{}", pc, name, operands).unwrap();
+        if let Some(label) = jump_target(pc, instruction).and_then(|pc| labels.get(&pc)) {
+            if !operands.is_empty() {
+                write!(output, " ").unwrap();
+            }
+            write!(output, "-> {}", label).unwrap();
+        }
+        writeln!(output).unwrap();
+    }
+    output
+}
+
+/// Disassembles the function and every function nested in it, each headed by a path of closure
+/// indices, e.g. `0.2` for the third closure of the main function.
+pub fn disassemble(function: &Function) -> String {
+    let mut output = String::new();
+    let mut stack = vec![(function, "0".to_string())];
+    while let Some((function, path)) = stack.pop() {
+        writeln!(
+            output,
+            "; function {} defined at line {}, {} parameters{}, {} upvalues, {} registers",
+            path,
+            function.line_defined,
+            function.number_of_parameters,
+            if function.is_vararg() {
+                " and varargs"
+            } else {
+                ""
+            },
+            function.number_of_upvalues,
+            function.maximum_stack_size
+        )
+        .unwrap();
+        output += &disassemble_function(function);
+        output.push('\n');
+        stack.extend(
+            function
+                .closures
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(index, closure)| (closure, format!("{}.{}", path, index))),
+        );
+    }
+    output
+}
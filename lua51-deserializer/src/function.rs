@@ -1,19 +1,14 @@
-use nom::{
-    combinator::opt,
-    multi::count,
-    number::complete::{le_u32, le_u8},
-    IResult,
-};
+use nom::{error::ErrorKind, multi::count, number::complete::le_u8, IResult};
 
 use crate::{
-    instruction::{position::Position, Instruction},
-    local::Local,
+    chunk::Header,
+    debug_info::DebugInfo,
+    instruction::Instruction,
     value::{self, Value},
 };
 
 #[derive(Debug)]
 pub struct Function<'a> {
-    pub name: &'a [u8],
     pub line_defined: u32,
     pub last_line_defined: u32,
     pub number_of_upvalues: u8,
@@ -22,35 +17,68 @@ pub struct Function<'a> {
     pub code: Vec<Instruction>,
     pub constants: Vec<Value<'a>>,
     pub closures: Vec<Function<'a>>,
-    pub positions: Vec<Position>,
-    pub locals: Vec<Local<'a>>,
-    pub upvalues: Vec<&'a [u8]>,
     pub number_of_parameters: u8,
+    pub debug_info: DebugInfo<'a>,
 }
 
+// well above the nesting the Lua 5.1 compiler allows
+const MAX_DEPTH: usize = 256;
+
+// `vararg_flag` bits, see `lobject.h`
+const VARARG_ISVARARG: u8 = 2;
+const VARARG_NEEDSARG: u8 = 4;
+
 impl<'a> Function<'a> {
-    pub fn parse(input: &'a [u8]) -> IResult<&'a [u8], Self> {
-        let (input, name) = value::parse_string(input)?;
-        let (input, line_defined) = le_u32(input)?;
-        let (input, last_line_defined) = le_u32(input)?;
+    /// Whether the function takes `...`
+    pub fn is_vararg(&self) -> bool {
+        self.vararg_flag & VARARG_ISVARARG != 0
+    }
+
+    /// Whether the function uses the implicit `arg` table of Lua 5.0, which is then the local
+    /// after the parameters
+    pub fn needs_arg(&self) -> bool {
+        self.is_vararg() && self.vararg_flag & VARARG_NEEDSARG != 0
+    }
+
+    pub fn parse(input: &'a [u8], header: &Header) -> IResult<&'a [u8], Self> {
+        Self::parse_nested(input, header, 0)
+    }
+
+    // closures are parsed recursively, so their depth is limited to avoid overflowing the stack
+    fn parse_nested(input: &'a [u8], header: &Header, depth: usize) -> IResult<&'a [u8], Self> {
+        if depth > MAX_DEPTH {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                input,
+                ErrorKind::TooLarge,
+            )));
+        }
+        let (input, source) = value::parse_string(input, header)?;
+        let (input, line_defined) = header.parse_int(input)?;
+        let (input, last_line_defined) = header.parse_int(input)?;
         let (input, number_of_upvalues) = le_u8(input)?;
         let (input, number_of_parameters) = le_u8(input)?;
         let (input, vararg_flag) = le_u8(input)?;
         let (input, maximum_stack_size) = le_u8(input)?;
-        let (input, code_length) = le_u32(input)?;
-        let (input, code) = count(Instruction::parse, code_length as usize)(input)?;
-        let (input, constants_length) = le_u32(input)?;
-        let (input, constants) = count(Value::parse, constants_length as usize)(input)?;
-        let (input, closures_length) = le_u32(input)?;
-        let (input, closures) = count(Self::parse, closures_length as usize)(input)?;
-        let (input, positions) = opt(Position::parse)(input)?;
-        let (input, locals) = opt(Local::parse_list)(input)?;
-        let (input, upvalues) = opt(value::parse_strings)(input)?;
+        let (input, code_length) = header.parse_int(input)?;
+        let (input, code) = count(
+            |input| Instruction::parse(input, header),
+            code_length as usize,
+        )(input)?;
+        let (input, constants_length) = header.parse_int(input)?;
+        let (input, constants) = count(
+            |input| Value::parse(input, header),
+            constants_length as usize,
+        )(input)?;
+        let (input, closures_length) = header.parse_int(input)?;
+        let (input, closures) = count(
+            |input| Self::parse_nested(input, header, depth + 1),
+            closures_length as usize,
+        )(input)?;
+        let (input, debug_info) = DebugInfo::parse(input, header, source)?;
 
         Ok((
             input,
             Self {
-                name,
                 line_defined,
                 last_line_defined,
                 number_of_upvalues,
@@ -59,10 +87,8 @@ impl<'a> Function<'a> {
                 code,
                 constants,
                 closures,
-                positions: positions.unwrap_or_default(),
-                locals: locals.unwrap_or_default(),
-                upvalues: upvalues.unwrap_or_default(),
                 number_of_parameters,
+                debug_info,
             },
         ))
     }
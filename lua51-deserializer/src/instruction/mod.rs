@@ -2,11 +2,12 @@ use nom::{
     error::{Error, ErrorKind, ParseError},
     Err, IResult,
 };
-use num_traits::ToPrimitive;
 
 use argument::{Constant, Function, Register, RegisterOrConstant, Upvalue};
 use layout::Layout;
-use operation_code::OperationCode;
+pub use operation_code::OperationCode;
+
+use crate::chunk::Header;
 
 pub mod argument;
 mod layout;
@@ -17,12 +18,11 @@ pub mod position;
 struct RawInstruction(OperationCode, Layout);
 
 impl RawInstruction {
-    pub fn parse(input: &[u8]) -> IResult<&[u8], Self> {
-        // TODO: we read the operation code and then the instruction including the operation code
-        // while parsing the layout.
-        // we should instead read the instruction here and pass it to Layout::parse
-        let operation_code = OperationCode::parse(input).map(|r| r.1)?;
-        let (input, layout) = Layout::parse(input, operation_code.to_u8().unwrap())?;
+    pub fn parse<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], Self> {
+        let (input, instruction) = header.parse_instruction(input)?;
+        let operation_code = OperationCode::decode(instruction)
+            .ok_or_else(|| Err::Failure(Error::from_error_kind(input, ErrorKind::Switch)))?;
+        let layout = Layout::decode(instruction, &operation_code);
 
         Ok((input, Self(operation_code, layout)))
     }
@@ -201,8 +201,8 @@ pub enum Instruction {
 }
 
 impl Instruction {
-    pub fn parse(input: &[u8]) -> IResult<&[u8], Self> {
-        let (input, instruction) = RawInstruction::parse(input)?;
+    pub fn parse<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], Self> {
+        let (input, instruction) = RawInstruction::parse(input, header)?;
         let instruction = match instruction {
             RawInstruction(OperationCode::Move, Layout::BC { a, b, .. }) => Self::Move {
                 destination: Register(a),
@@ -1,9 +1,4 @@
 use crate::instruction::layout::LayoutDiscriminants;
-use nom::{
-    error::{Error, ErrorKind, ParseError},
-    number::complete::le_u8,
-    Err, IResult,
-};
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::FromPrimitive;
 
@@ -50,25 +45,12 @@ pub enum OperationCode {
 }
 
 impl OperationCode {
-    pub fn parse(input: &[u8]) -> IResult<&[u8], Self> {
-        let (input, operation_code) = le_u8(input)?;
-        let operation_code = operation_code & 0x3F;
-
-        Ok((
-            input,
-            match FromPrimitive::from_u8(operation_code) {
-                None => {
-                    return Err(Err::Failure(Error::from_error_kind(
-                        input,
-                        ErrorKind::Switch,
-                    )))
-                }
-                Some(operation_code) => operation_code,
-            },
-        ))
+    /// Returns the operation code in the low 6 bits of an instruction.
+    pub fn decode(instruction: u32) -> Option<Self> {
+        FromPrimitive::from_u32(instruction & 0x3F)
     }
 
-    pub fn instruction_layout(&self) -> LayoutDiscriminants {
+    pub(crate) fn instruction_layout(&self) -> LayoutDiscriminants {
         /*
            0 = BC
            1 = BX
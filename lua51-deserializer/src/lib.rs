@@ -3,7 +3,10 @@ pub use instruction::{argument, Instruction};
 pub use value::Value;
 
 pub mod chunk;
+pub mod debug_info;
+pub mod disassemble;
 pub mod function;
 pub mod instruction;
 pub mod local;
+pub mod validate;
 pub mod value;
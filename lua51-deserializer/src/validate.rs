@@ -0,0 +1,389 @@
+use std::fmt;
+
+use either::Either;
+
+use crate::{
+    argument::{Constant, Register, RegisterOrConstant, Upvalue},
+    function::Function,
+    instruction::Instruction,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidOperand {
+    Register {
+        register: u8,
+        maximum_stack_size: u8,
+    },
+    Constant {
+        constant: u32,
+        constants: usize,
+    },
+    Upvalue {
+        upvalue: u8,
+        upvalues: u8,
+    },
+    Closure {
+        closure: u32,
+        closures: usize,
+    },
+    JumpTarget {
+        target: i64,
+        code_length: usize,
+    },
+}
+
+impl fmt::Display for InvalidOperand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Register {
+                register,
+                maximum_stack_size,
+            } => write!(
+                f,
+                "register {} is outside the stack of {} registers",
+                register, maximum_stack_size
+            ),
+            Self::Constant {
+                constant,
+                constants,
+            } => write!(
+                f,
+                "constant {} is outside the pool of {} constants",
+                constant, constants
+            ),
+            Self::Upvalue { upvalue, upvalues } => {
+                write!(
+                    f,
+                    "upvalue {} is outside the {} upvalues",
+                    upvalue, upvalues
+                )
+            }
+            Self::Closure { closure, closures } => write!(
+                f,
+                "closure {} is outside the {} nested functions",
+                closure, closures
+            ),
+            Self::JumpTarget {
+                target,
+                code_length,
+            } => write!(
+                f,
+                "jump to {} is outside the code of {} instructions",
+                target, code_length
+            ),
+        }
+    }
+}
+
+/// An instruction with an operand that doesn't fit its function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Indices of the closures leading to the function, empty for the main function
+    pub function: Vec<usize>,
+    pub pc: usize,
+    pub operand: InvalidOperand,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid instruction at pc {} in ", self.pc)?;
+        if self.function.is_empty() {
+            write!(f, "main function")?;
+        } else {
+            write!(f, "function ")?;
+            for (i, index) in self.function.iter().enumerate() {
+                if i != 0 {
+                    write!(f, ".")?;
+                }
+                write!(f, "{}", index)?;
+            }
+        }
+        write!(f, ": {}", self.operand)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+struct Validator<'a, 'b> {
+    function: &'a Function<'b>,
+    pc: usize,
+}
+
+impl Validator<'_, '_> {
+    fn register(&self, register: Register) -> Result<(), InvalidOperand> {
+        if register.0 < self.function.maximum_stack_size {
+            Ok(())
+        } else {
+            Err(InvalidOperand::Register {
+                register: register.0,
+                maximum_stack_size: self.function.maximum_stack_size,
+            })
+        }
+    }
+
+    fn constant(&self, constant: Constant) -> Result<(), InvalidOperand> {
+        if (constant.0 as usize) < self.function.constants.len() {
+            Ok(())
+        } else {
+            Err(InvalidOperand::Constant {
+                constant: constant.0,
+                constants: self.function.constants.len(),
+            })
+        }
+    }
+
+    fn register_or_constant(&self, operand: RegisterOrConstant) -> Result<(), InvalidOperand> {
+        match operand.0 {
+            Either::Left(register) => self.register(register),
+            Either::Right(constant) => self.constant(constant),
+        }
+    }
+
+    fn upvalue(&self, upvalue: &Upvalue) -> Result<(), InvalidOperand> {
+        if upvalue.0 < self.function.number_of_upvalues {
+            Ok(())
+        } else {
+            Err(InvalidOperand::Upvalue {
+                upvalue: upvalue.0,
+                upvalues: self.function.number_of_upvalues,
+            })
+        }
+    }
+
+    // `offset` is relative to the next instruction
+    fn jump(&self, offset: i64) -> Result<(), InvalidOperand> {
+        let target = self.pc as i64 + 1 + offset;
+        if target >= 0 && (target as usize) < self.function.code.len() {
+            Ok(())
+        } else {
+            Err(InvalidOperand::JumpTarget {
+                target,
+                code_length: self.function.code.len(),
+            })
+        }
+    }
+
+    fn instruction(&self, instruction: &Instruction) -> Result<(), InvalidOperand> {
+        match instruction {
+            Instruction::Move {
+                destination,
+                source,
+            } => {
+                self.register(*destination)?;
+                self.register(*source)
+            }
+            Instruction::LoadConstant {
+                destination,
+                source,
+            } => {
+                self.register(*destination)?;
+                self.constant(*source)
+            }
+            Instruction::LoadBoolean {
+                destination,
+                skip_next,
+                ..
+            } => {
+                self.register(*destination)?;
+                if *skip_next {
+                    self.jump(1)?;
+                }
+                Ok(())
+            }
+            Instruction::LoadNil(registers) => registers.iter().try_for_each(|&r| self.register(r)),
+            Instruction::GetUpvalue {
+                destination,
+                upvalue,
+            } => {
+                self.register(*destination)?;
+                self.upvalue(upvalue)
+            }
+            Instruction::GetGlobal {
+                destination,
+                global,
+            } => {
+                self.register(*destination)?;
+                self.constant(*global)
+            }
+            Instruction::GetIndex {
+                destination,
+                object,
+                key,
+            } => {
+                self.register(*destination)?;
+                self.register(*object)?;
+                self.register_or_constant(*key)
+            }
+            Instruction::SetGlobal { destination, value } => {
+                self.constant(*destination)?;
+                self.register(*value)
+            }
+            Instruction::SetUpvalue {
+                destination,
+                source,
+            } => {
+                self.upvalue(destination)?;
+                self.register(*source)
+            }
+            Instruction::SetIndex { object, key, value } => {
+                self.register(*object)?;
+                self.register_or_constant(*key)?;
+                self.register_or_constant(*value)
+            }
+            Instruction::NewTable { destination, .. } => self.register(*destination),
+            Instruction::PrepMethodCall {
+                destination,
+                self_arg,
+                object,
+                method,
+            } => {
+                self.register(*destination)?;
+                self.register(*self_arg)?;
+                self.register(*object)?;
+                self.register_or_constant(*method)
+            }
+            Instruction::Add {
+                destination,
+                lhs,
+                rhs,
+            }
+            | Instruction::Sub {
+                destination,
+                lhs,
+                rhs,
+            }
+            | Instruction::Mul {
+                destination,
+                lhs,
+                rhs,
+            }
+            | Instruction::Div {
+                destination,
+                lhs,
+                rhs,
+            }
+            | Instruction::Mod {
+                destination,
+                lhs,
+                rhs,
+            }
+            | Instruction::Pow {
+                destination,
+                lhs,
+                rhs,
+            } => {
+                self.register(*destination)?;
+                self.register_or_constant(*lhs)?;
+                self.register_or_constant(*rhs)
+            }
+            Instruction::Minus {
+                destination,
+                operand,
+            }
+            | Instruction::Not {
+                destination,
+                operand,
+            }
+            | Instruction::Length {
+                destination,
+                operand,
+            } => {
+                self.register(*destination)?;
+                self.register(*operand)
+            }
+            Instruction::Concatenate {
+                destination,
+                operands,
+            } => {
+                self.register(*destination)?;
+                operands.iter().try_for_each(|&r| self.register(r))
+            }
+            Instruction::Jump(offset) => self.jump(*offset as i64),
+            Instruction::Equal { lhs, rhs, .. }
+            | Instruction::LessThan { lhs, rhs, .. }
+            | Instruction::LessThanOrEqual { lhs, rhs, .. } => {
+                self.register_or_constant(*lhs)?;
+                self.register_or_constant(*rhs)?;
+                self.jump(1)
+            }
+            Instruction::Test { value, .. } => {
+                self.register(*value)?;
+                self.jump(1)
+            }
+            Instruction::TestSet {
+                destination, value, ..
+            } => {
+                self.register(*destination)?;
+                self.register(*value)?;
+                self.jump(1)
+            }
+            Instruction::Call { function, .. } | Instruction::TailCall { function, .. } => {
+                self.register(*function)
+            }
+            // `return` without values uses the first free register, which may be past the stack
+            Instruction::Return(_, 1) => Ok(()),
+            Instruction::Return(register, _) => self.register(*register),
+            Instruction::IterateNumericForLoop { control, skip }
+            | Instruction::InitNumericForLoop { control, skip } => {
+                // the loop uses 4 registers, the last one in `control` may be past the stack
+                control.iter().take(4).try_for_each(|&r| self.register(r))?;
+                self.jump(*skip as i64)
+            }
+            Instruction::IterateGenericForLoop {
+                generator,
+                state,
+                internal_control,
+                vars,
+            } => {
+                self.register(*generator)?;
+                self.register(*state)?;
+                self.register(*internal_control)?;
+                vars.iter().try_for_each(|&r| self.register(r))?;
+                self.jump(1)
+            }
+            Instruction::SetList { table, .. } => self.register(*table),
+            // closes upvalues from a stack level, not a register that is read
+            Instruction::Close(_) => Ok(()),
+            Instruction::Closure {
+                destination,
+                function,
+            } => {
+                self.register(*destination)?;
+                if (function.0 as usize) < self.function.closures.len() {
+                    Ok(())
+                } else {
+                    Err(InvalidOperand::Closure {
+                        closure: function.0,
+                        closures: self.function.closures.len(),
+                    })
+                }
+            }
+            Instruction::VarArg(register, _) => self.register(*register),
+        }
+    }
+}
+
+impl Function<'_> {
+    /// Checks that every register, constant, upvalue, closure and jump target referenced by the
+    /// instructions of this function and its closures is in range, so lifting it doesn't panic.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut stack = vec![(Vec::new(), self)];
+        while let Some((path, function)) = stack.pop() {
+            for (pc, instruction) in function.code.iter().enumerate() {
+                Validator { function, pc }
+                    .instruction(instruction)
+                    .map_err(|operand| ValidationError {
+                        function: path.clone(),
+                        pc,
+                        operand,
+                    })?;
+            }
+            for (index, closure) in function.closures.iter().enumerate() {
+                let mut path = path.clone();
+                path.push(index);
+                stack.push((path, closure));
+            }
+        }
+        Ok(())
+    }
+}
@@ -3,10 +3,12 @@ use nom::{
     bytes::complete::take,
     error::{Error, ErrorKind, ParseError},
     multi::count,
-    number::complete::{le_f64, le_u32, le_u8},
+    number::complete::le_u8,
     Err, IResult,
 };
 
+use crate::chunk::Header;
+
 #[derive(Debug, EnumAsInner)]
 pub enum Value<'a> {
     Nil,
@@ -16,7 +18,7 @@ pub enum Value<'a> {
 }
 
 impl<'a> Value<'a> {
-    pub fn parse(input: &'a [u8]) -> IResult<&'a [u8], Self> {
+    pub fn parse(input: &'a [u8], header: &Header) -> IResult<&'a [u8], Self> {
         let (input, kind) = le_u8(input)?;
 
         match kind {
@@ -27,12 +29,12 @@ impl<'a> Value<'a> {
                 Ok((input, Self::Boolean(value != 0)))
             }
             3 => {
-                let (input, value) = le_f64(input)?;
+                let (input, value) = header.parse_number(input)?;
 
                 Ok((input, Self::Number(value)))
             }
             4 => {
-                let (input, value) = parse_string(input)?;
+                let (input, value) = parse_string(input, header)?;
 
                 // TODO: lua bytecode actually allows the string to be completely empty
                 // it sets the type to string but gc to NULL
@@ -50,14 +52,20 @@ impl<'a> Value<'a> {
     }
 }
 
-pub fn parse_string(input: &[u8]) -> IResult<&[u8], &[u8]> {
-    let (input, string_length) = le_u32(input)?;
-    take(string_length as usize)(input)
+// strings other than constants are stored with a null terminator
+pub(crate) fn without_terminator(string: &[u8]) -> &[u8] {
+    string.strip_suffix(b"\0").unwrap_or(string)
+}
+
+pub fn parse_string<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], &'a [u8]> {
+    let (input, string_length) = header.parse_size_t(input)?;
+    take(string_length)(input)
 }
 
-pub fn parse_strings(input: &[u8]) -> IResult<&[u8], Vec<&[u8]>> {
-    let (input, string_count) = le_u32(input)?;
-    let (input, strings) = count(parse_string, string_count as usize)(input)?;
+pub fn parse_strings<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], Vec<&'a [u8]>> {
+    let (input, string_count) = header.parse_int(input)?;
+    let (input, strings) =
+        count(|input| parse_string(input, header), string_count as usize)(input)?;
 
     Ok((input, strings))
 }
@@ -0,0 +1,375 @@
+#![feature(box_patterns)]
+#![feature(let_chains)]
+
+use anyhow::anyhow;
+use ast::{
+    dispatch_chains::normalize_dispatch_chains,
+    emitter::{DisplayEmitter, Emitter},
+    forward_varargs::forward_varargs,
+    local_declarations::LocalDeclarer,
+    lower_continue::lower_continue,
+    name_locals::name_locals,
+    replace_locals::replace_locals,
+    Traverse,
+};
+use by_address::ByAddress;
+use cfg::ssa::{
+    self,
+    structuring::{structure_conditionals, structure_jumps, structure_method_calls},
+};
+use indexmap::IndexMap;
+use lifter::{Lifter, UpvalueContext};
+use parking_lot::Mutex;
+use petgraph::algo::dominators::simple_fast;
+use rustc_hash::FxHashMap;
+use triomphe::Arc;
+use web_time::Instant;
+
+use lua51_deserializer::chunk::{Chunk, Header};
+
+mod lifter;
+
+pub use lifter::LiftError;
+pub use pipeline::{
+    CancellationToken, Cancelled, FnPass, Pass, PassManager, Pipeline, PipelineError, Progress,
+    ProgressSink, SsaFunction,
+};
+pub use restructure::{Fallback, StructureOptions};
+
+/// Decompiles a Lua 5.1 chunk.
+pub fn decompile_bytecode(bytecode: &[u8]) -> anyhow::Result<String> {
+    decompile_bytecode_with_fallback(bytecode, Fallback::default())
+}
+
+/// Like [`decompile_bytecode`], but control flow that can't be structured is left to `fallback`.
+/// The output needs Lua 5.2 or later with [`Fallback::Goto`].
+pub fn decompile_bytecode_with_fallback(
+    bytecode: &[u8],
+    fallback: Fallback,
+) -> anyhow::Result<String> {
+    decompile_bytecode_with_emitter(
+        bytecode,
+        StructureOptions {
+            fallback,
+            ..Default::default()
+        },
+        &mut DisplayEmitter::default(),
+    )
+}
+
+/// Like [`decompile_bytecode`], but functions are structured as `options` say and the decompiled
+/// main function is handed to `emitter` instead of being formatted with the default options.
+pub fn decompile_bytecode_with_emitter<E: Emitter>(
+    bytecode: &[u8],
+    options: StructureOptions,
+    emitter: &mut E,
+) -> anyhow::Result<E::Output> {
+    decompile_bytecode_with_progress(
+        bytecode,
+        options,
+        &default_pipeline(),
+        emitter,
+        &Progress::default(),
+    )
+}
+
+/// Like [`decompile_bytecode_with_emitter`], but every function is decompiled with the passes of
+/// `pipeline`, and the functions lifted and decompiled are reported to `progress` as the
+/// `"lifting"` and `"decompiling"` stages, and [`Cancelled`] is returned once its token is
+/// cancelled.
+pub fn decompile_bytecode_with_progress<E: Emitter>(
+    bytecode: &[u8],
+    options: StructureOptions,
+    pipeline: &Pipeline,
+    emitter: &mut E,
+    progress: &Progress,
+) -> anyhow::Result<E::Output> {
+    // functions are lifted before any of them are decompiled, so locals are numbered across the
+    // whole chunk
+    let body = ast::number_locals(0, 1, || {
+        decompile_chunk(bytecode, options, pipeline, progress)
+    })
+    .0?;
+    Ok(emitter.emit(&body))
+}
+
+/// The passes every function is decompiled with, in this order. `"dead code elimination"` removes
+/// code that is in the bytecode but never used, which can be what a reader of an obfuscated script
+/// is after, and `"normalize dispatch chains"` reorders the arms of if chains, so they are
+/// disabled unless they're enabled with [`PassManager::set_enabled`].
+pub fn default_pipeline() -> Pipeline {
+    let mut pipeline = Pipeline::default();
+    pipeline
+        .ssa
+        .register(FnPass::new("structure jumps", |ssa: &mut SsaFunction| {
+            let dominators = simple_fast(ssa.function.graph(), ssa.function.entry().unwrap());
+            ssa.changed |= structure_jumps(&mut ssa.function, &dominators);
+        }))
+        .unwrap();
+    pipeline
+        .ssa
+        .register(FnPass::new("inline", |ssa: &mut SsaFunction| {
+            ssa::inline::inline(
+                &mut ssa.function,
+                &ssa.local_to_group,
+                &ssa.upvalue_to_group,
+            )
+        }))
+        .unwrap();
+    pipeline
+        .ssa
+        .register(FnPass::new(
+            "dead code elimination",
+            |ssa: &mut SsaFunction| {
+                ssa.changed |=
+                    cfg::passes::dce::eliminate_dead_code(&mut ssa.function, &ssa.upvalue_to_group);
+            },
+        ))
+        .unwrap();
+    pipeline
+        .ssa
+        .set_enabled("dead code elimination", false)
+        .unwrap();
+    pipeline
+        .ssa
+        .register(FnPass::new(
+            "structure conditionals",
+            |ssa: &mut SsaFunction| ssa.changed |= structure_conditionals(&mut ssa.function),
+        ))
+        .unwrap();
+    pipeline
+        .ssa
+        .register(FnPass::new(
+            "structure method calls",
+            |ssa: &mut SsaFunction| ssa.changed |= structure_method_calls(&mut ssa.function),
+        ))
+        .unwrap();
+    pipeline
+        .ssa
+        .register(FnPass::new(
+            "remove unnecessary params",
+            |ssa: &mut SsaFunction| {
+                let mut local_map = FxHashMap::default();
+                // TODO: loop until returns false?
+                ssa.changed |=
+                    ssa::construct::remove_unnecessary_params(&mut ssa.function, &mut local_map);
+                ssa::construct::apply_local_map(&mut ssa.function, local_map);
+            },
+        ))
+        .unwrap();
+    pipeline
+        .blocks
+        .register(FnPass::new("lower continue", lower_continue))
+        .unwrap();
+    pipeline
+        .blocks
+        .register(FnPass::new(
+            "normalize dispatch chains",
+            normalize_dispatch_chains,
+        ))
+        .unwrap();
+    pipeline
+        .blocks
+        .set_enabled("normalize dispatch chains", false)
+        .unwrap();
+    // forward_varargs only changes functions that pack `...`, which have to be vararg
+    pipeline
+        .blocks
+        .register(FnPass::new("forward varargs", forward_varargs))
+        .unwrap();
+    pipeline
+}
+
+/// Lists the instructions of every function in a Lua 5.1 chunk, see
+/// [`lua51_deserializer::disassemble`].
+pub fn disassemble_bytecode(bytecode: &[u8]) -> anyhow::Result<String> {
+    let chunk = parse_chunk(bytecode)?;
+    Ok(lua51_deserializer::disassemble::disassemble(
+        &chunk.function,
+    ))
+}
+
+/// Parses and validates a Lua 5.1 chunk without decompiling it.
+pub fn parse_chunk(bytecode: &[u8]) -> anyhow::Result<Chunk<'_>> {
+    let (_, header) =
+        Header::parse(bytecode).map_err(|e| anyhow!("failed to parse chunk header: {}", e))?;
+    header.check()?;
+    let chunk = Chunk::parse(bytecode)
+        .map_err(|e| anyhow!("failed to parse chunk: {}", e))?
+        .1;
+    chunk.function.validate()?;
+    Ok(chunk)
+}
+
+fn count_functions(function: &lua51_deserializer::Function) -> usize {
+    1 + function.closures.iter().map(count_functions).sum::<usize>()
+}
+
+fn decompile_chunk(
+    bytecode: &[u8],
+    options: StructureOptions,
+    pipeline: &Pipeline,
+    progress: &Progress,
+) -> anyhow::Result<ast::Block> {
+    pipeline.validate()?;
+    let chunk = parse_chunk(bytecode)?;
+    let total = count_functions(&chunk.function);
+    // closures are lifted iteratively, nesting is limited by the deserializer
+    let start = Instant::now();
+    let mut lifted = Vec::new();
+    let mut stack = vec![(
+        Arc::<Mutex<_>>::default(),
+        &chunk.function,
+        UpvalueContext::default(),
+    )];
+    while let Some((ast_function, bytecode, upvalue_context)) = stack.pop() {
+        progress.check()?;
+        let (function, upvalues, child_functions) = Lifter::lift(bytecode, &upvalue_context)?;
+        lifted.push((ast_function, function, upvalues));
+        progress.report("lifting", lifted.len(), total);
+        stack.extend(child_functions);
+    }
+    log::debug!(
+        "lifting {} functions took {:?}",
+        lifted.len(),
+        start.elapsed()
+    );
+
+    let (main, ..) = lifted.first().unwrap().clone();
+    let mut upvalues = lifted
+        .into_iter()
+        .enumerate()
+        .map(
+            |(index, (ast_function, mut function, upvalues_in))| -> anyhow::Result<_> {
+                progress.check()?;
+                let start = Instant::now();
+                let (local_count, local_groups, upvalue_in_groups, upvalue_passed_groups) =
+                    cfg::ssa::construct(&mut function, &upvalues_in);
+                let upvalue_to_group = upvalue_in_groups
+                    .into_iter()
+                    .chain(
+                        upvalue_passed_groups
+                            .into_iter()
+                            .map(|m| (ast::RcLocal::default(), m)),
+                    )
+                    .flat_map(|(i, g)| g.into_iter().map(move |u| (u, i.clone())))
+                    .collect::<IndexMap<_, _>>();
+                // TODO: do we even need this?
+                let local_to_group = local_groups
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(i, g)| g.into_iter().map(move |l| (l, i)))
+                    .collect::<FxHashMap<_, _>>();
+                let mut ssa = SsaFunction {
+                    function,
+                    local_to_group,
+                    upvalue_to_group,
+                    changed: true,
+                };
+                while ssa.changed {
+                    ssa.changed = false;
+                    pipeline
+                        .ssa
+                        .run_cancellable(&mut ssa, &progress.cancellation)?;
+                }
+                let SsaFunction {
+                    mut function,
+                    upvalue_to_group,
+                    ..
+                } = ssa;
+                ssa::Destructor::new(
+                    &mut function,
+                    upvalue_to_group,
+                    upvalues_in.iter().cloned().collect(),
+                    local_count,
+                )
+                .destruct();
+                log::debug!("function {}: ssa took {:?}", index, start.elapsed());
+                pipeline
+                    .functions
+                    .run_cancellable(&mut function, &progress.cancellation)?;
+
+                let params = std::mem::take(&mut function.parameters);
+                let is_variadic = function.is_variadic;
+                let start = Instant::now();
+                let block =
+                    restructure::lift_with_cancellation(function, options, &progress.cancellation)?;
+                let block = Arc::new(block.into());
+                log::debug!("function {}: structuring took {:?}", index, start.elapsed());
+                LocalDeclarer::default().declare_locals(
+                    // TODO: why does block.clone() not work?
+                    Arc::clone(&block),
+                    &upvalues_in.iter().chain(params.iter()).cloned().collect(),
+                );
+
+                {
+                    let mut ast_function = ast_function.lock();
+                    ast_function.body = Arc::try_unwrap(block).unwrap().into_inner();
+                    pipeline
+                        .blocks
+                        .run_cancellable(&mut ast_function.body, &progress.cancellation)?;
+                    ast_function.parameters = params;
+                    ast_function.is_variadic = is_variadic;
+                }
+                progress.report("decompiling", index + 1, total);
+                Ok((ByAddress(ast_function), upvalues_in))
+            },
+        )
+        .collect::<anyhow::Result<FxHashMap<_, _>>>()?;
+
+    let main = ByAddress(main);
+    upvalues.remove(&main);
+    let mut body = Arc::try_unwrap(main.0).unwrap().into_inner().body;
+    link_upvalues(&mut body, &mut upvalues);
+    name_locals(&mut body, false);
+    Ok(body)
+}
+
+fn link_upvalues(
+    body: &mut ast::Block,
+    upvalues: &mut FxHashMap<ByAddress<Arc<Mutex<ast::Function>>>, Vec<ast::RcLocal>>,
+) {
+    for stat in &mut body.0 {
+        stat.traverse_rvalues(&mut |rvalue| {
+            if let ast::RValue::Closure(closure) = rvalue {
+                let old_upvalues = upvalues.remove(&closure.function).unwrap();
+                let mut function = closure.function.lock();
+                // TODO: inefficient, try constructing a map of all up -> new up first
+                // and then call replace_locals on main body
+                let mut local_map =
+                    FxHashMap::with_capacity_and_hasher(old_upvalues.len(), Default::default());
+                for (old, new) in
+                    old_upvalues
+                        .iter()
+                        .zip(closure.upvalues.iter().map(|u| match u {
+                            ast::Upvalue::Copy(l) | ast::Upvalue::Ref(l) => l,
+                        }))
+                {
+                    // println!("{} -> {}", old, new);
+                    local_map.insert(old.clone(), new.clone());
+                }
+                link_upvalues(&mut function.body, upvalues);
+                replace_locals(&mut function.body, &local_map);
+            }
+        });
+        match stat {
+            ast::Statement::If(r#if) => {
+                link_upvalues(&mut r#if.then_block.lock(), upvalues);
+                link_upvalues(&mut r#if.else_block.lock(), upvalues);
+            }
+            ast::Statement::While(r#while) => {
+                link_upvalues(&mut r#while.block.lock(), upvalues);
+            }
+            ast::Statement::Repeat(repeat) => {
+                link_upvalues(&mut repeat.block.lock(), upvalues);
+            }
+            ast::Statement::NumericFor(numeric_for) => {
+                link_upvalues(&mut numeric_for.block.lock(), upvalues);
+            }
+            ast::Statement::GenericFor(generic_for) => {
+                link_upvalues(&mut generic_for.block.lock(), upvalues);
+            }
+            _ => {}
+        }
+    }
+}
@@ -5,6 +5,7 @@ use either::Either;
 use itertools::Itertools;
 use parking_lot::Mutex;
 use rustc_hash::FxHashMap;
+use std::ops::Range;
 
 use ast::{RcLocal, Statement};
 use cfg::function::Function;
@@ -16,42 +17,224 @@ use lua51_deserializer::{
 
 use petgraph::{stable_graph::NodeIndex, visit::EdgeRef, Direction};
 
+use thiserror::Error;
 use triomphe::Arc;
 
-pub struct Lifter<'a, 'b> {
+/// Why a function couldn't be lifted, the bytecode uses instructions in a way the Lua compiler
+/// doesn't or that isn't supported yet.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LiftError {
+    #[error("instruction at pc {0} continues outside the function")]
+    InvalidJump(usize),
+    #[error("constant {0} isn't a string")]
+    ExpectedString(u32),
+    #[error("instruction at pc {0} uses the results of a multiple return that didn't happen")]
+    MissingMultRet(usize),
+    #[error("unexpected instruction at pc {pc}: {instruction}")]
+    UnexpectedInstruction { pc: usize, instruction: String },
+    #[error("instruction at pc {pc} isn't supported: {reason}")]
+    Unsupported { pc: usize, reason: &'static str },
+}
+
+type Result<T> = std::result::Result<T, LiftError>;
+
+/// The locals a closure captures, by upvalue slot. A closure lifted with the context of the
+/// function that creates it uses the captured locals as its upvalues, so a captured variable is
+/// the same local on both sides of the closure boundary.
+#[derive(Debug, Clone, Default)]
+pub struct UpvalueContext(Vec<RcLocal>);
+
+type ChildFunctions<'a> = Vec<(
+    Arc<Mutex<ast::Function>>,
+    &'a BytecodeFunction<'a>,
+    UpvalueContext,
+)>;
+
+/// A named local from the debug info, it's held in `register` while the pc is in `range`
+struct DebugLocal {
+    register: Register,
+    range: Range<usize>,
+    local: RcLocal,
+}
+
+pub struct Lifter<'a> {
     bytecode: &'a BytecodeFunction<'a>,
     nodes: FxHashMap<usize, NodeIndex>,
     insert_between: FxHashMap<NodeIndex, (NodeIndex, Statement)>,
+    /// Copies into the debug locals whose scope starts at the first pc of a block, with the
+    /// scope of each. They only happen on the edges entering the block from outside the scope,
+    /// see `enter_scope_edges`
+    scope_entries: FxHashMap<usize, Vec<(Range<usize>, Statement)>>,
+    /// The local each register refers to at the instruction being lifted
     locals: FxHashMap<Register, RcLocal>,
+    /// The local each register refers to outside the scope of any debug local
+    register_locals: FxHashMap<Register, RcLocal>,
+    debug_locals: Vec<DebugLocal>,
     constants: FxHashMap<usize, ast::Literal>,
     function: Function,
     upvalues: Vec<RcLocal>,
-    lifted_functions: &'b mut Vec<(Arc<Mutex<ast::Function>>, Function, Vec<RcLocal>)>,
+    /// Closures created by this function, they are lifted after it
+    child_functions: ChildFunctions<'a>,
 }
 
-impl<'a, 'b> Lifter<'a, 'b> {
-    fn allocate_locals(&mut self) {
+// a local named `name`, if it can be written as an identifier
+fn named_local(name: &[u8]) -> Option<RcLocal> {
+    if name.is_empty() || !ast::formatter::Formatter::<String>::is_valid_name(name) {
+        return None;
+    }
+    let name = std::str::from_utf8(name).ok()?;
+    Some(RcLocal::new(ast::Local::new(Some(name.to_string()))))
+}
+
+impl<'a> Lifter<'a> {
+    fn allocate_locals(&mut self, upvalue_context: &UpvalueContext) {
         self.upvalues
             .reserve(self.bytecode.number_of_upvalues as usize);
-        for _ in 0..self.bytecode.number_of_upvalues {
-            self.upvalues.push(RcLocal::default());
+        for i in 0..self.bytecode.number_of_upvalues as usize {
+            // a captured local already has the name its parent gave it
+            let upvalue = upvalue_context.0.get(i).cloned().unwrap_or_else(|| {
+                let name = self.bytecode.debug_info.upvalue_name(i);
+                name.and_then(named_local).unwrap_or_default()
+            });
+            self.upvalues.push(upvalue);
+        }
+
+        for (register, local) in self.bytecode.debug_info.local_registers() {
+            let range = local.range.start as usize..local.range.end as usize;
+            if range.is_empty() || register >= self.bytecode.maximum_stack_size as usize {
+                continue;
+            }
+            if let Some(named) = named_local(local.name) {
+                self.debug_locals.push(DebugLocal {
+                    register: Register(register as u8),
+                    range,
+                    local: named,
+                });
+            }
         }
+        self.disambiguate_debug_locals();
 
-        self.locals
+        self.register_locals
             .reserve(self.bytecode.maximum_stack_size as usize);
         for i in 0..self.bytecode.maximum_stack_size {
             let local = RcLocal::default();
             if i < self.bytecode.number_of_parameters {
-                self.function.parameters.push(local.clone());
+                let parameter = self
+                    .debug_locals
+                    .iter()
+                    .find(|l| l.register == Register(i) && l.range.start == 0)
+                    .map_or_else(|| local.clone(), |l| l.local.clone());
+                self.function.parameters.push(parameter);
             }
-            self.locals.insert(Register(i), local);
+            self.register_locals.insert(Register(i), local);
         }
+        self.function.is_variadic = self.bytecode.is_vararg();
     }
 
-    // TODO: support jumps to invalid destinations
-    // including cases where there is usize::MAX instructions and the last instruction
-    // skips forward, overflowing
-    fn create_block_map(&mut self) {
+    // a register holds one local at a time, but edited debug info or other compilers can give
+    // locals of the same register overlapping ranges. the local declared later in the source,
+    // by the line of the instruction before its scope, takes the register over from the other.
+    fn disambiguate_debug_locals(&mut self) {
+        let debug_info = &self.bytecode.debug_info;
+        let declared = |local: &DebugLocal| {
+            let line = debug_info.line(local.range.start.saturating_sub(1));
+            (line, local.range.start)
+        };
+        for i in 0..self.debug_locals.len() {
+            for j in 0..self.debug_locals.len() {
+                let (earlier, later) = (&self.debug_locals[i], &self.debug_locals[j]);
+                if i == j
+                    || earlier.register != later.register
+                    || earlier.range.start >= later.range.end
+                    || later.range.start >= earlier.range.end
+                    || declared(earlier) > declared(later)
+                    || (declared(earlier) == declared(later) && i > j)
+                {
+                    continue;
+                }
+                let (start, end) = (later.range.start, later.range.end);
+                let earlier = &mut self.debug_locals[i].range;
+                if earlier.start < start {
+                    earlier.end = start;
+                } else {
+                    earlier.start = end.max(earlier.start).min(earlier.end);
+                }
+            }
+        }
+        self.debug_locals.retain(|l| !l.range.is_empty());
+    }
+
+    // the locals registers refer to at the start of a range of instructions beginning at `pc`,
+    // locals whose scope starts at `pc` are handled by `enter_scopes`
+    fn reset_locals(&mut self, pc: usize) {
+        self.locals.clone_from(&self.register_locals);
+        for debug_local in &self.debug_locals {
+            if debug_local.range.start < pc && pc < debug_local.range.end {
+                self.locals
+                    .insert(debug_local.register, debug_local.local.clone());
+            }
+        }
+    }
+
+    // ends the scopes of the debug locals that end at `pc` and starts the ones that start there,
+    // a local that starts in the middle of a function is assigned the value its register already
+    // holds. a block starting at `pc` can be a loop header, the assignment then mustn't happen on
+    // the back edges from within the scope, so it's deferred to `enter_scope_edges`
+    fn enter_scopes(&mut self, pc: usize, statements: &mut Vec<Statement>) {
+        for debug_local in &self.debug_locals {
+            if debug_local.range.end == pc {
+                self.locals.insert(
+                    debug_local.register,
+                    self.register_locals[&debug_local.register].clone(),
+                );
+            }
+        }
+        for debug_local in &self.debug_locals {
+            if debug_local.range.start == pc {
+                if pc != 0 {
+                    let copy = ast::Assign::new(
+                        vec![debug_local.local.clone().into()],
+                        vec![self.locals[&debug_local.register].clone().into()],
+                    )
+                    .into();
+                    if self.nodes.contains_key(&pc) {
+                        self.scope_entries
+                            .entry(pc)
+                            .or_default()
+                            .push((debug_local.range.clone(), copy));
+                    } else {
+                        statements.push(copy);
+                    }
+                }
+                self.locals
+                    .insert(debug_local.register, debug_local.local.clone());
+            }
+        }
+    }
+
+    // `{ n = select("#", ...), ... }`, what the VM fills the implicit `arg` local with
+    fn arg_table() -> ast::RValue {
+        let count = ast::Call::new(
+            ast::Global::from("select").into(),
+            vec![ast::Literal::from("#").into(), ast::VarArg.into()],
+        );
+        ast::Table(vec![
+            (Some(ast::Literal::from("n").into()), count.into()),
+            (None, ast::VarArg.into()),
+        ])
+        .into()
+    }
+
+    // the instruction execution continues at after skipping `skip` instructions past the one at
+    // `insn_index`
+    fn successor(&self, insn_index: usize, skip: i32) -> Result<usize> {
+        (insn_index + 1)
+            .checked_add_signed(skip as isize)
+            .filter(|&index| index < self.bytecode.code.len())
+            .ok_or(LiftError::InvalidJump(insn_index))
+    }
+
+    fn create_block_map(&mut self) -> Result<()> {
         self.nodes.insert(0, self.function.new_block());
         for (insn_index, insn) in self.bytecode.code.iter().enumerate() {
             match *insn {
@@ -59,11 +242,15 @@ impl<'a, 'b> Lifter<'a, 'b> {
                     block_number: 0, ..
                 } => {
                     // TODO: skip next instruction
-                    todo!();
+                    return Err(LiftError::Unsupported {
+                        pc: insn_index,
+                        reason: "block number is in the next instruction",
+                    });
                 }
                 Instruction::LoadBoolean {
                     skip_next: true, ..
                 } => {
+                    self.successor(insn_index, 1)?;
                     self.nodes
                         .entry(insn_index + 1)
                         .or_insert_with(|| self.function.new_block());
@@ -77,6 +264,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
                 | Instruction::Test { .. }
                 | Instruction::TestSet { .. }
                 | Instruction::IterateGenericForLoop { .. } => {
+                    self.successor(insn_index, 1)?;
                     self.nodes
                         .entry(insn_index + 1)
                         .or_insert_with(|| self.function.new_block());
@@ -85,9 +273,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
                         .or_insert_with(|| self.function.new_block());
                 }
                 Instruction::Jump(skip) => {
-                    let dest_index = (insn_index + 1)
-                        .checked_add_signed(skip.try_into().unwrap())
-                        .unwrap();
+                    let dest_index = self.successor(insn_index, skip)?;
                     self.nodes
                         .entry(dest_index)
                         .or_insert_with(|| self.function.new_block());
@@ -97,12 +283,9 @@ impl<'a, 'b> Lifter<'a, 'b> {
                 }
                 Instruction::IterateNumericForLoop { skip, .. }
                 | Instruction::InitNumericForLoop { skip, .. } => {
+                    let dest_index = self.successor(insn_index, skip)?;
                     self.nodes
-                        .entry(
-                            (insn_index + 1)
-                                .checked_add_signed(skip.try_into().unwrap())
-                                .unwrap(),
-                        )
+                        .entry(dest_index)
                         .or_insert_with(|| self.function.new_block());
                     self.nodes
                         .entry(insn_index + 1)
@@ -116,6 +299,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
                 _ => {}
             }
         }
+        Ok(())
     }
 
     fn code_ranges(&self) -> Vec<(usize, usize)> {
@@ -137,12 +321,19 @@ impl<'a, 'b> Lifter<'a, 'b> {
                     Value::Nil => ast::Literal::Nil,
                     Value::Boolean(v) => ast::Literal::Boolean(*v),
                     Value::Number(v) => ast::Literal::Number(*v),
-                    Value::String(v) => ast::Literal::String(v.to_vec()),
+                    Value::String(v) => ast::Literal::from(*v),
                 },
             )
             .clone()
     }
 
+    fn constant_string(&mut self, constant: Constant) -> Result<Vec<u8>> {
+        self.constant(constant)
+            .into_string()
+            .map(|string| string.to_vec())
+            .map_err(|_| LiftError::ExpectedString(constant.0))
+    }
+
     fn register_or_constant(&mut self, value: RegisterOrConstant) -> ast::RValue {
         match value.0 {
             Either::Left(register) => self.locals[&register].clone().into(),
@@ -151,14 +342,32 @@ impl<'a, 'b> Lifter<'a, 'b> {
     }
 
     // TODO: rename to one of: lift_instructions, lift_range, lift_instruction_range, lift_block?
-    fn lift_instruction(&mut self, start: usize, end: usize, statements: &mut Vec<Statement>) {
+    fn lift_instruction(
+        &mut self,
+        start: usize,
+        end: usize,
+        statements: &mut Vec<Statement>,
+    ) -> Result<()> {
         if end > start {
             statements.reserve(end - start + 1);
         }
         let mut top: Option<(ast::RValue, u8)> = None;
+        self.reset_locals(start);
+        // instructions consumed by the one before them are skipped, but scopes can still change there
+        let mut scope_pc = start;
         // TODO: we should consume the instructions, reducing clones
-        let mut iter = self.bytecode.code[start..=end].iter();
-        while let Some(instruction) = iter.next() {
+        let mut iter = self.bytecode.code[start..=end].iter().enumerate();
+        while let Some((index, instruction)) = iter.next() {
+            let pc = start + index;
+            while scope_pc <= pc {
+                self.enter_scopes(scope_pc, statements);
+                scope_pc += 1;
+            }
+            let statement_count = statements.len();
+            let unexpected = || LiftError::UnexpectedInstruction {
+                pc,
+                instruction: format!("{:?}", instruction),
+            };
             match instruction {
                 Instruction::Move {
                     destination,
@@ -210,7 +419,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
                     destination,
                     global,
                 } => {
-                    let global_str = self.constant(global).as_string().unwrap().clone();
+                    let global_str = self.constant_string(global)?;
                     statements.push(
                         ast::Assign::new(
                             vec![self.locals[&destination].clone().into()],
@@ -220,7 +429,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
                     );
                 }
                 &Instruction::SetGlobal { destination, value } => {
-                    let global_str = self.constant(destination).as_string().unwrap().clone();
+                    let global_str = self.constant_string(destination)?;
                     statements.push(
                         ast::Assign::new(
                             vec![ast::Global::new(global_str).into()],
@@ -312,7 +521,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
                             .map(|r| self.locals[&Register(r)].clone().into())
                             .collect()
                     } else {
-                        let (tail, end) = top.take().unwrap();
+                        let (tail, end) = top.take().ok_or(LiftError::MissingMultRet(pc))?;
                         (values.0..end)
                             .map(|r| self.locals[&Register(r)].clone().into())
                             .chain(std::iter::once(tail))
@@ -320,7 +529,11 @@ impl<'a, 'b> Lifter<'a, 'b> {
                     };
                     statements.push(ast::Return::new(values).into());
                 }
-                Instruction::Jump(..) => {}
+                &Instruction::Jump(skip) => {
+                    if let Some(for_init) = self.generic_for_init(pc, skip) {
+                        statements.push(for_init.into());
+                    }
+                }
                 &Instruction::Add {
                     destination,
                     lhs,
@@ -376,7 +589,9 @@ impl<'a, 'b> Lifter<'a, 'b> {
                     destination,
                     operands,
                 } => {
-                    assert!(operands.len() >= 2);
+                    if operands.len() < 2 {
+                        return Err(unexpected());
+                    }
                     let mut operands = operands.into_iter().rev();
 
                     let right = operands.next().unwrap();
@@ -514,7 +729,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
                             .map(|r| self.locals[&Register(r)].clone().into())
                             .collect()
                     } else {
-                        let top = top.take().unwrap();
+                        let top = top.take().ok_or(LiftError::MissingMultRet(pc))?;
                         (function.0 + 1..top.1)
                             .map(|r| self.locals[&Register(r)].clone().into())
                             .chain(std::iter::once(top.0))
@@ -592,25 +807,33 @@ impl<'a, 'b> Lifter<'a, 'b> {
 
                     let mut upvalues_passed = Vec::with_capacity(closure.number_of_upvalues.into());
                     for _ in 0..closure.number_of_upvalues {
-                        let local = match iter.next().as_ref().unwrap() {
-                            Instruction::Move {
-                                destination: _,
-                                source,
-                            } => self.locals[source].clone(),
-                            Instruction::GetUpvalue {
-                                destination: _,
-                                upvalue,
-                            } => self.upvalues[upvalue.0 as usize].clone(),
-                            _ => panic!(),
+                        let local = match iter.next() {
+                            Some((
+                                _,
+                                Instruction::Move {
+                                    destination: _,
+                                    source,
+                                },
+                            )) => self.locals[source].clone(),
+                            Some((
+                                _,
+                                Instruction::GetUpvalue {
+                                    destination: _,
+                                    upvalue,
+                                },
+                            )) => self.upvalues[upvalue.0 as usize].clone(),
+                            _ => return Err(unexpected()),
                         };
                         upvalues_passed.push(local);
                     }
 
                     let ast_function = Arc::<Mutex<_>>::default();
 
-                    let (function, upvalues) = Lifter::lift(closure, self.lifted_functions);
-                    self.lifted_functions
-                        .push((ast_function.clone(), function, upvalues));
+                    self.child_functions.push((
+                        ast_function.clone(),
+                        closure,
+                        UpvalueContext(upvalues_passed.clone()),
+                    ));
 
                     statements.push(
                         ast::Assign::new(
@@ -653,7 +876,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
                             None,
                         )
                     } else {
-                        let top = top.take().unwrap();
+                        let top = top.take().ok_or(LiftError::MissingMultRet(pc))?;
                         ast::SetList::new(
                             self.locals[&table].clone(),
                             (block_number - 1) as usize * FIELDS_PER_FLUSH + 1,
@@ -731,64 +954,50 @@ impl<'a, 'b> Lifter<'a, 'b> {
                 Instruction::IterateGenericForLoop {
                     generator,
                     state,
-                    internal_control,
                     vars,
+                    ..
                 } => {
                     let generator = self.locals[generator].clone();
                     let state = self.locals[state].clone();
-                    let internal_control = self.locals[internal_control].clone();
-                    let vars = vars
-                        .iter()
-                        .map(|x| self.locals[x].clone())
-                        .collect::<Vec<_>>();
-                    let control = vars[0].clone();
-                    statements.push(
-                        ast::Assign::new(
-                            vars.into_iter().map(|l| l.into()).collect(),
-                            vec![ast::Call::new(
-                                generator.clone().into(),
-                                vec![state.clone().into(), internal_control.clone().into()],
-                            )
-                            .into()],
-                        )
-                        .into(),
-                    );
-                    statements.push(
-                        ast::If::new(
-                            ast::Binary::new(
-                                control.clone().into(),
-                                ast::Literal::Nil.into(),
-                                ast::BinaryOperation::NotEqual,
-                            )
-                            .into(),
-                            ast::Block::default(),
-                            ast::Block::default(),
-                        )
-                        .into(),
-                    );
-
-                    let body_node = self.get_node(&(end + 1));
-                    assert!(self
-                        .insert_between
-                        .insert(
-                            self.nodes[&start],
-                            (
-                                body_node,
-                                ast::Assign::new(
-                                    vec![internal_control.clone().into()],
-                                    vec![control.clone().into()],
-                                )
-                                .into()
-                            )
-                        )
-                        .is_none());
+                    let vars = vars.iter().map(|x| self.locals[x].clone()).collect();
+                    statements.push(ast::GenericForNext::new(vars, generator.into(), state).into());
                 }
             }
 
+            // the MOVE and GETUPVAL pseudo instructions after a CLOSURE are part of its span
+            let pc_end = iter.clone().next().map_or(end, |(i, _)| start + i - 1);
+            for statement in &mut statements[statement_count..] {
+                statement.extend_span(Some(ast::Span::new(pc, pc_end)));
+            }
+
             if matches!(instruction, Instruction::Return { .. }) {
                 break;
             }
         }
+        Ok(())
+    }
+
+    // the jump to the TFORLOOP at the end of a generic for loop that starts it, which is
+    // followed by a jump back to the instruction after it
+    fn generic_for_init(&self, pc: usize, skip: i32) -> Option<ast::GenericForInit> {
+        let target = (pc + 1).checked_add_signed(skip as isize)?;
+        match self.bytecode.code.get(target..target + 2)? {
+            [Instruction::IterateGenericForLoop {
+                generator,
+                state,
+                internal_control,
+                ..
+            }, Instruction::Jump(back)]
+                if (target + 2).checked_add_signed(*back as isize) == Some(pc + 1) =>
+            {
+                Some(ast::GenericForInit::new(
+                    self.locals[generator].clone(),
+                    self.locals[state].clone(),
+                    self.locals[internal_control].clone(),
+                ))
+            }
+            _ => None,
+        }
     }
 
     // TODO: REFACTOR: this function doesnt need to exist
@@ -796,7 +1005,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
         self.nodes[index]
     }
 
-    fn lift_blocks(&mut self) {
+    fn lift_blocks(&mut self) -> Result<()> {
         let ranges = self.code_ranges();
         for (start, end) in ranges {
             // TODO: gotta be a better way
@@ -804,7 +1013,7 @@ impl<'a, 'b> Lifter<'a, 'b> {
             // see: IterateNumericForLoop
             let mut statements =
                 std::mem::take(self.function.block_mut(self.nodes[&start]).unwrap());
-            self.lift_instruction(start, end, &mut statements);
+            self.lift_instruction(start, end, &mut statements)?;
             *self.function.block_mut(self.nodes[&start]).unwrap() = statements;
 
             match self.bytecode.code[end] {
@@ -872,40 +1081,126 @@ impl<'a, 'b> Lifter<'a, 'b> {
                 }
             }
         }
+        Ok(())
+    }
+
+    // places the copies into the locals whose scope starts at a block on the edges entering it
+    // from outside the scope. an edge from within the scope is the back edge of a loop the local
+    // is declared before, its value is already in the local. the back edge of a for loop comes
+    // from the loop instruction after the scope of the counter, which is copied again.
+    fn enter_scope_edges(&mut self) {
+        let block_ends = self
+            .code_ranges()
+            .into_iter()
+            .map(|(start, end)| (self.nodes[&start], end))
+            .collect::<FxHashMap<_, _>>();
+        for (pc, copies) in std::mem::take(&mut self.scope_entries) {
+            let node = self.nodes[&pc];
+            let edges = self
+                .function
+                .graph()
+                .edges_directed(node, Direction::Incoming)
+                .map(|e| {
+                    let end = block_ends[&e.source()];
+                    let entering = copies
+                        .iter()
+                        .positions(|(range, _)| !range.contains(&end))
+                        .collect::<Vec<_>>();
+                    (e.id(), e.source(), entering)
+                })
+                .collect::<Vec<_>>();
+            if edges
+                .iter()
+                .all(|(.., entering)| entering.len() == copies.len())
+            {
+                let copies = copies.into_iter().map(|(_, copy)| copy);
+                self.function.block_mut(node).unwrap().splice(0..0, copies);
+                continue;
+            }
+            let mut entry_nodes = FxHashMap::default();
+            for (edge, source, entering) in edges {
+                if entering.is_empty() {
+                    continue;
+                }
+                let entry_node = *entry_nodes.entry(entering.clone()).or_insert_with(|| {
+                    let entry_node = self.function.new_block();
+                    self.function
+                        .block_mut(entry_node)
+                        .unwrap()
+                        .extend(entering.iter().map(|&i| copies[i].1.clone()));
+                    self.function.set_edges(
+                        entry_node,
+                        vec![(node, BlockEdge::new(BranchType::Unconditional))],
+                    );
+                    entry_node
+                });
+                let edge = self.function.graph_mut().remove_edge(edge).unwrap();
+                self.function.graph_mut().add_edge(source, entry_node, edge);
+            }
+        }
     }
 
     pub fn lift(
-        bytecode: &'a BytecodeFunction,
-        lifted_functions: &'b mut Vec<(Arc<Mutex<ast::Function>>, Function, Vec<RcLocal>)>,
-    ) -> (Function, Vec<RcLocal>) {
+        bytecode: &'a BytecodeFunction<'a>,
+        upvalue_context: &UpvalueContext,
+    ) -> Result<(Function, Vec<RcLocal>, ChildFunctions<'a>)> {
         let mut context = Self {
             bytecode,
             nodes: FxHashMap::default(),
             insert_between: FxHashMap::default(),
+            scope_entries: FxHashMap::default(),
             locals: FxHashMap::default(),
+            register_locals: FxHashMap::default(),
+            debug_locals: Vec::new(),
             constants: FxHashMap::default(),
             function: Function::new(0),
             upvalues: Vec::new(),
-            lifted_functions,
+            child_functions: Vec::new(),
         };
 
-        context.create_block_map();
-        context.allocate_locals();
-        context.lift_blocks();
+        context.create_block_map()?;
+        context.allocate_locals(upvalue_context);
+        context.lift_blocks()?;
+        context.enter_scope_edges();
 
         // TODO: STYLE: instead of naming NodeIndex vars `{}_node`, we should name them
         // `{}_index`, or if it's the corresponding var for `block`, `block_index`
         let stack_init_node = context.function.new_block();
-        let stack_init_block = context.function.block_mut(stack_init_node).unwrap();
-        stack_init_block.reserve(context.locals.len());
-        for (_, local) in context.locals {
-            if !context.function.parameters.contains(&local) {
-                let stack_init_block = context.function.block_mut(stack_init_node).unwrap();
-                stack_init_block.push(
-                    ast::Assign::new(vec![local.into()], vec![ast::Literal::Nil.into()]).into(),
+        let arg = bytecode
+            .needs_arg()
+            .then_some(Register(bytecode.number_of_parameters));
+        // registers hold nil until they are written, apart from the parameters and `arg`. a
+        // register named for the whole function is only referred to by its named local.
+        let named_at_entry = context
+            .debug_locals
+            .iter()
+            .filter(|l| l.range.start == 0)
+            .map(|l| (l.register, &l.local))
+            .collect::<FxHashMap<_, _>>();
+        let stack_init = context
+            .register_locals
+            .iter()
+            .map(|(register, local)| {
+                (
+                    *register,
+                    named_at_entry.get(register).copied().unwrap_or(local),
                 )
-            }
-        }
+            })
+            .filter(|(_, local)| !context.function.parameters.contains(local))
+            .map(|(register, local)| {
+                let value = if Some(register) == arg {
+                    Self::arg_table()
+                } else {
+                    ast::Literal::Nil.into()
+                };
+                ast::Assign::new(vec![local.clone().into()], vec![value]).into()
+            })
+            .collect::<Vec<_>>();
+        context
+            .function
+            .block_mut(stack_init_node)
+            .unwrap()
+            .extend(stack_init);
         context.function.set_edges(
             stack_init_node,
             vec![(context.nodes[&0], BlockEdge::new(BranchType::Unconditional))],
@@ -943,6 +1238,6 @@ impl<'a, 'b> Lifter<'a, 'b> {
             }
         }
 
-        (context.function, context.upvalues)
+        Ok((context.function, context.upvalues, context.child_functions))
     }
 }
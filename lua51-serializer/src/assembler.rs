@@ -0,0 +1,643 @@
+//! Assembles the listing written by [`lua51_deserializer::disassemble`] back into functions, so
+//! chunks can be patched as text and test cases for the lifter written by hand.
+//!
+//! A listing is a sequence of functions, each starting with a header like
+//! `; function 0.1 defined at line 3, 2 parameters and varargs, 1 upvalues, 5 registers`, where
+//! `0.1` is the path of closure indices from the main function `0`. Its instructions follow one
+//! per line, optionally prefixed with their pc, and labels like `L0:` mark jump targets:
+//!
+//! ```text
+//! ; function 0 defined at line 0, 0 parameters and varargs, 0 upvalues, 2 registers
+//!     0  GETGLOBAL r0, "print"
+//!     1  LOADK     r1, "hi\010"
+//!     2  CALL      r0, 2, 1
+//!     3  RETURN    r0, 1
+//! ```
+//!
+//! Constants are written by value and added to the constant table in the order they first
+//! appear. Anything after a `;` outside of a string is a comment. Debug info isn't part of the
+//! listing, so the assembled functions are stripped.
+
+use std::fmt;
+
+use either::Either;
+use lua51_deserializer::{
+    argument::{
+        Constant as ConstantIndex, Function as FunctionIndex, Register, RegisterOrConstant, Upvalue,
+    },
+    Instruction,
+};
+use rustc_hash::FxHashMap;
+
+use crate::prototype::{Constant, Prototype};
+
+// constants with a higher index can't be used as an RK operand
+const MAX_RK_CONSTANT: u32 = 255;
+// `vararg_flag` of a function that takes `...`, see `lobject.h`
+const VARARG_ISVARARG: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    /// 1-based, `None` for errors about the listing as a whole
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+type Result<T> = std::result::Result<T, String>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    String(Vec<u8>),
+    Comma,
+    Arrow,
+}
+
+// splits the operands of an instruction, stopping at a comment
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&char) = chars.peek() {
+        match char {
+            ';' => break,
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '"' => {
+                chars.next();
+                let mut string = Vec::new();
+                loop {
+                    match chars.next() {
+                        None => return Err("unterminated string".to_string()),
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(digit) if digit.is_ascii_digit() => {
+                                let mut code = digit.to_digit(10).unwrap();
+                                for _ in 0..2 {
+                                    match chars.peek().and_then(|c| c.to_digit(10)) {
+                                        Some(digit) => {
+                                            code = code * 10 + digit;
+                                            chars.next();
+                                        }
+                                        None => break,
+                                    }
+                                }
+                                string.push(
+                                    u8::try_from(code)
+                                        .map_err(|_| format!("escape \\{} is too large", code))?,
+                                );
+                            }
+                            Some('n') => string.push(b'\n'),
+                            Some('t') => string.push(b'\t'),
+                            Some('r') => string.push(b'\r'),
+                            Some(char @ ('"' | '\\' | '\'')) => string.push(char as u8),
+                            Some(char) => return Err(format!("unknown escape \\{}", char)),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(char) => {
+                            let mut buffer = [0; 4];
+                            string.extend_from_slice(char.encode_utf8(&mut buffer).as_bytes());
+                        }
+                    }
+                }
+                tokens.push(Token::String(string));
+            }
+            char if char.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&char) = chars.peek() {
+                    if char.is_whitespace() || matches!(char, ',' | ';' | '"') {
+                        break;
+                    }
+                    word.push(char);
+                    chars.next();
+                }
+                tokens.push(if word == "->" {
+                    Token::Arrow
+                } else {
+                    Token::Word(word)
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn number<T: std::str::FromStr>(word: &str, what: &str) -> Result<T> {
+    word.parse()
+        .map_err(|_| format!("expected {}, found {}", what, word))
+}
+
+fn prefixed<T: std::str::FromStr>(word: &str, prefix: char, what: &str) -> Result<T> {
+    word.strip_prefix(prefix)
+        .ok_or_else(|| format!("expected {}, found {}", what, word))
+        .and_then(|index| number(index, what))
+}
+
+// the register `offset` after `register`, which instructions use implicitly
+fn following_register(register: u8, offset: u8) -> Result<u8> {
+    register.checked_add(offset).ok_or_else(|| {
+        format!(
+            "register r{} is out of range",
+            register as usize + offset as usize
+        )
+    })
+}
+
+#[derive(Default)]
+struct FunctionBuilder {
+    prototype: Prototype,
+    labels: FxHashMap<String, usize>,
+    // instructions whose jump offset is filled in once every label is known
+    jumps: Vec<(usize, String, usize)>,
+}
+
+impl FunctionBuilder {
+    fn constant(&mut self, constant: Constant) -> ConstantIndex {
+        let index = self
+            .prototype
+            .constants
+            .iter()
+            .position(|c| match (c, &constant) {
+                // compared by bits so 0 and -0 stay distinct
+                (Constant::Number(a), Constant::Number(b)) => a.to_bits() == b.to_bits(),
+                (a, b) => a == b,
+            })
+            .unwrap_or_else(|| {
+                self.prototype.constants.push(constant);
+                self.prototype.constants.len() - 1
+            });
+        ConstantIndex(index as u32)
+    }
+
+    fn instruction(&mut self, line: usize, text: &str) -> Result<()> {
+        let mut words = text.split_whitespace();
+        let mut name = words.next().unwrap();
+        // the pc is only there for the reader
+        if name.bytes().all(|b| b.is_ascii_digit()) {
+            name = words.next().ok_or("expected an instruction")?;
+        }
+        let operands_start = text.find(name).unwrap() + name.len();
+        let mut tokens = tokenize(&text[operands_start..])?;
+        let label = match tokens.iter().position(|t| *t == Token::Arrow) {
+            Some(arrow) => match tokens.split_off(arrow).as_slice() {
+                [Token::Arrow, Token::Word(label)] => Some(label.clone()),
+                _ => return Err("expected a label after ->".to_string()),
+            },
+            None => None,
+        };
+        let mut operands = Vec::new();
+        for (i, token) in tokens.into_iter().enumerate() {
+            match (i % 2, token) {
+                (0, Token::Comma) => return Err("expected an operand".to_string()),
+                (0, token) => operands.push(token),
+                (_, Token::Comma) => {}
+                _ => return Err("expected a comma between operands".to_string()),
+            }
+        }
+        let instruction = Operands {
+            builder: self,
+            operands,
+            next: 0,
+        }
+        .instruction(name)?;
+        let pc = self.prototype.code.len();
+        match (&instruction, label) {
+            (
+                Instruction::Jump(_)
+                | Instruction::IterateNumericForLoop { .. }
+                | Instruction::InitNumericForLoop { .. },
+                Some(label),
+            ) => self.jumps.push((pc, label, line)),
+            (Instruction::Jump(_), None) => return Err("expected a label to jump to".to_string()),
+            // the target of LOADBOOL is given by its operands
+            _ => {}
+        }
+        self.prototype.code.push(instruction);
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::result::Result<Prototype, AssembleError> {
+        for (pc, label, line) in self.jumps {
+            let target = *self.labels.get(&label).ok_or_else(|| AssembleError {
+                line: Some(line),
+                message: format!("unknown label {}", label),
+            })?;
+            let offset = target as i32 - pc as i32 - 1;
+            match &mut self.prototype.code[pc] {
+                Instruction::Jump(skip)
+                | Instruction::IterateNumericForLoop { skip, .. }
+                | Instruction::InitNumericForLoop { skip, .. } => *skip = offset,
+                _ => unreachable!(),
+            }
+        }
+        Ok(self.prototype)
+    }
+}
+
+struct Operands<'a> {
+    builder: &'a mut FunctionBuilder,
+    operands: Vec<Token>,
+    next: usize,
+}
+
+impl Operands<'_> {
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .operands
+            .get(self.next)
+            .cloned()
+            .ok_or("missing operand")?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn word(&mut self, what: &str) -> Result<String> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            _ => Err(format!("expected {}, found a string", what)),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        number(&self.word("a number")?, "a number")
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(format!("expected 0 or 1, found {}", value)),
+        }
+    }
+
+    fn register(&mut self) -> Result<Register> {
+        prefixed(&self.word("a register")?, 'r', "a register").map(Register)
+    }
+
+    fn registers(&mut self) -> Result<(u8, u8)> {
+        let word = self.word("registers")?;
+        match word.split_once("..") {
+            Some((first, last)) => Ok((
+                prefixed(first, 'r', "a register")?,
+                prefixed(last, 'r', "a register")?,
+            )),
+            None => {
+                let register = prefixed(&word, 'r', "a register")?;
+                Ok((register, register))
+            }
+        }
+    }
+
+    fn constant(&mut self) -> Result<ConstantIndex> {
+        let constant = match self.next()? {
+            Token::String(string) => Constant::String(string),
+            Token::Word(word) => match word.as_str() {
+                "nil" => Constant::Nil,
+                "true" => Constant::Boolean(true),
+                "false" => Constant::Boolean(false),
+                _ => Constant::Number(number(&word, "a constant")?),
+            },
+            _ => unreachable!(),
+        };
+        Ok(self.builder.constant(constant))
+    }
+
+    fn register_or_constant(&mut self) -> Result<RegisterOrConstant> {
+        if matches!(self.operands.get(self.next), Some(Token::Word(word)) if word.starts_with('r'))
+        {
+            return Ok(RegisterOrConstant(Either::Left(self.register()?)));
+        }
+        let constant = self.constant()?;
+        if constant.0 > MAX_RK_CONSTANT {
+            return Err("too many constants to use one as an operand".to_string());
+        }
+        Ok(RegisterOrConstant(Either::Right(constant)))
+    }
+
+    fn upvalue(&mut self) -> Result<Upvalue> {
+        prefixed(&self.word("an upvalue")?, 'u', "an upvalue").map(Upvalue)
+    }
+
+    fn instruction(mut self, name: &str) -> Result<Instruction> {
+        let instruction = match name {
+            "MOVE" => Instruction::Move {
+                destination: self.register()?,
+                source: self.register()?,
+            },
+            "LOADK" => Instruction::LoadConstant {
+                destination: self.register()?,
+                source: self.constant()?,
+            },
+            "LOADBOOL" => Instruction::LoadBoolean {
+                destination: self.register()?,
+                value: match self.word("a boolean")?.as_str() {
+                    "true" => true,
+                    "false" => false,
+                    word => return Err(format!("expected a boolean, found {}", word)),
+                },
+                skip_next: self.bool()?,
+            },
+            "LOADNIL" => {
+                let (first, last) = self.registers()?;
+                Instruction::LoadNil((first..=last).map(Register).collect())
+            }
+            "GETUPVAL" => Instruction::GetUpvalue {
+                destination: self.register()?,
+                upvalue: self.upvalue()?,
+            },
+            "GETGLOBAL" => Instruction::GetGlobal {
+                destination: self.register()?,
+                global: self.constant()?,
+            },
+            "GETTABLE" => Instruction::GetIndex {
+                destination: self.register()?,
+                object: self.register()?,
+                key: self.register_or_constant()?,
+            },
+            "SETGLOBAL" => {
+                let value = self.register()?;
+                Instruction::SetGlobal {
+                    destination: self.constant()?,
+                    value,
+                }
+            }
+            "SETUPVAL" => {
+                let source = self.register()?;
+                Instruction::SetUpvalue {
+                    destination: self.upvalue()?,
+                    source,
+                }
+            }
+            "SETTABLE" => Instruction::SetIndex {
+                object: self.register()?,
+                key: self.register_or_constant()?,
+                value: self.register_or_constant()?,
+            },
+            "NEWTABLE" => Instruction::NewTable {
+                destination: self.register()?,
+                array_size: self.u8()?,
+                hash_size: self.u8()?,
+            },
+            "SELF" => {
+                let destination = self.register()?;
+                Instruction::PrepMethodCall {
+                    destination,
+                    self_arg: Register(following_register(destination.0, 1)?),
+                    object: self.register()?,
+                    method: self.register_or_constant()?,
+                }
+            }
+            "ADD" | "SUB" | "MUL" | "DIV" | "MOD" | "POW" => {
+                let destination = self.register()?;
+                let lhs = self.register_or_constant()?;
+                let rhs = self.register_or_constant()?;
+                match name {
+                    "ADD" => Instruction::Add {
+                        destination,
+                        lhs,
+                        rhs,
+                    },
+                    "SUB" => Instruction::Sub {
+                        destination,
+                        lhs,
+                        rhs,
+                    },
+                    "MUL" => Instruction::Mul {
+                        destination,
+                        lhs,
+                        rhs,
+                    },
+                    "DIV" => Instruction::Div {
+                        destination,
+                        lhs,
+                        rhs,
+                    },
+                    "MOD" => Instruction::Mod {
+                        destination,
+                        lhs,
+                        rhs,
+                    },
+                    _ => Instruction::Pow {
+                        destination,
+                        lhs,
+                        rhs,
+                    },
+                }
+            }
+            "UNM" => Instruction::Minus {
+                destination: self.register()?,
+                operand: self.register()?,
+            },
+            "NOT" => Instruction::Not {
+                destination: self.register()?,
+                operand: self.register()?,
+            },
+            "LEN" => Instruction::Length {
+                destination: self.register()?,
+                operand: self.register()?,
+            },
+            "CONCAT" => {
+                let destination = self.register()?;
+                let (first, last) = self.registers()?;
+                Instruction::Concatenate {
+                    destination,
+                    operands: (first..=last).map(Register).collect(),
+                }
+            }
+            "JMP" => Instruction::Jump(0),
+            "EQ" | "LT" | "LE" => {
+                let invert = !self.bool()?;
+                let lhs = self.register_or_constant()?;
+                let rhs = self.register_or_constant()?;
+                match name {
+                    "EQ" => Instruction::Equal { lhs, rhs, invert },
+                    "LT" => Instruction::LessThan { lhs, rhs, invert },
+                    _ => Instruction::LessThanOrEqual { lhs, rhs, invert },
+                }
+            }
+            "TEST" => Instruction::Test {
+                value: self.register()?,
+                invert: !self.bool()?,
+            },
+            "TESTSET" => Instruction::TestSet {
+                destination: self.register()?,
+                value: self.register()?,
+                invert: !self.bool()?,
+            },
+            "CALL" => Instruction::Call {
+                function: self.register()?,
+                arguments: self.u8()?,
+                return_values: self.u8()?,
+            },
+            "TAILCALL" => Instruction::TailCall {
+                function: self.register()?,
+                arguments: self.u8()?,
+            },
+            "RETURN" => Instruction::Return(self.register()?, self.u8()?),
+            "FORLOOP" | "FORPREP" => {
+                let (first, _) = self.registers()?;
+                let control = (first..=following_register(first, 4)?)
+                    .map(Register)
+                    .collect();
+                if name == "FORLOOP" {
+                    Instruction::IterateNumericForLoop { control, skip: 0 }
+                } else {
+                    Instruction::InitNumericForLoop { control, skip: 0 }
+                }
+            }
+            "TFORLOOP" => {
+                let generator = self.register()?.0;
+                let (first, last) = self.registers()?;
+                if first != following_register(generator, 3)? || last < first {
+                    return Err("the variables of TFORLOOP must follow its state".to_string());
+                }
+                Instruction::IterateGenericForLoop {
+                    generator: Register(generator),
+                    state: Register(following_register(generator, 1)?),
+                    internal_control: Register(following_register(generator, 2)?),
+                    vars: (first..=last).map(Register).collect(),
+                }
+            }
+            "SETLIST" => Instruction::SetList {
+                table: self.register()?,
+                number_of_elements: self.u8()?,
+                block_number: self.u8()?,
+            },
+            "CLOSE" => Instruction::Close(self.register()?),
+            "CLOSURE" => Instruction::Closure {
+                destination: self.register()?,
+                function: FunctionIndex(prefixed(&self.word("a function")?, 'f', "a function")?),
+            },
+            "VARARG" => Instruction::VarArg(self.register()?, self.u8()?),
+            _ => return Err(format!("unknown instruction {}", name)),
+        };
+        if self.next != self.operands.len() {
+            return Err(format!("too many operands for {}", name));
+        }
+        Ok(instruction)
+    }
+}
+
+// the path and prototype of a function from its header, see the module documentation
+fn parse_header(header: &str) -> Result<(Vec<usize>, Prototype)> {
+    let mut parts = header.split(", ");
+    let first = parts.next().unwrap();
+    let (path, line_defined) = first
+        .split_once(" defined at line ")
+        .ok_or("expected a path and the line the function is defined at")?;
+    let path = path
+        .split('.')
+        .map(|index| number(index, "a path of closure indices"))
+        .collect::<Result<Vec<_>>>()?;
+    let mut prototype = Prototype {
+        line_defined: number(line_defined, "a line")?,
+        ..Default::default()
+    };
+    for part in parts {
+        let (count, what) = part
+            .split_once(' ')
+            .ok_or_else(|| format!("expected a count, found {}", part))?;
+        let count = number(count, "a count")?;
+        match what {
+            "parameters" => prototype.number_of_parameters = count,
+            "parameters and varargs" => {
+                prototype.number_of_parameters = count;
+                prototype.vararg_flag = VARARG_ISVARARG;
+            }
+            "upvalues" => prototype.number_of_upvalues = count,
+            "registers" => prototype.maximum_stack_size = count,
+            _ => return Err(format!("unknown function property {}", what)),
+        }
+    }
+    Ok((path, prototype))
+}
+
+// moves the closures of the function at `path` into it, they must be numbered from 0
+fn nest(path: &mut Vec<usize>, functions: &mut FxHashMap<Vec<usize>, Prototype>) -> Prototype {
+    let mut prototype = functions.remove(path).unwrap();
+    path.push(0);
+    while functions.contains_key(path) {
+        prototype.closures.push(nest(path, functions));
+        *path.last_mut().unwrap() += 1;
+    }
+    path.pop();
+    prototype
+}
+
+/// Assembles a listing into the main function of a chunk, see the module documentation for the
+/// format. Serialize it with [`serialize`](crate::serialize).
+pub fn assemble(listing: &str) -> std::result::Result<Prototype, AssembleError> {
+    let mut functions = FxHashMap::default();
+    let mut current: Option<(Vec<usize>, FunctionBuilder)> = None;
+    let mut finish = |current: Option<(Vec<usize>, FunctionBuilder)>| {
+        if let Some((path, builder)) = current {
+            functions.insert(path, builder.finish()?);
+        }
+        Ok(())
+    };
+    for (index, text) in listing.lines().enumerate() {
+        let line = index + 1;
+        let error = |message| AssembleError {
+            line: Some(line),
+            message,
+        };
+        let text = text.trim();
+        if let Some(header) = text.strip_prefix("; function ") {
+            finish(current.take())?;
+            let (path, prototype) = parse_header(header).map_err(error)?;
+            current = Some((
+                path,
+                FunctionBuilder {
+                    prototype,
+                    ..Default::default()
+                },
+            ));
+            continue;
+        }
+        if text.is_empty() || text.starts_with(';') {
+            continue;
+        }
+        let (_, builder) = current
+            .as_mut()
+            .ok_or_else(|| error("expected a function header".to_string()))?;
+        if let Some(label) = text.strip_suffix(':') {
+            let pc = builder.prototype.code.len();
+            if builder.labels.insert(label.to_string(), pc).is_some() {
+                return Err(error(format!("label {} is defined twice", label)));
+            }
+            continue;
+        }
+        builder.instruction(line, text).map_err(error)?;
+    }
+    finish(current)?;
+
+    let error = |message: &str| AssembleError {
+        line: None,
+        message: message.to_string(),
+    };
+    if !functions.contains_key(&vec![0]) {
+        return Err(error("the listing has no main function 0"));
+    }
+    let main = nest(&mut vec![0], &mut functions);
+    if !functions.is_empty() {
+        return Err(error(
+            "a function's parent is missing or its siblings aren't numbered from 0",
+        ));
+    }
+    Ok(main)
+}
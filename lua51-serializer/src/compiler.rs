@@ -0,0 +1,1400 @@
+use std::fmt;
+
+use ast::{
+    Assign, BinaryOperation, Block, Call, Closure, Environment, Global, Index, LValue, Literal,
+    MethodCall, RValue, RcLocal, Select, Statement, Table, UnaryOperation,
+};
+use either::Either;
+use lua51_deserializer::{
+    argument::{
+        Constant as ConstantIndex, Function as FunctionIndex, Register, RegisterOrConstant, Upvalue,
+    },
+    Instruction,
+};
+use rustc_hash::FxHashMap;
+
+use crate::prototype::{Constant, Prototype};
+
+// MAXSTACK in llimits.h
+const MAX_REGISTERS: usize = 250;
+// LUAI_MAXUPVALUES in luaconf.h
+const MAX_UPVALUES: usize = 60;
+// constants with a higher index can't be used as an RK operand
+const MAX_RK_CONSTANT: u32 = 255;
+const MAX_CONSTANTS: u32 = (1 << 18) - 1;
+const MAX_JUMP: i64 = ((1 << 18) - 1) >> 1;
+// LFIELDS_PER_FLUSH in lopcodes.h
+const FIELDS_PER_FLUSH: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// A construct that can't be expressed in Lua 5.1 or is only part of the AST during lifting
+    Unsupported(&'static str),
+    /// A local that isn't declared in the function or any function it's nested in
+    UndeclaredLocal(String),
+    UnknownLabel(String),
+    TooManyRegisters,
+    TooManyConstants,
+    TooManyUpvalues,
+    TooManyTableItems,
+    JumpTooFar,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(what) => write!(f, "{} can't be compiled to Lua 5.1", what),
+            Self::UndeclaredLocal(local) => {
+                write!(f, "local {} is used before it's declared", local)
+            }
+            Self::UnknownLabel(label) => write!(f, "goto to unknown label {}", label),
+            Self::TooManyRegisters => {
+                write!(f, "function needs more than {} registers", MAX_REGISTERS)
+            }
+            Self::TooManyConstants => {
+                write!(f, "function has more than {} constants", MAX_CONSTANTS)
+            }
+            Self::TooManyUpvalues => write!(f, "function has more than {} upvalues", MAX_UPVALUES),
+            Self::TooManyTableItems => write!(f, "table constructor has too many items"),
+            Self::JumpTooFar => write!(f, "jump offset doesn't fit in an instruction"),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+#[derive(Debug, Clone, Copy)]
+enum Variable {
+    Register(u8),
+    Upvalue(u8),
+}
+
+// what a closure captures, emitted after the closure instruction
+#[derive(Debug, Clone, Copy)]
+enum UpvalueSource {
+    Register(u8),
+    Upvalue(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Nil,
+    Boolean(bool),
+    // by bits, so -0 and NaN get their own constants
+    Number(u64),
+    String(Vec<u8>),
+}
+
+impl From<&Constant> for ConstantKey {
+    fn from(constant: &Constant) -> Self {
+        match constant {
+            Constant::Nil => Self::Nil,
+            Constant::Boolean(value) => Self::Boolean(*value),
+            Constant::Number(value) => Self::Number(value.to_bits()),
+            Constant::String(value) => Self::String(value.clone()),
+        }
+    }
+}
+
+// values that can be adjusted to any number of results
+#[derive(Clone, Copy)]
+enum Multiple<'a> {
+    Call(&'a Call),
+    MethodCall(&'a MethodCall),
+    VarArg,
+}
+
+fn multiple(rvalue: &RValue) -> Option<Multiple<'_>> {
+    match rvalue {
+        RValue::Call(call) | RValue::Select(Select::Call(call)) => Some(Multiple::Call(call)),
+        RValue::MethodCall(method_call) | RValue::Select(Select::MethodCall(method_call)) => {
+            Some(Multiple::MethodCall(method_call))
+        }
+        RValue::VarArg(_) | RValue::Select(Select::VarArg(_)) => Some(Multiple::VarArg),
+        _ => None,
+    }
+}
+
+// `and`, `or` and tables write to their target before they're done reading other values
+fn writes_early(rvalue: &RValue) -> bool {
+    match rvalue {
+        RValue::Table(_) => true,
+        RValue::Binary(binary) => {
+            matches!(binary.operation, BinaryOperation::And | BinaryOperation::Or)
+        }
+        _ => false,
+    }
+}
+
+fn literal_constant(rvalue: &RValue) -> Option<Constant> {
+    match rvalue {
+        RValue::Literal(Literal::Nil) => Some(Constant::Nil),
+        RValue::Literal(Literal::Boolean(value)) => Some(Constant::Boolean(*value)),
+        RValue::Literal(Literal::Number(value)) => Some(Constant::Number(*value)),
+        RValue::Literal(Literal::String(value)) => Some(Constant::String(value.to_vec())),
+        _ => None,
+    }
+}
+
+// a global looked up in a table other than the function's environment is an index of that table
+fn environment_index(global: &Global) -> Option<Index> {
+    match &global.1 {
+        Environment::Function => None,
+        Environment::Local(local) => Some(Index::new(
+            local.clone().into(),
+            Literal::String(global.0.as_slice().into()).into(),
+        )),
+    }
+}
+
+fn environment_lvalue(lvalue: &LValue) -> Option<LValue> {
+    environment_index(lvalue.as_global()?).map(LValue::Index)
+}
+
+// luaO_int2fb, the size hints of NEWTABLE are encoded as floating point bytes
+fn int2fb(mut x: usize) -> u8 {
+    let mut e = 0;
+    while x >= 16 {
+        x = (x + 1) >> 1;
+        e += 1;
+    }
+    if x < 8 {
+        x as u8
+    } else {
+        (((e + 1) << 3) | (x - 8)) as u8
+    }
+}
+
+fn constant_operand(index: u32) -> RegisterOrConstant {
+    RegisterOrConstant(Either::Right(ConstantIndex(index)))
+}
+
+fn register_operand(register: u8) -> RegisterOrConstant {
+    RegisterOrConstant(Either::Left(Register(register)))
+}
+
+#[derive(Debug)]
+struct Scope {
+    // index of the first local declared in the scope, which is also its register
+    first: usize,
+    captured: bool,
+}
+
+#[derive(Debug)]
+struct Loop {
+    // the scope of the loop body
+    scope: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+#[derive(Debug)]
+struct Goto {
+    label: String,
+    // closes the locals that go out of scope, a no-op until the label is known
+    close: usize,
+    jump: usize,
+    active: usize,
+}
+
+#[derive(Debug, Default)]
+struct FunctionState {
+    prototype: Prototype,
+    constants: FxHashMap<ConstantKey, u32>,
+    upvalues: Vec<(RcLocal, UpvalueSource)>,
+    // locals in registers, the hidden locals of for loops are `None`
+    active: Vec<Option<RcLocal>>,
+    free: usize,
+    scopes: Vec<Scope>,
+    loops: Vec<Loop>,
+    labels: FxHashMap<String, (usize, usize)>,
+    gotos: Vec<Goto>,
+}
+
+impl FunctionState {
+    fn pc(&self) -> usize {
+        self.prototype.code.len()
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.prototype.code.push(instruction);
+        self.prototype.code.len() - 1
+    }
+
+    fn reserve(&mut self, count: usize) -> Result<u8> {
+        let register = self.free;
+        self.free += count;
+        self.check_stack(self.free)?;
+        Ok(register as u8)
+    }
+
+    fn check_stack(&mut self, size: usize) -> Result<()> {
+        if size > MAX_REGISTERS {
+            return Err(CompileError::TooManyRegisters);
+        }
+        self.prototype.maximum_stack_size = self.prototype.maximum_stack_size.max(size as u8);
+        Ok(())
+    }
+
+    fn constant(&mut self, constant: Constant) -> Result<u32> {
+        let key = ConstantKey::from(&constant);
+        if let Some(&index) = self.constants.get(&key) {
+            return Ok(index);
+        }
+        let index = self.prototype.constants.len() as u32;
+        if index > MAX_CONSTANTS {
+            return Err(CompileError::TooManyConstants);
+        }
+        self.prototype.constants.push(constant);
+        self.constants.insert(key, index);
+        Ok(index)
+    }
+
+    fn register_of(&self, local: &RcLocal) -> Option<u8> {
+        self.active
+            .iter()
+            .rposition(|l| l.as_ref() == Some(local))
+            .map(|r| r as u8)
+    }
+
+    fn activate(&mut self, local: Option<RcLocal>) {
+        self.active.push(local);
+        debug_assert!(self.active.len() <= self.free);
+    }
+
+    // marks the scope of a register as having a local that's an upvalue of a closure
+    fn capture(&mut self, register: u8) {
+        if let Some(scope) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find(|s| s.first <= register as usize)
+        {
+            scope.captured = true;
+        }
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(Scope {
+            first: self.active.len(),
+            captured: false,
+        });
+    }
+
+    fn leave_scope(&mut self, close: bool) {
+        let scope = self.scopes.pop().unwrap();
+        if close && scope.captured && self.active.len() > scope.first {
+            self.emit(Instruction::Close(Register(scope.first as u8)));
+        }
+        self.active.truncate(scope.first);
+        self.free = self.active.len();
+    }
+
+    fn jump(&mut self) -> usize {
+        self.emit(Instruction::Jump(0))
+    }
+
+    fn patch(&mut self, jump: usize, target: usize) -> Result<()> {
+        let offset = target as i64 - jump as i64 - 1;
+        if offset.abs() > MAX_JUMP {
+            return Err(CompileError::JumpTooFar);
+        }
+        match &mut self.prototype.code[jump] {
+            Instruction::Jump(skip)
+            | Instruction::InitNumericForLoop { skip, .. }
+            | Instruction::IterateNumericForLoop { skip, .. } => *skip = offset as i32,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn patch_here(&mut self, jumps: Vec<usize>) -> Result<()> {
+        let pc = self.pc();
+        jumps.into_iter().try_for_each(|j| self.patch(j, pc))
+    }
+
+    fn jump_to(&mut self, target: usize) -> Result<()> {
+        let jump = self.jump();
+        self.patch(jump, target)
+    }
+
+    fn load_nil(&mut self, first: u8, count: usize) {
+        if count != 0 {
+            self.emit(Instruction::LoadNil(
+                (first..first + count as u8).map(Register).collect(),
+            ));
+        }
+    }
+
+    // `break` and `continue` close the locals of the loop body, the scope isn't known to be
+    // captured until the whole body is compiled
+    fn leave_loop(&mut self, continues: bool) -> Result<()> {
+        let Some(r#loop) = self.loops.last() else {
+            return Err(CompileError::Unsupported(if continues {
+                "continue outside of a loop"
+            } else {
+                "break outside of a loop"
+            }));
+        };
+        let first = self.scopes[r#loop.scope].first;
+        if self.active.len() > first {
+            self.emit(Instruction::Close(Register(first as u8)));
+        }
+        let jump = self.jump();
+        let r#loop = self.loops.last_mut().unwrap();
+        if continues {
+            r#loop.continues.push(jump);
+        } else {
+            r#loop.breaks.push(jump);
+        }
+        Ok(())
+    }
+
+    fn resolve_gotos(&mut self) -> Result<()> {
+        for goto in std::mem::take(&mut self.gotos) {
+            let &(target, active) = self
+                .labels
+                .get(&goto.label)
+                .ok_or_else(|| CompileError::UnknownLabel(goto.label.clone()))?;
+            if active < goto.active {
+                self.prototype.code[goto.close] = Instruction::Close(Register(active as u8));
+            }
+            self.patch(goto.jump, target)?;
+        }
+        Ok(())
+    }
+}
+
+struct Compiler {
+    // the function being compiled and the functions it's nested in
+    functions: Vec<FunctionState>,
+}
+
+impl Compiler {
+    fn f(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn resolve(&mut self, level: usize, local: &RcLocal) -> Result<Option<Variable>> {
+        let function = &self.functions[level];
+        if let Some(register) = function.register_of(local) {
+            return Ok(Some(Variable::Register(register)));
+        }
+        if let Some(upvalue) = function.upvalues.iter().position(|(l, _)| l == local) {
+            return Ok(Some(Variable::Upvalue(upvalue as u8)));
+        }
+        if level == 0 {
+            return Ok(None);
+        }
+        let source = match self.resolve(level - 1, local)? {
+            Some(Variable::Register(register)) => {
+                self.functions[level - 1].capture(register);
+                UpvalueSource::Register(register)
+            }
+            Some(Variable::Upvalue(upvalue)) => UpvalueSource::Upvalue(upvalue),
+            None => return Ok(None),
+        };
+        let function = &mut self.functions[level];
+        if function.upvalues.len() == MAX_UPVALUES {
+            return Err(CompileError::TooManyUpvalues);
+        }
+        function.upvalues.push((local.clone(), source));
+        Ok(Some(Variable::Upvalue(function.upvalues.len() as u8 - 1)))
+    }
+
+    fn variable(&mut self, local: &RcLocal) -> Result<Variable> {
+        self.resolve(self.functions.len() - 1, local)?
+            .ok_or_else(|| CompileError::UndeclaredLocal(local.to_string()))
+    }
+
+    // a register that can be used as the base of a call or table without moving the result,
+    // the target must be a temporary at the top of the stack
+    fn is_top(&mut self, target: u8) -> bool {
+        let f = self.f();
+        target as usize >= f.active.len() && target as usize + 1 == f.free
+    }
+
+    fn operand(&mut self, rvalue: &RValue) -> Result<u8> {
+        let variable = match rvalue {
+            RValue::Local(local) => Some(self.variable(local)?),
+            _ => None,
+        };
+        if let Some(Variable::Register(register)) = variable {
+            return Ok(register);
+        }
+        let register = self.f().reserve(1)?;
+        self.expression(rvalue, register)?;
+        Ok(register)
+    }
+
+    fn constant_operand(&mut self, rvalue: &RValue) -> Result<Option<RegisterOrConstant>> {
+        if let Some(constant) = literal_constant(rvalue) {
+            let index = self.f().constant(constant)?;
+            if index <= MAX_RK_CONSTANT {
+                return Ok(Some(constant_operand(index)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn rk(&mut self, rvalue: &RValue) -> Result<RegisterOrConstant> {
+        match self.constant_operand(rvalue)? {
+            Some(operand) => Ok(operand),
+            None => Ok(register_operand(self.operand(rvalue)?)),
+        }
+    }
+
+    // like `rk`, but never the register of a local, for values that are used after locals are
+    // assigned to
+    fn rk_temporary(&mut self, rvalue: &RValue) -> Result<RegisterOrConstant> {
+        match self.constant_operand(rvalue)? {
+            Some(operand) => Ok(operand),
+            None => {
+                let register = self.f().reserve(1)?;
+                self.expression(rvalue, register)?;
+                Ok(register_operand(register))
+            }
+        }
+    }
+
+    fn string_constant(&mut self, string: &[u8]) -> Result<ConstantIndex> {
+        Ok(ConstantIndex(
+            self.f().constant(Constant::String(string.to_vec()))?,
+        ))
+    }
+
+    fn literal(&mut self, literal: &Literal, target: u8) -> Result<()> {
+        let destination = Register(target);
+        let instruction = match *literal {
+            Literal::Nil => Instruction::LoadNil(vec![destination]),
+            Literal::Boolean(value) => Instruction::LoadBoolean {
+                destination,
+                value,
+                skip_next: false,
+            },
+            Literal::Number(value) => Instruction::LoadConstant {
+                destination,
+                source: ConstantIndex(self.f().constant(Constant::Number(value))?),
+            },
+            Literal::String(ref value) => Instruction::LoadConstant {
+                destination,
+                source: self.string_constant(value)?,
+            },
+            Literal::Vector(..) => return Err(CompileError::Unsupported("vector literal")),
+        };
+        self.f().emit(instruction);
+        Ok(())
+    }
+
+    // evaluates to the first free register, `results` is `None` to keep all of them for the
+    // instruction that follows
+    fn multiple_values(&mut self, multiple: Multiple, results: Option<usize>) -> Result<u8> {
+        match multiple {
+            Multiple::Call(call) => self.call(&call.value, None, &call.arguments, results, false),
+            Multiple::MethodCall(method_call) => self.call(
+                &method_call.value,
+                Some(&method_call.method),
+                &method_call.arguments,
+                results,
+                false,
+            ),
+            Multiple::VarArg => {
+                if self.f().prototype.vararg_flag == 0 {
+                    return Err(CompileError::Unsupported(
+                        "`...` outside of a vararg function",
+                    ));
+                }
+                let base = self.f().reserve(results.unwrap_or(1))?;
+                self.f().emit(Instruction::VarArg(
+                    Register(base),
+                    results.map_or(0, |r| r as u8 + 1),
+                ));
+                Ok(base)
+            }
+        }
+    }
+
+    fn call(
+        &mut self,
+        value: &RValue,
+        method: Option<&String>,
+        arguments: &[RValue],
+        results: Option<usize>,
+        tail: bool,
+    ) -> Result<u8> {
+        let base = self.f().reserve(1)?;
+        match method {
+            None => self.expression(value, base)?,
+            Some(method) => {
+                let object = self.operand(value)?;
+                let method = self.rk(&Literal::from(method.as_str()).into())?;
+                self.f().free = base as usize + 1;
+                let self_arg = self.f().reserve(1)?;
+                self.f().emit(Instruction::PrepMethodCall {
+                    destination: Register(base),
+                    self_arg: Register(self_arg),
+                    object: Register(object),
+                    method,
+                });
+            }
+        }
+        let mut variadic = false;
+        for (i, argument) in arguments.iter().enumerate() {
+            match multiple(argument) {
+                Some(multiple) if i + 1 == arguments.len() => {
+                    self.multiple_values(multiple, None)?;
+                    variadic = true;
+                }
+                _ => {
+                    let register = self.f().reserve(1)?;
+                    self.expression(argument, register)?;
+                }
+            }
+        }
+        let arguments = if variadic {
+            0
+        } else {
+            (arguments.len() + method.is_some() as usize + 1) as u8
+        };
+        let f = self.f();
+        if tail {
+            f.emit(Instruction::TailCall {
+                function: Register(base),
+                arguments,
+            });
+            f.emit(Instruction::Return(Register(base), 0));
+        } else {
+            f.emit(Instruction::Call {
+                function: Register(base),
+                arguments,
+                return_values: results.map_or(0, |r| r as u8 + 1),
+            });
+        }
+        f.free = base as usize;
+        f.reserve(results.unwrap_or(1))?;
+        Ok(base)
+    }
+
+    fn concatenate(&mut self, left: &RValue, right: &RValue, target: u8) -> Result<()> {
+        // `a .. b .. c` is right associative and concatenated by a single instruction
+        let mut operands = vec![left];
+        let mut right = right;
+        loop {
+            match right {
+                RValue::Binary(binary) if binary.operation == BinaryOperation::Concat => {
+                    operands.push(&binary.left);
+                    right = &binary.right;
+                }
+                _ => break,
+            }
+        }
+        operands.push(right);
+        let base = self.f().reserve(operands.len())?;
+        for (i, operand) in operands.iter().enumerate() {
+            self.expression(operand, base + i as u8)?;
+        }
+        self.f().emit(Instruction::Concatenate {
+            destination: Register(target),
+            operands: (base..base + operands.len() as u8).map(Register).collect(),
+        });
+        Ok(())
+    }
+
+    fn arithmetic(
+        &mut self,
+        operation: BinaryOperation,
+        left: &RValue,
+        right: &RValue,
+        target: u8,
+    ) -> Result<()> {
+        let lhs = self.rk(left)?;
+        let rhs = self.rk(right)?;
+        let destination = Register(target);
+        let instruction = match operation {
+            BinaryOperation::Add => Instruction::Add {
+                destination,
+                lhs,
+                rhs,
+            },
+            BinaryOperation::Sub => Instruction::Sub {
+                destination,
+                lhs,
+                rhs,
+            },
+            BinaryOperation::Mul => Instruction::Mul {
+                destination,
+                lhs,
+                rhs,
+            },
+            BinaryOperation::Div => Instruction::Div {
+                destination,
+                lhs,
+                rhs,
+            },
+            BinaryOperation::Mod => Instruction::Mod {
+                destination,
+                lhs,
+                rhs,
+            },
+            BinaryOperation::Pow => Instruction::Pow {
+                destination,
+                lhs,
+                rhs,
+            },
+            BinaryOperation::IDiv => return Err(CompileError::Unsupported("floor division")),
+            _ => unreachable!(),
+        };
+        self.f().emit(instruction);
+        Ok(())
+    }
+
+    // emits jumps that are taken when the truthiness of `rvalue` is `jump_if`
+    fn condition(&mut self, rvalue: &RValue, jump_if: bool) -> Result<Vec<usize>> {
+        let free = self.f().free;
+        let jumps = match rvalue {
+            RValue::Literal(literal) if !matches!(literal, Literal::Vector(..)) => {
+                let truthy = !matches!(literal, Literal::Nil | Literal::Boolean(false));
+                if truthy == jump_if {
+                    vec![self.f().jump()]
+                } else {
+                    Vec::new()
+                }
+            }
+            RValue::Unary(unary) if unary.operation == UnaryOperation::Not => {
+                self.condition(&unary.value, !jump_if)?
+            }
+            RValue::Binary(binary) if binary.operation.is_comparator() => {
+                let lhs = self.rk(&binary.left)?;
+                let rhs = self.rk(&binary.right)?;
+                let instruction = match binary.operation {
+                    BinaryOperation::Equal => Instruction::Equal {
+                        lhs,
+                        rhs,
+                        invert: !jump_if,
+                    },
+                    BinaryOperation::NotEqual => Instruction::Equal {
+                        lhs,
+                        rhs,
+                        invert: jump_if,
+                    },
+                    BinaryOperation::LessThan => Instruction::LessThan {
+                        lhs,
+                        rhs,
+                        invert: !jump_if,
+                    },
+                    BinaryOperation::LessThanOrEqual => Instruction::LessThanOrEqual {
+                        lhs,
+                        rhs,
+                        invert: !jump_if,
+                    },
+                    // the operands are evaluated in order and swapped
+                    BinaryOperation::GreaterThan => Instruction::LessThan {
+                        lhs: rhs,
+                        rhs: lhs,
+                        invert: !jump_if,
+                    },
+                    BinaryOperation::GreaterThanOrEqual => Instruction::LessThanOrEqual {
+                        lhs: rhs,
+                        rhs: lhs,
+                        invert: !jump_if,
+                    },
+                    _ => unreachable!(),
+                };
+                self.f().emit(instruction);
+                vec![self.f().jump()]
+            }
+            RValue::Binary(binary)
+                if matches!(binary.operation, BinaryOperation::And | BinaryOperation::Or) =>
+            {
+                // `a and b` is falsy if either is, `a or b` is truthy if either is
+                let short_circuit = binary.operation == BinaryOperation::Or;
+                if jump_if == short_circuit {
+                    let mut jumps = self.condition(&binary.left, jump_if)?;
+                    jumps.extend(self.condition(&binary.right, jump_if)?);
+                    jumps
+                } else {
+                    let skip = self.condition(&binary.left, short_circuit)?;
+                    let jumps = self.condition(&binary.right, jump_if)?;
+                    self.f().patch_here(skip)?;
+                    jumps
+                }
+            }
+            _ => {
+                let value = self.operand(rvalue)?;
+                self.f().emit(Instruction::Test {
+                    value: Register(value),
+                    invert: !jump_if,
+                });
+                vec![self.f().jump()]
+            }
+        };
+        self.f().free = free;
+        Ok(jumps)
+    }
+
+    fn table(&mut self, table: &Table, target: u8) -> Result<()> {
+        // the array items are set from the registers after the table
+        let register = if self.is_top(target) {
+            target
+        } else {
+            self.f().reserve(1)?
+        };
+        let array_size = table.0.iter().filter(|(k, _)| k.is_none()).count();
+        self.f().emit(Instruction::NewTable {
+            destination: Register(register),
+            array_size: int2fb(array_size),
+            hash_size: int2fb(table.0.len() - array_size),
+        });
+        let mut pending = 0;
+        let mut block_number = 1;
+        for (i, (key, value)) in table.0.iter().enumerate() {
+            match key {
+                None => {
+                    if let Some(multiple) = multiple(value).filter(|_| i + 1 == table.0.len()) {
+                        self.multiple_values(multiple, None)?;
+                        self.set_list(register, 0, block_number)?;
+                        pending = 0;
+                        break;
+                    }
+                    let item = self.f().reserve(1)?;
+                    self.expression(value, item)?;
+                    pending += 1;
+                    if pending == FIELDS_PER_FLUSH {
+                        self.set_list(register, pending, block_number)?;
+                        block_number += 1;
+                        pending = 0;
+                        self.f().free = register as usize + 1;
+                    }
+                }
+                Some(key) => {
+                    let free = self.f().free;
+                    let key = self.rk(key)?;
+                    let value = self.rk(value)?;
+                    let f = self.f();
+                    f.emit(Instruction::SetIndex {
+                        object: Register(register),
+                        key,
+                        value,
+                    });
+                    f.free = free;
+                }
+            }
+        }
+        if pending != 0 {
+            self.set_list(register, pending, block_number)?;
+        }
+        if register != target {
+            self.f().emit(Instruction::Move {
+                destination: Register(target),
+                source: Register(register),
+            });
+        }
+        Ok(())
+    }
+
+    fn set_list(&mut self, table: u8, items: usize, block_number: usize) -> Result<()> {
+        self.f().emit(Instruction::SetList {
+            table: Register(table),
+            number_of_elements: items as u8,
+            block_number: block_number
+                .try_into()
+                .map_err(|_| CompileError::TooManyTableItems)?,
+        });
+        Ok(())
+    }
+
+    fn closure(&mut self, closure: &Closure, target: u8) -> Result<()> {
+        let function = closure.function.lock();
+        let (prototype, upvalues) =
+            self.function(&function.parameters, function.is_variadic, &function.body)?;
+        let f = self.f();
+        let index = f.prototype.closures.len() as u32;
+        f.prototype.closures.push(prototype);
+        f.emit(Instruction::Closure {
+            destination: Register(target),
+            function: FunctionIndex(index),
+        });
+        // pseudo instructions telling the VM where to find each upvalue
+        for source in upvalues {
+            f.emit(match source {
+                UpvalueSource::Register(register) => Instruction::Move {
+                    destination: Register(0),
+                    source: Register(register),
+                },
+                UpvalueSource::Upvalue(upvalue) => Instruction::GetUpvalue {
+                    destination: Register(0),
+                    upvalue: Upvalue(upvalue),
+                },
+            });
+        }
+        Ok(())
+    }
+
+    // evaluates to exactly one value in `target`, temporaries are freed afterwards
+    fn expression(&mut self, rvalue: &RValue, target: u8) -> Result<()> {
+        let free = self.f().free;
+        match rvalue {
+            RValue::Local(local) => {
+                let instruction = match self.variable(local)? {
+                    Variable::Register(register) if register == target => None,
+                    Variable::Register(register) => Some(Instruction::Move {
+                        destination: Register(target),
+                        source: Register(register),
+                    }),
+                    Variable::Upvalue(upvalue) => Some(Instruction::GetUpvalue {
+                        destination: Register(target),
+                        upvalue: Upvalue(upvalue),
+                    }),
+                };
+                if let Some(instruction) = instruction {
+                    self.f().emit(instruction);
+                }
+            }
+            RValue::Global(global) => match environment_index(global) {
+                Some(index) => self.expression(&RValue::Index(index), target)?,
+                None => {
+                    let global = self.string_constant(&global.0)?;
+                    self.f().emit(Instruction::GetGlobal {
+                        destination: Register(target),
+                        global,
+                    });
+                }
+            },
+            RValue::Literal(literal) => self.literal(literal, target)?,
+            RValue::Call(_) | RValue::MethodCall(_) | RValue::VarArg(_) | RValue::Select(_) => {
+                if self.is_top(target) {
+                    self.f().free = target as usize;
+                }
+                let base = self.multiple_values(multiple(rvalue).unwrap(), Some(1))?;
+                if base != target {
+                    self.f().emit(Instruction::Move {
+                        destination: Register(target),
+                        source: Register(base),
+                    });
+                }
+            }
+            RValue::Table(table) => self.table(table, target)?,
+            RValue::Index(index) => {
+                let object = self.operand(&index.left)?;
+                let key = self.rk(&index.right)?;
+                self.f().emit(Instruction::GetIndex {
+                    destination: Register(target),
+                    object: Register(object),
+                    key,
+                });
+            }
+            RValue::Unary(unary) => {
+                let operand = Register(self.operand(&unary.value)?);
+                let destination = Register(target);
+                self.f().emit(match unary.operation {
+                    UnaryOperation::Not => Instruction::Not {
+                        destination,
+                        operand,
+                    },
+                    UnaryOperation::Negate => Instruction::Minus {
+                        destination,
+                        operand,
+                    },
+                    UnaryOperation::Length => Instruction::Length {
+                        destination,
+                        operand,
+                    },
+                });
+            }
+            RValue::Binary(binary) => match binary.operation {
+                BinaryOperation::And | BinaryOperation::Or => {
+                    self.expression(&binary.left, target)?;
+                    // skip the right operand if the left one decides the result
+                    self.f().emit(Instruction::Test {
+                        value: Register(target),
+                        invert: binary.operation == BinaryOperation::And,
+                    });
+                    let skip = self.f().jump();
+                    self.expression(&binary.right, target)?;
+                    self.f().patch_here(vec![skip])?;
+                }
+                BinaryOperation::Concat => self.concatenate(&binary.left, &binary.right, target)?,
+                operation if operation.is_comparator() => {
+                    let jumps = self.condition(rvalue, true)?;
+                    let f = self.f();
+                    f.emit(Instruction::LoadBoolean {
+                        destination: Register(target),
+                        value: false,
+                        skip_next: true,
+                    });
+                    f.patch_here(jumps)?;
+                    f.emit(Instruction::LoadBoolean {
+                        destination: Register(target),
+                        value: true,
+                        skip_next: false,
+                    });
+                }
+                operation => self.arithmetic(operation, &binary.left, &binary.right, target)?,
+            },
+            RValue::Closure(closure) => self.closure(closure, target)?,
+            RValue::Interpolation(_) => {
+                return Err(CompileError::Unsupported("interpolated string"));
+            }
+        }
+        self.f().free = free;
+        Ok(())
+    }
+
+    // evaluates `count` values to consecutive registers starting at the first free one
+    fn values(&mut self, rvalues: &[RValue], count: usize) -> Result<u8> {
+        let base = self.f().free as u8;
+        for (i, rvalue) in rvalues.iter().enumerate() {
+            if i >= count {
+                // only evaluated for side effects
+                let free = self.f().free;
+                let register = self.f().reserve(1)?;
+                self.expression(rvalue, register)?;
+                self.f().free = free;
+                continue;
+            }
+            match multiple(rvalue) {
+                Some(multiple) if i + 1 == rvalues.len() && count > i + 1 => {
+                    self.multiple_values(multiple, Some(count - i))?;
+                    return Ok(base);
+                }
+                _ => {}
+            }
+            let register = self.f().reserve(1)?;
+            self.expression(rvalue, register)?;
+        }
+        if rvalues.len() < count {
+            let missing = count - rvalues.len();
+            let first = self.f().reserve(missing)?;
+            self.f().load_nil(first, missing);
+        }
+        Ok(base)
+    }
+
+    fn declare(&mut self, assign: &Assign) -> Result<()> {
+        let locals = assign
+            .left
+            .iter()
+            .map(|l| match l {
+                LValue::Local(local) => Ok(local.clone()),
+                _ => Err(CompileError::Unsupported(
+                    "declaration of a global or index",
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let ([local], [RValue::Closure(closure)]) = (&locals[..], &assign.right[..]) {
+            // in scope in its own body, like `local function`
+            let register = self.f().reserve(1)?;
+            self.f().activate(Some(local.clone()));
+            return self.closure(closure, register);
+        }
+        self.values(&assign.right, locals.len())?;
+        for local in locals {
+            self.f().activate(Some(local));
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, assign: &Assign) -> Result<()> {
+        if assign.prefix {
+            return self.declare(assign);
+        }
+        if let ([left], [right]) = (&assign.left[..], &assign.right[..]) {
+            return self.assign_single(left, right);
+        }
+        // every value is evaluated before any is assigned
+        enum Target {
+            Variable(Variable),
+            Global(ConstantIndex),
+            Index(u8, RegisterOrConstant),
+        }
+        let mut targets = Vec::with_capacity(assign.left.len());
+        for left in &assign.left {
+            let environment = environment_lvalue(left);
+            targets.push(match environment.as_ref().unwrap_or(left) {
+                LValue::Local(local) => Target::Variable(self.variable(local)?),
+                LValue::Global(global) => Target::Global(self.string_constant(&global.0)?),
+                LValue::Index(index) => {
+                    let object = self.f().reserve(1)?;
+                    self.expression(&index.left, object)?;
+                    Target::Index(object, self.rk_temporary(&index.right)?)
+                }
+            });
+        }
+        let base = self.values(&assign.right, assign.left.len())?;
+        for (i, target) in targets.into_iter().enumerate().rev() {
+            let value = Register(base + i as u8);
+            self.f().emit(match target {
+                Target::Variable(Variable::Register(register)) => Instruction::Move {
+                    destination: Register(register),
+                    source: value,
+                },
+                Target::Variable(Variable::Upvalue(upvalue)) => Instruction::SetUpvalue {
+                    destination: Upvalue(upvalue),
+                    source: value,
+                },
+                Target::Global(global) => Instruction::SetGlobal {
+                    destination: global,
+                    value,
+                },
+                Target::Index(object, key) => Instruction::SetIndex {
+                    object: Register(object),
+                    key,
+                    value: register_operand(value.0),
+                },
+            });
+        }
+        Ok(())
+    }
+
+    fn assign_single(&mut self, left: &LValue, right: &RValue) -> Result<()> {
+        if let Some(left) = environment_lvalue(left) {
+            return self.assign_single(&left, right);
+        }
+        match left {
+            LValue::Local(local) => match self.variable(local)? {
+                Variable::Register(register) if !writes_early(right) => {
+                    self.expression(right, register)
+                }
+                Variable::Register(register) => {
+                    let value = self.f().reserve(1)?;
+                    self.expression(right, value)?;
+                    self.f().emit(Instruction::Move {
+                        destination: Register(register),
+                        source: Register(value),
+                    });
+                    Ok(())
+                }
+                Variable::Upvalue(upvalue) => {
+                    let value = self.operand(right)?;
+                    self.f().emit(Instruction::SetUpvalue {
+                        destination: Upvalue(upvalue),
+                        source: Register(value),
+                    });
+                    Ok(())
+                }
+            },
+            LValue::Global(global) => {
+                let global = self.string_constant(&global.0)?;
+                let value = self.operand(right)?;
+                self.f().emit(Instruction::SetGlobal {
+                    destination: global,
+                    value: Register(value),
+                });
+                Ok(())
+            }
+            LValue::Index(index) => {
+                let object = self.operand(&index.left)?;
+                let key = self.rk(&index.right)?;
+                let value = self.rk(right)?;
+                self.f().emit(Instruction::SetIndex {
+                    object: Register(object),
+                    key,
+                    value,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn r#return(&mut self, values: &[RValue]) -> Result<()> {
+        match values {
+            [] => {
+                self.f().emit(Instruction::Return(Register(0), 1));
+            }
+            [RValue::Call(call)] => {
+                self.call(&call.value, None, &call.arguments, None, true)?;
+            }
+            [RValue::MethodCall(method_call)] => {
+                self.call(
+                    &method_call.value,
+                    Some(&method_call.method),
+                    &method_call.arguments,
+                    None,
+                    true,
+                )?;
+            }
+            _ => {
+                let base = self.f().free as u8;
+                let mut variadic = false;
+                for (i, value) in values.iter().enumerate() {
+                    match multiple(value) {
+                        Some(multiple) if i + 1 == values.len() => {
+                            self.multiple_values(multiple, None)?;
+                            variadic = true;
+                        }
+                        _ => {
+                            let register = self.f().reserve(1)?;
+                            self.expression(value, register)?;
+                        }
+                    }
+                }
+                let count = if variadic { 0 } else { values.len() as u8 + 1 };
+                self.f().emit(Instruction::Return(Register(base), count));
+            }
+        }
+        Ok(())
+    }
+
+    fn r#loop(&mut self, body: &Block) -> Result<Loop> {
+        let f = self.f();
+        f.enter_scope();
+        f.loops.push(Loop {
+            scope: f.scopes.len() - 1,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+        self.statements(body)?;
+        Ok(self.f().loops.pop().unwrap())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Empty(_) | Statement::Comment(_) => {}
+            Statement::Call(call) => {
+                self.call(&call.value, None, &call.arguments, Some(0), false)?;
+            }
+            Statement::MethodCall(method_call) => {
+                self.call(
+                    &method_call.value,
+                    Some(&method_call.method),
+                    &method_call.arguments,
+                    Some(0),
+                    false,
+                )?;
+            }
+            Statement::Assign(assign) => self.assign(assign)?,
+            Statement::If(r#if) => {
+                let skip = self.condition(&r#if.condition, false)?;
+                self.block(&r#if.then_block.lock())?;
+                let else_block = r#if.else_block.lock();
+                if else_block.is_empty() {
+                    self.f().patch_here(skip)?;
+                } else {
+                    let exit = self.f().jump();
+                    self.f().patch_here(skip)?;
+                    self.block(&else_block)?;
+                    self.f().patch_here(vec![exit])?;
+                }
+            }
+            Statement::While(r#while) => {
+                let start = self.f().pc();
+                let exits = self.condition(&r#while.condition, false)?;
+                let r#loop = self.r#loop(&r#while.block.lock())?;
+                let f = self.f();
+                f.leave_scope(true);
+                f.jump_to(start)?;
+                f.patch_here(exits)?;
+                f.patch_here(r#loop.breaks)?;
+                for jump in r#loop.continues {
+                    f.patch(jump, start)?;
+                }
+            }
+            Statement::Repeat(repeat) => {
+                let start = self.f().pc();
+                let r#loop = self.r#loop(&repeat.block.lock())?;
+                self.f().patch_here(r#loop.continues)?;
+                // the condition is in the scope of the body
+                let exits = self.condition(&repeat.condition, true)?;
+                let f = self.f();
+                let scope = f.scopes.last().unwrap();
+                let close = (scope.captured && f.active.len() > scope.first)
+                    .then_some(Register(scope.first as u8));
+                if let Some(close) = close {
+                    f.emit(Instruction::Close(close));
+                }
+                f.jump_to(start)?;
+                f.patch_here(exits)?;
+                if let Some(close) = close {
+                    f.emit(Instruction::Close(close));
+                }
+                f.leave_scope(false);
+                f.patch_here(r#loop.breaks)?;
+            }
+            Statement::NumericFor(numeric_for) => {
+                let base = self.f().free as u8;
+                for value in [&numeric_for.initial, &numeric_for.limit, &numeric_for.step] {
+                    let register = self.f().reserve(1)?;
+                    self.expression(value, register)?;
+                }
+                let control = (base..=base + 4).map(Register).collect::<Vec<_>>();
+                let f = self.f();
+                f.enter_scope();
+                (0..3).for_each(|_| f.activate(None));
+                let prepare = f.emit(Instruction::InitNumericForLoop {
+                    control: control.clone(),
+                    skip: 0,
+                });
+                let body = f.pc();
+                // the counter is in the scope of the body, so it's closed every iteration
+                f.enter_scope();
+                f.reserve(1)?;
+                f.activate(Some(numeric_for.counter.clone()));
+                f.loops.push(Loop {
+                    scope: f.scopes.len() - 1,
+                    breaks: Vec::new(),
+                    continues: Vec::new(),
+                });
+                self.statements(&numeric_for.block.lock())?;
+                let f = self.f();
+                let r#loop = f.loops.pop().unwrap();
+                f.leave_scope(true);
+                f.patch_here(r#loop.continues)?;
+                f.patch_here(vec![prepare])?;
+                let iterate = f.emit(Instruction::IterateNumericForLoop { control, skip: 0 });
+                f.patch(iterate, body)?;
+                f.patch_here(r#loop.breaks)?;
+                f.leave_scope(false);
+            }
+            Statement::GenericFor(generic_for) => {
+                let base = self.values(&generic_for.right, 3)?;
+                let f = self.f();
+                f.enter_scope();
+                (0..3).for_each(|_| f.activate(None));
+                let prepare = f.jump();
+                let body = f.pc();
+                f.enter_scope();
+                let vars = f.reserve(generic_for.res_locals.len())?;
+                // the generator is called with its arguments after the hidden locals
+                f.check_stack(base as usize + 6)?;
+                for local in &generic_for.res_locals {
+                    f.activate(Some(local.clone()));
+                }
+                f.loops.push(Loop {
+                    scope: f.scopes.len() - 1,
+                    breaks: Vec::new(),
+                    continues: Vec::new(),
+                });
+                self.statements(&generic_for.block.lock())?;
+                let f = self.f();
+                let r#loop = f.loops.pop().unwrap();
+                f.leave_scope(true);
+                f.patch_here(r#loop.continues)?;
+                f.patch_here(vec![prepare])?;
+                f.emit(Instruction::IterateGenericForLoop {
+                    generator: Register(base),
+                    state: Register(base + 1),
+                    internal_control: Register(base + 2),
+                    vars: (vars..vars + generic_for.res_locals.len() as u8)
+                        .map(Register)
+                        .collect(),
+                });
+                f.jump_to(body)?;
+                f.patch_here(r#loop.breaks)?;
+                f.leave_scope(false);
+            }
+            Statement::Return(r#return) => self.r#return(&r#return.values)?,
+            Statement::Break(_) => self.f().leave_loop(false)?,
+            Statement::Continue(_) => self.f().leave_loop(true)?,
+            Statement::Goto(goto) => {
+                let f = self.f();
+                let active = f.active.len();
+                let close = f.emit(Instruction::Close(Register(active as u8)));
+                let jump = f.jump();
+                f.gotos.push(Goto {
+                    label: goto.0 .0.clone(),
+                    close,
+                    jump,
+                    active,
+                });
+            }
+            Statement::Label(label) => {
+                let f = self.f();
+                let target = (f.pc(), f.active.len());
+                f.labels.insert(label.0.clone(), target);
+            }
+            Statement::SetList(set_list) => {
+                if (set_list.index - 1) % FIELDS_PER_FLUSH != 0
+                    || set_list.values.len() > FIELDS_PER_FLUSH
+                {
+                    return Err(CompileError::Unsupported(
+                        "set list that isn't a single flush",
+                    ));
+                }
+                let table = self.f().reserve(1)?;
+                self.expression(&RValue::Local(set_list.object_local.clone()), table)?;
+                for value in &set_list.values {
+                    let register = self.f().reserve(1)?;
+                    self.expression(value, register)?;
+                }
+                let items = match set_list.tail.as_ref().map(|t| (t, multiple(t))) {
+                    Some((_, Some(multiple))) => {
+                        self.multiple_values(multiple, None)?;
+                        0
+                    }
+                    Some((tail, None)) => {
+                        let register = self.f().reserve(1)?;
+                        self.expression(tail, register)?;
+                        set_list.values.len() + 1
+                    }
+                    None => set_list.values.len(),
+                };
+                self.set_list(table, items, (set_list.index - 1) / FIELDS_PER_FLUSH + 1)?;
+            }
+            Statement::NumForInit(_)
+            | Statement::NumForNext(_)
+            | Statement::GenericForInit(_)
+            | Statement::GenericForNext(_)
+            | Statement::Close(_) => {
+                return Err(CompileError::Unsupported("unstructured for loop or close"));
+            }
+            Statement::CompoundAssign(_) => {
+                return Err(CompileError::Unsupported("compound assignment"));
+            }
+        }
+        let f = self.f();
+        f.free = f.active.len();
+        Ok(())
+    }
+
+    fn statements(&mut self, block: &Block) -> Result<()> {
+        block.iter().try_for_each(|s| self.statement(s))
+    }
+
+    fn block(&mut self, block: &Block) -> Result<()> {
+        self.f().enter_scope();
+        self.statements(block)?;
+        self.f().leave_scope(true);
+        Ok(())
+    }
+
+    fn function(
+        &mut self,
+        parameters: &[RcLocal],
+        is_variadic: bool,
+        body: &Block,
+    ) -> Result<(Prototype, Vec<UpvalueSource>)> {
+        let mut state = FunctionState::default();
+        state.prototype.number_of_parameters = parameters.len() as u8;
+        // VARARG_ISVARARG, `arg` isn't created
+        state.prototype.vararg_flag = if is_variadic { 2 } else { 0 };
+        state.enter_scope();
+        state.reserve(parameters.len())?;
+        for parameter in parameters {
+            state.activate(Some(parameter.clone()));
+        }
+        self.functions.push(state);
+        let result = self.statements(body);
+        let mut state = self.functions.pop().unwrap();
+        result?;
+        state.leave_scope(false);
+        state.emit(Instruction::Return(Register(0), 1));
+        state.resolve_gotos()?;
+        let mut prototype = state.prototype;
+        prototype.maximum_stack_size = prototype.maximum_stack_size.max(2);
+        prototype.number_of_upvalues = state.upvalues.len() as u8;
+        let upvalues = state.upvalues.into_iter().map(|(_, s)| s).collect();
+        Ok((prototype, upvalues))
+    }
+}
+
+/// Compiles a main function to Lua 5.1 bytecode, without debug info. The code is what `luac`
+/// would generate for the same source, but not necessarily instruction for instruction.
+pub fn compile(block: &Block) -> Result<Prototype> {
+    let mut compiler = Compiler {
+        functions: Vec::new(),
+    };
+    let (prototype, _) = compiler.function(&[], true, block)?;
+    Ok(prototype)
+}
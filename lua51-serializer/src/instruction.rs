@@ -0,0 +1,245 @@
+use either::Either;
+use lua51_deserializer::{
+    argument::{Register, RegisterOrConstant},
+    instruction::OperationCode,
+    Instruction,
+};
+
+// offset added to signed operands, the maximum 18 bit signed int
+const SIGNED_BIAS: i32 = ((1 << 18) - 1) >> 1;
+
+fn bc(operation_code: OperationCode, a: u8, b: u32, c: u32) -> u32 {
+    debug_assert!(b <= 0x1FF && c <= 0x1FF);
+    operation_code as u32 | (a as u32) << 6 | c << 14 | b << 23
+}
+
+fn bx(operation_code: OperationCode, a: u8, b_x: u32) -> u32 {
+    debug_assert!(b_x <= 0x3FFFF);
+    operation_code as u32 | (a as u32) << 6 | b_x << 14
+}
+
+fn bsx(operation_code: OperationCode, a: u8, b_sx: i32) -> u32 {
+    bx(operation_code, a, (b_sx + SIGNED_BIAS) as u32)
+}
+
+fn rk(operand: RegisterOrConstant) -> u32 {
+    match operand.0 {
+        Either::Left(register) => register.0 as u32,
+        Either::Right(constant) => constant.0 + 256,
+    }
+}
+
+fn first(registers: &[Register]) -> u8 {
+    registers.first().map_or(0, |r| r.0)
+}
+
+fn last(registers: &[Register]) -> u32 {
+    registers.last().map_or(0, |r| r.0 as u32)
+}
+
+/// Encodes an instruction the way [`Instruction::parse`] decodes it.
+pub fn encode(instruction: &Instruction) -> u32 {
+    match *instruction {
+        Instruction::Move {
+            destination,
+            source,
+        } => bc(OperationCode::Move, destination.0, source.0 as u32, 0),
+        Instruction::LoadConstant {
+            destination,
+            source,
+        } => bx(OperationCode::LoadConstant, destination.0, source.0),
+        Instruction::LoadBoolean {
+            destination,
+            value,
+            skip_next,
+        } => bc(
+            OperationCode::LoadBoolean,
+            destination.0,
+            value as u32,
+            skip_next as u32,
+        ),
+        Instruction::LoadNil(ref registers) => {
+            bc(OperationCode::LoadNil, first(registers), last(registers), 0)
+        }
+        Instruction::GetUpvalue {
+            destination,
+            ref upvalue,
+        } => bc(
+            OperationCode::GetUpvalue,
+            destination.0,
+            upvalue.0 as u32,
+            0,
+        ),
+        Instruction::GetGlobal {
+            destination,
+            global,
+        } => bx(OperationCode::GetGlobal, destination.0, global.0),
+        Instruction::GetIndex {
+            destination,
+            object,
+            key,
+        } => bc(
+            OperationCode::GetIndex,
+            destination.0,
+            object.0 as u32,
+            rk(key),
+        ),
+        Instruction::SetGlobal { destination, value } => {
+            bx(OperationCode::SetGlobal, value.0, destination.0)
+        }
+        Instruction::SetUpvalue {
+            ref destination,
+            source,
+        } => bc(OperationCode::SetUpvalue, source.0, destination.0 as u32, 0),
+        Instruction::SetIndex { object, key, value } => {
+            bc(OperationCode::SetIndex, object.0, rk(key), rk(value))
+        }
+        Instruction::NewTable {
+            destination,
+            array_size,
+            hash_size,
+        } => bc(
+            OperationCode::NewTable,
+            destination.0,
+            array_size as u32,
+            hash_size as u32,
+        ),
+        Instruction::PrepMethodCall {
+            destination,
+            object,
+            method,
+            ..
+        } => bc(
+            OperationCode::PrepMethodCall,
+            destination.0,
+            object.0 as u32,
+            rk(method),
+        ),
+        Instruction::Add {
+            destination,
+            lhs,
+            rhs,
+        } => bc(OperationCode::Add, destination.0, rk(lhs), rk(rhs)),
+        Instruction::Sub {
+            destination,
+            lhs,
+            rhs,
+        } => bc(OperationCode::Subtract, destination.0, rk(lhs), rk(rhs)),
+        Instruction::Mul {
+            destination,
+            lhs,
+            rhs,
+        } => bc(OperationCode::Multiply, destination.0, rk(lhs), rk(rhs)),
+        Instruction::Div {
+            destination,
+            lhs,
+            rhs,
+        } => bc(OperationCode::Divide, destination.0, rk(lhs), rk(rhs)),
+        Instruction::Mod {
+            destination,
+            lhs,
+            rhs,
+        } => bc(OperationCode::Modulo, destination.0, rk(lhs), rk(rhs)),
+        Instruction::Pow {
+            destination,
+            lhs,
+            rhs,
+        } => bc(OperationCode::Power, destination.0, rk(lhs), rk(rhs)),
+        Instruction::Minus {
+            destination,
+            operand,
+        } => bc(OperationCode::Minus, destination.0, operand.0 as u32, 0),
+        Instruction::Not {
+            destination,
+            operand,
+        } => bc(OperationCode::Not, destination.0, operand.0 as u32, 0),
+        Instruction::Length {
+            destination,
+            operand,
+        } => bc(OperationCode::Length, destination.0, operand.0 as u32, 0),
+        Instruction::Concatenate {
+            destination,
+            ref operands,
+        } => bc(
+            OperationCode::Concatenate,
+            destination.0,
+            first(operands) as u32,
+            last(operands),
+        ),
+        Instruction::Jump(offset) => bsx(OperationCode::Jump, 0, offset),
+        Instruction::Equal { lhs, rhs, invert } => {
+            bc(OperationCode::Equal, !invert as u8, rk(lhs), rk(rhs))
+        }
+        Instruction::LessThan { lhs, rhs, invert } => {
+            bc(OperationCode::LessThan, !invert as u8, rk(lhs), rk(rhs))
+        }
+        Instruction::LessThanOrEqual { lhs, rhs, invert } => bc(
+            OperationCode::LessThanOrEqual,
+            !invert as u8,
+            rk(lhs),
+            rk(rhs),
+        ),
+        Instruction::Test { value, invert } => bc(OperationCode::Test, value.0, 0, !invert as u32),
+        Instruction::TestSet {
+            destination,
+            value,
+            invert,
+        } => bc(
+            OperationCode::TestSet,
+            destination.0,
+            value.0 as u32,
+            !invert as u32,
+        ),
+        Instruction::Call {
+            function,
+            arguments,
+            return_values,
+        } => bc(
+            OperationCode::Call,
+            function.0,
+            arguments as u32,
+            return_values as u32,
+        ),
+        Instruction::TailCall {
+            function,
+            arguments,
+        } => bc(OperationCode::TailCall, function.0, arguments as u32, 0),
+        Instruction::Return(register, values) => {
+            bc(OperationCode::Return, register.0, values as u32, 0)
+        }
+        Instruction::IterateNumericForLoop { ref control, skip } => {
+            bsx(OperationCode::IterateNumericForLoop, first(control), skip)
+        }
+        Instruction::InitNumericForLoop { ref control, skip } => {
+            bsx(OperationCode::InitNumericForLoop, first(control), skip)
+        }
+        Instruction::IterateGenericForLoop {
+            generator,
+            ref vars,
+            ..
+        } => bc(
+            OperationCode::IterateGenericForLoop,
+            generator.0,
+            0,
+            vars.len() as u32,
+        ),
+        Instruction::SetList {
+            table,
+            number_of_elements,
+            block_number,
+        } => bc(
+            OperationCode::SetList,
+            table.0,
+            number_of_elements as u32,
+            block_number as u32,
+        ),
+        Instruction::Close(register) => bc(OperationCode::Close, register.0, 0, 0),
+        Instruction::Closure {
+            destination,
+            ref function,
+        } => bx(OperationCode::Closure, destination.0, function.0),
+        Instruction::VarArg(register, values) => {
+            bc(OperationCode::VarArg, register.0, values as u32, 0)
+        }
+    }
+}
@@ -0,0 +1,10 @@
+pub use assembler::{assemble, AssembleError};
+pub use compiler::{compile, CompileError};
+pub use prototype::{Constant, Local, Prototype};
+pub use writer::serialize;
+
+mod assembler;
+mod compiler;
+pub mod instruction;
+mod prototype;
+mod writer;
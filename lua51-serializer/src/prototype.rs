@@ -0,0 +1,87 @@
+use std::ops::Range;
+
+use lua51_deserializer::{Function, Instruction, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Vec<u8>),
+}
+
+impl From<&Value<'_>> for Constant {
+    fn from(value: &Value<'_>) -> Self {
+        match *value {
+            Value::Nil => Self::Nil,
+            Value::Boolean(value) => Self::Boolean(value),
+            Value::Number(value) => Self::Number(value),
+            Value::String(value) => Self::String(value.to_vec()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Local {
+    pub name: Vec<u8>,
+    /// The instructions the local is live for
+    pub range: Range<u32>,
+}
+
+/// A function ready to be serialized. This is an owned [`Function`], strings don't include the
+/// null terminator.
+#[derive(Debug, Clone, Default)]
+pub struct Prototype {
+    pub source: Vec<u8>,
+    pub line_defined: u32,
+    pub last_line_defined: u32,
+    pub number_of_upvalues: u8,
+    pub number_of_parameters: u8,
+    pub vararg_flag: u8,
+    pub maximum_stack_size: u8,
+    pub code: Vec<Instruction>,
+    pub constants: Vec<Constant>,
+    pub closures: Vec<Prototype>,
+    /// The line of each instruction, empty if the debug info is stripped
+    pub positions: Vec<u32>,
+    pub locals: Vec<Local>,
+    pub upvalues: Vec<Vec<u8>>,
+}
+
+impl From<&Function<'_>> for Prototype {
+    fn from(function: &Function<'_>) -> Self {
+        Self {
+            source: function.debug_info.source.unwrap_or_default().to_vec(),
+            line_defined: function.line_defined,
+            last_line_defined: function.last_line_defined,
+            number_of_upvalues: function.number_of_upvalues,
+            number_of_parameters: function.number_of_parameters,
+            vararg_flag: function.vararg_flag,
+            maximum_stack_size: function.maximum_stack_size,
+            code: function.code.clone(),
+            constants: function.constants.iter().map(Constant::from).collect(),
+            closures: function.closures.iter().map(Prototype::from).collect(),
+            positions: function
+                .debug_info
+                .positions
+                .iter()
+                .map(|p| p.source)
+                .collect(),
+            locals: function
+                .debug_info
+                .locals
+                .iter()
+                .map(|l| Local {
+                    name: l.name.to_vec(),
+                    range: l.range.clone(),
+                })
+                .collect(),
+            upvalues: function
+                .debug_info
+                .upvalues
+                .iter()
+                .map(|u| u.to_vec())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    instruction::encode,
+    prototype::{Constant, Prototype},
+};
+
+// official format, little endian, 4 byte ints, size_ts and instructions and 8 byte floating
+// point numbers
+const HEADER: &[u8] = b"\x1BLua\x51\x00\x01\x04\x04\x04\x08\x00";
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn length(&mut self, length: usize) {
+        self.u32(length.try_into().expect("too many elements"));
+    }
+
+    // an empty string is written without a null terminator, which Lua reads as no string
+    fn string(&mut self, string: &[u8]) {
+        if string.is_empty() {
+            self.u32(0);
+        } else {
+            self.length(string.len() + 1);
+            self.0.extend_from_slice(string);
+            self.u8(0);
+        }
+    }
+
+    fn constant(&mut self, constant: &Constant) {
+        match constant {
+            Constant::Nil => self.u8(0),
+            Constant::Boolean(value) => {
+                self.u8(1);
+                self.u8(*value as u8);
+            }
+            Constant::Number(value) => {
+                self.u8(3);
+                self.0.extend_from_slice(&value.to_le_bytes());
+            }
+            Constant::String(value) => {
+                self.u8(4);
+                // unlike other strings, an empty constant is still a string
+                self.length(value.len() + 1);
+                self.0.extend_from_slice(value);
+                self.u8(0);
+            }
+        }
+    }
+
+    fn function(&mut self, prototype: &Prototype) {
+        self.string(&prototype.source);
+        self.u32(prototype.line_defined);
+        self.u32(prototype.last_line_defined);
+        self.u8(prototype.number_of_upvalues);
+        self.u8(prototype.number_of_parameters);
+        self.u8(prototype.vararg_flag);
+        self.u8(prototype.maximum_stack_size);
+        self.length(prototype.code.len());
+        for instruction in &prototype.code {
+            self.u32(encode(instruction));
+        }
+        self.length(prototype.constants.len());
+        for constant in &prototype.constants {
+            self.constant(constant);
+        }
+        self.length(prototype.closures.len());
+        for closure in &prototype.closures {
+            self.function(closure);
+        }
+        self.length(prototype.positions.len());
+        for &line in &prototype.positions {
+            self.u32(line);
+        }
+        self.length(prototype.locals.len());
+        for local in &prototype.locals {
+            self.string(&local.name);
+            self.u32(local.range.start);
+            self.u32(local.range.end);
+        }
+        self.length(prototype.upvalues.len());
+        for upvalue in &prototype.upvalues {
+            self.string(upvalue);
+        }
+    }
+}
+
+/// Serializes a main function to a Lua 5.1 chunk for the official VM on a little endian machine.
+pub fn serialize(prototype: &Prototype) -> Vec<u8> {
+    let mut writer = Writer(HEADER.to_vec());
+    writer.function(prototype);
+    writer.0
+}
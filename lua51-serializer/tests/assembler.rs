@@ -0,0 +1,94 @@
+use std::fs;
+
+use lua51_deserializer::{chunk::Chunk, disassemble::disassemble};
+use lua51_serializer::{assemble, serialize, AssembleError};
+
+fn reassemble(listing: &str) -> String {
+    let bytecode = serialize(&assemble(listing).unwrap());
+    let (_, chunk) = Chunk::parse(&bytecode).unwrap();
+    disassemble(&chunk.function)
+}
+
+// constants are rebuilt in order of first use and debug info is dropped, neither of which shows
+// in the listing, so it assembles back to the same text
+#[test]
+fn round_trip() {
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/fixtures/lua51");
+    for entry in fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        let bytecode = fs::read(&path).unwrap();
+        let (_, chunk) = Chunk::parse(&bytecode).unwrap();
+        let listing = disassemble(&chunk.function);
+        assert_eq!(reassemble(&listing), listing, "{}", path.display());
+    }
+}
+
+#[test]
+fn hand_written() {
+    let listing = r#"
+; function 0 defined at line 0, 0 parameters and varargs, 0 upvalues, 3 registers
+    GETGLOBAL r0, "print"  ; a comment
+    LOADK     r1, "a;b\010"
+    LOADBOOL  r2, true, 0
+    TEST      r2, 0
+    JMP       -> skip
+    CALL      r0, 3, 1
+skip:
+    CLOSURE   r0, f0
+    RETURN    r0, 1
+; function 0.0 defined at line 1, 1 parameters, 0 upvalues, 2 registers
+    ADD       r1, r0, 1
+    RETURN    r1, 2
+"#;
+    let expected = r#"; function 0 defined at line 0, 0 parameters and varargs, 0 upvalues, 3 registers
+    0  GETGLOBAL r0, "print"
+    1  LOADK     r1, "a;b\010"
+    2  LOADBOOL  r2, true, 0
+    3  TEST      r2, 0
+    4  JMP       -> L0
+    5  CALL      r0, 3, 1
+L0:
+    6  CLOSURE   r0, f0
+    7  RETURN    r0, 1
+
+; function 0.0 defined at line 1, 1 parameters, 0 upvalues, 2 registers
+    0  ADD       r1, r0, 1
+    1  RETURN    r1, 2
+
+"#;
+    assert_eq!(reassemble(listing), expected);
+}
+
+#[test]
+fn errors() {
+    let error = |listing: &str| assemble(listing).unwrap_err();
+    let header = "; function 0 defined at line 0, 0 parameters, 0 upvalues, 2 registers\n";
+    assert_eq!(
+        error(&format!("{}    JMP -> nowhere\n", header)),
+        AssembleError {
+            line: Some(2),
+            message: "unknown label nowhere".to_string()
+        }
+    );
+    assert_eq!(
+        error(&format!("{}    FROB r0\n", header)).message,
+        "unknown instruction FROB"
+    );
+    assert_eq!(
+        error(&format!("{}    SELF r255, r0, \"f\"\n", header)).message,
+        "register r256 is out of range"
+    );
+    assert_eq!(
+        error(&format!("{}    FORPREP r252 -> nowhere\n", header)).message,
+        "register r256 is out of range"
+    );
+    assert_eq!(
+        error(&format!("{}    TFORLOOP r254, r1\n", header)).message,
+        "register r257 is out of range"
+    );
+    assert_eq!(error("    RETURN r0, 1\n").line, Some(1));
+    assert_eq!(
+        error("; function 0.0 defined at line 0, 0 parameters, 0 upvalues, 2 registers\n").message,
+        "the listing has no main function 0"
+    );
+}
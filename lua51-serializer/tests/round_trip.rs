@@ -0,0 +1,84 @@
+use std::{fs, path::PathBuf};
+
+use lua51_deserializer::{chunk::Chunk, disassemble::disassemble};
+use lua51_serializer::{instruction::encode, serialize, Prototype};
+
+fn fixtures() -> Vec<(PathBuf, Vec<u8>)> {
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/fixtures/lua51");
+    let mut fixtures = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let bytecode = fs::read(&path).unwrap();
+            (path, bytecode)
+        })
+        .collect::<Vec<_>>();
+    fixtures.sort();
+    fixtures
+}
+
+fn deserialize(bytecode: &[u8]) -> Prototype {
+    let (_, chunk) = Chunk::parse(bytecode).unwrap();
+    Prototype::from(&chunk.function)
+}
+
+fn assert_same(expected: &Prototype, actual: &Prototype) {
+    assert_eq!(expected.source, actual.source);
+    assert_eq!(expected.line_defined, actual.line_defined);
+    assert_eq!(expected.last_line_defined, actual.last_line_defined);
+    assert_eq!(expected.number_of_upvalues, actual.number_of_upvalues);
+    assert_eq!(expected.number_of_parameters, actual.number_of_parameters);
+    assert_eq!(expected.vararg_flag, actual.vararg_flag);
+    assert_eq!(expected.maximum_stack_size, actual.maximum_stack_size);
+    assert_eq!(
+        expected.code.iter().map(encode).collect::<Vec<_>>(),
+        actual.code.iter().map(encode).collect::<Vec<_>>()
+    );
+    assert_eq!(expected.constants, actual.constants);
+    assert_eq!(expected.positions, actual.positions);
+    assert_eq!(expected.locals, actual.locals);
+    assert_eq!(expected.upvalues, actual.upvalues);
+    assert_eq!(expected.closures.len(), actual.closures.len());
+    for (expected, actual) in expected.closures.iter().zip(&actual.closures) {
+        assert_same(expected, actual);
+    }
+}
+
+// the fixtures were compiled with 8 byte size_ts and are written back with 4 byte ones, so the
+// bytes differ but every function has to read back the same
+#[test]
+fn round_trip() {
+    for (path, bytecode) in fixtures() {
+        let prototype = deserialize(&bytecode);
+        assert!(
+            !prototype.positions.is_empty(),
+            "{} was compiled without debug info",
+            path.display()
+        );
+        let serialized = serialize(&prototype);
+        let deserialized = deserialize(&serialized);
+        assert_same(&prototype, &deserialized);
+        assert!(
+            serialize(&deserialized) == serialized,
+            "{} changed when serialized again",
+            path.display()
+        );
+    }
+}
+
+// the listing shows every operand, so it only stays the same if instructions are encoded back
+// exactly as they were read
+#[test]
+fn same_listing() {
+    for (path, bytecode) in fixtures() {
+        let serialized = serialize(&deserialize(&bytecode));
+        let (_, original) = Chunk::parse(&bytecode).unwrap();
+        let (_, written) = Chunk::parse(&serialized).unwrap();
+        assert_eq!(
+            disassemble(&original.function),
+            disassemble(&written.function),
+            "{}",
+            path.display()
+        );
+    }
+}
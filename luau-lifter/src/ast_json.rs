@@ -0,0 +1,58 @@
+//! The decompiled AST as JSON, for tools that would rather consume its structure than parse the
+//! printed source.
+//!
+//! The output is an object with the schema version and the body of the main function,
+//! `{"version": 4, "body": [...]}`. Nodes are written with serde's default JSON representation:
+//!
+//! - structs are objects with a key per field, blocks are arrays of statements
+//! - enums are externally tagged, e.g. `{"Literal": {"Number": 1.0}}`, and unit variants are
+//!   strings
+//! - locals are `[id, name or null, number]`, every occurrence of the same local shares an id
+//! - closures refer to their function as `[id, function or null]`, the function is only written
+//!   the first time an id appears
+//! - string literals, global names and the text of interpolated strings are arrays of bytes as Lua
+//!   strings needn't be UTF-8
+//! - globals are `[name, environment]`, the environment is `"Function"` for the function's own
+//!   environment or `{"Local": local}` for a table that replaced it
+//! - non-finite numbers are `null`
+//!
+//! The schema follows the declarations of the AST types like the format of a saved
+//! [`LiftedChunk`](crate::LiftedChunk), so any change to them that affects it must increment
+//! [`AST_JSON_VERSION`].
+
+use ast::emitter::Emitter;
+use serde::Serialize;
+
+pub const AST_JSON_VERSION: u32 = 4;
+
+#[derive(Serialize)]
+struct AstJson<'a> {
+    version: u32,
+    body: &'a ast::Block,
+}
+
+/// Emits the decompiled AST as JSON instead of source code, see the [module](self) documentation
+/// for the schema.
+#[derive(Default, Clone)]
+pub struct AstJsonEmitter {
+    /// Indent the output
+    pub pretty: bool,
+}
+
+impl Emitter for AstJsonEmitter {
+    type Output = serde_json::Result<String>;
+
+    fn emit(&mut self, block: &ast::Block) -> Self::Output {
+        let ast_json = AstJson {
+            version: AST_JSON_VERSION,
+            body: block,
+        };
+        ast::serialize::scope(|| {
+            if self.pretty {
+                serde_json::to_string_pretty(&ast_json)
+            } else {
+                serde_json::to_string(&ast_json)
+            }
+        })
+    }
+}
@@ -0,0 +1,41 @@
+use std::fmt::Write;
+
+use sha2::{Digest, Sha256};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::deserialize_chunk;
+
+/// Returns a comment block describing the tool and the input that a decompilation was produced from,
+/// so shared output can be traced back to the exact bytecode.
+/// Compile options aren't stored in bytecode, so the debug level is inferred from what debug info is present.
+pub fn provenance_banner(bytecode: &[u8], encode_key: u8) -> anyhow::Result<String> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let has_line_info = chunk.functions.iter().any(|f| f.line_gap_log2.is_some());
+    let has_function_names = chunk.functions.iter().any(|f| f.function_name != 0);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let mut banner = String::new();
+    writeln!(
+        banner,
+        "-- decompiled by medal {}",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(banner, "-- input sha256: {:x}", Sha256::digest(bytecode))?;
+    writeln!(
+        banner,
+        "-- bytecode version: {}, types version: {}",
+        chunk.version, chunk.types_version
+    )?;
+    writeln!(
+        banner,
+        "-- encode key: {}, line info: {}, function names: {}",
+        encode_key,
+        if has_line_info { "yes" } else { "no" },
+        if has_function_names { "yes" } else { "no" }
+    )?;
+    writeln!(banner, "-- timestamp: {} (unix)", timestamp)?;
+    Ok(banner)
+}
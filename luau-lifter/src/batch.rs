@@ -0,0 +1,349 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender},
+    },
+    thread,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use web_time::Instant;
+
+use crate::{
+    append_programs, decompile_lifted_chunk, deserialize_chunk, embedded, Chunk, Decompilation,
+    DecompileOptions, DisplayEmitter, Emitter, FormatOptions, LiftedChunk, Progress, RenameMap,
+    StructureOptions, DEFAULT_PIPELINE,
+};
+
+/// Options for [`decompile_batch`]
+#[derive(Debug, Clone, Copy)]
+pub struct BatchOptions<'a> {
+    pub encode_key: u8,
+    pub renames: &'a RenameMap,
+    /// Inputs are lifted chunks saved with [`LiftedChunk::save`] instead of bytecode
+    pub lifted: bool,
+    /// Number of threads optimizing and structuring functions
+    pub jobs: usize,
+    pub format: FormatOptions,
+}
+
+/// A file decompiled by [`decompile_batch`]
+#[derive(Debug)]
+pub struct BatchOutput {
+    pub file: PathBuf,
+    /// The contents of the file
+    pub input: Vec<u8>,
+    pub decompilation: anyhow::Result<Decompilation>,
+    /// The time spent deserializing, lifting and decompiling the file
+    pub elapsed: Duration,
+}
+
+// a file on its way through the pipeline, errors are passed along to be reported with the output
+struct Job<T> {
+    file: PathBuf,
+    input: Vec<u8>,
+    state: anyhow::Result<T>,
+    elapsed: Duration,
+}
+
+impl<T> Job<T> {
+    fn then<U>(self, f: impl FnOnce(&[u8], T) -> anyhow::Result<U>) -> Job<U> {
+        let start = Instant::now();
+        let state = match self.state {
+            Ok(state) => f(&self.input, state),
+            Err(err) => Err(err),
+        };
+        Job {
+            file: self.file,
+            input: self.input,
+            state,
+            elapsed: self.elapsed + start.elapsed(),
+        }
+    }
+}
+
+enum Loaded {
+    Bytecode(Chunk<'static>),
+    Lifted(LiftedChunk),
+}
+
+fn load(input: &[u8], options: &BatchOptions) -> anyhow::Result<Loaded> {
+    if options.lifted {
+        #[cfg(feature = "checkpoint")]
+        return LiftedChunk::load(input).map(Loaded::Lifted);
+        #[cfg(not(feature = "checkpoint"))]
+        return Err(anyhow::anyhow!(
+            "loading lifted chunks requires the checkpoint feature"
+        ));
+    }
+    deserialize_chunk(input, options.encode_key).map(|chunk| Loaded::Bytecode(chunk.into_owned()))
+}
+
+// the original chunk is kept to decompile its embedded chunks
+fn lift(loaded: Loaded) -> (Option<Chunk<'static>>, LiftedChunk) {
+    match loaded {
+        Loaded::Bytecode(chunk) => {
+            let lifted = LiftedChunk::lift(&chunk);
+            (Some(chunk), lifted)
+        }
+        Loaded::Lifted(lifted) => (None, lifted),
+    }
+}
+
+fn decompile(
+    chunk: Option<Chunk<'static>>,
+    lifted: LiftedChunk,
+    options: &BatchOptions,
+) -> anyhow::Result<Decompilation> {
+    let decompiled = decompile_lifted_chunk(
+        lifted,
+        options.renames,
+        &DEFAULT_PIPELINE,
+        &Progress::default(),
+        StructureOptions::default(),
+    );
+    let mut emitter = DisplayEmitter {
+        options: options.format,
+    };
+    let mut decompilation = Decompilation::new(emitter.emit(&decompiled.body), decompiled.failures);
+    if let Some(chunk) = chunk {
+        let programs = embedded::embedded_programs(&chunk, options.encode_key);
+        let options = DecompileOptions {
+            encode_key: options.encode_key,
+            emitter,
+            ..Default::default()
+        };
+        append_programs(&mut decompilation, programs, &options);
+    }
+    Ok(decompilation)
+}
+
+fn forward<T, U>(
+    receiver: Receiver<Job<T>>,
+    sender: SyncSender<Job<U>>,
+    mut f: impl FnMut(&[u8], T) -> anyhow::Result<U>,
+) {
+    for job in receiver {
+        if sender.send(job.then(&mut f)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Decompiles many files, calling `emit` with each one as it's finished, in no particular order.
+///
+/// Files go through a pipeline where reading, deserializing, lifting, decompiling and emitting
+/// run at the same time: one thread for each of the first three stages, `options.jobs` threads
+/// optimizing and structuring whole chunks, and `emit` on the calling thread. Stages are connected
+/// by queues that hold at most `options.jobs` files, so memory use doesn't grow with the number of
+/// files when a stage falls behind.
+pub fn decompile_batch(
+    files: Vec<PathBuf>,
+    options: BatchOptions,
+    mut emit: impl FnMut(BatchOutput) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let jobs = options.jobs.max(1);
+    let options = &options;
+    let (read_sender, read_receiver) = sync_channel(jobs);
+    let (load_sender, load_receiver) = sync_channel(jobs);
+    let (lift_sender, lift_receiver) = sync_channel(jobs);
+    let (output_sender, output_receiver) = sync_channel(jobs);
+    let lift_receiver = Mutex::new(lift_receiver);
+    let cancelled = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let cancelled = &cancelled;
+        scope.spawn(move || {
+            for file in files {
+                if cancelled.load(Ordering::Relaxed) {
+                    break;
+                }
+                let (input, state) = match std::fs::read(&file) {
+                    Ok(input) => (input, Ok(())),
+                    Err(err) => (Vec::new(), Err(err.into())),
+                };
+                let job = Job {
+                    file,
+                    input,
+                    state,
+                    elapsed: Duration::ZERO,
+                };
+                if read_sender.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+        scope.spawn(move || forward(read_receiver, load_sender, |input, ()| load(input, options)));
+        scope.spawn(move || forward(load_receiver, lift_sender, |_, loaded| Ok(lift(loaded))));
+        for _ in 0..jobs {
+            let lift_receiver = &lift_receiver;
+            let output_sender = output_sender.clone();
+            scope.spawn(move || loop {
+                // the lock is released before decompiling so the other workers can take a file
+                let Ok(job) = lift_receiver.lock().recv() else {
+                    break;
+                };
+                let job = job.then(|_, (chunk, lifted)| decompile(chunk, lifted, options));
+                if output_sender.send(job).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(output_sender);
+
+        let mut result = Ok(());
+        for job in output_receiver {
+            if result.is_err() {
+                // files already in the pipeline are dropped
+                continue;
+            }
+            result = emit(BatchOutput {
+                file: job.file,
+                input: job.input,
+                decompilation: job.state,
+                elapsed: job.elapsed,
+            });
+            if result.is_err() {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+        result
+    })
+}
+
+/// Every file in `dir` and its subdirectories, sorted by path so they are decompiled in the same
+/// order every time.
+pub fn files_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Where the output of `file`, found in the directory `root`, is written to mirror `root` in
+/// `output_dir`, e.g. `root/a/b.luau` is written to `output_dir/a/b.lua`.
+pub fn mirrored_path(root: &Path, file: &Path, output_dir: &Path) -> PathBuf {
+    let relative = file.strip_prefix(root).unwrap_or(file);
+    output_dir.join(relative).with_extension("lua")
+}
+
+/// How decompiling a file went, see [`BatchReport`].
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub file: PathBuf,
+    /// Why the file couldn't be decompiled at all
+    pub error: Option<String>,
+    /// Functions that failed to decompile and the reason why
+    pub failed_functions: Vec<(usize, String)>,
+    pub warnings: usize,
+    /// The time spent deserializing, lifting and decompiling the file
+    pub milliseconds: f64,
+}
+
+/// A summary of the files decompiled by [`decompile_batch`], displayed as a line counting the
+/// files that succeeded and failed. Serializes to a report with the outcome and timing of every
+/// file.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchReport {
+    pub files: Vec<FileReport>,
+}
+
+impl BatchReport {
+    /// Adds a file as it's emitted, files are sorted by [`BatchReport::finish`].
+    pub fn add(&mut self, output: &BatchOutput) {
+        let (error, failed_functions, warnings) = match &output.decompilation {
+            Ok(decompilation) => (None, decompilation.failures.clone(), decompilation.warnings),
+            Err(err) => (Some(format!("{:#}", err)), Vec::new(), 0),
+        };
+        self.files.push(FileReport {
+            file: output.file.clone(),
+            error,
+            failed_functions,
+            warnings,
+            milliseconds: output.elapsed.as_secs_f64() * 1000.0,
+        });
+    }
+
+    /// Sorts the files by path, as they finish decompiling in any order.
+    pub fn finish(&mut self) {
+        self.files.sort_by(|a, b| a.file.cmp(&b.file));
+    }
+
+    /// Files that decompiled without failed functions
+    pub fn succeeded(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|f| f.error.is_none() && f.failed_functions.is_empty())
+            .count()
+    }
+
+    /// Files with functions that failed to decompile
+    pub fn partial(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|f| f.error.is_none() && !f.failed_functions.is_empty())
+            .count()
+    }
+
+    /// Files that couldn't be decompiled at all
+    pub fn failed(&self) -> usize {
+        self.files.iter().filter(|f| f.error.is_some()).count()
+    }
+
+    /// The time spent on all files, which is more than the batch took if it ran on several
+    /// threads
+    pub fn total_time(&self) -> Duration {
+        Duration::from_secs_f64(self.files.iter().map(|f| f.milliseconds).sum::<f64>() / 1000.0)
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "decompiled {} files: {} succeeded, {} partially, {} failed ({:.2?} spent decompiling)",
+            self.files.len(),
+            self.succeeded(),
+            self.partial(),
+            self.failed(),
+            self.total_time()
+        )
+    }
+}
+
+/// Decompiles every file in `dir` and its subdirectories with [`decompile_batch`], writing the
+/// source of each to the same path in `output_dir`, see [`mirrored_path`]. Files that aren't
+/// bytecode are reported as failures.
+pub fn decompile_directory(
+    dir: &Path,
+    output_dir: &Path,
+    options: BatchOptions,
+) -> anyhow::Result<BatchReport> {
+    let mut report = BatchReport::default();
+    decompile_batch(files_in(dir)?, options, |output| {
+        report.add(&output);
+        if let Ok(decompilation) = &output.decompilation {
+            let path = mirrored_path(dir, &output.file, output_dir);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, &decompilation.source)?;
+        }
+        Ok(())
+    })?;
+    report.finish();
+    Ok(report)
+}
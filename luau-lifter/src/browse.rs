@@ -0,0 +1,93 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    decompile_chunk, deserialize_chunk, disassembler::disassemble_function, DecompiledChunk,
+    RenameMap,
+};
+
+const HELP: &str = "\
+commands:
+  tree            list all functions
+  open <n>        select function n
+  src             print the decompiled source of the selected function
+  dis             print the disassembly of the selected function
+  save <file>     write the decompiled source of the selected function to a file
+  help            print this message
+  quit            exit";
+
+/// Runs an interactive session for exploring the functions of a chunk.
+/// Every function is decompiled up front, commands are read line by line from `input`.
+// TODO: show the instructions a statement was lifted from once the AST tracks pcs
+pub fn browse(
+    bytecode: &[u8],
+    encode_key: u8,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> anyhow::Result<()> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let function_paths = chunk.function_paths();
+    let DecompiledChunk {
+        body: main_body,
+        functions,
+        ..
+    } = decompile_chunk(&chunk, &RenameMap::default());
+    let sources = function_paths
+        .iter()
+        .map(|(function_id, _)| {
+            if *function_id == chunk.main {
+                main_body.to_string()
+            } else {
+                functions[function_id].lock().body.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut selected = 0;
+    writeln!(output, "{}", HELP)?;
+    write!(output, "{}> ", function_paths[selected].1)?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("tree"), _) => {
+                for (index, (function_id, path)) in function_paths.iter().enumerate() {
+                    let depth = path.matches('/').count();
+                    writeln!(
+                        output,
+                        "{:>4} {}{} (function {})",
+                        index,
+                        "  ".repeat(depth),
+                        path.rsplit('/').next().unwrap(),
+                        function_id
+                    )?;
+                }
+            }
+            (Some("open"), Some(index)) => match index.parse::<usize>() {
+                Ok(index) if index < function_paths.len() => selected = index,
+                _ => writeln!(output, "no function {}", index)?,
+            },
+            (Some("src"), _) => writeln!(output, "{}", sources[selected])?,
+            (Some("dis"), _) => write!(
+                output,
+                "{}",
+                disassemble_function(&chunk, function_paths[selected].0)
+            )?,
+            (Some("save"), Some(file_name)) => {
+                std::fs::write(file_name, &sources[selected])?;
+                writeln!(
+                    output,
+                    "saved {} to {}",
+                    function_paths[selected].1, file_name
+                )?;
+            }
+            (Some("quit"), _) => break,
+            (Some("help"), _) => writeln!(output, "{}", HELP)?,
+            (None, _) => {}
+            _ => writeln!(output, "unknown command, type help for a list of commands")?,
+        }
+        write!(output, "{}> ", function_paths[selected].1)?;
+        output.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+// the functions FASTCALL instructions refer to by id, in the order of LuauBuiltinFunction. 0 is
+// LBF_NONE and 54 is the vector constructor the compiler was configured with, e.g. Vector3.new
+const BUILTINS: &[&str] = &[
+    "",
+    "assert",
+    "math.abs",
+    "math.acos",
+    "math.asin",
+    "math.atan2",
+    "math.atan",
+    "math.ceil",
+    "math.cosh",
+    "math.cos",
+    "math.deg",
+    "math.exp",
+    "math.floor",
+    "math.fmod",
+    "math.frexp",
+    "math.ldexp",
+    "math.log10",
+    "math.log",
+    "math.max",
+    "math.min",
+    "math.modf",
+    "math.pow",
+    "math.rad",
+    "math.sinh",
+    "math.sin",
+    "math.sqrt",
+    "math.tanh",
+    "math.tan",
+    "bit32.arshift",
+    "bit32.band",
+    "bit32.bnot",
+    "bit32.bor",
+    "bit32.bxor",
+    "bit32.btest",
+    "bit32.extract",
+    "bit32.lrotate",
+    "bit32.lshift",
+    "bit32.replace",
+    "bit32.rrotate",
+    "bit32.rshift",
+    "type",
+    "string.byte",
+    "string.char",
+    "string.len",
+    "typeof",
+    "string.sub",
+    "math.clamp",
+    "math.sign",
+    "math.round",
+    "rawset",
+    "rawget",
+    "rawequal",
+    "table.insert",
+    "table.unpack",
+    "vector",
+    "bit32.countlz",
+    "bit32.countrz",
+    "select",
+    "rawlen",
+    // extract with a constant field and width
+    "bit32.extract",
+    "getmetatable",
+    "setmetatable",
+    "tonumber",
+    "tostring",
+    "bit32.byteswap",
+    "buffer.readi8",
+    "buffer.readu8",
+    "buffer.writeu8",
+    "buffer.readi16",
+    "buffer.readu16",
+    "buffer.writeu16",
+    "buffer.readi32",
+    "buffer.readu32",
+    "buffer.writeu32",
+    "buffer.readf32",
+    "buffer.writef32",
+    "buffer.readf64",
+    "buffer.writef64",
+    "vector.magnitude",
+    "vector.normalize",
+    "vector.cross",
+    "vector.dot",
+    "vector.floor",
+    "vector.ceil",
+    "vector.abs",
+    "vector.sign",
+    "vector.clamp",
+    "vector.min",
+    "vector.max",
+];
+
+/// The name of the library function a FASTCALL instruction calls, e.g. `math.max` for 18.
+pub(crate) fn builtin_name(id: u8) -> Option<&'static str> {
+    BUILTINS
+        .get(id as usize)
+        .copied()
+        .filter(|name| !name.is_empty())
+}
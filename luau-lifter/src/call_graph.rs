@@ -0,0 +1,267 @@
+use std::fmt;
+
+use petgraph::{
+    dot::{Config, Dot},
+    graph::{DiGraph, NodeIndex},
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    deserializer::{
+        chunk::Chunk,
+        constant::{decode_import, Constant},
+    },
+    instruction::Instruction,
+    op_code::OpCode,
+    xref::writes_a,
+};
+
+// how many definitions are followed when resolving a value, guards against cycles
+const MAX_DEPTH: usize = 16;
+
+/// What a register is known to hold
+#[derive(Debug, Clone)]
+enum Value {
+    Closure(usize),
+    Global(String),
+    Field(String),
+    Upvalue(u8),
+}
+
+#[derive(Default)]
+struct Definitions {
+    globals: FxHashMap<String, Vec<(usize, Value)>>,
+    fields: FxHashMap<String, Vec<(usize, Value)>>,
+    upvalues: FxHashMap<(usize, u8), Vec<(usize, Value)>>,
+}
+
+impl Definitions {
+    fn resolve(&self, function_id: usize, value: &Value, depth: usize) -> FxHashSet<usize> {
+        let definitions = match value {
+            Value::Closure(callee) => return [*callee].into_iter().collect(),
+            Value::Global(name) => {
+                // imports like `module.function` may refer to a field that was assigned a closure
+                let field = name.rsplit_once('.').map(|(_, field)| field);
+                self.globals
+                    .get(name)
+                    .into_iter()
+                    .chain(field.and_then(|field| self.fields.get(field)))
+                    .flatten()
+                    .collect::<Vec<_>>()
+            }
+            Value::Field(name) => self.fields.get(name).into_iter().flatten().collect(),
+            Value::Upvalue(index) => self
+                .upvalues
+                .get(&(function_id, *index))
+                .into_iter()
+                .flatten()
+                .collect(),
+        };
+        if depth == MAX_DEPTH {
+            return FxHashSet::default();
+        }
+        definitions
+            .into_iter()
+            .flat_map(|(owner, value)| self.resolve(*owner, value, depth + 1))
+            .collect()
+    }
+}
+
+/// Walks the instructions of a function, recording definitions and returning the calls made.
+/// Registers are tracked in instruction order without considering control flow.
+fn walk(chunk: &Chunk, function_id: usize, definitions: &mut Definitions) -> Vec<(usize, Value)> {
+    let function = &chunk.functions[function_id];
+    let mut calls = Vec::new();
+    let mut registers = FxHashMap::<u8, Value>::default();
+    // the closure whose upvalues are being captured and the index of the next upvalue
+    let mut capturing = None;
+    for (pc, instruction) in function.instructions.iter().enumerate() {
+        match *instruction {
+            Instruction::BC {
+                op_code: OpCode::LOP_CAPTURE,
+                a: capture_type,
+                b: source,
+                ..
+            } => {
+                if let Some((child, index)) = &mut capturing {
+                    let value = match capture_type {
+                        0 | 1 => registers.get(&source).cloned(),
+                        _ => Some(Value::Upvalue(source)),
+                    };
+                    if let Some(value) = value {
+                        definitions
+                            .upvalues
+                            .entry((*child, *index))
+                            .or_default()
+                            .push((function_id, value));
+                    }
+                    *index += 1;
+                }
+                continue;
+            }
+            Instruction::BC {
+                op_code, a, b, aux, ..
+            } => {
+                capturing = None;
+                let constant = || chunk.constant_string(function_id, aux as usize);
+                let value = match op_code {
+                    OpCode::LOP_MOVE => registers.get(&b).cloned(),
+                    OpCode::LOP_GETGLOBAL => constant().map(Value::Global),
+                    OpCode::LOP_GETTABLEKS | OpCode::LOP_NAMECALL => constant().map(Value::Field),
+                    OpCode::LOP_GETUPVAL => Some(Value::Upvalue(b)),
+                    OpCode::LOP_SETGLOBAL | OpCode::LOP_SETTABLEKS => {
+                        if let (Some(name), Some(value)) = (constant(), registers.get(&a)) {
+                            let map = if op_code == OpCode::LOP_SETGLOBAL {
+                                &mut definitions.globals
+                            } else {
+                                &mut definitions.fields
+                            };
+                            map.entry(name)
+                                .or_default()
+                                .push((function_id, value.clone()));
+                        }
+                        None
+                    }
+                    OpCode::LOP_CALL => {
+                        if let Some(value) = registers.get(&a) {
+                            calls.push((pc, value.clone()));
+                        }
+                        // results are written from A upwards
+                        registers.retain(|&register, _| register < a);
+                        None
+                    }
+                    _ => None,
+                };
+                match value {
+                    Some(value) => {
+                        registers.insert(a, value);
+                    }
+                    None if writes_a(op_code) => {
+                        registers.remove(&a);
+                    }
+                    None => {}
+                }
+            }
+            Instruction::AD { op_code, a, d, aux } => {
+                let value = match op_code {
+                    OpCode::LOP_GETIMPORT => chunk
+                        .import_path(function_id, &decode_import(aux))
+                        .map(Value::Global),
+                    OpCode::LOP_NEWCLOSURE => function
+                        .functions
+                        .get(d as u16 as usize)
+                        .map(|&child| Value::Closure(child)),
+                    OpCode::LOP_DUPCLOSURE => match function.constants.get(d as u16 as usize) {
+                        Some(&Constant::Closure(child)) => Some(Value::Closure(child)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                capturing = match value {
+                    Some(Value::Closure(child)) => Some((child, 0)),
+                    _ => None,
+                };
+                match value {
+                    Some(value) => {
+                        registers.insert(a, value);
+                    }
+                    None if writes_a(op_code) => {
+                        registers.remove(&a);
+                    }
+                    None => {}
+                }
+            }
+            Instruction::E { .. } => {}
+        }
+    }
+    calls
+}
+
+#[derive(Debug, Clone)]
+pub struct CallGraphNode {
+    pub function_id: usize,
+    pub function_path: String,
+}
+
+impl fmt::Display for CallGraphNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.function_path)
+    }
+}
+
+/// The pc of a call instruction
+#[derive(Debug, Clone, Copy)]
+pub struct CallSite(pub usize);
+
+impl fmt::Display for CallSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pc {}", self.0)
+    }
+}
+
+/// An approximate call graph with an edge from a function to every function it may call.
+/// Callees are found by following closures through registers, upvalues, globals, imports and
+/// table fields (by field name only). Calls of values that can't be traced aren't included.
+pub struct CallGraph {
+    pub graph: DiGraph<CallGraphNode, CallSite>,
+    nodes: FxHashMap<usize, NodeIndex>,
+}
+
+impl CallGraph {
+    /// The node of a function, if it is reachable from the main function.
+    pub fn node(&self, function_id: usize) -> Option<NodeIndex> {
+        self.nodes.get(&function_id).copied()
+    }
+
+    /// The ids of the functions that `function_id` may call.
+    pub fn callees(&self, function_id: usize) -> Vec<usize> {
+        let mut callees = self
+            .node(function_id)
+            .into_iter()
+            .flat_map(|node| self.graph.neighbors(node))
+            .map(|node| self.graph[node].function_id)
+            .collect::<Vec<_>>();
+        callees.sort_unstable();
+        callees.dedup();
+        callees
+    }
+
+    pub fn to_dot(&self) -> String {
+        Dot::with_config(&self.graph, &[Config::EdgeNoLabel]).to_string()
+    }
+}
+
+impl Chunk<'_> {
+    pub fn call_graph(&self) -> CallGraph {
+        let mut graph = DiGraph::new();
+        let mut nodes = FxHashMap::default();
+        let mut definitions = Definitions::default();
+        let mut calls = Vec::new();
+        for (function_id, function_path) in self.function_paths() {
+            nodes.insert(
+                function_id,
+                graph.add_node(CallGraphNode {
+                    function_id,
+                    function_path,
+                }),
+            );
+            calls.push((function_id, walk(self, function_id, &mut definitions)));
+        }
+        // definitions can come after the calls that use them, so calls are resolved last
+        for (caller, calls) in calls {
+            for (pc, value) in calls {
+                let mut callees = definitions
+                    .resolve(caller, &value, 0)
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                callees.sort_unstable();
+                for callee in callees {
+                    if let Some(&callee) = nodes.get(&callee) {
+                        graph.add_edge(nodes[&caller], callee, CallSite(pc));
+                    }
+                }
+            }
+        }
+        CallGraph { graph, nodes }
+    }
+}
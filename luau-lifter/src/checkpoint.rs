@@ -0,0 +1,231 @@
+//! The binary format of a saved [`LiftedChunk`], which lets other tools read the lifted control
+//! flow graphs without reimplementing the bytecode frontends.
+//!
+//! A file starts with the 4 byte magic `MDLR` and the format version as a little endian `u32`,
+//! followed by the chunk encoded with [bincode]'s default options:
+//!
+//! - integers are fixed width little endian, `usize` is written as a `u64`
+//! - strings, byte strings and sequences are a `u64` length followed by their elements
+//! - an `Option` is a `u8` tag (0 for `None`, 1 for `Some`) followed by the value if present
+//! - an enum is its variant index as a `u32` followed by the fields of the variant
+//! - structs and tuples are their fields in declaration order, without any framing
+//!
+//! The chunk is a sequence of functions, main first and parents before their children. Each one
+//! is a `(id, Option<ast::Function>)` pair that closures refer to by id (the function is only
+//! written the first time an id appears), the [`cfg::function::Function`] with its graph in
+//! petgraph's `StableGraph` serde layout, the upvalues of the function and the number to give the
+//! next local created in it. Locals are written as `(id, Option<name>, number, scope)`, every
+//! occurrence of the same local shares an id, including the upvalues of a closure and the locals
+//! its parent captures for them, and the scope is the one the local was numbered in, see
+//! [`ast::number_locals`]. The functions are followed by the path of every function in the chunk,
+//! a map written as its length and `(id, path)` pairs.
+//!
+//! The layout follows the declarations of the AST and CFG types, so any change to them that
+//! affects it, including reordering enum variants, must increment [`LiftedChunk::FORMAT_VERSION`].
+
+#[cfg(feature = "checkpoint")]
+use anyhow::anyhow;
+use by_address::ByAddress;
+use cfg::function::Function;
+use parking_lot::Mutex;
+use pipeline::{Cancelled, Progress};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use triomphe::Arc;
+use web_time::Instant;
+
+use crate::{
+    deserialize_chunk,
+    deserializer::chunk::Chunk,
+    lifter::{Lifter, UpvalueContext},
+    select::function_paths,
+};
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LiftedFunction {
+    /// The function that closures of this function refer to, filled in once it is decompiled
+    #[serde(with = "ast::serialize::shared_function")]
+    pub ast_function: ByAddress<Arc<Mutex<ast::Function>>>,
+    pub function: Function,
+    pub upvalues: Vec<ast::RcLocal>,
+    /// Locals created while decompiling the function are numbered from here, see
+    /// [`ast::number_locals`]
+    pub next_local_id: usize,
+}
+
+/// Every function of a chunk after lifting and before SSA construction and structuring.
+/// Lifting a large chunk is expensive, so this can be saved to disk and decompiled later,
+/// which is also useful for attaching the state that triggers a bug to a report.
+#[derive(Serialize, Deserialize)]
+pub struct LiftedChunk {
+    /// Parents come before their children, the first function is the main function
+    pub(crate) functions: Vec<LiftedFunction>,
+    /// The path of every function in the chunk by id, see
+    /// [`FunctionInfo::path`](crate::FunctionInfo::path)
+    pub(crate) paths: FxHashMap<usize, String>,
+}
+
+// well above the nesting the Luau compiler allows
+const MAX_DEPTH: usize = 256;
+
+// a function that only returns, with a comment explaining why it wasn't lifted
+fn placeholder_function(function_id: usize, comment: String) -> Function {
+    let mut function = Function::new(function_id);
+    let entry = function.new_block();
+    function.set_entry(entry);
+    function.block_mut(entry).unwrap().extend([
+        ast::Comment::new(comment).into(),
+        ast::Return::new(Vec::new()).into(),
+    ]);
+    function
+}
+
+fn unliftable_function(function_id: usize, error: &str) -> Function {
+    placeholder_function(function_id, format!("warning: {}", error))
+}
+
+#[cfg(feature = "checkpoint")]
+const MAGIC: &[u8; 4] = b"MDLR";
+
+impl LiftedChunk {
+    /// The version of the [binary format](self) written by [`LiftedChunk::save`].
+    pub const FORMAT_VERSION: u32 = 8;
+
+    pub(crate) fn lift(chunk: &Chunk) -> Self {
+        Self::lift_function(chunk, chunk.main, true)
+    }
+
+    /// Lifts a function of the chunk as if it was the main function. Its closures are lifted
+    /// too if `children` is set, otherwise they are left empty apart from a comment.
+    pub(crate) fn lift_function(chunk: &Chunk, root: usize, children: bool) -> Self {
+        // nothing else holds the token, so it can't be cancelled
+        Self::lift_with_progress(chunk, root, children, &Progress::default()).unwrap()
+    }
+
+    /// Like [`LiftedChunk::lift_function`], but reports every function lifted to `progress` as
+    /// the `"lifting"` stage and stops once it's cancelled.
+    pub(crate) fn lift_with_progress(
+        chunk: &Chunk,
+        root: usize,
+        children: bool,
+        progress: &Progress,
+    ) -> Result<Self, Cancelled> {
+        let start = Instant::now();
+        let mut functions = Vec::new();
+        // closures are lifted iteratively along with the functions they are nested in, which a
+        // hostile chunk could make arbitrarily deep or cyclic
+        let mut stack = vec![(
+            ByAddress(Arc::default()),
+            root,
+            UpvalueContext::default(),
+            Vec::new(),
+        )];
+        while let Some((ast_function, function_id, upvalue_context, ancestors)) = stack.pop() {
+            progress.check()?;
+            progress.report("lifting", functions.len(), chunk.functions.len());
+            if !children && !ancestors.is_empty() {
+                functions.push(LiftedFunction {
+                    ast_function,
+                    function: placeholder_function(
+                        function_id,
+                        format!("function {} isn't decompiled", function_id),
+                    ),
+                    upvalues: Vec::new(),
+                    next_local_id: 1,
+                });
+                continue;
+            }
+            let error = if ancestors.contains(&function_id) {
+                Some("function is nested in itself")
+            } else if ancestors.len() >= MAX_DEPTH {
+                Some("functions are nested too deeply")
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                functions.push(LiftedFunction {
+                    ast_function,
+                    function: unliftable_function(function_id, error),
+                    upvalues: Vec::new(),
+                    next_local_id: 1,
+                });
+                continue;
+            }
+            let (lifted, next_local_id) = ast::number_locals(function_id, 1, || {
+                Lifter::lift(
+                    &chunk.functions,
+                    &chunk.string_table,
+                    function_id,
+                    &upvalue_context,
+                )
+            });
+            let (function, upvalues, child_functions) = match lifted {
+                Ok(lifted) => lifted,
+                Err(error) => {
+                    functions.push(LiftedFunction {
+                        ast_function,
+                        function: unliftable_function(function_id, &error.to_string()),
+                        upvalues: Vec::new(),
+                        next_local_id: 1,
+                    });
+                    continue;
+                }
+            };
+            functions.push(LiftedFunction {
+                ast_function,
+                function,
+                upvalues,
+                next_local_id,
+            });
+            let mut ancestors = ancestors;
+            ancestors.push(function_id);
+            stack.extend(child_functions.into_iter().map(
+                |(ast_function, (child, upvalue_context))| {
+                    (ast_function, child, upvalue_context, ancestors.clone())
+                },
+            ));
+        }
+        log::debug!(
+            "lifting {} functions took {:?}",
+            functions.len(),
+            start.elapsed()
+        );
+        progress.report("lifting", functions.len(), chunk.functions.len());
+        Ok(Self {
+            functions,
+            paths: function_paths(chunk).into_iter().collect(),
+        })
+    }
+
+    /// Lifts every function in the bytecode.
+    pub fn from_bytecode(bytecode: &[u8], encode_key: u8) -> anyhow::Result<Self> {
+        Ok(Self::lift(&deserialize_chunk(bytecode, encode_key)?))
+    }
+
+    #[cfg(feature = "checkpoint")]
+    pub fn save(&self) -> anyhow::Result<Vec<u8>> {
+        let mut data = MAGIC.to_vec();
+        data.extend(Self::FORMAT_VERSION.to_le_bytes());
+        ast::serialize::scope(|| bincode::serialize_into(&mut data, self))?;
+        Ok(data)
+    }
+
+    #[cfg(feature = "checkpoint")]
+    pub fn load(data: &[u8]) -> anyhow::Result<Self> {
+        let data = data
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| anyhow!("not a lifted chunk"))?;
+        let (version, data) = data
+            .split_first_chunk()
+            .ok_or_else(|| anyhow!("not a lifted chunk"))?;
+        let version = u32::from_le_bytes(*version);
+        if version != Self::FORMAT_VERSION {
+            return Err(anyhow!(
+                "unsupported lifted chunk version {}, expected {}",
+                version,
+                Self::FORMAT_VERSION
+            ));
+        }
+        Ok(ast::serialize::scope(|| bincode::deserialize(data))?)
+    }
+}
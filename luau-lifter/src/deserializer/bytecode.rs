@@ -1,15 +1,15 @@
-use nom::{bytes::complete::take, number::complete::le_u8, IResult};
+use nom::{bytes::complete::take, number::complete::le_u8};
 
-use super::chunk::Chunk;
+use super::{chunk::Chunk, error::IResult, BytecodeVersion};
 
 #[derive(Debug)]
-pub enum Bytecode {
+pub enum Bytecode<'a> {
     Error(String),
-    Chunk(Chunk),
+    Chunk(Chunk<'a>),
 }
 
-impl Bytecode {
-    pub fn parse(input: &[u8], encode_key: u8) -> IResult<&[u8], Bytecode> {
+impl<'a> Bytecode<'a> {
+    pub fn parse(input: &'a [u8], encode_key: u8) -> IResult<'a, Self> {
         let (input, status_code) = le_u8(input)?;
         match status_code {
             0 => {
@@ -19,11 +19,11 @@ impl Bytecode {
                     Bytecode::Error(String::from_utf8_lossy(error_msg).to_string()),
                 ))
             }
-            4..=6 => {
-                let (input, chunk) = Chunk::parse(input, encode_key, status_code)?;
+            _ => {
+                let version = BytecodeVersion::try_from(status_code).map_err(nom::Err::Failure)?;
+                let (input, chunk) = Chunk::parse(input, encode_key, version)?;
                 Ok((input, Bytecode::Chunk(chunk)))
             }
-            _ => panic!("Unsupported bytecode version: {}", status_code),
         }
     }
 }
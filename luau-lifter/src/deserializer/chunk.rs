@@ -1,43 +1,149 @@
-use super::{function::Function, list::parse_list, parse_string};
+use std::borrow::Cow;
+
+use super::{
+    constant::Constant, error::IResult, function::Function, list::parse_list,
+    string_table::StringTable, BytecodeVersion, DeserializeError,
+};
 use nom::character::complete::char;
 use nom::multi::many_till;
 use nom::number::complete::le_u8;
-use nom::IResult;
 use nom_leb128::leb128_usize;
+use rustc_hash::FxHashSet;
 
 #[derive(Debug)]
-pub struct Chunk {
-    pub string_table: Vec<Vec<u8>>,
+pub struct Chunk<'a> {
+    pub version: BytecodeVersion,
+    pub types_version: u8,
+    pub userdata_types: Vec<usize>,
+    pub string_table: StringTable<'a>,
     pub functions: Vec<Function>,
     pub main: usize,
 }
 
-impl Chunk {
-    pub(crate) fn parse(input: &[u8], encode_key: u8, version: u8) -> IResult<&[u8], Self> {
-        let (input, types_version) = if version >= 4 {
+impl<'a> Chunk<'a> {
+    pub(crate) fn parse(
+        input: &'a [u8],
+        encode_key: u8,
+        version: BytecodeVersion,
+    ) -> IResult<'a, Self> {
+        let (input, types_version) = if version.has_type_info() {
             le_u8(input)?
         } else {
             (input, 0)
         };
         if types_version > 3 {
-            panic!("unsupported types version");
+            return Err(nom::Err::Failure(
+                DeserializeError::UnsupportedTypesVersion(types_version),
+            ));
         }
-        let (input, string_table) = parse_list(input, parse_string)?;
-        let input = if types_version == 3 {
-            many_till(leb128_usize, char('\0'))(input)?.0
+        let (input, string_table) = StringTable::parse(input)?;
+        let (input, userdata_types) = if types_version == 3 {
+            let (input, (userdata_types, _)) = many_till(leb128_usize, char('\0'))(input)?;
+            (input, userdata_types)
         } else {
-            input
+            (input, Vec::new())
         };
-        let (input, functions) = parse_list(input, |i| Function::parse(i, encode_key))?;
+        let (input, functions) = parse_list(input, |i| Function::parse(i, encode_key, version))?;
         let (input, main) = leb128_usize(input)?;
+        Self::validate_function_ids(&functions, main).map_err(nom::Err::Failure)?;
 
         Ok((
             input,
             Self {
+                version,
+                types_version,
+                userdata_types,
                 string_table,
                 functions,
                 main,
             },
         ))
     }
+
+    // functions are indexed with these ids everywhere, so they're checked once here
+    fn validate_function_ids(functions: &[Function], main: usize) -> Result<(), DeserializeError> {
+        let check = |id: usize| {
+            if id < functions.len() {
+                Ok(())
+            } else {
+                Err(DeserializeError::InvalidFunctionId(id))
+            }
+        };
+        check(main)?;
+        for function in functions {
+            function.functions.iter().try_for_each(|&id| check(id))?;
+            for constant in &function.constants {
+                if let &Constant::Closure(id) = constant {
+                    check(id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the strings the chunk borrows from its bytecode, so it can outlive it.
+    pub fn into_owned(self) -> Chunk<'static> {
+        Chunk {
+            version: self.version,
+            types_version: self.types_version,
+            userdata_types: self.userdata_types,
+            string_table: self.string_table.into_owned(),
+            functions: self.functions,
+            main: self.main,
+        }
+    }
+
+    /// Returns the debug name of a function, if it has one.
+    pub fn function_name(&self, function_id: usize) -> Option<String> {
+        // 0 means the function is anonymous
+        self.string_table
+            .get(self.functions[function_id].function_name)
+            .map(Cow::into_owned)
+    }
+
+    /// Returns the contents of a string constant of a function.
+    pub fn constant_string(&self, function_id: usize, index: usize) -> Option<String> {
+        match self.functions[function_id].constants.get(index)? {
+            &Constant::String(string_index) => {
+                self.string_table.get(string_index).map(Cow::into_owned)
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolves the path of an import (see [`decode_import`](super::constant::decode_import)) to a
+    /// dotted path, e.g. `math.floor`.
+    pub fn import_path(&self, function_id: usize, path: &[usize]) -> Option<String> {
+        path.iter()
+            .map(|&index| self.constant_string(function_id, index))
+            .collect::<Option<Vec<_>>>()
+            .map(|names| names.join("."))
+    }
+
+    /// Returns every function reachable from the main function along with a path
+    /// describing where it is nested, e.g. `main/foo/#2` for the third (anonymous)
+    /// closure inside `foo`.
+    pub fn function_paths(&self) -> Vec<(usize, String)> {
+        let mut paths = Vec::new();
+        let mut visited = FxHashSet::default();
+        let mut stack = vec![(self.main, "main".to_string())];
+        while let Some((function_id, path)) = stack.pop() {
+            if !visited.insert(function_id) {
+                continue;
+            }
+            for (index, &child_id) in self.functions[function_id]
+                .functions
+                .iter()
+                .enumerate()
+                .rev()
+            {
+                let child_name = self
+                    .function_name(child_id)
+                    .unwrap_or_else(|| format!("#{}", index));
+                stack.push((child_id, format!("{}/{}", path, child_name)));
+            }
+            paths.push((function_id, path));
+        }
+        paths
+    }
 }
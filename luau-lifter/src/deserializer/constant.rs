@@ -1,8 +1,5 @@
-use super::list::parse_list;
-use nom::{
-    number::complete::{le_f32, le_f64, le_u32, le_u8},
-    IResult,
-};
+use super::{error::IResult, list::parse_list, BytecodeVersion, DeserializeError};
+use nom::number::complete::{le_f32, le_f64, le_u32, le_u8};
 use nom_leb128::leb128_usize;
 
 const CONSTANT_NIL: u8 = 0;
@@ -14,20 +11,35 @@ const CONSTANT_TABLE: u8 = 5;
 const CONSTANT_CLOSURE: u8 = 6;
 const CONSTANT_VECTOR: u8 = 7;
 
+/// Splits an import id (an import constant or `GETIMPORT` aux) into the indices of its string
+/// constants: the length in the top 2 bits followed by up to three 10 bit indices.
+pub fn decode_import(id: u32) -> Vec<usize> {
+    let len = (id >> 30) as usize;
+    [(id >> 20) & 1023, (id >> 10) & 1023, id & 1023]
+        .into_iter()
+        .take(len)
+        .map(|index| index as usize)
+        .collect()
+}
+
 #[derive(Debug)]
 pub enum Constant {
     Nil,
     Boolean(bool),
     Number(f64),
     String(usize),
-    Import(usize),
+    /// A global followed by up to two fields, encoded as in `GETIMPORT`. The id is kept as is, so
+    /// unused bits survive serialization, and decoded with [`decode_import`].
+    Import {
+        id: u32,
+    },
     Table(Vec<usize>),
     Closure(usize),
     Vector(f32, f32, f32, f32),
 }
 
 impl Constant {
-    pub(crate) fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+    pub(crate) fn parse(input: &[u8], version: BytecodeVersion) -> IResult<'_, Self> {
         let (input, tag) = le_u8(input)?;
         match tag {
             CONSTANT_NIL => Ok((input, Constant::Nil)),
@@ -44,8 +56,8 @@ impl Constant {
                 Ok((input, Constant::String(string_index)))
             }
             CONSTANT_IMPORT => {
-                let (input, import_index) = le_u32(input)?;
-                Ok((input, Constant::Import(import_index as usize)))
+                let (input, id) = le_u32(input)?;
+                Ok((input, Constant::Import { id }))
             }
             CONSTANT_TABLE => {
                 let (input, keys) = parse_list(input, leb128_usize)?;
@@ -55,14 +67,14 @@ impl Constant {
                 let (input, f_id) = leb128_usize(input)?;
                 Ok((input, Constant::Closure(f_id)))
             }
-            CONSTANT_VECTOR => {
+            CONSTANT_VECTOR if version.has_vector_constants() => {
                 let (input, x) = le_f32(input)?;
                 let (input, y) = le_f32(input)?;
                 let (input, z) = le_f32(input)?;
                 let (input, w) = le_f32(input)?;
                 Ok((input, Constant::Vector(x, y, z, w)))
             }
-            _ => panic!("{}", tag),
+            _ => Err(nom::Err::Failure(DeserializeError::UnknownConstant(tag))),
         }
     }
 }
@@ -0,0 +1,48 @@
+use nom::number::complete::le_u8;
+use nom_leb128::leb128_usize;
+
+use super::{error::IResult, list::parse_list};
+
+/// A local variable of a function, live in `register` while the pc is in `start_pc..end_pc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalVariable {
+    /// Index into the string table
+    pub name: usize,
+    pub start_pc: usize,
+    pub end_pc: usize,
+    pub register: u8,
+}
+
+impl LocalVariable {
+    fn parse(input: &[u8]) -> IResult<'_, Self> {
+        let (input, name) = leb128_usize(input)?;
+        let (input, start_pc) = leb128_usize(input)?;
+        let (input, end_pc) = leb128_usize(input)?;
+        let (input, register) = le_u8(input)?;
+        Ok((
+            input,
+            Self {
+                name,
+                start_pc,
+                end_pc,
+                register,
+            },
+        ))
+    }
+}
+
+/// The names of a function's locals and upvalues, only compiled in with `-g1` or higher.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    pub locals: Vec<LocalVariable>,
+    /// Indices into the string table, in the order of the upvalues
+    pub upvalues: Vec<usize>,
+}
+
+impl DebugInfo {
+    pub(crate) fn parse(input: &[u8]) -> IResult<'_, Self> {
+        let (input, locals) = parse_list(input, LocalVariable::parse)?;
+        let (input, upvalues) = parse_list(input, leb128_usize)?;
+        Ok((input, Self { locals, upvalues }))
+    }
+}
@@ -0,0 +1,42 @@
+use nom::error::{ErrorKind, FromExternalError, ParseError};
+use thiserror::Error;
+
+/// Why a chunk couldn't be deserialized.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DeserializeError {
+    #[error("unsupported bytecode version {0}")]
+    UnsupportedVersion(u8),
+    #[error("unsupported types version {0}")]
+    UnsupportedTypesVersion(u8),
+    #[error("unknown constant type {0}")]
+    UnknownConstant(u8),
+    #[error("invalid instruction {instruction:#010x} at pc {pc}")]
+    InvalidInstruction { pc: usize, instruction: u32 },
+    #[error("instruction at pc {0} is missing its aux word")]
+    MissingAux(usize),
+    /// The main function, a child function or a closure constant refers to a function that
+    /// doesn't exist
+    #[error("function id {0} is out of range")]
+    InvalidFunctionId(usize),
+    /// A nom parser failed, usually because the input ended early
+    #[error("malformed bytecode ({0:?})")]
+    Malformed(ErrorKind),
+}
+
+impl<I> ParseError<I> for DeserializeError {
+    fn from_error_kind(_: I, kind: ErrorKind) -> Self {
+        Self::Malformed(kind)
+    }
+
+    fn append(_: I, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I, E> FromExternalError<I, E> for DeserializeError {
+    fn from_external_error(_: I, kind: ErrorKind, _: E) -> Self {
+        Self::Malformed(kind)
+    }
+}
+
+pub(crate) type IResult<'a, T> = nom::IResult<&'a [u8], T, DeserializeError>;
@@ -1,15 +1,12 @@
-use core::num;
-
-use nom::{
-    complete::take,
-    number::complete::{le_u32, le_u8},
-    IResult,
-};
+use nom::number::complete::{le_u32, le_u8};
 use nom_leb128::leb128_usize;
 
 use super::{
     constant::Constant,
+    debug_info::DebugInfo,
+    error::IResult,
     list::{parse_list, parse_list_len},
+    BytecodeVersion, DeserializeError,
 };
 
 use crate::{instruction::*, op_code::OpCode};
@@ -20,7 +17,10 @@ pub struct Function {
     pub num_parameters: u8,
     pub num_upvalues: u8,
     pub is_vararg: bool,
-    //pub instructions: Vec<u32>,
+    pub flags: u8,
+    pub type_info: Vec<u8>,
+    /// The encoded instruction words, kept so the function can be serialized again.
+    pub code: Vec<u32>,
     pub instructions: Vec<Instruction>,
     pub constants: Vec<Constant>,
     pub functions: Vec<usize>,
@@ -29,100 +29,93 @@ pub struct Function {
     pub line_gap_log2: Option<u8>,
     pub line_info_delta: Option<Vec<u8>>,
     pub abs_line_info_delta: Option<Vec<u32>>,
+    pub debug_info: Option<DebugInfo>,
 }
 
 impl Function {
-    fn parse_instructions(vec: &Vec<u32>, encode_key: u8) -> Vec<Instruction> {
+    fn parse_instructions(
+        vec: &[u32],
+        encode_key: u8,
+        version: BytecodeVersion,
+    ) -> Result<Vec<Instruction>, DeserializeError> {
         let mut v: Vec<Instruction> = Vec::new();
         let mut pc = 0;
 
-        loop {
-            let ins = Instruction::parse(vec[pc], encode_key).unwrap();
+        while pc < vec.len() {
+            let invalid = DeserializeError::InvalidInstruction {
+                pc,
+                instruction: vec[pc],
+            };
+            let ins = Instruction::parse(vec[pc], encode_key).map_err(|_| invalid.clone())?;
             let op = match ins {
                 Instruction::BC { op_code, .. } => op_code,
                 Instruction::AD { op_code, .. } => op_code,
                 Instruction::E { op_code, .. } => op_code,
             };
+            if !version.supports(op) {
+                return Err(invalid);
+            }
 
             // handle ops with aux values
-            match op {
-                OpCode::LOP_GETGLOBAL
-                | OpCode::LOP_SETGLOBAL
-                | OpCode::LOP_GETIMPORT
-                | OpCode::LOP_GETTABLEKS
-                | OpCode::LOP_SETTABLEKS
-                | OpCode::LOP_NAMECALL
-                | OpCode::LOP_JUMPIFEQ
-                | OpCode::LOP_JUMPIFLE
-                | OpCode::LOP_JUMPIFLT
-                | OpCode::LOP_JUMPIFNOTEQ
-                | OpCode::LOP_JUMPIFNOTLE
-                | OpCode::LOP_JUMPIFNOTLT
-                | OpCode::LOP_NEWTABLE
-                | OpCode::LOP_SETLIST
-                | OpCode::LOP_FORGLOOP
-                | OpCode::LOP_LOADKX
-                | OpCode::LOP_FASTCALL2
-                | OpCode::LOP_FASTCALL2K
-                | OpCode::LOP_FASTCALL3
-                | OpCode::LOP_JUMPXEQKNIL
-                | OpCode::LOP_JUMPXEQKB
-                | OpCode::LOP_JUMPXEQKN
-                | OpCode::LOP_JUMPXEQKS => {
-                    let aux = vec[pc + 1];
-                    pc += 2;
-                    match ins {
-                        Instruction::BC {
-                            op_code, a, b, c, ..
-                        } => {
-                            v.push(Instruction::BC {
-                                op_code,
-                                a,
-                                b,
-                                c,
-                                aux,
-                            });
-                        }
-                        Instruction::AD { op_code, a, d, .. } => {
-                            v.push(Instruction::AD { op_code, a, d, aux });
-                        }
-                        _ => unreachable!(),
+            if op.has_aux() {
+                let aux = *vec.get(pc + 1).ok_or(DeserializeError::MissingAux(pc))?;
+                pc += 2;
+                match ins {
+                    Instruction::BC {
+                        op_code, a, b, c, ..
+                    } => {
+                        v.push(Instruction::BC {
+                            op_code,
+                            a,
+                            b,
+                            c,
+                            aux,
+                        });
                     }
-                    v.push(Instruction::BC {
-                        op_code: OpCode::LOP_NOP,
-                        a: 0,
-                        b: 0,
-                        c: 0,
-                        aux: 0,
-                    });
-                }
-                _ => {
-                    v.push(ins);
-                    pc += 1;
+                    Instruction::AD { op_code, a, d, .. } => {
+                        v.push(Instruction::AD { op_code, a, d, aux });
+                    }
+                    _ => unreachable!(),
                 }
-            }
-
-            if pc == vec.len() {
-                break;
+                v.push(Instruction::BC {
+                    op_code: OpCode::LOP_NOP,
+                    a: 0,
+                    b: 0,
+                    c: 0,
+                    aux: 0,
+                });
+            } else {
+                v.push(ins);
+                pc += 1;
             }
         }
 
-        v
+        Ok(v)
     }
 
-    pub(crate) fn parse(input: &[u8], encode_key: u8) -> IResult<&[u8], Self> {
+    pub(crate) fn parse(
+        input: &[u8],
+        encode_key: u8,
+        version: BytecodeVersion,
+    ) -> IResult<'_, Self> {
         let (input, max_stack_size) = le_u8(input)?;
         let (input, num_parameters) = le_u8(input)?;
         let (input, num_upvalues) = le_u8(input)?;
         let (input, is_vararg) = le_u8(input)?;
 
-        let (input, flags) = le_u8(input)?;
-        let (input, _) = parse_list(input, le_u8)?;
+        let (input, (flags, type_info)) = if version.has_type_info() {
+            let (input, flags) = le_u8(input)?;
+            let (input, type_info) = parse_list(input, le_u8)?;
+            (input, (flags, type_info))
+        } else {
+            (input, (0, Vec::new()))
+        };
 
         let (input, u32_instructions) = parse_list(input, le_u32)?;
         //let (input, instructions) = parse_list(input, Function::parse_instrution)?;
-        let instructions = Self::parse_instructions(&u32_instructions, encode_key);
-        let (input, constants) = parse_list(input, Constant::parse)?;
+        let instructions = Self::parse_instructions(&u32_instructions, encode_key, version)
+            .map_err(nom::Err::Failure)?;
+        let (input, constants) = parse_list(input, |i| Constant::parse(i, version))?;
         let (input, functions) = parse_list(input, leb128_usize)?;
         let (input, line_defined) = leb128_usize(input)?;
         let (input, function_name) = leb128_usize(input)?;
@@ -145,30 +138,19 @@ impl Function {
         let (input, abs_line_info_delta) = match has_line_info {
             0 => (input, None),
             _ => {
-                let (input, abs_line_info_delta) = parse_list_len(
-                    input,
-                    le_u32,
-                    ((u32_instructions.len() - 1) >> line_gap_log2.unwrap()) + 1,
-                )?;
+                // one absolute line for every 2^line_gap_log2 instructions
+                let intervals = u32_instructions.len().checked_sub(1).map_or(0, |last| {
+                    last.checked_shr(line_gap_log2.unwrap().into()).unwrap_or(0) + 1
+                });
+                let (input, abs_line_info_delta) = parse_list_len(input, le_u32, intervals)?;
                 (input, Some(abs_line_info_delta))
             }
         };
-        let input = match le_u8(input)? {
-            (input, 0) => input,
+        let (input, debug_info) = match le_u8(input)? {
+            (input, 0) => (input, None),
             (input, _) => {
-                panic!("we have debug info");
-                let (mut input, num_locvars) = leb128_usize(input)?;
-                for _ in 0..num_locvars {
-                    (input, _) = leb128_usize(input)?;
-                    (input, _) = leb128_usize(input)?;
-                    (input, _) = leb128_usize(input)?;
-                    (input, _) = le_u8(input)?;
-                }
-                let (mut input, num_upvalues) = leb128_usize(input)?;
-                for _ in 0..num_upvalues {
-                    (input, _) = leb128_usize(input)?;
-                }
-                input
+                let (input, debug_info) = DebugInfo::parse(input)?;
+                (input, Some(debug_info))
             }
         };
         Ok((
@@ -178,6 +160,9 @@ impl Function {
                 num_parameters,
                 num_upvalues,
                 is_vararg: is_vararg != 0u8,
+                flags,
+                type_info,
+                code: u32_instructions,
                 instructions,
                 constants,
                 functions,
@@ -186,6 +171,7 @@ impl Function {
                 line_gap_log2,
                 line_info_delta,
                 abs_line_info_delta,
+                debug_info,
             },
         ))
     }
@@ -1,22 +1,27 @@
-use nom::{bytes::complete::take, IResult};
-use nom_leb128::leb128_usize;
+use nom::error::ErrorKind;
 
 pub mod bytecode;
 pub mod chunk;
 pub mod constant;
+pub mod debug_info;
+mod error;
 pub mod function;
 mod list;
+pub mod string_table;
+pub mod version;
 
-fn parse_string(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
-    let (input, length) = leb128_usize(input)?;
-    let (input, bytes) = take(length)(input)?;
-    Ok((input, bytes.to_owned()))
-}
+pub use error::DeserializeError;
+pub use version::BytecodeVersion;
 
-pub fn deserialize(bytecode: &[u8], encode_key: u8) -> Result<bytecode::Bytecode, String> {
+pub fn deserialize(
+    bytecode: &[u8],
+    encode_key: u8,
+) -> Result<bytecode::Bytecode<'_>, DeserializeError> {
     match bytecode::Bytecode::parse(bytecode, encode_key) {
         Ok((_, deserialized_bytecode)) => Ok(deserialized_bytecode),
-        Err(err) => Err(err.to_string()),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(err),
+        // the parsers are all complete
+        Err(nom::Err::Incomplete(_)) => Err(DeserializeError::Malformed(ErrorKind::Eof)),
     }
 }
 
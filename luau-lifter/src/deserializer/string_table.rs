@@ -0,0 +1,70 @@
+use std::borrow::Cow;
+
+use nom::bytes::complete::take;
+use nom_leb128::leb128_usize;
+
+use super::{error::IResult, list::parse_list};
+
+fn parse_string(input: &[u8]) -> IResult<'_, Cow<'_, [u8]>> {
+    let (input, length) = leb128_usize(input)?;
+    let (input, bytes) = take(length)(input)?;
+    Ok((input, Cow::Borrowed(bytes)))
+}
+
+/// The strings of a chunk, borrowed from the bytecode they were deserialized from.
+///
+/// Constants and debug names refer to strings with 1-based indices where 0 means there is no
+/// string, every method here takes indices in that form. Strings aren't required to be UTF-8,
+/// they're only decoded when they're looked up with [`StringTable::get`].
+#[derive(Debug, Clone, Default)]
+pub struct StringTable<'a>(Vec<Cow<'a, [u8]>>);
+
+impl<'a> StringTable<'a> {
+    pub(crate) fn parse(input: &'a [u8]) -> IResult<'a, Self> {
+        let (input, strings) = parse_list(input, parse_string)?;
+        Ok((input, Self(strings)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the raw bytes of a string.
+    pub fn get_bytes(&self, index: usize) -> Option<&[u8]> {
+        self.0.get(index.checked_sub(1)?).map(|string| &**string)
+    }
+
+    /// Returns a string decoded as UTF-8, invalid sequences are replaced and only then is the
+    /// string copied.
+    pub fn get(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.get_bytes(index).map(String::from_utf8_lossy)
+    }
+
+    /// Replaces a string, returning `None` if there is no string at `index`.
+    pub fn set(&mut self, index: usize, value: Vec<u8>) -> Option<()> {
+        *self.0.get_mut(index.checked_sub(1)?)? = Cow::Owned(value);
+        Some(())
+    }
+
+    /// Iterates over the index and bytes of every string.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(index, string)| (index + 1, &**string))
+    }
+
+    /// Copies every borrowed string so the table no longer borrows the bytecode.
+    pub fn into_owned(self) -> StringTable<'static> {
+        StringTable(
+            self.0
+                .into_iter()
+                .map(|string| Cow::Owned(string.into_owned()))
+                .collect(),
+        )
+    }
+}
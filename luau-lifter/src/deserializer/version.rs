@@ -0,0 +1,64 @@
+use std::fmt;
+
+use super::DeserializeError;
+use crate::op_code::OpCode;
+
+/// A bytecode version the deserializer understands, see `Bytecode.h` in Luau for the history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BytecodeVersion {
+    /// `FORGPREP`, `JUMPXEQK*` and the current `FORGLOOP` aux encoding
+    V3 = 3,
+    /// Types version, function flags and type info, `IDIV` and `IDIVK`
+    V4,
+    /// `SUBRK`, `DIVRK` and vector constants
+    V5,
+    /// `FASTCALL3`
+    V6,
+}
+
+impl BytecodeVersion {
+    /// Whether the chunk has a types version and functions have flags and type info.
+    pub fn has_type_info(self) -> bool {
+        self >= Self::V4
+    }
+
+    pub fn has_vector_constants(self) -> bool {
+        self >= Self::V5
+    }
+
+    /// Whether the compiler for this version can emit the instruction.
+    pub(crate) fn supports(self, op_code: OpCode) -> bool {
+        match op_code {
+            OpCode::LOP_IDIV | OpCode::LOP_IDIVK => self >= Self::V4,
+            OpCode::LOP_SUBRK | OpCode::LOP_DIVRK => self >= Self::V5,
+            OpCode::LOP_FASTCALL3 => self >= Self::V6,
+            _ => true,
+        }
+    }
+}
+
+impl TryFrom<u8> for BytecodeVersion {
+    type Error = DeserializeError;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            3 => Ok(Self::V3),
+            4 => Ok(Self::V4),
+            5 => Ok(Self::V5),
+            6 => Ok(Self::V6),
+            _ => Err(DeserializeError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+impl From<BytecodeVersion> for u8 {
+    fn from(version: BytecodeVersion) -> Self {
+        version as u8
+    }
+}
+
+impl fmt::Display for BytecodeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", *self as u8)
+    }
+}
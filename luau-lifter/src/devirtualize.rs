@@ -0,0 +1,55 @@
+//! Recovering the programs run by interpreters that obfuscators embed in scripts. The obfuscated
+//! script is decompiled first, then every [`Devirtualizer`] looks for an interpreter in it and
+//! translates the program it runs back into Luau bytecode, which is decompiled like the input and
+//! appended to the output.
+
+use crate::deserializer::chunk::Chunk;
+
+/// A program recovered by a [`Devirtualizer`].
+#[derive(Debug, Clone)]
+pub struct RecoveredProgram {
+    /// Where the program was found, written above its source, e.g. the table it was read from
+    pub origin: String,
+    /// The program as Luau bytecode
+    pub bytecode: Vec<u8>,
+    pub encode_key: u8,
+}
+
+/// Recognizes an interpreter embedded in a chunk and recovers the program it runs. Obfuscators
+/// keep the program in a format of their own, so this is where it is translated into Luau
+/// bytecode. Devirtualizers are run on the chunks decompiled with them in
+/// [`DecompileOptions::devirtualizers`](crate::DecompileOptions::devirtualizers).
+pub trait Devirtualizer {
+    /// Written above the programs it recovers
+    fn name(&self) -> &str;
+
+    /// The programs run by interpreters in `chunk`, whose main function decompiled to `body`
+    fn devirtualize(
+        &self,
+        chunk: &Chunk,
+        encode_key: u8,
+        body: &ast::Block,
+    ) -> Vec<RecoveredProgram>;
+}
+
+// the programs `devirtualizer` recovers from `chunk` with the heading their decompiled source is
+// appended under, see `append_programs`
+pub(crate) fn recovered_programs(
+    devirtualizer: &dyn Devirtualizer,
+    chunk: &Chunk,
+    encode_key: u8,
+    body: &ast::Block,
+) -> Vec<(String, Vec<u8>, u8)> {
+    devirtualizer
+        .devirtualize(chunk, encode_key, body)
+        .into_iter()
+        .map(|program| {
+            let heading = format!(
+                "devirtualized by {} from {}",
+                devirtualizer.name(),
+                program.origin
+            );
+            (heading, program.bytecode, program.encode_key)
+        })
+        .collect()
+}
@@ -0,0 +1,281 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Write,
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    decompile_chunk, deserialize_chunk, deserializer::chunk::Chunk, instruction::Instruction,
+    DecompiledChunk, RenameMap,
+};
+
+// number of unchanged lines shown around each change
+const CONTEXT_LINES: usize = 2;
+
+struct Prototype {
+    id: usize,
+    path: String,
+    name: Option<String>,
+    structure_hash: u64,
+}
+
+// the structure hash ignores constants and register allocation so that
+// prototypes can be matched across versions with changed literals
+fn structure_hash(chunk: &Chunk, function_id: usize) -> u64 {
+    let function = &chunk.functions[function_id];
+    let mut hasher = DefaultHasher::new();
+    function.num_parameters.hash(&mut hasher);
+    function.num_upvalues.hash(&mut hasher);
+    function.is_vararg.hash(&mut hasher);
+    function.functions.len().hash(&mut hasher);
+    for instruction in &function.instructions {
+        let op_code = match instruction {
+            Instruction::BC { op_code, .. }
+            | Instruction::AD { op_code, .. }
+            | Instruction::E { op_code, .. } => *op_code,
+        };
+        (op_code as u8).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn collect_prototypes(chunk: &Chunk) -> Vec<Prototype> {
+    chunk
+        .function_paths()
+        .into_iter()
+        .map(|(function_id, path)| Prototype {
+            id: function_id,
+            path,
+            name: chunk.function_name(function_id),
+            structure_hash: structure_hash(chunk, function_id),
+        })
+        .collect()
+}
+
+// returns pairs of indices into `old` and `new`, unmatched prototypes are paired with `None`
+fn match_prototypes(old: &[Prototype], new: &[Prototype]) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+    let mut matches = Vec::new();
+
+    // strongest evidence first: identical structure at the same place,
+    // then a shared debug name, then identical structure anywhere, then the same place
+    let passes: [fn(&Prototype, &Prototype) -> bool; 4] = [
+        |a, b| a.structure_hash == b.structure_hash && a.path == b.path,
+        |a, b| a.name.is_some() && a.name == b.name,
+        |a, b| a.structure_hash == b.structure_hash,
+        |a, b| a.path == b.path,
+    ];
+    for pass in passes {
+        for (old_index, old_prototype) in old.iter().enumerate() {
+            if old_matched[old_index] {
+                continue;
+            }
+            if let Some(new_index) = new
+                .iter()
+                .enumerate()
+                .position(|(i, p)| !new_matched[i] && pass(old_prototype, p))
+            {
+                old_matched[old_index] = true;
+                new_matched[new_index] = true;
+                matches.push((Some(old_index), Some(new_index)));
+            }
+        }
+    }
+
+    matches.extend(
+        old_matched
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| !**m)
+            .map(|(i, _)| (Some(i), None)),
+    );
+    matches.extend(
+        new_matched
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| !**m)
+            .map(|(i, _)| (None, Some(i))),
+    );
+    matches
+}
+
+// the point where a shortest edit script turning `old` into `new` crosses its middle, found by
+// searching from both ends at once in linear space, see Myers, "An O(ND) Difference Algorithm and
+// Its Variations", section 4b
+fn middle_snake(old: &[&str], new: &[&str]) -> Option<(usize, usize)> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2 + 1;
+    let offset = max + 1;
+    // the furthest x reached on every diagonal k = x - y, from the start and from the end
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let index = |k: isize| (k + offset) as usize;
+    for d in 0..max {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)]) {
+                forward[index(k + 1)]
+            } else {
+                forward[index(k - 1)] + 1
+            };
+            let (x0, y0) = (x, x - k);
+            while x < n && x - k < m && old[x as usize] == new[(x - k) as usize] {
+                x += 1;
+            }
+            forward[index(k)] = x;
+            if odd && (k - delta).abs() < d && forward[index(k)] + backward[index(delta - k)] >= n {
+                return Some((x0 as usize, y0 as usize));
+            }
+        }
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                backward[index(k + 1)]
+            } else {
+                backward[index(k - 1)] + 1
+            };
+            while x < n && x - k < m && old[(n - x - 1) as usize] == new[(m - x + k - 1) as usize] {
+                x += 1;
+            }
+            backward[index(k)] = x;
+            if !odd && (k - delta).abs() <= d && backward[index(k)] + forward[index(delta - k)] >= n
+            {
+                return Some(((n - x) as usize, (m - x + k) as usize));
+            }
+        }
+    }
+    None
+}
+
+// appends the lines of a shortest line diff, tagged with ' ', '-' or '+', to `lines`. common
+// prefixes and suffixes are taken off first and the rest is split at its middle snake, so the
+// memory used is linear in the number of lines
+fn diff_range<'a>(old: &[&'a str], new: &[&'a str], lines: &mut Vec<(char, &'a str)>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    lines.extend(old[..prefix].iter().map(|&l| (' ', l)));
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    if old_middle.is_empty() || new_middle.is_empty() {
+        lines.extend(old_middle.iter().map(|&l| ('-', l)));
+        lines.extend(new_middle.iter().map(|&l| ('+', l)));
+    } else if let Some((x, y)) = middle_snake(old_middle, new_middle) {
+        diff_range(&old_middle[..x], &new_middle[..y], lines);
+        diff_range(&old_middle[x..], &new_middle[y..], lines);
+    } else {
+        lines.extend(old_middle.iter().map(|&l| ('-', l)));
+        lines.extend(new_middle.iter().map(|&l| ('+', l)));
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|&l| (' ', l)));
+}
+
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)> {
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    diff_range(old, new, &mut lines);
+    lines
+}
+
+fn write_hunks(output: &mut String, lines: &[(char, &str)]) {
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, (tag, _))| *tag != ' ')
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut last_written = None;
+    for &index in &changed {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES).min(lines.len() - 1);
+        let start = match last_written {
+            Some(last) if last + 1 >= start => last + 1,
+            Some(_) => {
+                writeln!(output, "  ...").unwrap();
+                start
+            }
+            None => start,
+        };
+        for (tag, line) in lines.iter().take(end + 1).skip(start) {
+            writeln!(output, "  {} {}", tag, line).unwrap();
+        }
+        last_written = Some(last_written.map_or(end, |last: usize| last.max(end)));
+    }
+}
+
+fn decompile_prototypes(chunk: &Chunk, prototypes: &[Prototype]) -> Vec<String> {
+    let DecompiledChunk {
+        body: main_body,
+        functions,
+        ..
+    } = decompile_chunk(chunk, &RenameMap::default());
+    prototypes
+        .iter()
+        .map(|p| {
+            if p.id == chunk.main {
+                main_body.to_string()
+            } else {
+                functions[&p.id].lock().body.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Matches the functions of two versions of a chunk by structure and debug name,
+/// decompiles both and returns a function-level summary followed by a line diff
+/// of the decompiled body of every changed function.
+pub fn diff_bytecode(old: &[u8], new: &[u8], encode_key: u8) -> anyhow::Result<String> {
+    let old_chunk = deserialize_chunk(old, encode_key)?;
+    let new_chunk = deserialize_chunk(new, encode_key)?;
+    let old_prototypes = collect_prototypes(&old_chunk);
+    let new_prototypes = collect_prototypes(&new_chunk);
+    let old_sources = decompile_prototypes(&old_chunk, &old_prototypes);
+    let new_sources = decompile_prototypes(&new_chunk, &new_prototypes);
+
+    let mut output = String::new();
+    let (mut changed, mut added, mut removed, mut unchanged) = (0, 0, 0, 0);
+    for (old_index, new_index) in match_prototypes(&old_prototypes, &new_prototypes) {
+        match (old_index, new_index) {
+            (Some(old_index), Some(new_index)) => {
+                let old_source = &old_sources[old_index];
+                let new_source = &new_sources[new_index];
+                if old_source == new_source {
+                    unchanged += 1;
+                    continue;
+                }
+                changed += 1;
+                let (old_path, new_path) = (
+                    &old_prototypes[old_index].path,
+                    &new_prototypes[new_index].path,
+                );
+                if old_path == new_path {
+                    writeln!(output, "changed {}", new_path)?;
+                } else {
+                    writeln!(output, "changed {} -> {}", old_path, new_path)?;
+                }
+                let old_lines = old_source.lines().collect::<Vec<_>>();
+                let new_lines = new_source.lines().collect::<Vec<_>>();
+                write_hunks(&mut output, &diff_lines(&old_lines, &new_lines));
+            }
+            (Some(old_index), None) => {
+                removed += 1;
+                writeln!(output, "removed {}", old_prototypes[old_index].path)?;
+            }
+            (None, Some(new_index)) => {
+                added += 1;
+                writeln!(output, "added {}", new_prototypes[new_index].path)?;
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    write!(
+        output,
+        "{} changed, {} added, {} removed, {} unchanged",
+        changed, added, removed, unchanged
+    )?;
+    Ok(output)
+}
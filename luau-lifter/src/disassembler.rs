@@ -0,0 +1,176 @@
+use std::fmt::Write;
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    builtin::builtin_name,
+    deserialize_chunk,
+    deserializer::{
+        chunk::Chunk,
+        constant::{decode_import, Constant},
+    },
+    instruction::Instruction,
+    op_code::OpCode,
+    xref::jump_target,
+};
+
+// a constant as it would be written in source, if it can be
+fn format_constant(chunk: &Chunk, function_id: usize, index: usize) -> Option<String> {
+    Some(match chunk.functions[function_id].constants.get(index)? {
+        Constant::Nil => "nil".to_string(),
+        Constant::Boolean(value) => value.to_string(),
+        Constant::Number(value) => value.to_string(),
+        Constant::String(_) => format!("{:?}", chunk.constant_string(function_id, index)?),
+        &Constant::Import { id } => chunk.import_path(function_id, &decode_import(id))?,
+        Constant::Table(keys) => format!("table with {} keys", keys.len()),
+        &Constant::Closure(child) => format_function(chunk, child),
+        Constant::Vector(x, y, z, w) => format!("vector({}, {}, {}, {})", x, y, z, w),
+    })
+}
+
+fn format_function(chunk: &Chunk, function_id: usize) -> String {
+    match chunk.function_name(function_id) {
+        Some(name) => format!("function {} ({})", function_id, name),
+        None => format!("function {}", function_id),
+    }
+}
+
+// the constant or child function an instruction refers to
+fn operand_comment(chunk: &Chunk, function_id: usize, instruction: &Instruction) -> Option<String> {
+    let constant = |index| format_constant(chunk, function_id, index);
+    match *instruction {
+        Instruction::BC {
+            op_code, a, aux, ..
+        } => match op_code {
+            OpCode::LOP_GETGLOBAL
+            | OpCode::LOP_SETGLOBAL
+            | OpCode::LOP_GETTABLEKS
+            | OpCode::LOP_SETTABLEKS
+            | OpCode::LOP_NAMECALL => constant(aux as usize),
+            OpCode::LOP_FASTCALL2K => {
+                Some(format!("{}, {}", builtin_name(a)?, constant(aux as usize)?))
+            }
+            OpCode::LOP_FASTCALL
+            | OpCode::LOP_FASTCALL1
+            | OpCode::LOP_FASTCALL2
+            | OpCode::LOP_FASTCALL3 => builtin_name(a).map(str::to_string),
+            _ => None,
+        },
+        Instruction::AD {
+            op_code, d, aux, ..
+        } => match op_code {
+            OpCode::LOP_LOADK | OpCode::LOP_DUPCLOSURE => constant(d as u16 as usize),
+            OpCode::LOP_LOADKX => constant(aux as usize),
+            OpCode::LOP_GETIMPORT => chunk.import_path(function_id, &decode_import(aux)),
+            OpCode::LOP_NEWCLOSURE => chunk.functions[function_id]
+                .functions
+                .get(d as u16 as usize)
+                .map(|&child| format_function(chunk, child)),
+            OpCode::LOP_JUMPXEQKN | OpCode::LOP_JUMPXEQKS => constant((aux & 0xFFFFFF) as usize),
+            OpCode::LOP_JUMPXEQKB => Some((aux & 1 != 0).to_string()),
+            _ => None,
+        },
+        Instruction::E { .. } => None,
+    }
+}
+
+/// Returns a listing of the function's instructions, one per line, prefixed with their pc.
+/// Constants and child functions are written in a comment after the instruction that uses them
+/// and jump targets are given labels.
+pub(crate) fn disassemble_function(chunk: &Chunk, function_id: usize) -> String {
+    let instructions = &chunk.functions[function_id].instructions;
+    let target = |pc, instruction: &Instruction| match *instruction {
+        Instruction::BC {
+            op_code: OpCode::LOP_LOADB,
+            c,
+            ..
+        } if c != 0 => Some(pc + 1 + c as usize),
+        _ => jump_target(pc, instruction),
+    };
+    let mut targets = instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(pc, instruction)| target(pc, instruction))
+        .collect::<Vec<_>>();
+    targets.sort_unstable();
+    targets.dedup();
+    let labels = targets
+        .into_iter()
+        .enumerate()
+        .map(|(label, pc)| (pc, format!("L{}", label)))
+        .collect::<FxHashMap<_, _>>();
+
+    let mut output = String::new();
+    let mut instructions = instructions.iter().enumerate();
+    while let Some((pc, instruction)) = instructions.next() {
+        if let Some(label) = labels.get(&pc) {
+            writeln!(output, "{}:", label).unwrap();
+        }
+        match *instruction {
+            Instruction::BC {
+                op_code,
+                a,
+                b,
+                c,
+                aux,
+            } => {
+                write!(output, "{:>5}  {:<16}{} {} {}", pc, op_code.name(), a, b, c).unwrap();
+                if op_code.has_aux() {
+                    write!(output, " [{}]", aux).unwrap();
+                }
+            }
+            Instruction::AD { op_code, a, d, aux } => {
+                write!(output, "{:>5}  {:<16}{} {}", pc, op_code.name(), a, d).unwrap();
+                if op_code.has_aux() {
+                    write!(output, " [{}]", aux).unwrap();
+                }
+            }
+            Instruction::E { op_code, e } => {
+                write!(output, "{:>5}  {:<16}{}", pc, op_code.name(), e).unwrap();
+            }
+        }
+        if let Some(label) = target(pc, instruction).and_then(|pc| labels.get(&pc)) {
+            write!(output, " -> {}", label).unwrap();
+        }
+        if let Some(comment) = operand_comment(chunk, function_id, instruction) {
+            write!(output, " ; {}", comment).unwrap();
+        }
+        writeln!(output).unwrap();
+
+        // the deserializer inserts a nop in place of the aux word so that pcs stay the same
+        let has_aux = match instruction {
+            Instruction::BC { op_code, .. } | Instruction::AD { op_code, .. } => op_code.has_aux(),
+            Instruction::E { .. } => false,
+        };
+        if has_aux {
+            instructions.next();
+        }
+    }
+    output
+}
+
+/// Disassembles every function reachable from the main function, each headed by its id and path.
+pub fn disassemble_bytecode(bytecode: &[u8], encode_key: u8) -> anyhow::Result<String> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let mut output = String::new();
+    for (function_id, path) in chunk.function_paths() {
+        let function = &chunk.functions[function_id];
+        writeln!(
+            output,
+            "; function {} ({}), {} parameters{}, {} upvalues",
+            function_id,
+            path,
+            function.num_parameters,
+            if function.is_vararg {
+                " and varargs"
+            } else {
+                ""
+            },
+            function.num_upvalues
+        )
+        .unwrap();
+        output += &disassemble_function(&chunk, function_id);
+        output.push('\n');
+    }
+    Ok(output)
+}
@@ -0,0 +1,59 @@
+use std::fmt::Write;
+
+use crate::deserializer::{self, bytecode::Bytecode, chunk::Chunk, BytecodeVersion};
+
+pub(crate) fn is_chunk(bytes: &[u8], encode_key: u8) -> bool {
+    // check the version byte first so we don't try to deserialize every string
+    if !bytes
+        .first()
+        .is_some_and(|&version| BytecodeVersion::try_from(version).is_ok())
+    {
+        return false;
+    }
+    matches!(
+        deserializer::deserialize(bytes, encode_key),
+        Ok(Bytecode::Chunk(chunk)) if chunk.main < chunk.functions.len()
+    )
+}
+
+/// Returns the index and contents of every string constant in the chunk that is itself
+/// a valid chunk, e.g. a payload that is passed to `loadstring` at runtime.
+pub fn embedded_chunks<'a>(chunk: &'a Chunk<'_>, encode_key: u8) -> Vec<(usize, &'a [u8])> {
+    chunk
+        .string_table
+        .iter()
+        .filter(|(_, string)| is_chunk(string, encode_key))
+        .collect()
+}
+
+// every chunk embedded in `chunk` with the heading its decompiled source is appended under, see
+// `append_programs`
+pub(crate) fn embedded_programs(chunk: &Chunk, encode_key: u8) -> Vec<(String, Vec<u8>, u8)> {
+    embedded_chunks(chunk, encode_key)
+        .into_iter()
+        .map(|(string_index, bytecode)| {
+            let heading = format!(
+                "embedded chunk in string constant {} ({} bytes)",
+                string_index,
+                bytecode.len()
+            );
+            (heading, bytecode.to_vec(), encode_key)
+        })
+        .collect()
+}
+
+// appends `source` wrapped in a long comment with a line comment above it, the comment's level is
+// high enough for `source` not to close it
+pub(crate) fn append_long_comment(output: &mut String, heading: &str, source: &str) {
+    let mut level = 0;
+    while source.contains(&format!("]{}]", "=".repeat(level))) {
+        level += 1;
+    }
+    let equals = "=".repeat(level);
+    write!(
+        output,
+        "\n\n-- {}\n--[{}[\n{}\n]{}]",
+        heading, equals, source, equals
+    )
+    .unwrap();
+}
@@ -0,0 +1,105 @@
+use std::{collections::BTreeSet, fmt::Write, str::FromStr};
+
+use crate::{deserializer::chunk::Chunk, xref::XrefKind, RenameMap};
+
+/// Config formats for [`Globals`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalsFormat {
+    /// A `.luacheckrc` with `globals` and `read_globals`
+    Luacheck,
+    /// A selene standard library in YAML
+    Selene,
+}
+
+impl FromStr for GlobalsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "luacheck" => Ok(GlobalsFormat::Luacheck),
+            "selene" => Ok(GlobalsFormat::Selene),
+            _ => Err(format!("unknown format {}, expected luacheck or selene", s)),
+        }
+    }
+}
+
+/// The globals a chunk reads and writes, by top-level name, e.g. `string.char` is `string`.
+#[derive(Debug, Default, Clone)]
+pub struct Globals {
+    pub read: BTreeSet<String>,
+    pub written: BTreeSet<String>,
+}
+
+impl Globals {
+    /// Renames globals the same way [`RenameMap`] does when decompiling.
+    pub fn renamed(&self, renames: &RenameMap) -> Self {
+        let rename = |names: &BTreeSet<String>| {
+            names
+                .iter()
+                .map(|name| renames.globals.get(name).unwrap_or(name).clone())
+                .collect()
+        };
+        Self {
+            read: rename(&self.read),
+            written: rename(&self.written),
+        }
+    }
+
+    /// Globals that are read but never written.
+    pub fn read_only(&self) -> impl Iterator<Item = &String> {
+        self.read.difference(&self.written)
+    }
+
+    pub fn to_config(&self, format: GlobalsFormat) -> String {
+        match format {
+            GlobalsFormat::Luacheck => self.to_luacheck(),
+            GlobalsFormat::Selene => self.to_selene(),
+        }
+    }
+
+    pub fn to_luacheck(&self) -> String {
+        let mut config = String::new();
+        for (field, names) in [
+            ("globals", self.written.iter().collect::<Vec<_>>()),
+            ("read_globals", self.read_only().collect()),
+        ] {
+            writeln!(config, "{} = {{", field).unwrap();
+            for name in names {
+                writeln!(config, "\t{:?},", name).unwrap();
+            }
+            writeln!(config, "}}").unwrap();
+        }
+        config
+    }
+
+    pub fn to_selene(&self) -> String {
+        let mut config = String::from("---\nglobals:\n");
+        for name in &self.written {
+            writeln!(config, "  {:?}:\n    property: full-write", name).unwrap();
+        }
+        for name in self.read_only() {
+            writeln!(config, "  {:?}:\n    property: read-only", name).unwrap();
+        }
+        config
+    }
+}
+
+impl Chunk<'_> {
+    /// Collects the globals referenced by every function in the chunk.
+    pub fn globals(&self) -> Globals {
+        let mut globals = Globals::default();
+        for xref in self.xrefs().iter() {
+            let name = xref.name.split('.').next().unwrap().to_string();
+            match xref.kind {
+                XrefKind::ReadGlobal => {
+                    globals.read.insert(name);
+                }
+                XrefKind::WriteGlobal => {
+                    globals.written.insert(name);
+                }
+                _ => {}
+            }
+        }
+        globals
+    }
+}
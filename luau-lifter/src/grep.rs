@@ -0,0 +1,104 @@
+use std::fmt;
+
+use crate::{
+    deserialize_chunk,
+    deserializer::{chunk::Chunk, constant::decode_import},
+    instruction::Instruction,
+    op_code::OpCode,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    Global,
+    Import,
+    String,
+}
+
+impl fmt::Display for ReferenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReferenceKind::Global => write!(f, "global"),
+            ReferenceKind::Import => write!(f, "import"),
+            ReferenceKind::String => write!(f, "string"),
+        }
+    }
+}
+
+/// A constant referenced by an instruction that matched a search pattern.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub function_path: String,
+    pub pc: usize,
+    pub kind: ReferenceKind,
+    pub value: String,
+}
+
+fn instruction_reference(
+    chunk: &Chunk,
+    function_id: usize,
+    instruction: &Instruction,
+) -> Option<(ReferenceKind, String)> {
+    match *instruction {
+        Instruction::BC { op_code, aux, .. } => match op_code {
+            OpCode::LOP_GETGLOBAL | OpCode::LOP_SETGLOBAL => Some((
+                ReferenceKind::Global,
+                chunk.constant_string(function_id, aux as usize)?,
+            )),
+            OpCode::LOP_GETTABLEKS
+            | OpCode::LOP_SETTABLEKS
+            | OpCode::LOP_NAMECALL
+            | OpCode::LOP_FASTCALL2K => Some((
+                ReferenceKind::String,
+                chunk.constant_string(function_id, aux as usize)?,
+            )),
+            _ => None,
+        },
+        Instruction::AD {
+            op_code, d, aux, ..
+        } => match op_code {
+            OpCode::LOP_GETIMPORT => Some((
+                ReferenceKind::Import,
+                chunk.import_path(function_id, &decode_import(aux))?,
+            )),
+            OpCode::LOP_LOADK => Some((
+                ReferenceKind::String,
+                chunk.constant_string(function_id, d as u16 as usize)?,
+            )),
+            OpCode::LOP_LOADKX => Some((
+                ReferenceKind::String,
+                chunk.constant_string(function_id, aux as usize)?,
+            )),
+            OpCode::LOP_JUMPXEQKS => Some((
+                ReferenceKind::String,
+                chunk.constant_string(function_id, (aux & 0xFFFFFF) as usize)?,
+            )),
+            _ => None,
+        },
+        Instruction::E { .. } => None,
+    }
+}
+
+/// Finds every global, import and string constant referenced by the chunk that contains `pattern`.
+/// This only walks the constant pools and instructions, nothing is lifted or decompiled.
+pub fn grep_bytecode(
+    bytecode: &[u8],
+    encode_key: u8,
+    pattern: &str,
+) -> anyhow::Result<Vec<Reference>> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let mut references = Vec::new();
+    for (function_id, function_path) in chunk.function_paths() {
+        for (pc, instruction) in chunk.functions[function_id].instructions.iter().enumerate() {
+            match instruction_reference(&chunk, function_id, instruction) {
+                Some((kind, value)) if value.contains(pattern) => references.push(Reference {
+                    function_path: function_path.clone(),
+                    pc,
+                    kind,
+                    value,
+                }),
+                _ => {}
+            }
+        }
+    }
+    Ok(references)
+}
@@ -0,0 +1,127 @@
+use std::fmt::Write;
+
+use anyhow::anyhow;
+
+use crate::{
+    deserialize_chunk,
+    deserializer::{
+        chunk::Chunk,
+        constant::{decode_import, Constant},
+    },
+};
+
+// `0` is the main function, `0.2` is the third closure defined in the main function, etc.
+pub(crate) fn resolve_function(chunk: &Chunk, path: &str) -> anyhow::Result<usize> {
+    let mut indices = path.split('.');
+    if indices.next() != Some("0") {
+        return Err(anyhow!(
+            "function path must start with 0 (the main function)"
+        ));
+    }
+    let mut function_id = chunk.main;
+    for index in indices {
+        let index = index.parse::<usize>()?;
+        function_id = *chunk.functions[function_id]
+            .functions
+            .get(index)
+            .ok_or_else(|| anyhow!("function has no child {}", index))?;
+    }
+    Ok(function_id)
+}
+
+fn format_constant(chunk: &Chunk, function_id: usize, constant: &Constant) -> String {
+    match constant {
+        Constant::Nil => "nil".to_string(),
+        Constant::Boolean(value) => value.to_string(),
+        Constant::Number(value) => value.to_string(),
+        &Constant::String(index) => match chunk.string_table.get(index) {
+            Some(string) => format!("{:?}", string),
+            None => format!("<invalid string {}>", index),
+        },
+        &Constant::Import { id } => match chunk.import_path(function_id, &decode_import(id)) {
+            Some(name) => format!("import {}", name),
+            None => format!("<invalid import {:#010x}>", id),
+        },
+        Constant::Table(keys) => format!(
+            "table {{{}}}",
+            keys.iter()
+                .map(|&key| chunk
+                    .constant_string(function_id, key)
+                    .unwrap_or_else(|| format!("<constant {}>", key)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Constant::Closure(id) => format!("closure (function {})", id),
+        Constant::Vector(x, y, z, w) => format!("vector({}, {}, {}, {})", x, y, z, w),
+    }
+}
+
+/// Describes a function of a chunk: its signature, stack size, upvalues, locals and constant pool.
+/// `path` selects the function by closure indices, e.g. `0.2` for the third closure of the main function.
+pub fn describe_function(bytecode: &[u8], encode_key: u8, path: &str) -> anyhow::Result<String> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let function_id = resolve_function(&chunk, path)?;
+    describe(&chunk, function_id)
+}
+
+pub(crate) fn describe(chunk: &Chunk, function_id: usize) -> anyhow::Result<String> {
+    let function = &chunk.functions[function_id];
+
+    let mut output = String::new();
+    writeln!(
+        output,
+        "function {} ({})",
+        function_id,
+        chunk
+            .function_name(function_id)
+            .unwrap_or_else(|| "anonymous".to_string())
+    )?;
+    writeln!(output, "line defined: {}", function.line_defined)?;
+    writeln!(
+        output,
+        "parameters: {}{}",
+        function.num_parameters,
+        if function.is_vararg { " + ..." } else { "" }
+    )?;
+    writeln!(output, "max stack size: {}", function.max_stack_size)?;
+    writeln!(output, "instructions: {}", function.instructions.len())?;
+    writeln!(output, "closures: {}", function.functions.len())?;
+    // names are only available with debug info, otherwise upvalues are numbered
+    let debug_info = function.debug_info.as_ref();
+    writeln!(output, "upvalues ({}):", function.num_upvalues)?;
+    for index in 0..function.num_upvalues as usize {
+        let name = debug_info
+            .and_then(|debug_info| debug_info.upvalues.get(index))
+            .and_then(|&name| chunk.string_table.get(name));
+        match name {
+            Some(name) => writeln!(output, "{:>6}  {}", index, name)?,
+            None => writeln!(output, "{:>6}  u{}", index, index)?,
+        }
+    }
+    if let Some(debug_info) = debug_info {
+        writeln!(output, "locals ({}):", debug_info.locals.len())?;
+        for local in &debug_info.locals {
+            writeln!(
+                output,
+                "{:>6}  {} (pc {}..{})",
+                format!("r{}", local.register),
+                chunk
+                    .string_table
+                    .get(local.name)
+                    .unwrap_or_else(|| format!("<invalid string {}>", local.name).into()),
+                local.start_pc,
+                local.end_pc
+            )?;
+        }
+    }
+    writeln!(output, "constants ({}):", function.constants.len())?;
+    for (index, constant) in function.constants.iter().enumerate() {
+        writeln!(
+            output,
+            "{:>6}  {}",
+            index,
+            format_constant(chunk, function_id, constant)
+        )?;
+    }
+    Ok(output)
+}
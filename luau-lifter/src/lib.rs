@@ -1,11 +1,38 @@
+mod ast_json;
+mod banner;
+mod batch;
+mod browse;
+mod builtin;
+mod call_graph;
+mod checkpoint;
 mod deserializer;
+mod devirtualize;
+mod diff;
+mod disassembler;
+mod embedded;
+mod globals;
+mod grep;
+mod inspect;
 mod instruction;
 mod lifter;
 mod op_code;
+mod options;
+mod patch;
+mod rename;
+mod select;
+mod serializer;
+#[cfg(feature = "serve")]
+mod serve;
+mod split;
+mod stream;
+mod trace;
+mod xref;
 
 use ast::{
-    local_declarations::LocalDeclarer, name_locals::name_locals, replace_locals::replace_locals,
-    Traverse,
+    compound_assignments::compound_assignments, decrypt_strings::decrypt_strings,
+    dispatch_chains::normalize_dispatch_chains, forward_varargs::forward_varargs,
+    interpolate_strings::interpolate_strings, local_declarations::LocalDeclarer,
+    replace_locals::replace_locals, Traverse,
 };
 
 use by_address::ByAddress;
@@ -17,139 +44,718 @@ use cfg::{
     },
 };
 use indexmap::IndexMap;
+use lazy_static::lazy_static;
 
-use lifter::Lifter;
+pub use ast::{
+    decrypt_strings::{Callee, StringDecryptor},
+    emitter::{DisplayEmitter, Emitter, SourceMapEmitter},
+    formatter::{FormatOptions, GlobalStyle, IndentationMode, SourceMapping},
+    Span,
+};
+pub use ast_json::{AstJsonEmitter, AST_JSON_VERSION};
+pub use banner::provenance_banner;
+pub use batch::{
+    decompile_batch, decompile_directory, files_in, mirrored_path, BatchOptions, BatchOutput,
+    BatchReport, FileReport,
+};
+pub use browse::browse;
+pub use call_graph::{CallGraph, CallGraphNode, CallSite};
+pub use cfg::export::FunctionExport;
+pub use checkpoint::LiftedChunk;
+pub use deserializer::{chunk::Chunk, BytecodeVersion, DeserializeError};
+pub use devirtualize::{Devirtualizer, RecoveredProgram};
+pub use diff::diff_bytecode;
+pub use disassembler::disassemble_bytecode;
+pub use embedded::embedded_chunks;
+pub use globals::{Globals, GlobalsFormat};
+pub use grep::{grep_bytecode, Reference, ReferenceKind};
+pub use inspect::describe_function;
+pub use lifter::LiftError;
+pub use options::{AppendChunk, DecompileOptions};
+pub use patch::{patch_bytecode, Patch};
+pub use pipeline::{
+    CancellationToken, Cancelled, FnPass, Pass, PassManager, Pipeline, PipelineError, Progress,
+    ProgressSink, SsaFunction,
+};
+pub use rename::RenameMap;
+pub use restructure::{Budget, BudgetExceeded, StructureOptions};
+pub use select::{decompile_bytecode_function, list_functions, FunctionInfo};
+pub use serializer::serialize;
+#[cfg(feature = "serve")]
+pub use serve::Server;
+pub use split::decompile_bytecode_split;
+pub use stream::decompile_bytecode_streaming;
+pub use trace::{Observation, Trace, TraceEvent};
+pub use xref::{Xref, XrefKind, Xrefs};
 
 //use cfg_ir::{dot, function::Function, ssa};
-use clap::Parser;
 use parking_lot::Mutex;
 use petgraph::algo::dominators::simple_fast;
+#[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
 use anyhow::anyhow;
+use cfg::dot::GraphVisualizer;
 use rustc_hash::FxHashMap;
+use std::path::Path;
 use triomphe::Arc;
-use walkdir::WalkDir;
-
-use std::{
-    fs::File,
-    io::{Read, Write},
-    path::Path,
-    time::Instant,
-};
+use web_time::Instant;
 
 use deserializer::bytecode::Bytecode;
 
-#[cfg(feature = "dhat-heap")]
-#[global_allocator]
-static ALLOC: dhat::Alloc = dhat::Alloc;
-
-#[derive(Parser, Debug)]
-#[clap(about, version, author)]
-struct Args {
-    paths: Vec<String>,
-    /// Number of threads to use (0 = automatic)
-    #[clap(short, long, default_value_t = 0)]
-    threads: usize,
-    /// op = op * key % 256
-    /// For Roblox client bytecode, use 203
-    #[clap(short, long, default_value_t = 1)]
-    key: u8,
-    #[clap(short, long)]
-    recursive: bool,
-    #[clap(short, long)]
-    verbose: bool,
+pub fn decompile_bytecode(bytecode: &[u8], encode_key: u8) -> String {
+    let options = DecompileOptions {
+        encode_key,
+        ..Default::default()
+    };
+    match decompile_bytecode_with(bytecode, &options) {
+        Ok(decompilation) => decompilation.source,
+        Err(error) => error.to_string(),
+    }
 }
 
-pub fn decompile_bytecode(bytecode: &[u8], encode_key: u8) -> String {
-    let chunk = deserializer::deserialize(bytecode, encode_key).unwrap();
-    match chunk {
-        Bytecode::Error(msg) => msg,
-        Bytecode::Chunk(chunk) => {
-            let mut lifted = Vec::new();
-            let mut stack = vec![(Arc::<Mutex<ast::Function>>::default(), chunk.main)];
-            while let Some((ast_func, func_id)) = stack.pop() {
-                let (function, upvalues, child_functions) =
-                    Lifter::lift(&chunk.functions, &chunk.string_table, func_id);
-                lifted.push((ast_func, function, upvalues));
-                stack.extend(child_functions.into_iter().map(|(a, f)| (a.0, f)));
+/// Decompiles a chunk the way `options` say, returning an error if the bytecode can't be
+/// deserialized and reporting which functions failed to decompile. The chunks embedded in string
+/// constants are decompiled too and appended to the output, see [`embedded_chunks`].
+pub fn decompile_bytecode_with<E>(
+    bytecode: &[u8],
+    options: &DecompileOptions<E>,
+) -> anyhow::Result<Decompilation<E::Output>>
+where
+    E: Emitter + Clone,
+    E::Output: AppendChunk,
+{
+    options.pipeline.validate()?;
+    let chunk = deserialize_chunk(bytecode, options.encode_key)?;
+    let progress = options.progress;
+    let lifted = LiftedChunk::lift_with_progress(&chunk, chunk.main, true, progress)?;
+    let mut decompiled = decompile_lifted_chunk(
+        lifted,
+        options.renames,
+        options.pipeline,
+        progress,
+        options.structure_options(),
+    );
+    // functions that were cancelled failed to decompile, which isn't worth returning
+    progress.check()?;
+    decrypt_strings(&mut decompiled.body, options.decryptors);
+    if let Some(trace) = options.trace {
+        let function_paths = chunk.function_paths().into_iter().collect();
+        for (&function_id, function) in &decompiled.functions {
+            let comments = trace.comments(function_id, &function_paths);
+            function.lock().body.0.splice(0..0, comments);
+        }
+        let comments = trace.comments(chunk.main, &function_paths);
+        decompiled.body.0.splice(0..0, comments);
+    }
+
+    let mut decompilation = Decompilation {
+        source: options.emitter.clone().emit(&decompiled.body),
+        failures: decompiled.failures,
+        warnings: count_warnings(&decompiled.body),
+    };
+    let mut programs = embedded::embedded_programs(&chunk, options.encode_key);
+    for devirtualizer in options.devirtualizers {
+        programs.extend(devirtualize::recovered_programs(
+            *devirtualizer,
+            &chunk,
+            options.encode_key,
+            &decompiled.body,
+        ));
+    }
+    append_programs(&mut decompilation, programs, options);
+    Ok(decompilation)
+}
+
+// decompiles the chunks embedded in a chunk and the programs recovered from it, given by heading,
+// bytecode and encode key, and appends them to its decompilation. embedded chunks are searched for
+// recursively. a program that can't be decompiled is appended as a warning instead.
+pub(crate) fn append_programs<E>(
+    decompilation: &mut Decompilation<E::Output>,
+    programs: Vec<(String, Vec<u8>, u8)>,
+    options: &DecompileOptions<E>,
+) where
+    E: Emitter + Clone,
+    E::Output: AppendChunk,
+{
+    for (heading, bytecode, encode_key) in programs {
+        match decompile_bytecode_with(&bytecode, &options.for_program(encode_key)) {
+            Ok(appended) => {
+                decompilation.source.append_chunk(&heading, appended.source);
+                decompilation.warnings += appended.warnings;
+            }
+            Err(err) => {
+                let warning = ast::Block(vec![ast::Comment::new(format!(
+                    "warning: couldn't decompile embedded program: {}",
+                    err
+                ))
+                .into()]);
+                let source = options.emitter.clone().emit(&warning);
+                decompilation.source.append_chunk(&heading, source);
+                decompilation.warnings += 1;
             }
+        }
+    }
+}
 
-            let (main, ..) = lifted.first().unwrap().clone();
-            let mut upvalues = lifted
-                .into_iter()
-                .map(|(ast_function, function, upvalues_in)| {
-                    use std::{backtrace::Backtrace, cell::RefCell, fmt::Write, panic};
+// the number of warning comments in `body` and the closures in it, the lines
+// `Decompilation::new` counts
+fn count_warnings(body: &ast::Block) -> usize {
+    let mut warnings = 0;
+    visit_blocks(body, &mut |block| {
+        warnings += block
+            .iter()
+            .filter_map(|s| s.as_comment())
+            .filter(|c| c.text.starts_with("warning:"))
+            .count();
+    });
+    warnings
+}
 
-                    thread_local! {
-                        static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
-                    }
+/// Decompiles the chunk and keeps only the statements that may influence the locals and globals
+/// named `name`, see [`ast::slice::backward_slice`].
+pub fn decompile_bytecode_slice(
+    bytecode: &[u8],
+    encode_key: u8,
+    renames: &RenameMap,
+    name: &str,
+) -> anyhow::Result<String> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let body = decompile_chunk(&chunk, renames).body;
+    let locals = ast::slice::locals_named(&body, name);
+    let globals = std::iter::once(name.as_bytes().to_vec()).collect();
+    Ok(ast::slice::backward_slice(&body, locals, globals).to_string())
+}
 
-                    let function_id = function.id;
-                    let mut args = std::panic::AssertUnwindSafe(Some((
-                        ast_function.clone(),
-                        function,
-                        upvalues_in,
-                    )));
-
-                    let prev_hook = panic::take_hook();
-                    panic::set_hook(Box::new(|_| {
-                        let trace = Backtrace::capture();
-                        BACKTRACE.with(move |b| b.borrow_mut().replace(trace));
-                    }));
-                    let result = panic::catch_unwind(move || {
-                        let (ast_function, function, upvalues_in) = args.take().unwrap();
-                        decompile_function(ast_function, function, upvalues_in)
-                    });
-                    panic::set_hook(prev_hook);
-
-                    match result {
-                        Ok(r) => r,
-                        Err(e) => {
-                            let panic_information = match e.downcast::<String>() {
-                                Ok(v) => *v,
-                                Err(e) => match e.downcast::<&str>() {
-                                    Ok(v) => v.to_string(),
-                                    _ => "Unknown Source of Error".to_owned(),
-                                },
-                            };
-
-                            let mut message = String::new();
-                            writeln!(message, "failed to decompile").unwrap();
-                            // writeln!(message, "function {} panicked at '{}'", function_id, panic_information).unwrap();
-                            // if let Some(backtrace) = BACKTRACE.with(|b| b.borrow_mut().take()) {
-                            //     write!(message, "stack backtrace:\n{}", backtrace).unwrap();
-                            // }
-
-                            ast_function.lock().body.extend(
-                                message
-                                    .trim_end()
-                                    .split('\n')
-                                    .map(|s| ast::Comment::new(s.to_string()).into()),
-                            );
-                            (ByAddress(ast_function), Vec::new())
-                        }
-                    }
+/// Lifts every function in the chunk and prints it in SSA form after the SSA passes, before it is
+/// destructed and structured, see [`cfg::ssa::listing::render`]. Meant for debugging the decompiler.
+pub fn decompile_bytecode_ssa(bytecode: &[u8], encode_key: u8) -> anyhow::Result<String> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let function_paths = chunk
+        .function_paths()
+        .into_iter()
+        .collect::<FxHashMap<_, _>>();
+    let mut output = String::new();
+    for lifted in LiftedChunk::lift(&chunk).functions {
+        let mut function = lifted.function;
+        if let Some(path) = function_paths.get(&function.id) {
+            output += &format!("-- {}\n", path);
+        }
+        let listing = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            ast::number_locals(function.id, lifted.next_local_id, || {
+                construct_ssa(
+                    &mut function,
+                    &lifted.upvalues,
+                    &DEFAULT_PIPELINE,
+                    &mut |_, _| {},
+                    &NEVER,
+                )
+                .unwrap();
+                ssa::listing::render(&function)
+            })
+            .0
+        }));
+        match listing {
+            Ok(listing) => output += &listing,
+            Err(_) => output += &format!("-- function {} failed to decompile\n", function.id),
+        }
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+#[derive(serde::Serialize)]
+struct PassSnapshot<'a> {
+    function: usize,
+    pass: &'a str,
+    cfg: cfg::export::FunctionExport<'a>,
+}
+
+/// Lifts every function in the chunk and writes its control flow graph before the SSA passes and
+/// after each of them to `output`, a line of JSON per snapshot:
+/// `{"function": id, "pass": name, "cfg": graph}` with the graph laid out as a
+/// [`cfg::export::FunctionExport`]. The first snapshot of a function has the pass `lifted` and
+/// passes that run in a loop are reported every time they run. Locals have the same ids in every
+/// snapshot of a function. Functions that fail to decompile stop at the last pass that finished.
+pub fn dump_passes(
+    bytecode: &[u8],
+    encode_key: u8,
+    output: &mut impl std::io::Write,
+) -> anyhow::Result<()> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    for lifted in LiftedChunk::lift(&chunk).functions {
+        let mut function = lifted.function;
+        let function_id = function.id;
+        let mut snapshots = Vec::new();
+        let mut observe = |pass: &str, function: &Function| {
+            snapshots.push(serde_json::to_string(&PassSnapshot {
+                function: function_id,
+                pass,
+                cfg: cfg::export::FunctionExport::new(function),
+            }))
+        };
+        ast::serialize::scope(|| {
+            observe("lifted", &function);
+            // a panicking pass only ends the function's snapshots
+            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                ast::number_locals(function.id, lifted.next_local_id, || {
+                    destruct_ssa(
+                        &mut function,
+                        &lifted.upvalues,
+                        &DEFAULT_PIPELINE,
+                        &mut observe,
+                        &NEVER,
+                    )
                 })
-                .collect::<FxHashMap<_, _>>();
-
-            let main = ByAddress(main);
-            upvalues.remove(&main);
-            let mut body = Arc::try_unwrap(main.0).unwrap().into_inner().body;
-            link_upvalues(&mut body, &mut upvalues);
-            name_locals(&mut body, true);
-            body.to_string()
+            }));
+        });
+        for snapshot in snapshots {
+            writeln!(output, "{}", snapshot?)?;
         }
     }
+    Ok(())
 }
 
-fn decompile_function(
-    ast_function: Arc<Mutex<ast::Function>>,
-    mut function: Function,
-    upvalues_in: Vec<ast::RcLocal>,
-) -> (ByAddress<Arc<Mutex<ast::Function>>>, Vec<ast::RcLocal>) {
+/// Lifts every function in the chunk and renders its control flow graph in the DOT format, before
+/// any of the SSA passes, see [`cfg::dot::render_to`]. Functions are identified by id.
+pub fn render_cfgs(bytecode: &[u8], encode_key: u8) -> anyhow::Result<Vec<(usize, String)>> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    LiftedChunk::lift(&chunk)
+        .functions
+        .into_iter()
+        .map(|lifted| {
+            let mut dot = Vec::new();
+            cfg::dot::render_to(&lifted.function, &mut dot)?;
+            Ok((lifted.function.id, String::from_utf8(dot)?))
+        })
+        .collect()
+}
+
+/// Structures every function in the chunk and reports each pattern the structurer tried on each
+/// node, why it didn't match and which nodes were left over, see
+/// [`restructure::StructuringTrace`]. Functions are identified by id and path.
+pub fn explain_structuring(
+    bytecode: &[u8],
+    encode_key: u8,
+) -> anyhow::Result<Vec<(usize, String, restructure::StructuringTrace)>> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let function_paths = chunk
+        .function_paths()
+        .into_iter()
+        .collect::<FxHashMap<_, _>>();
+    let mut traces = Vec::new();
+    for lifted in LiftedChunk::lift(&chunk).functions {
+        let mut function = lifted.function;
+        let function_id = function.id;
+        let trace = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            ast::number_locals(function.id, lifted.next_local_id, || {
+                destruct_ssa(
+                    &mut function,
+                    &lifted.upvalues,
+                    &DEFAULT_PIPELINE,
+                    &mut |_, _| {},
+                    &NEVER,
+                )
+                .unwrap();
+                restructure::lift_with_trace(function).map(|(_, trace)| trace)
+            })
+            .0
+        }));
+        // functions that fail before structuring finishes have nothing to report
+        if let Ok(Ok(trace)) = trace {
+            let path = function_paths
+                .get(&function_id)
+                .cloned()
+                .unwrap_or_default();
+            traces.push((function_id, path, trace));
+        }
+    }
+    Ok(traces)
+}
+
+/// Structures every function in the chunk and writes snapshots of its control flow graph while it
+/// is structured to `<directory>/<function id>/`, see [`cfg::dot::GraphVisualizer`]. Snapshots are
+/// also rendered to SVG with `svg_renderer` if it is set.
+pub fn structuring_snapshots(
+    bytecode: &[u8],
+    encode_key: u8,
+    directory: &Path,
+    svg_renderer: Option<&str>,
+) -> anyhow::Result<()> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    for lifted in LiftedChunk::lift(&chunk).functions {
+        let mut function = lifted.function;
+        let mut visualizer = GraphVisualizer::new(directory.join(function.id.to_string()));
+        if let Some(svg_renderer) = svg_renderer {
+            visualizer = visualizer.with_svg_renderer(svg_renderer);
+        }
+        // functions that fail keep the snapshots written before they did
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            ast::number_locals(function.id, lifted.next_local_id, || {
+                destruct_ssa(
+                    &mut function,
+                    &lifted.upvalues,
+                    &DEFAULT_PIPELINE,
+                    &mut |_, _| {},
+                    &NEVER,
+                )
+                .unwrap();
+                restructure::lift_with_snapshots(function, &mut visualizer)
+            })
+        }));
+        if let Some(error) = visualizer.take_error() {
+            return Err(error.into());
+        }
+    }
+    Ok(())
+}
+
+/// The outcome of decompiling a chunk with [`decompile_bytecode_with`], the source is the output
+/// of its emitter.
+#[derive(Debug)]
+pub struct Decompilation<T = String> {
+    pub source: T,
+    /// Functions that failed to decompile and the reason why, their bodies are replaced with a comment
+    pub failures: Vec<(usize, String)>,
+    /// Number of warning comments in the output
+    pub warnings: usize,
+}
+
+/// Decompiles a chunk that was lifted earlier, e.g. one loaded with [`LiftedChunk::load`].
+/// Embedded chunks aren't decompiled as the original bytecode isn't available.
+pub fn decompile_lifted(lifted: LiftedChunk, renames: &RenameMap) -> Decompilation {
+    let decompiled = decompile_lifted_chunk(
+        lifted,
+        renames,
+        &DEFAULT_PIPELINE,
+        &Progress::default(),
+        StructureOptions::default(),
+    );
+    Decompilation::new(decompiled.body.to_string(), decompiled.failures)
+}
+
+impl Decompilation {
+    fn new(source: String, failures: Vec<(usize, String)>) -> Self {
+        let warnings = source
+            .lines()
+            .filter(|l| l.trim_start().starts_with("-- warning:"))
+            .count();
+        Self {
+            source,
+            failures,
+            warnings,
+        }
+    }
+}
+
+/// Parses bytecode into a chunk without decompiling it.
+pub fn deserialize_chunk(bytecode: &[u8], encode_key: u8) -> anyhow::Result<Chunk<'_>> {
+    match deserializer::deserialize(bytecode, encode_key)? {
+        Bytecode::Error(msg) => Err(anyhow!(msg)),
+        Bytecode::Chunk(chunk) => Ok(chunk),
+    }
+}
+
+type PanicHook = Box<dyn Fn(&std::panic::PanicHookInfo<'_>) + Sync + Send + 'static>;
+
+thread_local! {
+    static BACKTRACE: std::cell::RefCell<Option<std::backtrace::Backtrace>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+// the panic hook is process wide, so when several threads are decompiling it's installed by the
+// first one and the previous hook is restored by the last one
+static PANIC_HOOK: Mutex<(usize, Option<PanicHook>)> = parking_lot::const_mutex((0, None));
+
+// panics while decompiling a function are reported in the output instead
+fn install_panic_hook() {
+    let mut hook = PANIC_HOOK.lock();
+    if hook.0 == 0 {
+        hook.1 = Some(std::panic::take_hook());
+        std::panic::set_hook(Box::new(|_| {
+            let trace = std::backtrace::Backtrace::capture();
+            BACKTRACE.with(move |b| b.borrow_mut().replace(trace));
+        }));
+    }
+    hook.0 += 1;
+}
+
+fn restore_panic_hook() {
+    let mut hook = PANIC_HOOK.lock();
+    hook.0 -= 1;
+    if hook.0 == 0 {
+        std::panic::set_hook(hook.1.take().unwrap());
+    }
+}
+
+type DecompiledFunctions = FxHashMap<usize, Arc<Mutex<ast::Function>>>;
+
+pub(crate) struct DecompiledChunk {
+    pub body: ast::Block,
+    /// The decompiled form of every function other than main keyed by its id in the chunk.
+    /// Closures are linked into their parents, so these are also reachable from `body`.
+    pub functions: DecompiledFunctions,
+    /// Functions that failed to decompile and the reason why
+    pub failures: Vec<(usize, String)>,
+}
+
+/// The passes every function is decompiled with, in this order. `"dead code elimination"` removes
+/// code that is in the bytecode but never used, which can be what a reader of an obfuscated script
+/// is after, and `"normalize dispatch chains"` reorders the arms of if chains, so they are
+/// disabled unless they're enabled with [`PassManager::set_enabled`].
+pub fn default_pipeline() -> Pipeline {
+    let mut pipeline = Pipeline::default();
+    pipeline
+        .ssa
+        .register(FnPass::new("structure jumps", |ssa: &mut SsaFunction| {
+            let dominators = simple_fast(ssa.function.graph(), ssa.function.entry().unwrap());
+            ssa.changed |= structure_jumps(&mut ssa.function, &dominators);
+        }))
+        .unwrap();
+    pipeline
+        .ssa
+        .register(FnPass::new("inline", |ssa: &mut SsaFunction| {
+            ssa::inline::inline(
+                &mut ssa.function,
+                &ssa.local_to_group,
+                &ssa.upvalue_to_group,
+            )
+        }))
+        .unwrap();
+    pipeline
+        .ssa
+        .register(FnPass::new(
+            "dead code elimination",
+            |ssa: &mut SsaFunction| {
+                ssa.changed |=
+                    cfg::passes::dce::eliminate_dead_code(&mut ssa.function, &ssa.upvalue_to_group);
+            },
+        ))
+        .unwrap();
+    pipeline
+        .ssa
+        .set_enabled("dead code elimination", false)
+        .unwrap();
+    // we can't structure method calls like the Lua 5.1 lifter does because of __namecall
+    pipeline
+        .ssa
+        .register(FnPass::new(
+            "structure conditionals",
+            |ssa: &mut SsaFunction| ssa.changed |= structure_conditionals(&mut ssa.function),
+        ))
+        .unwrap();
+    pipeline
+        .ssa
+        .register(FnPass::new(
+            "remove unnecessary params",
+            |ssa: &mut SsaFunction| {
+                let mut local_map = FxHashMap::default();
+                // TODO: loop until returns false?
+                ssa.changed |=
+                    ssa::construct::remove_unnecessary_params(&mut ssa.function, &mut local_map);
+                ssa::construct::apply_local_map(&mut ssa.function, local_map);
+            },
+        ))
+        .unwrap();
+    // forward_varargs only changes functions that pack `...`, which have to be vararg
+    pipeline
+        .blocks
+        .register(FnPass::new("forward varargs", forward_varargs))
+        .unwrap();
+    pipeline
+        .blocks
+        .register(FnPass::new(
+            "normalize dispatch chains",
+            normalize_dispatch_chains,
+        ))
+        .unwrap();
+    pipeline
+        .blocks
+        .set_enabled("normalize dispatch chains", false)
+        .unwrap();
+    pipeline
+        .blocks
+        .register(FnPass::new("interpolate strings", interpolate_strings))
+        .unwrap();
+    pipeline
+        .blocks
+        .register(FnPass::new("compound assignments", compound_assignments))
+        .unwrap();
+    pipeline
+}
+
+lazy_static! {
+    pub(crate) static ref DEFAULT_PIPELINE: Pipeline = default_pipeline();
+    // the token of the entry points for debugging the decompiler, which can't be cancelled
+    static ref NEVER: CancellationToken = CancellationToken::default();
+}
+
+/// Decompiles every function in the chunk.
+pub(crate) fn decompile_chunk(chunk: &Chunk, renames: &RenameMap) -> DecompiledChunk {
+    decompile_lifted_chunk(
+        LiftedChunk::lift(chunk),
+        renames,
+        &DEFAULT_PIPELINE,
+        &Progress::default(),
+        StructureOptions::default(),
+    )
+}
+
+pub(crate) fn decompile_lifted_chunk(
+    mut lifted: LiftedChunk,
+    renames: &RenameMap,
+    pipeline: &Pipeline,
+    progress: &Progress,
+    structure: StructureOptions,
+) -> DecompiledChunk {
+    let root_path = lifted
+        .functions
+        .first()
+        .and_then(|f| lifted.paths.get(&f.function.id))
+        .cloned()
+        .unwrap_or_default();
+    let paths = std::mem::take(&mut lifted.paths);
+    let (main, functions, failures) =
+        decompile_lifted_functions(lifted, pipeline, progress, structure);
+    let mut body = main.body;
+    let functions_by_path = functions
+        .iter()
+        .filter_map(|(id, function)| Some((paths.get(id)?.clone(), function.clone())))
+        .collect();
+    let local_names = renames.local_names(&root_path, &body, &functions_by_path);
+    renames.apply(&mut body, local_names);
+    DecompiledChunk {
+        body,
+        functions,
+        failures,
+    }
+}
+
+/// Decompiles every lifted function and links closures into their parents, returning the first
+/// function, every other one by id and the functions that failed to decompile. Once `progress` is
+/// cancelled the remaining functions fail.
+pub(crate) fn decompile_lifted_functions(
+    lifted: LiftedChunk,
+    pipeline: &Pipeline,
+    progress: &Progress,
+    structure: StructureOptions,
+) -> (ast::Function, DecompiledFunctions, Vec<(usize, String)>) {
+    let lifted = lifted
+        .functions
+        .into_iter()
+        .map(|f| (f.ast_function.0, f.function, f.upvalues, f.next_local_id))
+        .collect::<Vec<_>>();
+    let functions = lifted
+        .iter()
+        .skip(1)
+        .map(|(ast_function, function, ..)| (function.id, ast_function.clone()))
+        .collect::<FxHashMap<_, _>>();
+    let (main, ..) = lifted.first().unwrap().clone();
+    let total = lifted.len();
+    let done = std::sync::atomic::AtomicUsize::new(0);
+    // every function is decompiled on its own with locals numbered from where its lifting left
+    // off, so they don't depend on each other until their upvalues are linked
+    #[cfg(feature = "parallel")]
+    let lifted = lifted.into_par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let lifted = lifted.into_iter();
+    let decompiled = lifted
+        .map(|(ast_function, function, upvalues_in, next_local_id)| {
+            use std::panic;
+
+            let function_id = function.id;
+            let mut args =
+                std::panic::AssertUnwindSafe(Some((ast_function.clone(), function, upvalues_in)));
+            // a pass that panics only fails the function it ran on
+            let pipeline = std::panic::AssertUnwindSafe(pipeline);
+            let cancellation = &progress.cancellation;
+
+            install_panic_hook();
+            let result = panic::catch_unwind(move || {
+                let (ast_function, function, upvalues_in) = args.take().unwrap();
+                ast::number_locals(function_id, next_local_id, || {
+                    decompile_function(
+                        ast_function,
+                        function,
+                        upvalues_in,
+                        *pipeline,
+                        cancellation,
+                        structure,
+                    )
+                })
+                .0
+            });
+            restore_panic_hook();
+            let done = done.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            progress.report("decompiling", done, total);
+
+            let result = match result {
+                Ok(Ok(r)) => Ok(r),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(e) => Err(match e.downcast::<String>() {
+                    Ok(v) => *v,
+                    Err(e) => match e.downcast::<&str>() {
+                        Ok(v) => v.to_string(),
+                        _ => "Unknown Source of Error".to_owned(),
+                    },
+                }),
+            };
+            (function_id, ast_function, result)
+        })
+        .collect::<Vec<_>>();
+
+    let mut failures = Vec::new();
+    let mut upvalues = decompiled
+        .into_iter()
+        .map(|(function_id, ast_function, result)| {
+            use std::fmt::Write;
+
+            let error = match result {
+                Ok(r) => return r,
+                Err(e) => e,
+            };
+            log::warn!("function {} failed to decompile: {}", function_id, error);
+            failures.push((function_id, error));
+
+            let mut message = String::new();
+            writeln!(message, "failed to decompile").unwrap();
+            // writeln!(message, "function {} panicked at '{}'", function_id, error).unwrap();
+            // if let Some(backtrace) = BACKTRACE.with(|b| b.borrow_mut().take()) {
+            //     write!(message, "stack backtrace:\n{}", backtrace).unwrap();
+            // }
+
+            ast_function.lock().body.extend(
+                message
+                    .trim_end()
+                    .split('\n')
+                    .map(|s| ast::Comment::new(s.to_string()).into()),
+            );
+            (ByAddress(ast_function), Vec::new())
+        })
+        .collect::<FxHashMap<_, _>>();
+
+    let main = ByAddress(main);
+    upvalues.remove(&main);
+    let mut main = Arc::try_unwrap(main.0).unwrap().into_inner();
+    link_upvalues(&mut main.body, &mut upvalues);
+    (main, functions, failures)
+}
+
+// called with the name of every pass over a function in SSA form and the function after it ran
+type PassObserver<'a> = &'a mut dyn FnMut(&str, &Function);
+
+/// Constructs SSA form and runs the passes that work on it, returning the local count and upvalue
+/// groups needed to destruct it.
+fn construct_ssa(
+    function: &mut Function,
+    upvalues_in: &Vec<ast::RcLocal>,
+    pipeline: &Pipeline,
+    observe: PassObserver,
+    cancellation: &CancellationToken,
+) -> Result<(usize, IndexMap<ast::RcLocal, ast::RcLocal>), PipelineError> {
     let (local_count, local_groups, upvalue_in_groups, upvalue_passed_groups) =
-        cfg::ssa::construct(&mut function, &upvalues_in);
+        cfg::ssa::construct(function, upvalues_in);
+    observe("ssa construction", function);
     let upvalue_to_group = upvalue_in_groups
         .into_iter()
         .chain(
@@ -165,64 +771,146 @@ fn decompile_function(
         .enumerate()
         .flat_map(|(i, g)| g.into_iter().map(move |l| (l, i)))
         .collect::<FxHashMap<_, _>>();
-    // TODO: REFACTOR: some way to write a macro that states
-    // if cfg::ssa::inline results in change then structure_jumps, structure_compound_conditionals,
-    // structure_for_loops and remove_unnecessary_params must run again.
-    // if structure_compound_conditionals results in change then dominators and post dominators
-    // must be recalculated.
-    // etc.
-    // the macro could also maybe generate an optimal ordering?
-    let mut changed = true;
-    while changed {
-        changed = false;
-
-        let dominators = simple_fast(function.graph(), function.entry().unwrap());
-        changed |= structure_jumps(&mut function, &dominators);
-
-        ssa::inline::inline(&mut function, &local_to_group, &upvalue_to_group);
-
-        if structure_conditionals(&mut function)
-        // || {
-        //     let post_dominators = post_dominators(function.graph_mut());
-        //     structure_for_loops(&mut function, &dominators, &post_dominators)
-        // }
-        // we can't structure method calls like this because of __namecall
-        // || structure_method_calls(&mut function)
-        {
-            changed = true;
-        }
-        let mut local_map = FxHashMap::default();
-        // TODO: loop until returns false?
-        if ssa::construct::remove_unnecessary_params(&mut function, &mut local_map) {
-            changed = true;
-        }
-        ssa::construct::apply_local_map(&mut function, local_map);
+    let mut ssa = SsaFunction {
+        function: std::mem::take(function),
+        local_to_group,
+        upvalue_to_group,
+        changed: true,
+    };
+    while ssa.changed {
+        ssa.changed = false;
+        pipeline
+            .ssa
+            .run_observed(&mut ssa, cancellation, &mut |pass, ssa| {
+                observe(pass, &ssa.function)
+            })?;
     }
-    // cfg::dot::render_to(&function, &mut std::io::stdout()).unwrap();
-    ssa::Destructor::new(
+    *function = ssa.function;
+    Ok((local_count, ssa.upvalue_to_group))
+}
+
+// runs a pass over a function and logs how long it took
+fn timed<T>(function_id: usize, pass: &str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    log::debug!(
+        "function {}: {} took {:?}",
+        function_id,
+        pass,
+        start.elapsed()
+    );
+    result
+}
+
+// constructs SSA form, runs the passes on it and destructs it, leaving the function ready to be
+// structured
+fn destruct_ssa(
+    function: &mut Function,
+    upvalues_in: &Vec<ast::RcLocal>,
+    pipeline: &Pipeline,
+    observe: PassObserver,
+    cancellation: &CancellationToken,
+) -> Result<(), PipelineError> {
+    let function_id = function.id;
+    let (local_count, upvalue_to_group) =
+        timed(function_id, "ssa construction and passes", || {
+            construct_ssa(function, upvalues_in, pipeline, observe, cancellation)
+        })?;
+    timed(function_id, "ssa destruction", || {
+        ssa::Destructor::new(
+            function,
+            upvalue_to_group,
+            upvalues_in.iter().cloned().collect(),
+            local_count,
+        )
+        .destruct()
+    });
+    observe("ssa destruction", function);
+    Ok(())
+}
+
+type DecompiledFunction = (ByAddress<Arc<Mutex<ast::Function>>>, Vec<ast::RcLocal>);
+
+// the pipeline has been validated, so running it only fails if it's cancelled
+fn decompile_function(
+    ast_function: Arc<Mutex<ast::Function>>,
+    mut function: Function,
+    upvalues_in: Vec<ast::RcLocal>,
+    pipeline: &Pipeline,
+    cancellation: &CancellationToken,
+    structure: StructureOptions,
+) -> anyhow::Result<DecompiledFunction> {
+    let function_id = function.id;
+    cancellation.check()?;
+    destruct_ssa(
         &mut function,
-        upvalue_to_group,
-        upvalues_in.iter().cloned().collect(),
-        local_count,
-    )
-    .destruct();
+        &upvalues_in,
+        pipeline,
+        &mut |_, _| {},
+        cancellation,
+    )?;
+    pipeline
+        .functions
+        .run_cancellable(&mut function, cancellation)?;
 
     let params = std::mem::take(&mut function.parameters);
     let is_variadic = function.is_variadic;
-    let block = Arc::new(restructure::lift(function).into());
-    LocalDeclarer::default().declare_locals(
-        // TODO: why does block.clone() not work?
-        Arc::clone(&block),
-        &upvalues_in.iter().chain(params.iter()).cloned().collect(),
-    );
+    let block = timed(function_id, "structuring", || {
+        restructure::lift_with_cancellation(function, structure, cancellation)
+    })?;
+    let block = Arc::new(block.into());
+    timed(function_id, "local declarations", || {
+        LocalDeclarer::default().declare_locals(
+            // TODO: why does block.clone() not work?
+            Arc::clone(&block),
+            &upvalues_in.iter().chain(params.iter()).cloned().collect(),
+        )
+    });
 
     {
         let mut ast_function = ast_function.lock();
         ast_function.body = Arc::try_unwrap(block).unwrap().into_inner();
+        pipeline
+            .blocks
+            .run_cancellable(&mut ast_function.body, cancellation)?;
         ast_function.parameters = params;
         ast_function.is_variadic = is_variadic;
     }
-    (ByAddress(ast_function), upvalues_in)
+    Ok((ByAddress(ast_function), upvalues_in))
+}
+
+// calls `on_block` with the block, its nested blocks and the bodies of the closures in them
+fn visit_blocks(block: &ast::Block, on_block: &mut dyn FnMut(&ast::Block)) {
+    fn visit_closures(rvalue: &ast::RValue, on_block: &mut dyn FnMut(&ast::Block)) {
+        if let ast::RValue::Closure(closure) = rvalue {
+            visit_blocks(&closure.function.lock().body, on_block);
+        }
+        for rvalue in rvalue.rvalues() {
+            visit_closures(rvalue, on_block);
+        }
+    }
+
+    on_block(block);
+    for statement in block.iter() {
+        for rvalue in statement.rvalues() {
+            visit_closures(rvalue, on_block);
+        }
+        match statement {
+            ast::Statement::If(r#if) => {
+                visit_blocks(&r#if.then_block.lock(), on_block);
+                visit_blocks(&r#if.else_block.lock(), on_block);
+            }
+            ast::Statement::While(r#while) => visit_blocks(&r#while.block.lock(), on_block),
+            ast::Statement::Repeat(repeat) => visit_blocks(&repeat.block.lock(), on_block),
+            ast::Statement::NumericFor(numeric_for) => {
+                visit_blocks(&numeric_for.block.lock(), on_block)
+            }
+            ast::Statement::GenericFor(generic_for) => {
+                visit_blocks(&generic_for.block.lock(), on_block)
+            }
+            _ => {}
+        }
+    }
 }
 
 fn link_upvalues(
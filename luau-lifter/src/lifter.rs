@@ -1,4 +1,4 @@
-use anyhow::Result;
+use std::borrow::Cow;
 
 use by_address::ByAddress;
 
@@ -11,7 +11,9 @@ use triomphe::Arc;
 
 use super::{
     deserializer::{
-        constant::Constant as BytecodeConstant, function::Function as BytecodeFunction,
+        constant::{decode_import, Constant as BytecodeConstant},
+        function::Function as BytecodeFunction,
+        string_table::StringTable,
     },
     instruction::Instruction,
     op_code::OpCode,
@@ -21,13 +23,54 @@ use cfg::{
     block::{BlockEdge, BranchType},
     function::Function,
 };
+use thiserror::Error;
+
+/// Why a function couldn't be lifted, the bytecode is malformed or uses instructions in a way the
+/// Luau compiler doesn't.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LiftError {
+    #[error("function {0} doesn't exist")]
+    InvalidFunction(usize),
+    #[error("jump at pc {0} leaves the function")]
+    InvalidJump(usize),
+    #[error("constant {0} doesn't exist")]
+    InvalidConstant(usize),
+    #[error("instruction at pc {pc} uses upvalue {upvalue}, which doesn't exist")]
+    InvalidUpvalue { pc: usize, upvalue: usize },
+    /// The constant isn't a string, or is used as a method name and isn't UTF-8
+    #[error("constant {0} isn't a string")]
+    ExpectedString(usize),
+    #[error("instruction at pc {0} uses the results of a multiple return that didn't happen")]
+    MissingMultRet(usize),
+    #[error("unexpected instruction at pc {pc}: {instruction}")]
+    UnexpectedInstruction { pc: usize, instruction: String },
+}
+
+type Result<T> = std::result::Result<T, LiftError>;
+
+/// The locals a closure captures, by upvalue slot. A closure lifted with the context of the
+/// function that creates it uses the captured locals as its upvalues, so a captured variable is
+/// the same local on both sides of the closure boundary.
+#[derive(Debug, Clone, Default)]
+pub struct UpvalueContext(Vec<ast::RcLocal>);
+
+impl UpvalueContext {
+    // the local for an upvalue slot, a fresh one if nothing was captured for it
+    fn upvalue(&self, index: usize) -> ast::RcLocal {
+        self.0.get(index).cloned().unwrap_or_default()
+    }
+}
+
+type ChildFunctions = FxHashMap<ByAddress<Arc<Mutex<ast::Function>>>, (usize, UpvalueContext)>;
+
+type Edges = Vec<(NodeIndex, BlockEdge)>;
 
 pub struct Lifter<'a> {
     function_list: &'a Vec<BytecodeFunction>,
-    string_table: &'a Vec<Vec<u8>>,
+    string_table: &'a StringTable<'a>,
     blocks: FxHashMap<usize, NodeIndex>,
     function: Function,
-    child_functions: FxHashMap<ByAddress<Arc<Mutex<ast::Function>>>, usize>,
+    child_functions: ChildFunctions,
     register_map: FxHashMap<usize, ast::RcLocal>,
     constant_map: FxHashMap<usize, ast::Literal>,
     current_node: Option<NodeIndex>,
@@ -37,13 +80,13 @@ pub struct Lifter<'a> {
 impl<'a> Lifter<'a> {
     pub fn lift(
         f_list: &'a Vec<BytecodeFunction>,
-        str_list: &'a Vec<Vec<u8>>,
+        str_list: &'a StringTable<'a>,
         function_id: usize,
-    ) -> (
-        Function,
-        Vec<ast::RcLocal>,
-        FxHashMap<ByAddress<Arc<Mutex<ast::Function>>>, usize>,
-    ) {
+        upvalue_context: &UpvalueContext,
+    ) -> Result<(Function, Vec<ast::RcLocal>, ChildFunctions)> {
+        if function_id >= f_list.len() {
+            return Err(LiftError::InvalidFunction(function_id));
+        }
         let mut context = Self {
             function_list: f_list,
             string_table: str_list,
@@ -56,12 +99,12 @@ impl<'a> Lifter<'a> {
             upvalues: Vec::new(),
         };
 
-        context.lift_function();
-        (context.function, context.upvalues, context.child_functions)
+        context.lift_function(upvalue_context)?;
+        Ok((context.function, context.upvalues, context.child_functions))
     }
 
-    fn lift_function(&mut self) {
-        self.discover_blocks().unwrap();
+    fn lift_function(&mut self, upvalue_context: &UpvalueContext) -> Result<()> {
+        self.discover_blocks()?;
 
         let mut blocks = self.blocks.keys().cloned().collect::<Vec<_>>();
 
@@ -91,8 +134,8 @@ impl<'a> Lifter<'a> {
             )
             .1;
 
-        for _ in 0..self.function_list[self.function.id].num_upvalues {
-            self.upvalues.push(ast::RcLocal::default());
+        for index in 0..self.function_list[self.function.id].num_upvalues {
+            self.upvalues.push(upvalue_context.upvalue(index as usize));
         }
 
         for i in 0..self.function_list[self.function.id].num_parameters {
@@ -105,7 +148,7 @@ impl<'a> Lifter<'a> {
 
         for (start_pc, end_pc) in block_ranges {
             self.current_node = Some(self.block_to_node(start_pc));
-            let (statements, edges) = self.lift_block(start_pc, end_pc);
+            let (statements, edges) = self.lift_block(start_pc, end_pc)?;
             let block = self.function.block_mut(self.current_node.unwrap()).unwrap();
             block.0.extend(statements);
             self.function.set_edges(self.current_node.unwrap(), edges);
@@ -120,6 +163,15 @@ impl<'a> Lifter<'a> {
             )],
         );
         self.function.set_entry(entry_node);
+        Ok(())
+    }
+
+    // the instruction a jump at `insn_index` goes to
+    fn jump_target(&self, insn_index: usize, offset: i32) -> Result<usize> {
+        (insn_index + 1)
+            .checked_add_signed(offset as isize)
+            .filter(|&target| target < self.function_list[self.function.id].instructions.len())
+            .ok_or(LiftError::InvalidJump(insn_index))
     }
 
     fn discover_blocks(&mut self) -> Result<()> {
@@ -132,7 +184,7 @@ impl<'a> Lifter<'a> {
             match insn {
                 Instruction::BC { op_code, c, .. } => match op_code {
                     OpCode::LOP_LOADB if *c != 0 => {
-                        let dest_index = (insn_index + 1).checked_add_signed((*c).into()).unwrap();
+                        let dest_index = self.jump_target(insn_index, (*c).into())?;
                         self.blocks
                             .entry(dest_index)
                             .or_insert_with(|| self.function.new_block());
@@ -150,7 +202,7 @@ impl<'a> Lifter<'a> {
                     | OpCode::LOP_JUMPBACK
                     | OpCode::LOP_JUMPIF
                     | OpCode::LOP_JUMPIFNOT => {
-                        let dest_index = (insn_index + 1).checked_add_signed((*d).into()).unwrap();
+                        let dest_index = self.jump_target(insn_index, (*d).into())?;
                         self.blocks
                             .entry(insn_index + 1)
                             .or_insert_with(|| self.function.new_block());
@@ -168,7 +220,7 @@ impl<'a> Lifter<'a> {
                     | OpCode::LOP_JUMPXEQKB
                     | OpCode::LOP_JUMPXEQKN
                     | OpCode::LOP_JUMPXEQKS => {
-                        let dest_index = (insn_index + 1).checked_add_signed((*d).into()).unwrap();
+                        let dest_index = self.jump_target(insn_index, (*d).into())?;
                         self.blocks
                             .entry(insn_index + 2)
                             .or_insert_with(|| self.function.new_block());
@@ -177,7 +229,7 @@ impl<'a> Lifter<'a> {
                             .or_insert_with(|| self.function.new_block());
                     }
                     OpCode::LOP_FORNPREP => {
-                        let dest_index = (insn_index + 1).checked_add_signed((*d).into()).unwrap();
+                        let dest_index = self.jump_target(insn_index, (*d).into())?;
                         self.blocks
                             .entry(insn_index + 1)
                             .or_insert_with(|| self.function.new_block());
@@ -188,7 +240,7 @@ impl<'a> Lifter<'a> {
                     OpCode::LOP_FORGPREP
                     | OpCode::LOP_FORGPREP_NEXT
                     | OpCode::LOP_FORGPREP_INEXT => {
-                        let dest_index = (insn_index + 1).checked_add_signed((*d).into()).unwrap();
+                        let dest_index = self.jump_target(insn_index, (*d).into())?;
                         self.blocks
                             .entry(insn_index + 1)
                             .or_insert_with(|| self.function.new_block());
@@ -197,7 +249,7 @@ impl<'a> Lifter<'a> {
                             .or_insert_with(|| self.function.new_block());
                     }
                     OpCode::LOP_FORNLOOP => {
-                        let dest_index = (insn_index + 1).checked_add_signed((*d).into()).unwrap();
+                        let dest_index = self.jump_target(insn_index, (*d).into())?;
                         self.blocks
                             .entry(insn_index)
                             .or_insert_with(|| self.function.new_block());
@@ -209,9 +261,7 @@ impl<'a> Lifter<'a> {
                             .or_insert_with(|| self.function.new_block());
                     }
                     OpCode::LOP_FORGLOOP => {
-                        let dest_index = (insn_index + 1)
-                            .checked_add_signed((*d).try_into().unwrap())
-                            .unwrap();
+                        let dest_index = self.jump_target(insn_index, (*d).into())?;
                         self.blocks
                             .entry(insn_index + 1)
                             .or_insert_with(|| self.function.new_block());
@@ -224,9 +274,7 @@ impl<'a> Lifter<'a> {
 
                 Instruction::E { op_code, e } => {
                     if *op_code == OpCode::LOP_JUMPX {
-                        let dest_index = (insn_index + 1)
-                            .checked_add_signed((*e).try_into().unwrap())
-                            .unwrap();
+                        let dest_index = self.jump_target(insn_index, *e)?;
                         self.blocks
                             .entry(insn_index + 1)
                             .or_insert_with(|| self.function.new_block());
@@ -245,8 +293,9 @@ impl<'a> Lifter<'a> {
         &mut self,
         block_start: usize,
         block_end: usize,
-    ) -> (Vec<ast::Statement>, Vec<(NodeIndex, BlockEdge)>) {
-        let mut statements = Vec::with_capacity((block_start..=block_end).count());
+    ) -> Result<(Vec<ast::Statement>, Edges)> {
+        let mut statements: Vec<ast::Statement> =
+            Vec::with_capacity((block_start..=block_end).count());
         let mut edges = Vec::new();
 
         let mut top: Option<(ast::RValue, u8)> = None;
@@ -256,6 +305,12 @@ impl<'a> Lifter<'a> {
             .enumerate();
 
         while let Some((index, instruction)) = iter.next() {
+            let pc = block_start + index;
+            let statement_count = statements.len();
+            let unexpected = |instruction: &Instruction| LiftError::UnexpectedInstruction {
+                pc,
+                instruction: format!("{:?}", instruction),
+            };
             match *instruction {
                 Instruction::BC {
                     op_code,
@@ -273,12 +328,12 @@ impl<'a> Lifter<'a> {
                     }
                     OpCode::LOP_GETUPVAL => {
                         let a = self.register(a as _);
-                        let up = self.upvalues[b as usize].clone();
+                        let up = self.upvalue(pc, b as _)?;
                         statements.push(ast::Assign::new(vec![a.into()], vec![up.into()]).into());
                     }
                     OpCode::LOP_SETUPVAL => {
                         let a = self.register(a as _);
-                        let up = self.upvalues[b as usize].clone();
+                        let up = self.upvalue(pc, b as _)?;
                         statements.push(ast::Assign::new(vec![up.into()], vec![a.into()]).into());
                     }
                     OpCode::LOP_LOADNIL => {
@@ -315,7 +370,7 @@ impl<'a> Lifter<'a> {
                     }
                     OpCode::LOP_GETGLOBAL => {
                         let value = self.register(a as _);
-                        let global_name = self.constant(aux as _).into_string().unwrap();
+                        let global_name = self.constant_string(aux as _)?;
                         statements.push(
                             ast::Assign::new(
                                 vec![value.into()],
@@ -326,7 +381,7 @@ impl<'a> Lifter<'a> {
                     }
                     OpCode::LOP_SETGLOBAL => {
                         let value = self.register(a as _);
-                        let global_name = self.constant(aux as _).into_string().unwrap();
+                        let global_name = self.constant_string(aux as _)?;
                         statements.push(
                             ast::Assign::new(
                                 vec![ast::Global::new(global_name).into()],
@@ -350,7 +405,7 @@ impl<'a> Lifter<'a> {
                     OpCode::LOP_GETTABLEKS => {
                         let target = self.register(a as _);
                         let table = self.register(b as _);
-                        let key = self.constant(aux as _);
+                        let key = self.constant(aux as _)?;
                         statements.push(
                             ast::Assign::new(
                                 vec![target.into()],
@@ -386,7 +441,7 @@ impl<'a> Lifter<'a> {
                     OpCode::LOP_SETTABLEKS => {
                         let value = self.register(a as _);
                         let table = self.register(b as _);
-                        let key = self.constant(aux as _);
+                        let key = self.constant(aux as _)?;
                         statements.push(
                             ast::Assign::new(
                                 vec![ast::Index::new(table.into(), key.into()).into()],
@@ -454,7 +509,7 @@ impl<'a> Lifter<'a> {
                         };
                         let target = self.register(a as _);
                         let left = self.register(b as _);
-                        let right = self.constant(c as _);
+                        let right = self.constant(c as _)?;
                         statements.push(
                             ast::Assign::new(
                                 vec![target.into()],
@@ -486,15 +541,19 @@ impl<'a> Lifter<'a> {
                                 .map(|r| self.register(r as _).into())
                                 .collect()
                         } else {
-                            let (tail, end) = top.take().unwrap();
+                            let (tail, end) = top.take().ok_or(LiftError::MissingMultRet(pc))?;
                             (a..end)
                                 .map(|r| self.register(r as _).into())
                                 .chain(std::iter::once(tail))
                                 .collect()
                         };
-                        statements.push(ast::Return::new(values).into());
+                        let mut r#return = ast::Return::new(values);
+                        r#return.span = Some(ast::Span::new(pc, pc));
+                        statements.push(r#return.into());
                         break;
                     }
+                    // the fallback after a fast call loads the builtin and calls it with the same
+                    // arguments, lifting only the fallback leaves a single call to it
                     OpCode::LOP_FASTCALL
                     | OpCode::LOP_FASTCALL1
                     | OpCode::LOP_FASTCALL2
@@ -503,56 +562,77 @@ impl<'a> Lifter<'a> {
                     OpCode::LOP_NAMECALL => {
                         let namecall_base = a;
                         let namecall_object = self.register(b as _);
-                        let namecall_method = match self.constant(aux as usize) {
-                            ast::Literal::String(string) => String::from_utf8(string).unwrap(),
-                            _ => unreachable!(),
-                        };
-                        assert!(matches!(
-                            iter.next().unwrap().1,
-                            Instruction::BC {
-                                op_code: OpCode::LOP_NOP,
-                                ..
-                            }
-                        ));
-                        match iter.next().unwrap().1 {
-                            &Instruction::BC {
-                                op_code: OpCode::LOP_CALL,
-                                a,
-                                b,
-                                c,
-                                ..
-                            } => {
-                                assert!(a == namecall_base);
+                        let namecall_method = String::from_utf8(self.constant_string(aux as _)?)
+                            .map_err(|_| LiftError::ExpectedString(aux as _))?;
+                        // the aux word
+                        iter.next();
+                        match iter.next() {
+                            Some((
+                                _,
+                                &Instruction::BC {
+                                    op_code: OpCode::LOP_CALL,
+                                    a,
+                                    b,
+                                    c,
+                                    ..
+                                },
+                            )) if a == namecall_base => {
                                 // TODO: repeated code :(
                                 let arguments = if b != 0 {
                                     (a + 2..a + b)
                                         .map(|r| self.register(r as _).into())
                                         .collect()
                                 } else {
-                                    let top = top.take().unwrap();
+                                    let top = top.take().ok_or(LiftError::MissingMultRet(pc))?;
                                     (a + 2..top.1)
                                         .map(|r| self.register(r as _).into())
                                         .chain(std::iter::once(top.0))
                                         .collect()
                                 };
 
-                                // TODO: make sure `a:method with space()` doesnt happen
-                                let call = ast::MethodCall::new(
-                                    namecall_object.into(),
-                                    namecall_method,
-                                    arguments,
-                                );
+                                // `a:method with space()` isn't valid syntax, so calls of methods
+                                // that aren't names are written as `a["method with space"](a)`
+                                let call: ast::Select =
+                                    if ast::formatter::Formatter::<String>::is_valid_name(
+                                        namecall_method.as_bytes(),
+                                    ) {
+                                        ast::MethodCall::new(
+                                            namecall_object.into(),
+                                            namecall_method,
+                                            arguments,
+                                        )
+                                        .into()
+                                    } else {
+                                        ast::Call::new(
+                                            ast::Index::new(
+                                                namecall_object.clone().into(),
+                                                ast::Literal::String(
+                                                    namecall_method.into_bytes().into(),
+                                                )
+                                                .into(),
+                                            )
+                                            .into(),
+                                            std::iter::once(namecall_object.into())
+                                                .chain(arguments)
+                                                .collect(),
+                                        )
+                                        .into()
+                                    };
 
                                 if c != 0 {
                                     if c == 1 {
-                                        statements.push(call.into());
+                                        statements.push(match call {
+                                            ast::Select::MethodCall(call) => call.into(),
+                                            ast::Select::Call(call) => call.into(),
+                                            ast::Select::VarArg(_) => unreachable!(),
+                                        });
                                     } else {
                                         statements.push(
                                             ast::Assign::new(
                                                 (a..a + c - 1)
                                                     .map(|r| self.register(r as _).into())
                                                     .collect(),
-                                                vec![ast::RValue::Select(call.into())],
+                                                vec![ast::RValue::Select(call)],
                                             )
                                             .into(),
                                         );
@@ -561,7 +641,7 @@ impl<'a> Lifter<'a> {
                                     top = Some((call.into(), a));
                                 }
                             }
-                            instruction => unreachable!("{:?}", instruction),
+                            _ => return Err(unexpected(instruction)),
                         }
                     }
                     OpCode::LOP_CALL => {
@@ -570,7 +650,7 @@ impl<'a> Lifter<'a> {
                                 .map(|r| self.register(r as _).into())
                                 .collect()
                         } else {
-                            let top = top.take().unwrap();
+                            let top = top.take().ok_or(LiftError::MissingMultRet(pc))?;
                             (a + 1..top.1)
                                 .map(|r| self.register(r as _).into())
                                 .chain(std::iter::once(top.0))
@@ -614,7 +694,7 @@ impl<'a> Lifter<'a> {
                                 None,
                             )
                         } else {
-                            let top = top.take().unwrap();
+                            let top = top.take().ok_or(LiftError::MissingMultRet(pc))?;
                             ast::SetList::new(
                                 self.register(a as _).clone(),
                                 aux as usize,
@@ -629,7 +709,9 @@ impl<'a> Lifter<'a> {
                             .map(|r| self.register(r as _))
                             .rev()
                             .collect::<Vec<_>>();
-                        assert!(operands.len() >= 2);
+                        if operands.len() < 2 {
+                            return Err(unexpected(instruction));
+                        }
                         let mut operands = operands.into_iter();
                         let right = operands.next().unwrap();
                         let left = operands.next().unwrap();
@@ -670,7 +752,7 @@ impl<'a> Lifter<'a> {
                             vec![self.register(a as _).into()],
                             vec![ast::Binary::new(
                                 self.register(b as _).into(),
-                                self.constant(c as _).into(),
+                                self.constant(c as _)?.into(),
                                 ast::BinaryOperation::And,
                             )
                             .into()],
@@ -694,7 +776,7 @@ impl<'a> Lifter<'a> {
                             vec![self.register(a as _).into()],
                             vec![ast::Binary::new(
                                 self.register(b as _).into(),
-                                self.constant(c as _).into(),
+                                self.constant(c as _)?.into(),
                                 ast::BinaryOperation::Or,
                             )
                             .into()],
@@ -717,7 +799,14 @@ impl<'a> Lifter<'a> {
                             top = Some((vararg.into(), a));
                         }
                     }
-                    OpCode::LOP_NOP => {}
+                    OpCode::LOP_NOP | OpCode::LOP_BREAK => {}
+                    OpCode::LOP_LOADKX => {
+                        let constant = self.constant(aux as _)?;
+                        let target = self.register(a as _);
+                        let statement =
+                            ast::Assign::new(vec![target.into()], vec![constant.into()]);
+                        statements.push(statement.into());
+                    }
                     OpCode::LOP_SUBRK | OpCode::LOP_DIVRK => {
                         let op = match op_code {
                             OpCode::LOP_SUBRK => ast::BinaryOperation::Sub,
@@ -725,7 +814,7 @@ impl<'a> Lifter<'a> {
                             _ => unreachable!(),
                         };
                         let target = self.register(a as _);
-                        let left = self.constant(b as _);
+                        let left = self.constant(b as _)?;
                         let right = self.register(c as _);
                         statements.push(
                             ast::Assign::new(
@@ -735,11 +824,11 @@ impl<'a> Lifter<'a> {
                             .into(),
                         );
                     }
-                    _ => unreachable!("{:?}", instruction),
+                    _ => return Err(unexpected(instruction)),
                 },
                 Instruction::AD { op_code, a, d, aux } => match op_code {
                     OpCode::LOP_LOADK => {
-                        let constant = self.constant(d as _);
+                        let constant = self.constant(d as _)?;
                         let target = self.register(a as _);
                         let statement =
                             ast::Assign::new(vec![target.into()], vec![constant.into()]);
@@ -755,27 +844,14 @@ impl<'a> Lifter<'a> {
                     }
                     OpCode::LOP_GETIMPORT => {
                         let target = self.register(a as _);
-                        let import_len = (aux >> 30) & 3;
-                        assert!(import_len <= 3);
-                        let mut import_expression: ast::RValue = ast::Global::new(
-                            self.constant(((aux >> 20) & 1023) as usize)
-                                .into_string()
-                                .unwrap(),
-                        )
-                        .into();
-                        if import_len > 1 {
-                            import_expression = ast::Index::new(
-                                import_expression,
-                                self.constant(((aux >> 10) & 1023) as usize).into(),
-                            )
-                            .into();
-                        }
-                        if import_len > 2 {
-                            import_expression = ast::Index::new(
-                                import_expression,
-                                self.constant((aux & 1023) as usize).into(),
-                            )
-                            .into();
+                        let mut path = decode_import(aux).into_iter();
+                        let global = path.next().ok_or_else(|| unexpected(instruction))?;
+                        let mut import_expression: ast::RValue =
+                            ast::Global::new(self.constant_string(global)?).into();
+                        for index in path {
+                            import_expression =
+                                ast::Index::new(import_expression, self.constant(index)?.into())
+                                    .into();
                         }
                         let assign = ast::Assign::new(vec![target.into()], vec![import_expression]);
                         statements.push(assign.into());
@@ -1065,7 +1141,7 @@ impl<'a> Lifter<'a> {
                     }
                     OpCode::LOP_JUMPXEQKN | OpCode::LOP_JUMPXEQKS => {
                         let a = self.register(a as _);
-                        let literal = self.constant((aux & ((1 << 24) - 1)) as _);
+                        let literal = self.constant((aux & ((1 << 24) - 1)) as _)?;
                         statements.push(
                             ast::If::new(
                                 ast::Binary::new(
@@ -1121,7 +1197,7 @@ impl<'a> Lifter<'a> {
                                     .is_some_and(|s| matches!(s, ast::Statement::NumForNext(_)))
                             })
                             .exactly_one()
-                            .unwrap();
+                            .map_err(|_| unexpected(instruction))?;
                         edges.push((loop_node, BlockEdge::new(BranchType::Unconditional)));
                     }
                     OpCode::LOP_FORNLOOP => {
@@ -1149,13 +1225,15 @@ impl<'a> Lifter<'a> {
                         let counter = self.register((a + 2) as _);
                         statements.push(ast::GenericForInit::new(generator, state, counter).into());
                         let loop_index = ((block_start + index + 1) as isize + d as isize) as usize;
-                        assert!(matches!(
+                        if !matches!(
                             self.function_list[self.function.id].instructions[loop_index],
                             Instruction::AD {
                                 op_code: OpCode::LOP_FORGLOOP,
                                 ..
                             }
-                        ));
+                        ) {
+                            return Err(unexpected(instruction));
+                        }
                         edges.push((
                             self.block_to_node(loop_index),
                             BlockEdge::new(BranchType::Unconditional),
@@ -1202,55 +1280,67 @@ impl<'a> Lifter<'a> {
                     OpCode::LOP_DUPCLOSURE | OpCode::LOP_NEWCLOSURE => {
                         let dest_local = self.register(a as _);
                         let func_index = match op_code {
-                            OpCode::LOP_NEWCLOSURE => {
-                                self.function_list[self.function.id].functions[d as usize]
-                            }
+                            OpCode::LOP_NEWCLOSURE => self.function_list[self.function.id]
+                                .functions
+                                .get(d as usize)
+                                .copied(),
                             OpCode::LOP_DUPCLOSURE => match self.function_list[self.function.id]
                                 .constants
                                 .get(d as usize)
-                                .unwrap()
                             {
-                                &BytecodeConstant::Closure(func_index) => func_index,
-                                _ => unreachable!(),
+                                Some(&BytecodeConstant::Closure(func_index)) => Some(func_index),
+                                _ => None,
                             },
                             _ => unreachable!(),
-                        };
-                        let func_name_index = self.function_list[func_index].function_name;
-                        let func_name = if func_name_index == 0 {
-                            None
-                        } else {
-                            Some(
-                                String::from_utf8_lossy(&self.string_table[func_name_index - 1])
-                                    .into_owned(),
-                            )
-                        };
+                        }
+                        .filter(|&func_index| func_index < self.function_list.len())
+                        .ok_or_else(|| unexpected(instruction))?;
+                        let func_name = self
+                            .string_table
+                            .get(self.function_list[func_index].function_name)
+                            .map(Cow::into_owned);
 
                         let func = &self.function_list[func_index];
                         let mut upvalues_passed = Vec::with_capacity(func.num_upvalues.into());
                         for _ in 0..func.num_upvalues {
-                            let local = match iter.next().as_ref().unwrap().1 {
-                                &Instruction::BC {
-                                    op_code: OpCode::LOP_CAPTURE,
-                                    a: capture_type,
-                                    b: source,
-                                    ..
-                                } => match capture_type {
+                            let local = match iter.next() {
+                                Some((
+                                    capture_index,
+                                    &Instruction::BC {
+                                        op_code: OpCode::LOP_CAPTURE,
+                                        a: capture_type,
+                                        b: source,
+                                        ..
+                                    },
+                                )) => match capture_type {
                                     // capture value
                                     0 => ast::Upvalue::Copy(self.register(source as _)),
                                     // capture ref
                                     1 => ast::Upvalue::Ref(self.register(source as _)),
                                     // capture upval
-                                    2 => ast::Upvalue::Ref(self.upvalues[source as usize].clone()),
-                                    _ => unreachable!(),
+                                    2 => ast::Upvalue::Ref(
+                                        self.upvalue(block_start + capture_index, source as _)?,
+                                    ),
+                                    _ => return Err(unexpected(instruction)),
                                 },
-                                _ => unreachable!(),
+                                _ => return Err(unexpected(instruction)),
                             };
                             upvalues_passed.push(local);
                         }
 
+                        let captured = upvalues_passed
+                            .iter()
+                            .map(|upvalue| match upvalue {
+                                ast::Upvalue::Copy(local) | ast::Upvalue::Ref(local) => {
+                                    local.clone()
+                                }
+                            })
+                            .collect();
                         let function = Arc::<Mutex<_>>::default();
-                        self.child_functions
-                            .insert(ByAddress(function.clone()), func_index);
+                        self.child_functions.insert(
+                            ByAddress(function.clone()),
+                            (func_index, UpvalueContext(captured)),
+                        );
                         function.lock().name = func_name;
                         statements.push(
                             ast::Assign::new(
@@ -1264,7 +1354,7 @@ impl<'a> Lifter<'a> {
                             .into(),
                         );
                     }
-                    _ => unreachable!("{:?}", instruction),
+                    _ => return Err(unexpected(instruction)),
                 },
                 Instruction::E { op_code, e } => match op_code {
                     OpCode::LOP_JUMPX => {
@@ -1275,9 +1365,18 @@ impl<'a> Lifter<'a> {
                             BlockEdge::new(BranchType::Unconditional),
                         ));
                     }
-                    _ => unreachable!("{:?}", instruction),
+                    OpCode::LOP_COVERAGE => {}
+                    _ => return Err(unexpected(instruction)),
                 },
-                _ => unimplemented!("{:?}", instruction),
+            }
+
+            // instructions with an aux word span more than one pc
+            let pc_end = iter
+                .clone()
+                .next()
+                .map_or(block_end, |(i, _)| block_start + i - 1);
+            for statement in &mut statements[statement_count..] {
+                statement.extend_span(Some(ast::Span::new(pc, pc_end)));
             }
         }
 
@@ -1299,33 +1398,53 @@ impl<'a> Lifter<'a> {
             }
         }
 
-        (statements, edges)
+        Ok((statements, edges))
     }
 
     fn register(&mut self, index: usize) -> ast::RcLocal {
         self.register_map.entry(index).or_default().clone()
     }
 
-    fn constant(&mut self, index: usize) -> ast::Literal {
+    fn upvalue(&self, pc: usize, index: usize) -> Result<ast::RcLocal> {
+        self.upvalues
+            .get(index)
+            .cloned()
+            .ok_or(LiftError::InvalidUpvalue { pc, upvalue: index })
+    }
+
+    fn constant(&mut self, index: usize) -> Result<ast::Literal> {
         let converted_constant = match self.function_list[self.function.id]
             .constants
             .get(index)
-            .unwrap()
+            .ok_or(LiftError::InvalidConstant(index))?
         {
             BytecodeConstant::Nil => ast::Literal::Nil,
             BytecodeConstant::Boolean(v) => ast::Literal::Boolean(*v),
             BytecodeConstant::Number(v) => ast::Literal::Number(*v),
             BytecodeConstant::String(v) => {
                 // TODO: what does the official deserializer do if v == 0?
-                ast::Literal::String(self.string_table[*v - 1].clone())
+                let string = self
+                    .string_table
+                    .get_bytes(*v)
+                    .ok_or(LiftError::InvalidConstant(index))?;
+                ast::Literal::from(string)
             }
             BytecodeConstant::Vector(x, y, z, _) => ast::Literal::Vector(*x, *y, *z),
-            _ => unimplemented!(),
+            // imports, tables and closures are only used by the instructions made for them
+            _ => return Err(LiftError::InvalidConstant(index)),
         };
-        self.constant_map
+        Ok(self
+            .constant_map
             .entry(index)
             .or_insert(converted_constant)
-            .clone()
+            .clone())
+    }
+
+    fn constant_string(&mut self, index: usize) -> Result<Vec<u8>> {
+        self.constant(index)?
+            .into_string()
+            .map(|string| string.to_vec())
+            .map_err(|_| LiftError::ExpectedString(index))
     }
 
     fn block_to_node(&self, insn_index: usize) -> NodeIndex {
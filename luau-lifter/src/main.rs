@@ -1,10 +1,747 @@
-fn main() {
-    let file_name = std::env::args().nth(1).expect("expected exactly one file");
-    let key = std::env::args()
-        .nth(2)
-        .or_else(|| None)
-        .map(|s| if s == "-e" { 203 } else { panic!() })
-        .unwrap_or(1);
-    let bytecode = std::fs::read(file_name).expect("failed to read file");
-    println!("{}", luau_lifter::decompile_bytecode(&bytecode, key));
+use anyhow::anyhow;
+use clap::{Parser, Subcommand};
+use luau_lifter::{
+    AstJsonEmitter, BatchOptions, BatchReport, DecompileOptions, FormatOptions, GlobalStyle,
+    GlobalsFormat, LiftedChunk, Patch, RenameMap, Server, SourceMapEmitter, Trace, XrefKind,
+};
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use std::{
+    io::BufReader,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+};
+use walkdir::WalkDir;
+
+#[cfg(feature = "dhat-heap")]
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+#[derive(Parser, Debug)]
+#[clap(about, version, author, args_conflicts_with_subcommands = true)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// Files to decompile and directories to decompile every file in. If there is more than one
+    /// file the output is written next to each file, or to --output-dir
+    files: Vec<String>,
+    /// Bytecode is encoded with the Roblox client key (203)
+    #[clap(short)]
+    encoded: bool,
+    /// Write every function to its own file under this directory
+    #[clap(long)]
+    split: Option<String>,
+    /// Prepend a comment with the tool version, input hash and bytecode version
+    #[clap(long)]
+    banner: bool,
+    /// JSON file mapping generated local names and globals to new names
+    #[clap(long)]
+    renames: Option<String>,
+    /// Where to write the failure manifest when decompiling more than one file
+    #[clap(long, default_value = "failures.json")]
+    failures: String,
+    /// Write the output of every file to this directory, at the same path relative to the
+    /// directory it was found in
+    #[clap(long, value_name = "DIR")]
+    output_dir: Option<String>,
+    /// Write the outcome and decompilation time of every file to this file as JSON
+    #[clap(long)]
+    report: Option<String>,
+    /// Save the lifted functions of the input to this file instead of decompiling it
+    #[clap(long)]
+    save_lifted: Option<String>,
+    /// Inputs are lifted functions saved with --save-lifted instead of bytecode
+    #[clap(long, conflicts_with_all = ["split", "banner", "save_lifted"])]
+    lifted: bool,
+    /// Only print the statements that may influence the local or global with this name
+    #[clap(long, conflicts_with_all = ["split", "save_lifted", "lifted"])]
+    slice: Option<String>,
+    /// Only decompile the function at this path of closure indices, e.g. 0.2, see the functions
+    /// command
+    #[clap(long, conflicts_with_all = ["split", "save_lifted", "lifted", "slice"])]
+    function: Option<String>,
+    /// Leave the closures of the function selected with --function empty
+    #[clap(long, requires = "function")]
+    no_children: bool,
+    /// Write the output a statement of the main function at a time while decompiling, which keeps
+    /// memory use bounded on very large chunks
+    #[clap(long, conflicts_with_all = ["split", "save_lifted", "lifted", "slice", "function"])]
+    stream: bool,
+    /// End every statement with a comment naming the instructions it was lifted from
+    #[clap(long, conflicts_with_all = ["split", "save_lifted", "lifted", "slice", "function", "stream"])]
+    pc_comments: bool,
+    /// Write the instructions every line of the output was lifted from to this file as JSON
+    #[clap(long, conflicts_with_all = ["split", "save_lifted", "lifted", "slice", "function", "stream"])]
+    source_map: Option<String>,
+    /// How to write globals: by name, as fields of _ENV (env) or of getfenv() (getfenv)
+    #[clap(long, default_value = "name", conflicts_with_all = ["split", "save_lifted", "slice", "function", "stream", "trace"])]
+    global_style: GlobalStyle,
+    /// What to output, the decompiled source or its AST as JSON (ast-json)
+    #[clap(long, default_value = "source", conflicts_with_all = ["split", "save_lifted", "lifted", "slice", "function", "stream", "pc_comments", "source_map", "trace"])]
+    emit: Emit,
+    /// Annotate the output with the values observed in this trace file, one JSON event per line
+    #[clap(long, conflicts_with_all = ["split", "save_lifted", "lifted", "slice", "function", "stream", "pc_comments", "source_map"])]
+    trace: Option<String>,
+    /// Print every function in SSA form with its phi nodes instead of decompiling it
+    #[clap(long, conflicts_with_all = ["split", "save_lifted", "lifted", "slice", "trace"])]
+    ssa: bool,
+    /// Print the control flow graph of every function before and after each SSA pass, a line of
+    /// JSON per snapshot
+    #[clap(long, conflicts_with_all = ["split", "save_lifted", "lifted", "slice", "trace", "ssa"])]
+    dump_passes: bool,
+    /// Explain which structuring patterns were tried on every node and why they didn't match
+    #[clap(long, conflicts_with_all = ["split", "save_lifted", "lifted", "slice", "trace", "ssa"])]
+    explain_structuring: bool,
+    /// Write a DOT snapshot of every function's control flow graph after each structuring step to
+    /// <dir>/<function id>/<step>.dot
+    #[clap(long, value_name = "DIR", conflicts_with_all = ["split", "save_lifted", "lifted", "slice", "trace", "ssa", "explain_structuring"])]
+    snapshots: Option<String>,
+    /// Also render the snapshots to SVG with this Graphviz command, e.g. dot
+    #[clap(long, requires = "snapshots")]
+    svg_renderer: Option<String>,
+    /// Number of threads decompiling files at once, defaults to the number of cores
+    #[clap(short, long)]
+    jobs: Option<usize>,
+    /// Log to stderr, -v logs how long every pass took and -vv every structuring decision
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    Source,
+    AstJson,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "source" => Ok(Emit::Source),
+            "ast-json" => Ok(Emit::AstJson),
+            _ => Err(format!("unknown output {}, expected source or ast-json", s)),
+        }
+    }
+}
+
+/// Exit status of the decompiler, ordered by severity.
+/// When decompiling more than one file the most severe status is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    Success = 0,
+    /// The output contains warnings
+    Warnings = 3,
+    /// Some functions failed to decompile
+    PartialDecompilation = 4,
+    /// The input couldn't be deserialized
+    ParseFailure = 5,
+}
+
+#[derive(Serialize)]
+struct Failure {
+    file: String,
+    error: Option<String>,
+    failed_functions: Vec<(usize, String)>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Match the functions of two chunks and diff their decompiled output
+    Diff {
+        old: String,
+        new: String,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// Interactively browse the functions of a chunk
+    Browse {
+        file: String,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// List every function of a chunk with its path, name, line and signature
+    Functions {
+        file: String,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// Print the constants, upvalues and stack information of a function
+    Constants {
+        file: String,
+        /// Path of closure indices starting at the main function, e.g. 0.2
+        #[clap(long, default_value = "0")]
+        function: String,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// Apply patches to a chunk and write the result to a new file
+    Patch {
+        file: String,
+        #[clap(short, long)]
+        output: String,
+        /// Replace a string, <string index>=<value>
+        #[clap(long = "string")]
+        strings: Vec<String>,
+        /// Change the target of a jump, <function>:<pc>=<target pc>
+        #[clap(long = "jump")]
+        jumps: Vec<String>,
+        /// Replace instructions with nops, <function>:<start pc>-<end pc>
+        #[clap(long = "nop")]
+        nops: Vec<String>,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// Search the globals, imports and string constants of every chunk in the given paths
+    Grep {
+        pattern: String,
+        paths: Vec<String>,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// List the functions that reference a global, call, field or string
+    Xref {
+        file: String,
+        /// Name to look up, e.g. string.char or Position; every reference is listed if omitted
+        name: Option<String>,
+        /// Only list references of this kind: read, write, call, field or string
+        #[clap(long)]
+        kind: Option<XrefKind>,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// Print the functions each function may call
+    Calls {
+        file: String,
+        /// Print the call graph in the dot format
+        #[clap(long)]
+        dot: bool,
+        /// Add the call targets observed in this trace file
+        #[clap(long)]
+        trace: Option<String>,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// Print the globals a chunk reads and writes as a linter config
+    Globals {
+        file: String,
+        /// luacheck or selene
+        #[clap(long, default_value = "luacheck")]
+        format: GlobalsFormat,
+        /// JSON file mapping globals to new names, the same one used when decompiling
+        #[clap(long)]
+        renames: Option<String>,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// Answer JSON-RPC requests, one per line, on stdin or a TCP address
+    Serve {
+        /// Address to listen on, e.g. 127.0.0.1:7000
+        #[clap(long)]
+        listen: Option<String>,
+    },
+}
+
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn init_logger(verbose: u8) {
+    let level = match verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    if log::set_boxed_logger(Box::new(StderrLogger)).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+fn encode_key(encoded: bool) -> u8 {
+    if encoded {
+        203
+    } else {
+        1
+    }
+}
+
+fn parse_patches(
+    strings: &[String],
+    jumps: &[String],
+    nops: &[String],
+) -> anyhow::Result<Vec<Patch>> {
+    let invalid = |s: &str| anyhow!("invalid patch: {}", s);
+    let mut patches = Vec::new();
+    for string in strings {
+        let (index, value) = string.split_once('=').ok_or_else(|| invalid(string))?;
+        patches.push(Patch::ReplaceString {
+            index: index.parse()?,
+            value: value.as_bytes().to_vec(),
+        });
+    }
+    for jump in jumps {
+        let (function, rest) = jump.split_once(':').ok_or_else(|| invalid(jump))?;
+        let (pc, target) = rest.split_once('=').ok_or_else(|| invalid(jump))?;
+        patches.push(Patch::SetJumpTarget {
+            function: function.parse()?,
+            pc: pc.parse()?,
+            target: target.parse()?,
+        });
+    }
+    for nop in nops {
+        let (function, range) = nop.split_once(':').ok_or_else(|| invalid(nop))?;
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        patches.push(Patch::Nop {
+            function: function.parse()?,
+            start: start.parse()?,
+            end: end.parse()?,
+        });
+    }
+    Ok(patches)
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+    init_logger(args.verbose);
+    match args.command {
+        Some(Command::Diff { old, new, encoded }) => {
+            let old = std::fs::read(old)?;
+            let new = std::fs::read(new)?;
+            println!(
+                "{}",
+                luau_lifter::diff_bytecode(&old, &new, encode_key(encoded))?
+            );
+        }
+        Some(Command::Browse { file, encoded }) => {
+            let bytecode = std::fs::read(file)?;
+            luau_lifter::browse(
+                &bytecode,
+                encode_key(encoded),
+                std::io::stdin().lock(),
+                std::io::stdout(),
+            )?;
+        }
+        Some(Command::Functions { file, encoded }) => {
+            let bytecode = std::fs::read(file)?;
+            for function in luau_lifter::list_functions(&bytecode, encode_key(encoded))? {
+                println!(
+                    "{}\t{}\tline {}\t{}{} parameters\t{} upvalues\t{} instructions\t{} closures",
+                    function.path,
+                    function.name.as_deref().unwrap_or("anonymous"),
+                    function.line_defined,
+                    function.num_parameters,
+                    if function.is_vararg { "+..." } else { "" },
+                    function.num_upvalues,
+                    function.num_instructions,
+                    function.num_children
+                );
+            }
+        }
+        Some(Command::Constants {
+            file,
+            function,
+            encoded,
+        }) => {
+            let bytecode = std::fs::read(file)?;
+            print!(
+                "{}",
+                luau_lifter::describe_function(&bytecode, encode_key(encoded), &function)?
+            );
+        }
+        Some(Command::Patch {
+            file,
+            output,
+            strings,
+            jumps,
+            nops,
+            encoded,
+        }) => {
+            let bytecode = std::fs::read(file)?;
+            let patches = parse_patches(&strings, &jumps, &nops)?;
+            std::fs::write(
+                output,
+                luau_lifter::patch_bytecode(&bytecode, encode_key(encoded), &patches)?,
+            )?;
+        }
+        Some(Command::Grep {
+            pattern,
+            paths,
+            encoded,
+        }) => {
+            for entry in paths.iter().flat_map(WalkDir::new) {
+                let entry = entry?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let bytecode = std::fs::read(entry.path())?;
+                // files that aren't valid bytecode are skipped
+                if let Ok(references) =
+                    luau_lifter::grep_bytecode(&bytecode, encode_key(encoded), &pattern)
+                {
+                    for reference in references {
+                        println!(
+                            "{}:{}:{}: {} {:?}",
+                            entry.path().display(),
+                            reference.function_path,
+                            reference.pc,
+                            reference.kind,
+                            reference.value
+                        );
+                    }
+                }
+            }
+        }
+        Some(Command::Xref {
+            file,
+            name,
+            kind,
+            encoded,
+        }) => {
+            let bytecode = std::fs::read(file)?;
+            let xrefs = luau_lifter::deserialize_chunk(&bytecode, encode_key(encoded))?.xrefs();
+            for xref in xrefs.iter().filter(|xref| {
+                (kind.is_none() || kind == Some(xref.kind))
+                    && (name.is_none() || name.as_deref() == Some(xref.name.as_str()))
+            }) {
+                println!(
+                    "{}:{}: {} {}",
+                    xref.function_path, xref.pc, xref.kind, xref.name
+                );
+            }
+        }
+        Some(Command::Calls {
+            file,
+            dot,
+            trace,
+            encoded,
+        }) => {
+            let bytecode = std::fs::read(file)?;
+            let mut call_graph =
+                luau_lifter::deserialize_chunk(&bytecode, encode_key(encoded))?.call_graph();
+            if let Some(trace) = trace {
+                call_graph.add_trace(&Trace::from_json_lines(&std::fs::read_to_string(trace)?)?);
+            }
+            if dot {
+                println!("{}", call_graph.to_dot());
+            } else {
+                let graph = &call_graph.graph;
+                for edge in graph.raw_edges() {
+                    println!(
+                        "{} -> {} ({})",
+                        graph[edge.source()],
+                        graph[edge.target()],
+                        edge.weight
+                    );
+                }
+            }
+        }
+        Some(Command::Globals {
+            file,
+            format,
+            renames,
+            encoded,
+        }) => {
+            let bytecode = std::fs::read(file)?;
+            let mut globals =
+                luau_lifter::deserialize_chunk(&bytecode, encode_key(encoded))?.globals();
+            if let Some(path) = renames {
+                globals = globals.renamed(&RenameMap::from_json(&std::fs::read_to_string(path)?)?);
+            }
+            print!("{}", globals.to_config(format));
+        }
+        Some(Command::Serve { listen }) => {
+            let mut server = Server::new();
+            match listen {
+                Some(address) => {
+                    for stream in TcpListener::bind(address)?.incoming() {
+                        let stream = stream?;
+                        // a connection going away shouldn't stop the server
+                        if let Err(err) = server.serve(BufReader::new(stream.try_clone()?), stream)
+                        {
+                            eprintln!("connection closed: {}", err);
+                        }
+                    }
+                }
+                None => server.serve(std::io::stdin().lock(), std::io::stdout().lock())?,
+            }
+        }
+        None => {
+            if args.files.is_empty() {
+                return Err(anyhow!("expected at least one file"));
+            }
+            let encode_key = encode_key(args.encoded);
+            if let Some(directory) = args.split {
+                let bytecode = std::fs::read(&args.files[0])?;
+                let directory = Path::new(&directory);
+                for (path, source) in luau_lifter::decompile_bytecode_split(&bytecode, encode_key)?
+                {
+                    let path = directory.join(path);
+                    std::fs::create_dir_all(path.parent().unwrap())?;
+                    std::fs::write(path, source)?;
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            if let Some(path) = args.save_lifted {
+                let bytecode = std::fs::read(&args.files[0])?;
+                std::fs::write(
+                    path,
+                    LiftedChunk::from_bytecode(&bytecode, encode_key)?.save()?,
+                )?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            if args.ssa {
+                let bytecode = std::fs::read(&args.files[0])?;
+                print!(
+                    "{}",
+                    luau_lifter::decompile_bytecode_ssa(&bytecode, encode_key)?
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            if args.dump_passes {
+                let bytecode = std::fs::read(&args.files[0])?;
+                luau_lifter::dump_passes(&bytecode, encode_key, &mut std::io::stdout().lock())?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            if args.explain_structuring {
+                let bytecode = std::fs::read(&args.files[0])?;
+                for (function_id, path, trace) in
+                    luau_lifter::explain_structuring(&bytecode, encode_key)?
+                {
+                    let status = if trace.is_structured() {
+                        "structured"
+                    } else {
+                        "not structured"
+                    };
+                    println!("function {} ({}): {}", function_id, path, status);
+                    println!("{}", trace);
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            if let Some(directory) = args.snapshots {
+                let bytecode = std::fs::read(&args.files[0])?;
+                luau_lifter::structuring_snapshots(
+                    &bytecode,
+                    encode_key,
+                    Path::new(&directory),
+                    args.svg_renderer.as_deref(),
+                )?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            let renames = match args.renames {
+                Some(path) => RenameMap::from_json(&std::fs::read_to_string(path)?)?,
+                None => RenameMap::default(),
+            };
+
+            if let Some(name) = args.slice {
+                let bytecode = std::fs::read(&args.files[0])?;
+                println!(
+                    "{}",
+                    luau_lifter::decompile_bytecode_slice(&bytecode, encode_key, &renames, &name)?
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            if let Some(path) = args.function {
+                let bytecode = std::fs::read(&args.files[0])?;
+                let decompilation = luau_lifter::decompile_bytecode_function(
+                    &bytecode,
+                    encode_key,
+                    &renames,
+                    &path,
+                    !args.no_children,
+                )?;
+                println!("{}", decompilation.source);
+                return Ok(ExitCode::SUCCESS);
+            }
+            if args.stream {
+                let bytecode = std::fs::read(&args.files[0])?;
+                let failures = luau_lifter::decompile_bytecode_streaming(
+                    &bytecode,
+                    encode_key,
+                    &renames,
+                    &mut std::io::stdout().lock(),
+                )?;
+                let status = if failures.is_empty() {
+                    Status::Success
+                } else {
+                    Status::PartialDecompilation
+                };
+                return Ok(ExitCode::from(status as u8));
+            }
+            if args.emit == Emit::AstJson {
+                let bytecode = std::fs::read(&args.files[0])?;
+                let options = DecompileOptions {
+                    encode_key,
+                    renames: &renames,
+                    ..Default::default()
+                }
+                .with_emitter(AstJsonEmitter { pretty: true });
+                println!(
+                    "{}",
+                    luau_lifter::decompile_bytecode_with(&bytecode, &options)?.source?
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            if args.pc_comments || args.source_map.is_some() {
+                let bytecode = std::fs::read(&args.files[0])?;
+                let format = FormatOptions {
+                    emit_spans: args.pc_comments,
+                    global_style: args.global_style,
+                    ..Default::default()
+                };
+                let options = DecompileOptions {
+                    encode_key,
+                    renames: &renames,
+                    ..Default::default()
+                }
+                .with_emitter(SourceMapEmitter { options: format });
+                let (source, source_map) =
+                    luau_lifter::decompile_bytecode_with(&bytecode, &options)?.source;
+                if let Some(path) = args.source_map {
+                    std::fs::write(path, serde_json::to_string_pretty(&source_map)?)?;
+                }
+                println!("{}", source);
+                return Ok(ExitCode::SUCCESS);
+            }
+            if let Some(trace) = args.trace {
+                let bytecode = std::fs::read(&args.files[0])?;
+                let trace = Trace::from_json_lines(&std::fs::read_to_string(trace)?)?;
+                let options = DecompileOptions {
+                    encode_key,
+                    renames: &renames,
+                    trace: Some(&trace),
+                    ..Default::default()
+                };
+                println!(
+                    "{}",
+                    luau_lifter::decompile_bytecode_with(&bytecode, &options)?.source
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            // directories are replaced with the files in them, which are mirrored in the output
+            // directory relative to the directory
+            let mut roots = FxHashMap::default();
+            for path in args.files.iter().map(PathBuf::from) {
+                if path.is_dir() {
+                    for file in luau_lifter::files_in(&path)? {
+                        roots.insert(file, path.clone());
+                    }
+                } else {
+                    let root = path.parent().map(Path::to_path_buf).unwrap_or_default();
+                    roots.insert(path, root);
+                }
+            }
+            let mut files = roots.keys().cloned().collect::<Vec<_>>();
+            files.sort();
+            // with more than one file the output of each is written to a file of its own
+            let batch = files.len() > 1 || args.output_dir.is_some();
+            let mut status = Status::Success;
+            let mut manifest = Vec::new();
+            let mut report = BatchReport::default();
+            let options = BatchOptions {
+                encode_key,
+                renames: &renames,
+                lifted: args.lifted,
+                jobs: args
+                    .jobs
+                    .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+                format: FormatOptions {
+                    global_style: args.global_style,
+                    ..Default::default()
+                },
+            };
+            luau_lifter::decompile_batch(files, options, |output| {
+                report.add(&output);
+                let file = output.file.display().to_string();
+                let (file_status, source) = match output.decompilation {
+                    Ok(decompilation) => {
+                        let file_status = if !decompilation.failures.is_empty() {
+                            Status::PartialDecompilation
+                        } else if decompilation.warnings > 0 {
+                            Status::Warnings
+                        } else {
+                            Status::Success
+                        };
+                        if !decompilation.failures.is_empty() {
+                            manifest.push(Failure {
+                                file: file.clone(),
+                                error: None,
+                                failed_functions: decompilation.failures,
+                            });
+                        }
+                        (file_status, Some(decompilation.source))
+                    }
+                    Err(err) => {
+                        eprintln!("{}: {}", file, err);
+                        manifest.push(Failure {
+                            file: file.clone(),
+                            error: Some(err.to_string()),
+                            failed_functions: Vec::new(),
+                        });
+                        (Status::ParseFailure, None)
+                    }
+                };
+                status = status.max(file_status);
+
+                if let Some(source) = source {
+                    let mut text = String::new();
+                    if args.banner {
+                        text += &luau_lifter::provenance_banner(&output.input, encode_key)?;
+                    }
+                    text += &source;
+                    if let Some(output_dir) = &args.output_dir {
+                        let path = luau_lifter::mirrored_path(
+                            &roots[&output.file],
+                            &output.file,
+                            Path::new(output_dir),
+                        );
+                        if let Some(parent) = path.parent() {
+                            std::fs::create_dir_all(parent)?;
+                        }
+                        std::fs::write(path, text)?;
+                    } else if batch {
+                        std::fs::write(output.file.with_extension("dec.lua"), text)?;
+                    } else {
+                        println!("{}", text);
+                    }
+                }
+                Ok(())
+            })?;
+            // files finish in any order
+            manifest.sort_by(|a, b| a.file.cmp(&b.file));
+            report.finish();
+            if batch {
+                std::fs::write(&args.failures, serde_json::to_string_pretty(&manifest)?)?;
+                eprintln!("{}", report);
+            }
+            if let Some(path) = args.report {
+                std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+            }
+            return Ok(ExitCode::from(status as u8));
+        }
+    }
+    Ok(ExitCode::SUCCESS)
 }
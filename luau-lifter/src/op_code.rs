@@ -368,3 +368,40 @@ pub enum OpCode {
     // Enum entry for number of opcodes, not a valid opcode by itself!
     LOP__COUNT,
 }
+
+impl OpCode {
+    /// Whether the instruction is followed by an auxiliary word.
+    pub fn has_aux(self) -> bool {
+        matches!(
+            self,
+            OpCode::LOP_GETGLOBAL
+                | OpCode::LOP_SETGLOBAL
+                | OpCode::LOP_GETIMPORT
+                | OpCode::LOP_GETTABLEKS
+                | OpCode::LOP_SETTABLEKS
+                | OpCode::LOP_NAMECALL
+                | OpCode::LOP_JUMPIFEQ
+                | OpCode::LOP_JUMPIFLE
+                | OpCode::LOP_JUMPIFLT
+                | OpCode::LOP_JUMPIFNOTEQ
+                | OpCode::LOP_JUMPIFNOTLE
+                | OpCode::LOP_JUMPIFNOTLT
+                | OpCode::LOP_NEWTABLE
+                | OpCode::LOP_SETLIST
+                | OpCode::LOP_FORGLOOP
+                | OpCode::LOP_LOADKX
+                | OpCode::LOP_FASTCALL2
+                | OpCode::LOP_FASTCALL2K
+                | OpCode::LOP_FASTCALL3
+                | OpCode::LOP_JUMPXEQKNIL
+                | OpCode::LOP_JUMPXEQKB
+                | OpCode::LOP_JUMPXEQKN
+                | OpCode::LOP_JUMPXEQKS
+        )
+    }
+
+    /// The name of the opcode as used by the official disassembler, e.g. `GETIMPORT`.
+    pub fn name(self) -> String {
+        format!("{:?}", self).trim_start_matches("LOP_").to_string()
+    }
+}
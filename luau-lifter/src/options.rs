@@ -0,0 +1,138 @@
+use lazy_static::lazy_static;
+use restructure::Fallback;
+
+use crate::{
+    embedded::append_long_comment, Budget, Devirtualizer, DisplayEmitter, Pipeline, Progress,
+    RenameMap, SourceMapping, StringDecryptor, StructureOptions, Trace, DEFAULT_PIPELINE,
+};
+
+lazy_static! {
+    static ref NO_RENAMES: RenameMap = RenameMap::default();
+    static ref NO_PROGRESS: Progress = Progress::default();
+}
+
+/// How [`decompile_bytecode_with`](crate::decompile_bytecode_with) decompiles a chunk. The default
+/// decompiles it like [`decompile_bytecode`](crate::decompile_bytecode) with opcodes that aren't
+/// encoded, fields are usually set with `..Default::default()` for the rest and another emitter
+/// with [`DecompileOptions::with_emitter`].
+pub struct DecompileOptions<'a, E = DisplayEmitter> {
+    /// Opcodes are multiplied by this, 203 for Roblox and 1 otherwise
+    pub encode_key: u8,
+    /// Names applied to locals and globals
+    pub renames: &'a RenameMap,
+    /// The passes every function is decompiled with, see [`default_pipeline`](crate::default_pipeline)
+    pub pipeline: &'a Pipeline,
+    /// Calls these recognize are replaced with the constants they decrypt, see
+    /// [`ast::decrypt_strings::decrypt_strings`]. Locals are named by then, so decryptors can
+    /// match them by their names in the output.
+    pub decryptors: &'a [StringDecryptor],
+    /// The programs these recover from the chunk are decompiled too and appended to the output
+    pub devirtualizers: &'a [&'a dyn Devirtualizer],
+    /// Every function starts with comments describing the values, types and call targets observed
+    /// at its instructions
+    pub trace: Option<&'a Trace>,
+    /// The functions lifted and decompiled are reported as the `"lifting"` and `"decompiling"`
+    /// stages, and [`Cancelled`](crate::Cancelled) is returned once its token is cancelled. The
+    /// token is checked between functions, passes and the patterns the structurer matches.
+    pub progress: &'a Progress,
+    /// Every function is structured within this
+    pub budget: Budget,
+    /// Write branches of ifs that end in a `return`, `break` or `continue` as guard clauses instead
+    /// of nesting the other branch in an else block
+    pub guard_clauses: bool,
+    /// Turns the decompiled chunk into the output, the chunks embedded in it and the programs
+    /// recovered from it are emitted with a clone of it
+    pub emitter: E,
+}
+
+impl Default for DecompileOptions<'_> {
+    fn default() -> Self {
+        Self {
+            encode_key: 1,
+            renames: &NO_RENAMES,
+            pipeline: &DEFAULT_PIPELINE,
+            decryptors: &[],
+            devirtualizers: &[],
+            trace: None,
+            progress: &NO_PROGRESS,
+            budget: Budget::default(),
+            guard_clauses: false,
+            emitter: DisplayEmitter::default(),
+        }
+    }
+}
+
+impl<'a, E> DecompileOptions<'a, E> {
+    /// The same options with the output emitted by `emitter` instead
+    pub fn with_emitter<F>(self, emitter: F) -> DecompileOptions<'a, F> {
+        DecompileOptions {
+            encode_key: self.encode_key,
+            renames: self.renames,
+            pipeline: self.pipeline,
+            decryptors: self.decryptors,
+            devirtualizers: self.devirtualizers,
+            trace: self.trace,
+            progress: self.progress,
+            budget: self.budget,
+            guard_clauses: self.guard_clauses,
+            emitter,
+        }
+    }
+}
+
+impl<E: Clone> DecompileOptions<'_, E> {
+    // the options a chunk embedded in the one decompiled with these or a program recovered from it
+    // are decompiled with. they are formatted the same, but the rest of the options are for the
+    // chunk they were found in. recovered programs aren't devirtualized again.
+    pub(crate) fn for_program(&self, encode_key: u8) -> Self {
+        Self {
+            encode_key,
+            renames: &NO_RENAMES,
+            pipeline: self.pipeline,
+            decryptors: &[],
+            devirtualizers: &[],
+            trace: None,
+            progress: self.progress,
+            budget: self.budget,
+            guard_clauses: self.guard_clauses,
+            emitter: self.emitter.clone(),
+        }
+    }
+}
+
+impl<E> DecompileOptions<'_, E> {
+    // how every function is structured, control flow that can't be structured is left to a state
+    // machine as Luau has no goto
+    pub(crate) fn structure_options(&self) -> StructureOptions {
+        StructureOptions {
+            fallback: Fallback::StateMachine,
+            guard_clauses: self.guard_clauses,
+            budget: self.budget,
+        }
+    }
+}
+
+/// The output of an [`Emitter`](crate::Emitter) that the output of other chunks can be appended
+/// to, the way embedded chunks and recovered programs are appended to the chunk they were found
+/// in. Outputs that aren't source code leave them out.
+pub trait AppendChunk: Sized {
+    /// Appends `chunk`, found where `heading` says
+    fn append_chunk(&mut self, heading: &str, chunk: Self);
+}
+
+impl AppendChunk for String {
+    fn append_chunk(&mut self, heading: &str, chunk: Self) {
+        append_long_comment(self, heading, &chunk);
+    }
+}
+
+// the lines of the appended chunk aren't mapped, they were lifted from other bytecode
+impl AppendChunk for (String, Vec<SourceMapping>) {
+    fn append_chunk(&mut self, heading: &str, chunk: Self) {
+        append_long_comment(&mut self.0, heading, &chunk.0);
+    }
+}
+
+impl AppendChunk for serde_json::Result<String> {
+    fn append_chunk(&mut self, _: &str, _: Self) {}
+}
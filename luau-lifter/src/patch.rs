@@ -0,0 +1,121 @@
+use anyhow::anyhow;
+
+use crate::{
+    deserialize_chunk, deserializer::chunk::Chunk, instruction::Instruction, op_code::OpCode,
+    serializer::serialize,
+};
+
+#[derive(Debug, Clone)]
+pub enum Patch {
+    /// Replace the contents of a string in the string table (1-based, like string constants)
+    ReplaceString { index: usize, value: Vec<u8> },
+    /// Make the jump at `pc` in `function` jump to `target` instead
+    SetJumpTarget {
+        function: usize,
+        pc: usize,
+        target: usize,
+    },
+    /// Replace the instruction words `start..=end` in `function` with `NOP`s
+    Nop {
+        function: usize,
+        start: usize,
+        end: usize,
+    },
+}
+
+fn apply_patch(chunk: &mut Chunk, encode_key: u8, patch: &Patch) -> anyhow::Result<()> {
+    match patch {
+        Patch::ReplaceString { index, value } => {
+            chunk
+                .string_table
+                .set(*index, value.clone())
+                .ok_or_else(|| anyhow!("no string {}", index))?;
+        }
+        &Patch::SetJumpTarget {
+            function,
+            pc,
+            target,
+        } => {
+            let code = &mut chunk
+                .functions
+                .get_mut(function)
+                .ok_or_else(|| anyhow!("no function {}", function))?
+                .code;
+            if target >= code.len() {
+                return Err(anyhow!("jump target {} is out of bounds", target));
+            }
+            let word = *code
+                .get(pc)
+                .ok_or_else(|| anyhow!("pc {} is out of bounds", pc))?;
+            let offset = target as isize - (pc as isize + 1);
+            code[pc] = match Instruction::parse(word, encode_key)
+                .map_err(|e| anyhow!("invalid instruction at pc {}: {:?}", pc, e))?
+            {
+                Instruction::E {
+                    op_code: OpCode::LOP_JUMPX,
+                    ..
+                } => {
+                    if !(-(1 << 23)..(1 << 23)).contains(&offset) {
+                        return Err(anyhow!("jump offset {} does not fit", offset));
+                    }
+                    (word & 0xFF) | ((offset as u32) << 8)
+                }
+                Instruction::AD {
+                    op_code:
+                        OpCode::LOP_JUMP
+                        | OpCode::LOP_JUMPBACK
+                        | OpCode::LOP_JUMPIF
+                        | OpCode::LOP_JUMPIFNOT
+                        | OpCode::LOP_JUMPIFEQ
+                        | OpCode::LOP_JUMPIFLE
+                        | OpCode::LOP_JUMPIFLT
+                        | OpCode::LOP_JUMPIFNOTEQ
+                        | OpCode::LOP_JUMPIFNOTLE
+                        | OpCode::LOP_JUMPIFNOTLT
+                        | OpCode::LOP_JUMPXEQKNIL
+                        | OpCode::LOP_JUMPXEQKB
+                        | OpCode::LOP_JUMPXEQKN
+                        | OpCode::LOP_JUMPXEQKS,
+                    ..
+                } => {
+                    let offset = i16::try_from(offset)
+                        .map_err(|_| anyhow!("jump offset {} does not fit", offset))?;
+                    (word & 0xFFFF) | ((offset as u16 as u32) << 16)
+                }
+                instruction => return Err(anyhow!("{:?} is not a jump", instruction)),
+            };
+        }
+        &Patch::Nop {
+            function,
+            start,
+            end,
+        } => {
+            let code = &mut chunk
+                .functions
+                .get_mut(function)
+                .ok_or_else(|| anyhow!("no function {}", function))?
+                .code;
+            if start > end || end >= code.len() {
+                return Err(anyhow!("invalid instruction range {}..={}", start, end));
+            }
+            // LOP_NOP is 0, which is 0 under any encode key
+            code[start..=end].fill(OpCode::LOP_NOP as u32);
+        }
+    }
+    Ok(())
+}
+
+/// Applies patches to a chunk and serializes it again.
+/// Patches work on the encoded instruction words, line info and the instruction
+/// count are left untouched.
+pub fn patch_bytecode(
+    bytecode: &[u8],
+    encode_key: u8,
+    patches: &[Patch],
+) -> anyhow::Result<Vec<u8>> {
+    let mut chunk = deserialize_chunk(bytecode, encode_key)?;
+    for patch in patches {
+        apply_patch(&mut chunk, encode_key, patch)?;
+    }
+    Ok(serialize(&chunk))
+}
@@ -0,0 +1,221 @@
+use anyhow::bail;
+use parking_lot::Mutex;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use triomphe::Arc;
+
+/// Names chosen by the user that are applied when naming locals and emitting globals.
+///
+/// A local is picked out either by its generated name, or by the path of the function it is in
+/// (see [`FunctionInfo::path`](crate::FunctionInfo::path)) and the pc of the instruction that
+/// declares it, followed by `#` and its position if the instruction declares more than one local.
+/// Unlike generated names, these don't change when other locals are added or removed.
+///
+/// ```json
+/// {
+///     "locals": { "v12": "player", "0.1@4": "character", "0@7#2": "value" },
+///     "globals": { "a": "Players" }
+/// }
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct RenameMap {
+    /// Generated local name or `<function path>@<pc>[#<position>]` -> new name
+    #[serde(default)]
+    pub locals: FxHashMap<String, String>,
+    /// Global name -> new name
+    #[serde(default)]
+    pub globals: FxHashMap<String, String>,
+}
+
+// where a local picked out by the instruction that declares it is
+#[derive(Debug, PartialEq, Eq)]
+struct Declaration<'a> {
+    path: &'a str,
+    pc: usize,
+    // 0-based position among the locals declared
+    position: usize,
+}
+
+impl<'a> Declaration<'a> {
+    // `None` if `key` is a generated name
+    fn parse(key: &'a str) -> Option<anyhow::Result<Self>> {
+        let (path, pc) = key.rsplit_once('@')?;
+        let (pc, position) = match pc.split_once('#') {
+            Some((pc, position)) => match position.parse::<usize>() {
+                Ok(position) if position != 0 => (pc, position - 1),
+                _ => return Some(Err(anyhow::anyhow!("invalid position in local {}", key))),
+            },
+            None => (pc, 0),
+        };
+        Some(match pc.parse() {
+            Ok(pc) => Ok(Self { path, pc, position }),
+            Err(_) => Err(anyhow::anyhow!("invalid pc in local {}", key)),
+        })
+    }
+
+    // the local in `block` declared by the statement lifted from `self.pc`, closures aren't
+    // searched since they are other functions
+    fn find(&self, block: &ast::Block) -> Option<ast::RcLocal> {
+        block.iter().find_map(|statement| match statement {
+            ast::Statement::Assign(assign)
+                if assign.prefix
+                    && assign
+                        .span
+                        .is_some_and(|span| (span.pc_start..=span.pc_end).contains(&self.pc)) =>
+            {
+                assign
+                    .left
+                    .get(self.position)
+                    .and_then(|l| l.as_local())
+                    .cloned()
+            }
+            ast::Statement::If(r#if) => self
+                .find(&r#if.then_block.lock())
+                .or_else(|| self.find(&r#if.else_block.lock())),
+            ast::Statement::While(r#while) => self.find(&r#while.block.lock()),
+            ast::Statement::Repeat(repeat) => self.find(&repeat.block.lock()),
+            ast::Statement::NumericFor(numeric_for) => self.find(&numeric_for.block.lock()),
+            ast::Statement::GenericFor(generic_for) => self.find(&generic_for.block.lock()),
+            _ => None,
+        })
+    }
+}
+
+impl RenameMap {
+    /// Parses and validates a rename map, every new local name has to be a name Lua accepts.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let map: Self = serde_json::from_str(json)?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for (key, name) in &self.locals {
+            if !ast::formatter::Formatter::<String>::is_valid_name(name.as_bytes()) {
+                bail!(
+                    "local {} can't be named {:?}, it isn't a valid name",
+                    key,
+                    name
+                );
+            }
+            if let Some(declaration) = Declaration::parse(key) {
+                declaration?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The locals picked out by where they are declared and their new names. `root` is the body
+    /// of the function at `root_path`, the bodies of the rest are found in `functions` by path.
+    pub(crate) fn local_names(
+        &self,
+        root_path: &str,
+        root: &ast::Block,
+        functions: &FxHashMap<String, Arc<Mutex<ast::Function>>>,
+    ) -> FxHashMap<ast::RcLocal, String> {
+        self.locals
+            .iter()
+            .filter_map(|(key, name)| {
+                let declaration = Declaration::parse(key)?.ok()?;
+                let local = if declaration.path == root_path {
+                    declaration.find(root)
+                } else {
+                    declaration.find(&functions.get(declaration.path)?.lock().body)
+                };
+                if local.is_none() {
+                    log::warn!("no local is declared at {}", key);
+                }
+                Some((local?, name.clone()))
+            })
+            .collect()
+    }
+
+    pub(crate) fn apply(
+        &self,
+        body: &mut ast::Block,
+        local_names: FxHashMap<ast::RcLocal, String>,
+    ) {
+        ast::name_locals::name_locals_with_overrides(body, true, &self.locals, local_names);
+        self.replace_globals(body);
+    }
+
+    /// A namer for naming a chunk a block at a time, [`RenameMap::replace_globals`] has to be
+    /// called on each block too.
+    pub(crate) fn namer(
+        &self,
+        local_names: FxHashMap<ast::RcLocal, String>,
+    ) -> ast::name_locals::Namer<'_> {
+        ast::name_locals::Namer::new(true, &self.locals).with_local_names(local_names)
+    }
+
+    pub(crate) fn replace_globals(&self, body: &mut ast::Block) {
+        if !self.globals.is_empty() {
+            let globals = self
+                .globals
+                .iter()
+                .map(|(from, to)| (from.as_bytes().to_vec(), to.as_bytes().to_vec()))
+                .collect::<FxHashMap<_, _>>();
+            ast::replace_globals::replace_globals(body, &globals);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_declarations() {
+        assert!(Declaration::parse("v12").is_none());
+        assert_eq!(
+            Declaration::parse("0.1@4").unwrap().unwrap(),
+            Declaration {
+                path: "0.1",
+                pc: 4,
+                position: 0
+            }
+        );
+        assert_eq!(
+            Declaration::parse("0@7#2").unwrap().unwrap(),
+            Declaration {
+                path: "0",
+                pc: 7,
+                position: 1
+            }
+        );
+        assert!(Declaration::parse("0@seven").unwrap().is_err());
+        assert!(Declaration::parse("0@7#0").unwrap().is_err());
+    }
+
+    #[test]
+    fn invalid_names() {
+        assert!(RenameMap::from_json(r#"{ "locals": { "v1": "player" } }"#).is_ok());
+        assert!(RenameMap::from_json(r#"{ "locals": { "v1": "end" } }"#).is_err());
+        assert!(RenameMap::from_json(r#"{ "locals": { "v1": "1st" } }"#).is_err());
+        assert!(RenameMap::from_json(r#"{ "locals": { "v1": "a b" } }"#).is_err());
+        assert!(RenameMap::from_json(r#"{ "locals": { "0@x": "a" } }"#).is_err());
+    }
+
+    #[test]
+    fn find_declaration() {
+        let local = ast::RcLocal::default();
+        let mut declaration = ast::Assign::new(
+            vec![ast::RcLocal::default().into(), local.clone().into()],
+            vec![ast::Call::new(ast::Global::from("f").into(), Vec::new()).into()],
+        );
+        declaration.prefix = true;
+        declaration.span = Some(ast::Span::new(3, 4));
+        let body: ast::Block = vec![ast::While::new(
+            ast::Literal::Boolean(true).into(),
+            vec![declaration.into()].into(),
+        )
+        .into()]
+        .into();
+
+        let map =
+            RenameMap::from_json(r#"{ "locals": { "0@4#2": "value", "0@5": "x" } }"#).unwrap();
+        let local_names = map.local_names("0", &body, &FxHashMap::default());
+        assert_eq!(local_names.len(), 1);
+        assert_eq!(local_names[&local], "value");
+    }
+}
@@ -0,0 +1,123 @@
+use parking_lot::Mutex;
+use rustc_hash::{FxHashMap, FxHashSet};
+use triomphe::Arc;
+
+use crate::{
+    checkpoint::LiftedChunk, decompile_lifted_functions, deserialize_chunk,
+    deserializer::chunk::Chunk, inspect::resolve_function, Decompilation, Progress, RenameMap,
+    StructureOptions, DEFAULT_PIPELINE,
+};
+
+/// A function of a chunk as listed by [`list_functions`].
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    pub id: usize,
+    /// Closure indices starting at the main function, e.g. `0.2` for the third closure of the
+    /// main function, which [`decompile_bytecode_function`] takes to select the function
+    pub path: String,
+    /// The name the function was given in the source, Luau bytecode has no source file names
+    pub name: Option<String>,
+    pub line_defined: usize,
+    pub num_parameters: u8,
+    pub is_vararg: bool,
+    pub num_upvalues: u8,
+    pub num_instructions: usize,
+    /// Number of closures defined directly in the function
+    pub num_children: usize,
+}
+
+/// Lists every function of the chunk reachable from the main function, parents before their
+/// children, without lifting any of them.
+pub fn list_functions(bytecode: &[u8], encode_key: u8) -> anyhow::Result<Vec<FunctionInfo>> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    Ok(function_paths(&chunk)
+        .into_iter()
+        .map(|(function_id, path)| {
+            let function = &chunk.functions[function_id];
+            FunctionInfo {
+                id: function_id,
+                path,
+                name: chunk.function_name(function_id),
+                line_defined: function.line_defined,
+                num_parameters: function.num_parameters,
+                is_vararg: function.is_vararg,
+                num_upvalues: function.num_upvalues,
+                num_instructions: function.instructions.len(),
+                num_children: function.functions.len(),
+            }
+        })
+        .collect())
+}
+
+// the id and path of every function reachable from the main function, parents before their
+// children, see `FunctionInfo::path`
+pub(crate) fn function_paths(chunk: &Chunk) -> Vec<(usize, String)> {
+    let mut paths = Vec::new();
+    let mut visited = FxHashSet::default();
+    let mut stack = vec![(chunk.main, "0".to_string())];
+    while let Some((function_id, path)) = stack.pop() {
+        if !visited.insert(function_id) {
+            continue;
+        }
+        stack.extend(
+            chunk.functions[function_id]
+                .functions
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(index, &child)| (child, format!("{}.{}", path, index))),
+        );
+        paths.push((function_id, path));
+    }
+    paths
+}
+
+/// Decompiles a single function of the chunk, selected by a path of closure indices like
+/// [`FunctionInfo::path`], without lifting the rest of the chunk. The closures it defines are
+/// decompiled too if `children` is set, otherwise their bodies are left empty.
+///
+/// The main function is decompiled as usual, any other function is declared with the name it has
+/// in the source, or returned if it is anonymous. Its upvalues are left undeclared.
+pub fn decompile_bytecode_function(
+    bytecode: &[u8],
+    encode_key: u8,
+    renames: &RenameMap,
+    path: &str,
+    children: bool,
+) -> anyhow::Result<Decompilation> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let function_id = resolve_function(&chunk, path)?;
+    let lifted = LiftedChunk::lift_function(&chunk, function_id, children);
+    let (mut function, _, failures) = decompile_lifted_functions(
+        lifted,
+        &DEFAULT_PIPELINE,
+        &Progress::default(),
+        StructureOptions::default(),
+    );
+    let local_names = function_paths(&chunk)
+        .into_iter()
+        .find(|&(id, _)| id == function_id)
+        .map(|(_, path)| renames.local_names(&path, &function.body, &FxHashMap::default()))
+        .unwrap_or_default();
+    let mut body = if function_id == chunk.main {
+        function.body
+    } else {
+        let name = chunk.function_name(function_id);
+        function.name.clone_from(&name);
+        let closure = ast::Closure {
+            function: Arc::new(Mutex::new(function)).into(),
+            upvalues: Vec::new(),
+        };
+        let statement = match name {
+            Some(name) => ast::Assign::new(
+                vec![ast::Global::new(name.into()).into()],
+                vec![closure.into()],
+            )
+            .into(),
+            None => ast::Return::new(vec![closure.into()]).into(),
+        };
+        vec![statement].into()
+    };
+    renames.apply(&mut body, local_names);
+    Ok(Decompilation::new(body.to_string(), failures))
+}
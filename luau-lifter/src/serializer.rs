@@ -0,0 +1,148 @@
+use crate::deserializer::{chunk::Chunk, constant::Constant, function::Function, BytecodeVersion};
+
+// must match the tags in deserializer/constant.rs
+const CONSTANT_NIL: u8 = 0;
+const CONSTANT_BOOLEAN: u8 = 1;
+const CONSTANT_NUMBER: u8 = 2;
+const CONSTANT_STRING: u8 = 3;
+const CONSTANT_IMPORT: u8 = 4;
+const CONSTANT_TABLE: u8 = 5;
+const CONSTANT_CLOSURE: u8 = 6;
+const CONSTANT_VECTOR: u8 = 7;
+
+fn write_leb128(output: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(byte);
+            break;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+fn write_constant(output: &mut Vec<u8>, constant: &Constant) {
+    match constant {
+        Constant::Nil => output.push(CONSTANT_NIL),
+        Constant::Boolean(value) => {
+            output.push(CONSTANT_BOOLEAN);
+            output.push(*value as u8);
+        }
+        Constant::Number(value) => {
+            output.push(CONSTANT_NUMBER);
+            output.extend(value.to_le_bytes());
+        }
+        Constant::String(index) => {
+            output.push(CONSTANT_STRING);
+            write_leb128(output, *index);
+        }
+        Constant::Import { id } => {
+            output.push(CONSTANT_IMPORT);
+            output.extend(id.to_le_bytes());
+        }
+        Constant::Table(keys) => {
+            output.push(CONSTANT_TABLE);
+            write_leb128(output, keys.len());
+            for &key in keys {
+                write_leb128(output, key);
+            }
+        }
+        Constant::Closure(function_id) => {
+            output.push(CONSTANT_CLOSURE);
+            write_leb128(output, *function_id);
+        }
+        Constant::Vector(x, y, z, w) => {
+            output.push(CONSTANT_VECTOR);
+            for component in [x, y, z, w] {
+                output.extend(component.to_le_bytes());
+            }
+        }
+    }
+}
+
+fn write_function(output: &mut Vec<u8>, function: &Function, version: BytecodeVersion) {
+    output.push(function.max_stack_size);
+    output.push(function.num_parameters);
+    output.push(function.num_upvalues);
+    output.push(function.is_vararg as u8);
+    if version.has_type_info() {
+        output.push(function.flags);
+        write_leb128(output, function.type_info.len());
+        output.extend(&function.type_info);
+    }
+
+    write_leb128(output, function.code.len());
+    for word in &function.code {
+        output.extend(word.to_le_bytes());
+    }
+    write_leb128(output, function.constants.len());
+    for constant in &function.constants {
+        write_constant(output, constant);
+    }
+    write_leb128(output, function.functions.len());
+    for &function_id in &function.functions {
+        write_leb128(output, function_id);
+    }
+    write_leb128(output, function.line_defined);
+    write_leb128(output, function.function_name);
+
+    match (
+        function.line_gap_log2,
+        &function.line_info_delta,
+        &function.abs_line_info_delta,
+    ) {
+        (Some(line_gap_log2), Some(line_info_delta), Some(abs_line_info_delta)) => {
+            output.push(1);
+            output.push(line_gap_log2);
+            output.extend(line_info_delta);
+            for line in abs_line_info_delta {
+                output.extend(line.to_le_bytes());
+            }
+        }
+        _ => output.push(0),
+    }
+    match &function.debug_info {
+        Some(debug_info) => {
+            output.push(1);
+            write_leb128(output, debug_info.locals.len());
+            for local in &debug_info.locals {
+                write_leb128(output, local.name);
+                write_leb128(output, local.start_pc);
+                write_leb128(output, local.end_pc);
+                output.push(local.register);
+            }
+            write_leb128(output, debug_info.upvalues.len());
+            for &upvalue in &debug_info.upvalues {
+                write_leb128(output, upvalue);
+            }
+        }
+        None => output.push(0),
+    }
+}
+
+/// Serializes a chunk back into bytecode that can be loaded by the Luau VM.
+/// Instructions are written from [`Function::code`], so they keep the encoding of the input.
+pub fn serialize(chunk: &Chunk) -> Vec<u8> {
+    let mut output = vec![chunk.version.into()];
+    if chunk.version.has_type_info() {
+        output.push(chunk.types_version);
+    }
+    write_leb128(&mut output, chunk.string_table.len());
+    for (_, string) in chunk.string_table.iter() {
+        write_leb128(&mut output, string.len());
+        output.extend(string);
+    }
+    if chunk.types_version == 3 {
+        for &userdata_type in &chunk.userdata_types {
+            write_leb128(&mut output, userdata_type);
+        }
+        output.push(0);
+    }
+    write_leb128(&mut output, chunk.functions.len());
+    for function in &chunk.functions {
+        write_function(&mut output, function, chunk.version);
+    }
+    write_leb128(&mut output, chunk.main);
+    output
+}
@@ -0,0 +1,198 @@
+use std::io::{BufRead, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    decompile_chunk, deserialize_chunk,
+    deserializer::chunk::Chunk,
+    disassembler::disassemble_function,
+    inspect::{describe, resolve_function},
+    DecompiledChunk, RenameMap,
+};
+
+/// Number of chunks kept decompiled between requests
+const CACHE_SIZE: usize = 16;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const DECOMPILATION_ERROR: i64 = -32000;
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct Params {
+    /// Base64 encoded bytecode
+    bytecode: String,
+    #[serde(default = "default_encode_key")]
+    encode_key: u8,
+    /// Path of closure indices starting at the main function, e.g. 0.2
+    #[serde(default = "default_function")]
+    function: String,
+}
+
+fn default_encode_key() -> u8 {
+    1
+}
+
+fn default_function() -> String {
+    "0".to_string()
+}
+
+struct CachedChunk {
+    chunk: Chunk<'static>,
+    decompiled: Option<DecompiledChunk>,
+}
+
+/// Answers JSON-RPC 2.0 requests for the `decompile`, `disassemble` and `info` methods.
+/// Every method takes the base64 encoded `bytecode`, an optional `encode_key` and an optional
+/// `function` path. Chunks are decompiled once and kept in a cache keyed by their hash,
+/// so requesting several functions of the same chunk is cheap.
+#[derive(Default)]
+pub struct Server {
+    cache: IndexMap<(u8, Vec<u8>), CachedChunk>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads one request per line from `input` and writes one response per line to `output`.
+    pub fn serve(&mut self, input: impl BufRead, mut output: impl Write) -> anyhow::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line) {
+                writeln!(output, "{}", response)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a single request, returns `None` for notifications.
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        let (id, result) = match serde_json::from_str::<Request>(request) {
+            Ok(request) => {
+                let result = self.call(&request.method, request.params);
+                // requests without an id are notifications and don't get a response
+                (request.id?, result)
+            }
+            Err(err) => (Value::Null, Err(RpcError::new(PARSE_ERROR, err))),
+        };
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": err.code, "message": err.message },
+            }),
+        };
+        Some(response.to_string())
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        if !matches!(method, "decompile" | "disassemble" | "info") {
+            return Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method {}", method),
+            ));
+        }
+        let params = serde_json::from_value::<Params>(params)
+            .map_err(|err| RpcError::new(INVALID_PARAMS, err))?;
+        let cached = self.chunk(&params)?;
+        let function_id = resolve_function(&cached.chunk, &params.function)
+            .map_err(|err| RpcError::new(INVALID_PARAMS, err))?;
+        match method {
+            "decompile" => {
+                let CachedChunk { chunk, decompiled } = cached;
+                let decompiled =
+                    decompiled.get_or_insert_with(|| decompile_chunk(chunk, &RenameMap::default()));
+                let source = if function_id == chunk.main {
+                    decompiled.body.to_string()
+                } else {
+                    decompiled.functions[&function_id].lock().body.to_string()
+                };
+                let failures = decompiled
+                    .failures
+                    .iter()
+                    .filter(|(id, _)| *id == function_id)
+                    .map(|(_, reason)| reason)
+                    .collect::<Vec<_>>();
+                Ok(json!({ "source": source, "failures": failures }))
+            }
+            "disassemble" => Ok(json!({
+                "disassembly": disassemble_function(&cached.chunk, function_id),
+            })),
+            "info" => {
+                let chunk = &cached.chunk;
+                let functions = chunk
+                    .function_paths()
+                    .into_iter()
+                    .map(|(id, path)| {
+                        json!({ "id": id, "path": path, "name": chunk.function_name(id) })
+                    })
+                    .collect::<Vec<_>>();
+                let description = describe(chunk, function_id)
+                    .map_err(|err| RpcError::new(DECOMPILATION_ERROR, err))?;
+                Ok(json!({
+                    "version": u8::from(chunk.version),
+                    "types_version": chunk.types_version,
+                    "functions": functions,
+                    "description": description,
+                }))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn chunk(&mut self, params: &Params) -> Result<&mut CachedChunk, RpcError> {
+        let bytecode = STANDARD
+            .decode(&params.bytecode)
+            .map_err(|err| RpcError::new(INVALID_PARAMS, err))?;
+        let key = (params.encode_key, Sha256::digest(&bytecode).to_vec());
+        if !self.cache.contains_key(&key) {
+            let chunk = deserialize_chunk(&bytecode, params.encode_key)
+                .map_err(|err| RpcError::new(DECOMPILATION_ERROR, err))?
+                .into_owned();
+            if self.cache.len() == CACHE_SIZE {
+                self.cache.shift_remove_index(0);
+            }
+            self.cache.insert(
+                key.clone(),
+                CachedChunk {
+                    chunk,
+                    decompiled: None,
+                },
+            );
+        }
+        Ok(self.cache.get_mut(&key).unwrap())
+    }
+}
@@ -0,0 +1,94 @@
+use std::path::{Component, Path, PathBuf};
+
+use rustc_hash::FxHashSet;
+
+use crate::{decompile_chunk, deserialize_chunk, RenameMap};
+
+// names come from the bytecode, so ones like `..` mustn't leave the directory they're joined to
+fn sanitize_file_name(name: &str) -> String {
+    if name.chars().all(|c| c == '.') {
+        return "_".to_string();
+    }
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn function_file_name(
+    function: &ast::Function,
+    target: &ast::LValue,
+    used: &mut FxHashSet<String>,
+) -> String {
+    let name = match (&function.name, target) {
+        (Some(name), _) => name.clone(),
+        (None, ast::LValue::Global(global)) => String::from_utf8_lossy(&global.0).into_owned(),
+        (None, target) => target.to_string(),
+    };
+    let name = sanitize_file_name(&name);
+    let mut unique_name = name.clone();
+    let mut suffix = 2;
+    while !used.insert(unique_name.clone()) {
+        unique_name = format!("{}_{}", name, suffix);
+        suffix += 1;
+    }
+    unique_name
+}
+
+// moves every function assigned at the top level of `block` into its own file under `directory`,
+// leaving a comment pointing to the file in its place
+fn split_block(block: &mut ast::Block, directory: &Path, files: &mut Vec<(PathBuf, String)>) {
+    let mut used = FxHashSet::default();
+    for statement in &mut block.0 {
+        let ast::Statement::Assign(assign) = statement else {
+            continue;
+        };
+        if assign.left.len() != 1 || assign.right.len() != 1 {
+            continue;
+        }
+        let ast::RValue::Closure(closure) = &assign.right[0] else {
+            continue;
+        };
+        let file_name = {
+            let mut function = closure.function.lock();
+            let file_name = function_file_name(&function, &assign.left[0], &mut used);
+            split_block(&mut function.body, &directory.join(&file_name), files);
+            file_name
+        };
+        let path = directory.join(file_name + ".lua");
+        assert!(
+            path.components()
+                .all(|component| matches!(component, Component::Normal(_))),
+            "{} isn't under the split root",
+            path.display()
+        );
+        files.push((path.clone(), assign.to_string()));
+        *statement = ast::Comment::new(format!(
+            "{} = function, see {}",
+            assign.left[0],
+            path.display()
+        ))
+        .into();
+    }
+}
+
+/// Decompiles a chunk into a tree of files, one for every function that is assigned at the top level
+/// of its parent. Nested functions are placed in a directory named after their parent and the
+/// main function is written to `main.lua` with comments linking to the other files.
+/// The returned paths are relative.
+pub fn decompile_bytecode_split(
+    bytecode: &[u8],
+    encode_key: u8,
+) -> anyhow::Result<Vec<(PathBuf, String)>> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    let mut body = decompile_chunk(&chunk, &RenameMap::default()).body;
+    let mut files = Vec::new();
+    split_block(&mut body, Path::new(""), &mut files);
+    files.push((PathBuf::from("main.lua"), body.to_string()));
+    Ok(files)
+}
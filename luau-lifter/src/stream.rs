@@ -0,0 +1,133 @@
+use std::{collections::VecDeque, io::Write};
+
+use ast::{
+    formatter::{FormatOptions, Formatter},
+    replace_locals::replace_locals,
+    Traverse,
+};
+use by_address::ByAddress;
+use parking_lot::Mutex;
+use rustc_hash::FxHashMap;
+use triomphe::Arc;
+
+use crate::{
+    checkpoint::LiftedChunk, decompile_lifted_functions, deserialize_chunk,
+    deserializer::chunk::Chunk, Progress, RenameMap, StructureOptions, DEFAULT_PIPELINE,
+};
+
+// the closures of the main function that haven't been decompiled yet, with their function ids
+type Placeholders = FxHashMap<ByAddress<Arc<Mutex<ast::Function>>>, usize>;
+
+fn find_closures(
+    statement: &mut ast::Statement,
+    placeholders: &mut Placeholders,
+    closures: &mut Vec<(usize, ast::Closure)>,
+) {
+    statement.traverse_rvalues(&mut |rvalue| {
+        let ast::RValue::Closure(closure) = rvalue else {
+            return;
+        };
+        if let Some(function_id) = placeholders.remove(&closure.function) {
+            closures.push((function_id, closure.clone()));
+        }
+    });
+    let blocks = match statement {
+        ast::Statement::If(r#if) => vec![&r#if.then_block, &r#if.else_block],
+        ast::Statement::While(r#while) => vec![&r#while.block],
+        ast::Statement::Repeat(repeat) => vec![&repeat.block],
+        ast::Statement::NumericFor(numeric_for) => vec![&numeric_for.block],
+        ast::Statement::GenericFor(generic_for) => vec![&generic_for.block],
+        _ => Vec::new(),
+    };
+    for block in blocks {
+        for statement in &mut block.lock().0 {
+            find_closures(statement, placeholders, closures);
+        }
+    }
+}
+
+// decompiles the function of a closure along with its own closures and links its upvalues
+fn decompile_closure(
+    chunk: &Chunk,
+    function_id: usize,
+    closure: &ast::Closure,
+) -> Vec<(usize, String)> {
+    let lifted = LiftedChunk::lift_function(chunk, function_id, true);
+    let upvalues_in = lifted.functions[0].upvalues.clone();
+    let (mut function, _, failures) = decompile_lifted_functions(
+        lifted,
+        &DEFAULT_PIPELINE,
+        &Progress::default(),
+        StructureOptions::default(),
+    );
+    let local_map = upvalues_in
+        .into_iter()
+        .zip(closure.upvalues.iter().map(|u| match u {
+            ast::Upvalue::Copy(l) | ast::Upvalue::Ref(l) => l.clone(),
+        }))
+        .collect::<FxHashMap<_, _>>();
+    replace_locals(&mut function.body, &local_map);
+    *closure.function.lock() = function;
+    failures
+}
+
+/// Like [`decompile_bytecode_with`](crate::decompile_bytecode_with), but the output is written to
+/// `output` a statement of the main function at a time. Only the closures of the statement being
+/// written are decompiled and they are freed once it is, so memory use is bounded by the largest
+/// statement rather than the whole chunk. Returns the functions that failed to decompile.
+///
+/// Type annotations and embedded chunks aren't supported, and locals can only be renamed by where
+/// they're declared in the main function.
+pub fn decompile_bytecode_streaming(
+    bytecode: &[u8],
+    encode_key: u8,
+    renames: &RenameMap,
+    output: &mut impl Write,
+) -> anyhow::Result<Vec<(usize, String)>> {
+    let chunk = deserialize_chunk(bytecode, encode_key)?;
+    // the closures are left empty until the statement they're in is written
+    let lifted = LiftedChunk::lift_function(&chunk, chunk.main, false);
+    // a function can have more than one closure, so they're found by address rather than id
+    let mut placeholders = lifted
+        .functions
+        .iter()
+        .skip(1)
+        .map(|lifted| (lifted.ast_function.clone(), lifted.function.id))
+        .collect::<Placeholders>();
+    let (mut main, _, mut failures) = decompile_lifted_functions(
+        lifted,
+        &DEFAULT_PIPELINE,
+        &Progress::default(),
+        StructureOptions::default(),
+    );
+
+    // the closures haven't been decompiled yet, so only declarations in the main function are found
+    let local_names = renames.local_names("0", &main.body, &FxHashMap::default());
+    let mut namer = renames.namer(local_names);
+    // finds the locals of the main function captured by its closures
+    namer.find_upvalues(&mut main.body);
+    let mut statements = VecDeque::from(std::mem::take(&mut main.body.0));
+    while let Some(mut statement) = statements.pop_front() {
+        let mut closures = Vec::new();
+        find_closures(&mut statement, &mut placeholders, &mut closures);
+        for (function_id, closure) in closures {
+            failures.extend(decompile_closure(&chunk, function_id, &closure));
+        }
+
+        let mut block = ast::Block::from(vec![statement]);
+        namer.find_upvalues(&mut block);
+        namer.name_locals(&mut block);
+        renames.replace_globals(&mut block);
+
+        let mut text = String::new();
+        let next_statement = statements.iter().find(|s| s.as_comment().is_none());
+        Formatter::format_main_statement(
+            &block.0[0],
+            next_statement,
+            &mut text,
+            FormatOptions::default(),
+        )?;
+        writeln!(output, "{}", text)?;
+    }
+    Ok(failures)
+}
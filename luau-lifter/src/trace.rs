@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ast::type_system::Type;
+use itertools::Itertools;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+use crate::{call_graph::CallSite, CallGraph};
+
+// distinct values kept for each instruction, the rest are only counted
+const MAX_VALUES: usize = 8;
+
+/// One line of a trace file, written by an instrumented VM every time it executes an instruction
+/// it has something to report about.
+///
+/// ```json
+/// { "function": 3, "pc": 12, "values": ["\"Players\""], "types": ["string"], "call_targets": [] }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct TraceEvent {
+    /// Id of the function in the chunk
+    pub function: usize,
+    pub pc: usize,
+    /// Values written by the instruction, formatted as Luau literals where possible
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// Luau type names of the values, e.g. `string` or `table`
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// Ids of the functions a call instruction called
+    #[serde(default)]
+    pub call_targets: Vec<usize>,
+}
+
+/// Everything observed at one instruction.
+#[derive(Debug, Default, Clone)]
+pub struct Observation {
+    pub values: Vec<String>,
+    /// Number of distinct values that didn't fit in `values`
+    pub omitted_values: usize,
+    pub types: BTreeSet<String>,
+    pub call_targets: BTreeSet<usize>,
+}
+
+impl Observation {
+    /// The type of the values observed at this instruction.
+    /// Types that don't have an equivalent in the decompiler's type system are `any`.
+    pub fn inferred_type(&self) -> Option<Type> {
+        let nil = self.types.contains("nil");
+        let types = self
+            .types
+            .iter()
+            .filter(|&name| name != "nil")
+            .map(|name| match name.as_str() {
+                "boolean" => Type::Boolean,
+                "number" => Type::Number,
+                "string" => Type::String,
+                "vector" => Type::Vector,
+                "table" => Type::Table {
+                    indexer: Box::new((Type::Any, Type::Any)),
+                    fields: BTreeMap::new(),
+                },
+                "function" => Type::Function(vec![Type::VarArg], vec![Type::VarArg]),
+                _ => Type::Any,
+            })
+            .collect::<BTreeSet<_>>();
+        let r#type = match types.len() {
+            0 if nil => return Some(Type::Nil),
+            0 => return None,
+            1 => types.into_iter().next().unwrap(),
+            _ if types.contains(&Type::Any) => Type::Any,
+            _ => Type::Union(types),
+        };
+        Some(if nil {
+            Type::Optional(Box::new(r#type))
+        } else {
+            r#type
+        })
+    }
+}
+
+/// Runtime values, types and call targets observed by an instrumented VM, keyed by function id and
+/// pc. Used to annotate decompiled output and to add calls that can't be resolved statically to a
+/// [`CallGraph`].
+#[derive(Debug, Default, Clone)]
+pub struct Trace {
+    observations: FxHashMap<usize, BTreeMap<usize, Observation>>,
+}
+
+impl Trace {
+    /// Reads a trace with one [`TraceEvent`] per line. Events for the same instruction are merged.
+    pub fn from_json_lines(json: &str) -> anyhow::Result<Self> {
+        let mut trace = Self::default();
+        for line in json.lines().filter(|l| !l.trim().is_empty()) {
+            trace.push(serde_json::from_str(line)?);
+        }
+        Ok(trace)
+    }
+
+    pub fn push(&mut self, event: TraceEvent) {
+        let observation = self
+            .observations
+            .entry(event.function)
+            .or_default()
+            .entry(event.pc)
+            .or_default();
+        for value in event.values {
+            if observation.values.contains(&value) {
+                continue;
+            }
+            if observation.values.len() < MAX_VALUES {
+                observation.values.push(value);
+            } else {
+                observation.omitted_values += 1;
+            }
+        }
+        observation.types.extend(event.types);
+        observation.call_targets.extend(event.call_targets);
+    }
+
+    pub fn observation(&self, function_id: usize, pc: usize) -> Option<&Observation> {
+        self.observations.get(&function_id)?.get(&pc)
+    }
+
+    /// Every observation of a function, ordered by pc.
+    pub fn function(&self, function_id: usize) -> impl Iterator<Item = (usize, &Observation)> {
+        self.observations
+            .get(&function_id)
+            .into_iter()
+            .flatten()
+            .map(|(&pc, observation)| (pc, observation))
+    }
+
+    /// Comments describing what was observed in a function, one per instruction.
+    /// Statements don't know which instructions they came from, so these go at the top of the
+    /// function body.
+    pub(crate) fn comments(
+        &self,
+        function_id: usize,
+        function_paths: &FxHashMap<usize, String>,
+    ) -> Vec<ast::Statement> {
+        self.function(function_id)
+            .map(|(pc, observation)| {
+                let mut comment = format!("trace: pc {}", pc);
+                if !observation.values.is_empty() {
+                    comment += &format!(" = {}", observation.values.join(", "));
+                    if observation.omitted_values > 0 {
+                        comment += &format!(" and {} more", observation.omitted_values);
+                    }
+                }
+                if let Some(r#type) = observation.inferred_type() {
+                    comment += &format!(": {}", r#type);
+                }
+                if !observation.call_targets.is_empty() {
+                    comment += &format!(
+                        ", calls {}",
+                        observation
+                            .call_targets
+                            .iter()
+                            .map(|target| match function_paths.get(target) {
+                                Some(path) => path.clone(),
+                                None => format!("function {}", target),
+                            })
+                            .join(", ")
+                    );
+                }
+                ast::Comment::new(comment).into()
+            })
+            .collect()
+    }
+}
+
+impl CallGraph {
+    /// Adds an edge for every call target in the trace that isn't already in the graph,
+    /// e.g. calls through tables or parameters.
+    pub fn add_trace(&mut self, trace: &Trace) {
+        for (&caller, observations) in &trace.observations {
+            let Some(caller_node) = self.node(caller) else {
+                continue;
+            };
+            for (&pc, observation) in observations {
+                for &callee in &observation.call_targets {
+                    let Some(callee_node) = self.node(callee) else {
+                        continue;
+                    };
+                    let exists = self
+                        .graph
+                        .edges_connecting(caller_node, callee_node)
+                        .any(|edge| edge.weight().0 == pc);
+                    if !exists {
+                        self.graph.add_edge(caller_node, callee_node, CallSite(pc));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::{fmt, str::FromStr};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    deserializer::{chunk::Chunk, constant::decode_import},
+    instruction::Instruction,
+    op_code::OpCode,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XrefKind {
+    /// `GETGLOBAL` or `GETIMPORT`, the name is the full import path, e.g. `string.char`
+    ReadGlobal,
+    /// `SETGLOBAL`
+    WriteGlobal,
+    /// A call of a global, import or a field of one, e.g. `string.char` or `game:GetService`
+    Call,
+    /// A field indexed with a constant string or a method called with `NAMECALL`
+    Field,
+    /// A string constant that is loaded into a register or compared against
+    String,
+}
+
+impl fmt::Display for XrefKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XrefKind::ReadGlobal => write!(f, "read"),
+            XrefKind::WriteGlobal => write!(f, "write"),
+            XrefKind::Call => write!(f, "call"),
+            XrefKind::Field => write!(f, "field"),
+            XrefKind::String => write!(f, "string"),
+        }
+    }
+}
+
+impl FromStr for XrefKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(XrefKind::ReadGlobal),
+            "write" => Ok(XrefKind::WriteGlobal),
+            "call" => Ok(XrefKind::Call),
+            "field" => Ok(XrefKind::Field),
+            "string" => Ok(XrefKind::String),
+            _ => Err(format!(
+                "unknown kind {}, expected one of read, write, call, field or string",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Xref {
+    pub function_id: usize,
+    pub function_path: String,
+    pub pc: usize,
+    pub kind: XrefKind,
+    pub name: String,
+}
+
+/// Every cross-reference in a chunk, indexed by kind and name.
+/// Calls are resolved by following registers within straight-line code,
+/// so calls through locals that are assigned elsewhere aren't found.
+#[derive(Debug, Default)]
+pub struct Xrefs {
+    xrefs: Vec<Xref>,
+    index: FxHashMap<(XrefKind, String), Vec<usize>>,
+}
+
+impl Xrefs {
+    pub fn iter(&self) -> impl Iterator<Item = &Xref> {
+        self.xrefs.iter()
+    }
+
+    /// Returns every reference of a kind to `name`.
+    pub fn query(&self, kind: XrefKind, name: &str) -> impl Iterator<Item = &Xref> {
+        self.index
+            .get(&(kind, name.to_string()))
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.xrefs[i])
+    }
+
+    /// Returns the ids of the functions that reference `name`, without duplicates.
+    pub fn functions(&self, kind: XrefKind, name: &str) -> Vec<usize> {
+        let mut seen = FxHashSet::default();
+        self.query(kind, name)
+            .map(|xref| xref.function_id)
+            .filter(|&function_id| seen.insert(function_id))
+            .collect()
+    }
+
+    fn push(&mut self, xref: Xref) {
+        self.index
+            .entry((xref.kind, xref.name.clone()))
+            .or_default()
+            .push(self.xrefs.len());
+        self.xrefs.push(xref);
+    }
+}
+
+// the instruction a jump goes to, if the instruction is one
+pub(crate) fn jump_target(pc: usize, instruction: &Instruction) -> Option<usize> {
+    let offset = match *instruction {
+        Instruction::AD {
+            op_code:
+                OpCode::LOP_JUMP
+                | OpCode::LOP_JUMPBACK
+                | OpCode::LOP_JUMPIF
+                | OpCode::LOP_JUMPIFNOT
+                | OpCode::LOP_JUMPIFEQ
+                | OpCode::LOP_JUMPIFLE
+                | OpCode::LOP_JUMPIFLT
+                | OpCode::LOP_JUMPIFNOTEQ
+                | OpCode::LOP_JUMPIFNOTLE
+                | OpCode::LOP_JUMPIFNOTLT
+                | OpCode::LOP_JUMPXEQKNIL
+                | OpCode::LOP_JUMPXEQKB
+                | OpCode::LOP_JUMPXEQKN
+                | OpCode::LOP_JUMPXEQKS
+                | OpCode::LOP_FORNPREP
+                | OpCode::LOP_FORNLOOP
+                | OpCode::LOP_FORGLOOP
+                | OpCode::LOP_FORGPREP
+                | OpCode::LOP_FORGPREP_INEXT
+                | OpCode::LOP_FORGPREP_NEXT,
+            d,
+            ..
+        } => d as isize,
+        Instruction::E {
+            op_code: OpCode::LOP_JUMPX,
+            e,
+        } => e as isize,
+        _ => return None,
+    };
+    usize::try_from(pc as isize + 1 + offset).ok()
+}
+
+// instructions that don't write to register A
+pub(crate) fn writes_a(op_code: OpCode) -> bool {
+    !matches!(
+        op_code,
+        OpCode::LOP_NOP
+            | OpCode::LOP_BREAK
+            | OpCode::LOP_SETGLOBAL
+            | OpCode::LOP_SETUPVAL
+            | OpCode::LOP_CLOSEUPVALS
+            | OpCode::LOP_SETTABLE
+            | OpCode::LOP_SETTABLEKS
+            | OpCode::LOP_SETTABLEN
+            | OpCode::LOP_RETURN
+            | OpCode::LOP_JUMP
+            | OpCode::LOP_JUMPBACK
+            | OpCode::LOP_JUMPIF
+            | OpCode::LOP_JUMPIFNOT
+            | OpCode::LOP_JUMPIFEQ
+            | OpCode::LOP_JUMPIFLE
+            | OpCode::LOP_JUMPIFLT
+            | OpCode::LOP_JUMPIFNOTEQ
+            | OpCode::LOP_JUMPIFNOTLE
+            | OpCode::LOP_JUMPIFNOTLT
+            | OpCode::LOP_JUMPXEQKNIL
+            | OpCode::LOP_JUMPXEQKB
+            | OpCode::LOP_JUMPXEQKN
+            | OpCode::LOP_JUMPXEQKS
+            | OpCode::LOP_SETLIST
+            | OpCode::LOP_FASTCALL
+            | OpCode::LOP_FASTCALL1
+            | OpCode::LOP_FASTCALL2
+            | OpCode::LOP_FASTCALL2K
+            | OpCode::LOP_FASTCALL3
+            | OpCode::LOP_COVERAGE
+            | OpCode::LOP_CAPTURE
+            | OpCode::LOP_PREPVARARGS
+            | OpCode::LOP_NATIVECALL
+    )
+}
+
+fn function_xrefs(chunk: &Chunk, function_id: usize, function_path: &str, xrefs: &mut Xrefs) {
+    let instructions = &chunk.functions[function_id].instructions;
+    let jump_targets = instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(pc, instruction)| jump_target(pc, instruction))
+        .collect::<FxHashSet<_>>();
+    let mut xref = |pc, kind, name| {
+        xrefs.push(Xref {
+            function_id,
+            function_path: function_path.to_string(),
+            pc,
+            kind,
+            name,
+        })
+    };
+
+    // the global, import or field of one that each register holds
+    let mut registers = FxHashMap::<u8, String>::default();
+    for (pc, instruction) in instructions.iter().enumerate() {
+        if jump_targets.contains(&pc) {
+            registers.clear();
+        }
+        match *instruction {
+            Instruction::BC {
+                op_code, a, b, aux, ..
+            } => {
+                let constant = || chunk.constant_string(function_id, aux as usize);
+                match op_code {
+                    OpCode::LOP_GETGLOBAL => {
+                        if let Some(name) = constant() {
+                            xref(pc, XrefKind::ReadGlobal, name.clone());
+                            registers.insert(a, name);
+                            continue;
+                        }
+                    }
+                    OpCode::LOP_SETGLOBAL => {
+                        if let Some(name) = constant() {
+                            xref(pc, XrefKind::WriteGlobal, name);
+                        }
+                    }
+                    OpCode::LOP_GETTABLEKS => {
+                        if let Some(field) = constant() {
+                            xref(pc, XrefKind::Field, field.clone());
+                            if let Some(object) = registers.get(&b) {
+                                let name = format!("{}.{}", object, field);
+                                registers.insert(a, name);
+                                continue;
+                            }
+                        }
+                    }
+                    OpCode::LOP_SETTABLEKS => {
+                        if let Some(field) = constant() {
+                            xref(pc, XrefKind::Field, field);
+                        }
+                    }
+                    OpCode::LOP_NAMECALL => {
+                        registers.remove(&a.wrapping_add(1));
+                        if let Some(method) = constant() {
+                            xref(pc, XrefKind::Field, method.clone());
+                            if let Some(object) = registers.get(&b) {
+                                let name = format!("{}:{}", object, method);
+                                registers.insert(a, name);
+                                continue;
+                            }
+                        }
+                    }
+                    OpCode::LOP_CALL => {
+                        if let Some(name) = registers.get(&a) {
+                            xref(pc, XrefKind::Call, name.clone());
+                        }
+                        // results are written from A upwards
+                        registers.retain(|&register, _| register < a);
+                        continue;
+                    }
+                    OpCode::LOP_GETVARARGS => {
+                        registers.retain(|&register, _| register < a);
+                        continue;
+                    }
+                    OpCode::LOP_FASTCALL2K => {
+                        if let Some(string) = constant() {
+                            xref(pc, XrefKind::String, string);
+                        }
+                    }
+                    _ => {}
+                }
+                if writes_a(op_code) {
+                    registers.remove(&a);
+                }
+            }
+            Instruction::AD { op_code, a, d, aux } => {
+                match op_code {
+                    OpCode::LOP_GETIMPORT => {
+                        if let Some(path) = chunk.import_path(function_id, &decode_import(aux)) {
+                            xref(pc, XrefKind::ReadGlobal, path.clone());
+                            registers.insert(a, path);
+                            continue;
+                        }
+                    }
+                    OpCode::LOP_LOADK => {
+                        if let Some(string) = chunk.constant_string(function_id, d as u16 as usize)
+                        {
+                            xref(pc, XrefKind::String, string);
+                        }
+                    }
+                    OpCode::LOP_LOADKX => {
+                        if let Some(string) = chunk.constant_string(function_id, aux as usize) {
+                            xref(pc, XrefKind::String, string);
+                        }
+                    }
+                    OpCode::LOP_JUMPXEQKS => {
+                        if let Some(string) =
+                            chunk.constant_string(function_id, (aux & 0xFFFFFF) as usize)
+                        {
+                            xref(pc, XrefKind::String, string);
+                        }
+                    }
+                    _ => {}
+                }
+                if writes_a(op_code) {
+                    registers.remove(&a);
+                }
+            }
+            Instruction::E { .. } => {}
+        }
+    }
+}
+
+impl Chunk<'_> {
+    /// Collects the cross-references of every function in the chunk.
+    /// Only the instructions and constant pools are read, nothing is lifted.
+    pub fn xrefs(&self) -> Xrefs {
+        let mut xrefs = Xrefs::default();
+        for (function_id, function_path) in self.function_paths() {
+            function_xrefs(self, function_id, &function_path, &mut xrefs);
+        }
+        xrefs
+    }
+}
@@ -0,0 +1,23 @@
+use std::fs;
+
+use luau_lifter::deserialize_chunk;
+
+// malformed bytecode is reported as an error instead of panicking, so callers don't need to
+// catch panics around the deserializer
+#[test]
+fn malformed() {
+    let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/fixtures/luau");
+    for entry in fs::read_dir(fixtures).unwrap() {
+        let bytecode = fs::read(entry.unwrap().path()).unwrap();
+        for len in 0..bytecode.len() {
+            assert!(deserialize_chunk(&bytecode[..len], 1).is_err());
+        }
+        for index in 0..bytecode.len() {
+            for byte in [0x00, 0x7f, 0xff] {
+                let mut corrupted = bytecode.clone();
+                corrupted[index] = byte;
+                let _ = deserialize_chunk(&corrupted, 1);
+            }
+        }
+    }
+}
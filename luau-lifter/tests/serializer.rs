@@ -0,0 +1,50 @@
+use std::fs;
+
+use luau_lifter::{deserialize_chunk, serialize};
+
+// every part of a chunk is kept when it's deserialized, so serializing it gives back the same bytes
+#[test]
+fn round_trip() {
+    let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/fixtures/luau");
+    for entry in fs::read_dir(fixtures).unwrap() {
+        let path = entry.unwrap().path();
+        let bytecode = fs::read(&path).unwrap();
+        let chunk = deserialize_chunk(&bytecode, 1).unwrap();
+        // functions without locals or upvalues have no debug info even with -g2
+        assert!(
+            chunk
+                .functions
+                .iter()
+                .any(|function| function.debug_info.is_some()),
+            "{} was compiled without debug info",
+            path.display()
+        );
+        assert!(
+            serialize(&chunk) == bytecode,
+            "{} changed when serialized again",
+            path.display()
+        );
+    }
+}
+
+// the id of an import constant is written back as is, even if it isn't a valid path
+#[test]
+fn import_id_round_trip() {
+    let id: u32 = 0x0030_0c01;
+    #[rustfmt::skip]
+    let bytecode = [
+        // version, types version, no strings, one function
+        &[6, 0, 0, 1][..],
+        // stack size, parameters, upvalues, vararg, flags, no type info
+        &[1, 0, 0, 0, 0, 0],
+        // RETURN r0, 1
+        &[1, 22, 0, 1, 0],
+        // the import constant
+        &[1, 4], &id.to_le_bytes(),
+        // no closures, line defined, name, line info or debug info, main function
+        &[0, 0, 0, 0, 0, 0],
+    ]
+    .concat();
+    let chunk = deserialize_chunk(&bytecode, 1).unwrap();
+    assert_eq!(serialize(&chunk), bytecode);
+}
@@ -0,0 +1,43 @@
+#ifndef MEDAL_H
+#define MEDAL_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum medal_status {
+    MEDAL_OK = 0,
+    /* a pointer that can't be null was null */
+    MEDAL_NULL_ARGUMENT = 1,
+    /* the bytecode couldn't be deserialized */
+    MEDAL_INVALID_BYTECODE = 2,
+    /* an option the flavor doesn't support */
+    MEDAL_UNSUPPORTED = 3,
+    /* the decompiler panicked */
+    MEDAL_PANICKED = 4,
+    /* the decompilation was cancelled, which the C API doesn't do yet */
+    MEDAL_CANCELLED = 5,
+} medal_status;
+
+/*
+ * Decompiles a chunk of `len` bytes. `*output` is set to the decompiled source when MEDAL_OK is
+ * returned and to the error message otherwise, either way it has to be freed with
+ * medal_free_string. It is only left unset when `output` is null.
+ */
+medal_status medal_decompile_lua51(const uint8_t *bytecode, size_t len, char **output);
+
+/* `encode_key` is what the opcodes are multiplied by, 203 for Roblox and 1 otherwise */
+medal_status medal_decompile_luau(const uint8_t *bytecode, size_t len, uint8_t encode_key,
+                                  char **output);
+
+/* frees a string returned by the library, null is ignored */
+void medal_free_string(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,130 @@
+//! C bindings for the decompiler, declared in `include/medal.h`. Build the shared or static
+//! library with `cargo build --release -p medal-capi` and link `libmedal_capi`:
+//!
+//! ```c
+//! char *output;
+//! if (medal_decompile_luau(bytecode, len, 203, &output) == MEDAL_OK)
+//!     puts(output);
+//! else
+//!     fprintf(stderr, "%s\n", output);
+//! medal_free_string(output);
+//! ```
+//!
+//! Every function returns a [`MedalStatus`]. The output is always a string allocated by the
+//! library, the decompiled source on success and the error message otherwise, and has to be freed
+//! with [`medal_free_string`].
+
+use std::{
+    ffi::{c_char, CString},
+    panic, ptr, slice,
+};
+
+use medal::{DecompileError, DecompileOptions, Flavor};
+
+/// The result of a call, `MEDAL_OK` or the kind of error whose message was written to the output
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MedalStatus {
+    Ok = 0,
+    /// A pointer that can't be null was null
+    NullArgument = 1,
+    /// The bytecode couldn't be deserialized
+    InvalidBytecode = 2,
+    /// An option the flavor doesn't support
+    Unsupported = 3,
+    /// The decompiler panicked
+    Panicked = 4,
+    /// The decompilation was cancelled, which the C API doesn't do yet
+    Cancelled = 5,
+}
+
+impl From<&DecompileError> for MedalStatus {
+    fn from(error: &DecompileError) -> Self {
+        match error {
+            DecompileError::InvalidBytecode(_) => Self::InvalidBytecode,
+            DecompileError::Unsupported(_) => Self::Unsupported,
+            DecompileError::Panicked(_) => Self::Panicked,
+            DecompileError::Cancelled => Self::Cancelled,
+        }
+    }
+}
+
+// C strings end at the first nul, so nuls in the output are escaped the way they would be in a
+// Lua string
+fn into_c_string(string: String) -> *mut c_char {
+    CString::new(string.replace('\0', "\\0"))
+        .unwrap()
+        .into_raw()
+}
+
+unsafe fn decompile(
+    bytecode: *const u8,
+    len: usize,
+    flavor: Flavor,
+    output: *mut *mut c_char,
+) -> MedalStatus {
+    unsafe {
+        if output.is_null() {
+            return MedalStatus::NullArgument;
+        }
+        *output = ptr::null_mut();
+        if bytecode.is_null() {
+            *output = into_c_string("bytecode is null".to_string());
+            return MedalStatus::NullArgument;
+        }
+        let bytecode = slice::from_raw_parts(bytecode, len);
+        // `decompile` catches the panics of the decompiler, this is for the ones outside of it
+        // since unwinding into C is undefined behavior
+        let result =
+            panic::catch_unwind(|| medal::decompile(bytecode, DecompileOptions::new(flavor)))
+                .unwrap_or(Err(DecompileError::Panicked(None)));
+        let (status, string) = match result {
+            Ok(source) => (MedalStatus::Ok, source),
+            Err(error) => ((&error).into(), error.to_string()),
+        };
+        *output = into_c_string(string);
+        status
+    }
+}
+
+/// Decompiles a Lua 5.1 chunk of `len` bytes.
+///
+/// # Safety
+///
+/// `bytecode` must point to `len` readable bytes and `output` to a writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn medal_decompile_lua51(
+    bytecode: *const u8,
+    len: usize,
+    output: *mut *mut c_char,
+) -> MedalStatus {
+    unsafe { decompile(bytecode, len, Flavor::Lua51, output) }
+}
+
+/// Decompiles a Luau chunk of `len` bytes whose opcodes are multiplied by `encode_key`, 203 for
+/// Roblox and 1 otherwise.
+///
+/// # Safety
+///
+/// `bytecode` must point to `len` readable bytes and `output` to a writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn medal_decompile_luau(
+    bytecode: *const u8,
+    len: usize,
+    encode_key: u8,
+    output: *mut *mut c_char,
+) -> MedalStatus {
+    unsafe { decompile(bytecode, len, Flavor::Luau { encode_key }, output) }
+}
+
+/// Frees a string returned by the library, null is ignored.
+///
+/// # Safety
+///
+/// `string` must be null or a string returned by the library that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn medal_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
@@ -0,0 +1,218 @@
+//! Python bindings for the decompiler, built with `maturin develop -m medal-py/Cargo.toml`:
+//!
+//! ```python
+//! import medal
+//!
+//! source = medal.decompile(bytecode, "luau", medal.Options(encoded=True))
+//! main = medal.prototypes(bytecode, "luau", encoded=True)
+//! for child in main.children:
+//!     print(child.name, child.num_parameters)
+//! ```
+
+use std::str::FromStr;
+
+use medal::{ast::formatter::GlobalStyle, DecompileOptions, Flavor};
+use pyo3::{create_exception, exceptions::PyException, prelude::*};
+
+create_exception!(
+    medal,
+    DecompileError,
+    PyException,
+    "The bytecode couldn't be decompiled."
+);
+
+fn error(err: impl ToString) -> PyErr {
+    DecompileError::new_err(err.to_string())
+}
+
+// `None` detects the flavor from the header
+fn flavor(bytecode: &[u8], flavor: Option<&str>, encoded: bool) -> PyResult<Flavor> {
+    let encode_key = if encoded { 203 } else { 1 };
+    match flavor {
+        Some("lua51") => Ok(Flavor::Lua51),
+        Some("luau") => Ok(Flavor::Luau { encode_key }),
+        Some(flavor) => Err(error(format!(
+            "unknown flavor {}, expected lua51 or luau",
+            flavor
+        ))),
+        None => match Flavor::detect(bytecode) {
+            Some(Flavor::Luau { .. }) => Ok(Flavor::Luau { encode_key }),
+            Some(flavor) => Ok(flavor),
+            None => Err(error("the input isn't Lua 5.1 or Luau bytecode")),
+        },
+    }
+}
+
+/// How a chunk is decompiled, every option can be passed to the constructor as a keyword.
+#[pyclass(module = "medal")]
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Luau bytecode is encoded with the Roblox client key (203)
+    #[pyo3(get, set)]
+    pub encoded: bool,
+    /// Keep the warning comments where something couldn't be decompiled
+    #[pyo3(get, set)]
+    pub comments: bool,
+    /// Use goto for control flow that can't be structured, Lua 5.1 only
+    #[pyo3(get, set)]
+    pub gotos: bool,
+    /// Write branches that end in a return, break or continue as guard clauses
+    #[pyo3(get, set)]
+    pub guard_clauses: bool,
+    /// `"name"`, `"env"` or `"getfenv"`
+    #[pyo3(get, set)]
+    pub global_style: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new(false, true, false, false, "name".to_string())
+    }
+}
+
+#[pymethods]
+impl Options {
+    #[new]
+    #[pyo3(signature = (
+        *,
+        encoded = false,
+        comments = true,
+        gotos = false,
+        guard_clauses = false,
+        global_style = "name".to_string(),
+    ))]
+    fn new(
+        encoded: bool,
+        comments: bool,
+        gotos: bool,
+        guard_clauses: bool,
+        global_style: String,
+    ) -> Self {
+        Self {
+            encoded,
+            comments,
+            gotos,
+            guard_clauses,
+            global_style,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Options(encoded={}, comments={}, gotos={}, guard_clauses={}, global_style={:?})",
+            py_bool(self.encoded),
+            py_bool(self.comments),
+            py_bool(self.gotos),
+            py_bool(self.guard_clauses),
+            self.global_style
+        )
+    }
+}
+
+fn py_bool(value: bool) -> &'static str {
+    if value {
+        "True"
+    } else {
+        "False"
+    }
+}
+
+/// A function of a chunk with the closures it defines.
+#[pyclass(module = "medal", frozen)]
+#[derive(Debug, Clone)]
+pub struct Prototype {
+    /// The name the function was given in the source, only Luau bytecode records it
+    #[pyo3(get)]
+    pub name: Option<String>,
+    #[pyo3(get)]
+    pub line_defined: usize,
+    #[pyo3(get)]
+    pub num_parameters: u8,
+    #[pyo3(get)]
+    pub is_vararg: bool,
+    #[pyo3(get)]
+    pub num_upvalues: u8,
+    #[pyo3(get)]
+    pub num_instructions: usize,
+    /// The closures defined directly in the function
+    #[pyo3(get)]
+    pub children: Vec<Prototype>,
+}
+
+impl From<medal::Prototype> for Prototype {
+    fn from(prototype: medal::Prototype) -> Self {
+        Self {
+            name: prototype.name,
+            line_defined: prototype.line_defined,
+            num_parameters: prototype.num_parameters,
+            is_vararg: prototype.is_vararg,
+            num_upvalues: prototype.num_upvalues,
+            num_instructions: prototype.num_instructions,
+            children: prototype.children.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[pymethods]
+impl Prototype {
+    fn __repr__(&self) -> String {
+        format!(
+            "<Prototype {} at line {}, {} parameters, {} children>",
+            self.name.as_deref().unwrap_or("(anonymous)"),
+            self.line_defined,
+            self.num_parameters,
+            self.children.len()
+        )
+    }
+}
+
+/// Decompiles a Lua 5.1 or Luau chunk. `flavor` is `"lua51"` or `"luau"`, or detected from the
+/// header if it's `None`.
+#[pyfunction]
+#[pyo3(signature = (bytecode, flavor = None, options = None))]
+fn decompile(
+    py: Python<'_>,
+    bytecode: &[u8],
+    flavor: Option<&str>,
+    options: Option<Options>,
+) -> PyResult<String> {
+    let options = options.unwrap_or_default();
+    let mut decompile_options =
+        DecompileOptions::new(self::flavor(bytecode, flavor, options.encoded)?);
+    decompile_options.comments = options.comments;
+    decompile_options.gotos = options.gotos;
+    decompile_options.guard_clauses = options.guard_clauses;
+    decompile_options.global_style = GlobalStyle::from_str(&options.global_style).map_err(error)?;
+    // the decompiler doesn't touch any Python objects, so other threads can run meanwhile
+    py.allow_threads(|| medal::decompile(bytecode, decompile_options))
+        .map_err(error)
+}
+
+/// The main function of a chunk with every function nested in it, without decompiling any of
+/// them. `flavor` is the same as for `decompile`.
+#[pyfunction]
+#[pyo3(signature = (bytecode, flavor = None, encoded = false))]
+fn prototypes(bytecode: &[u8], flavor: Option<&str>, encoded: bool) -> PyResult<Prototype> {
+    medal::prototypes(bytecode, self::flavor(bytecode, flavor, encoded)?)
+        .map(Into::into)
+        .map_err(error)
+}
+
+/// Lists the instructions of every function in a chunk.
+#[pyfunction]
+#[pyo3(signature = (bytecode, flavor = None, encoded = false))]
+fn disassemble(bytecode: &[u8], flavor: Option<&str>, encoded: bool) -> PyResult<String> {
+    medal::disassemble(bytecode, self::flavor(bytecode, flavor, encoded)?).map_err(error)
+}
+
+#[pymodule]
+#[pyo3(name = "medal")]
+fn medal_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("DecompileError", m.py().get_type_bound::<DecompileError>())?;
+    m.add_class::<Options>()?;
+    m.add_class::<Prototype>()?;
+    m.add_function(wrap_pyfunction!(decompile, m)?)?;
+    m.add_function(wrap_pyfunction!(prototypes, m)?)?;
+    m.add_function(wrap_pyfunction!(disassemble, m)?)?;
+    Ok(())
+}
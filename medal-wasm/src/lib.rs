@@ -0,0 +1,68 @@
+//! JavaScript bindings for the decompiler, built for `wasm32-unknown-unknown` with
+//! `wasm-pack build medal-wasm --target web`:
+//!
+//! ```js
+//! import init, { decompile } from "./pkg/medal_wasm.js";
+//!
+//! await init();
+//! const source = decompile(bytecode, { encoded: true, globalStyle: "env" });
+//! ```
+
+use std::str::FromStr;
+
+use js_sys::{Object, Reflect};
+use medal::{ast::formatter::GlobalStyle, DecompileOptions, Flavor};
+use wasm_bindgen::prelude::*;
+
+// a boolean field of the options object, missing fields and fields of other types are `default`
+fn flag(options: &Object, name: &str, default: bool) -> bool {
+    Reflect::get(options, &name.into())
+        .ok()
+        .and_then(|value| value.as_bool())
+        .unwrap_or(default)
+}
+
+fn flavor(bytecode: &[u8], encoded: bool) -> Result<Flavor, JsError> {
+    match Flavor::detect(bytecode) {
+        Some(Flavor::Luau { .. }) if encoded => Ok(Flavor::Luau { encode_key: 203 }),
+        Some(flavor) => Ok(flavor),
+        None => Err(JsError::new("the input isn't Lua 5.1 or Luau bytecode")),
+    }
+}
+
+/// Decompiles a Lua 5.1 or Luau chunk, the format is detected from its header. `options` is an
+/// optional object with these fields:
+///
+/// - `encoded`: Luau bytecode is encoded with the Roblox client key (203)
+/// - `comments`: keep the warning comments where something couldn't be decompiled, true by
+///   default
+/// - `gotos`: use goto for control flow that can't be structured, Lua 5.1 only
+/// - `guardClauses`: write branches that end in a return, break or continue as guard clauses
+/// - `globalStyle`: `"name"`, `"env"` or `"getfenv"`
+#[wasm_bindgen]
+pub fn decompile(bytecode: &[u8], options: Option<Object>) -> Result<String, JsError> {
+    console_error_panic_hook::set_once();
+    let options = options.unwrap_or_default();
+    let mut decompile_options =
+        DecompileOptions::new(flavor(bytecode, flag(&options, "encoded", false))?);
+    decompile_options.comments = flag(&options, "comments", true);
+    decompile_options.gotos = flag(&options, "gotos", false);
+    decompile_options.guard_clauses = flag(&options, "guardClauses", false);
+    if let Some(style) = Reflect::get(&options, &"globalStyle".into())
+        .ok()
+        .and_then(|value| value.as_string())
+    {
+        decompile_options.global_style =
+            GlobalStyle::from_str(&style).map_err(|err| JsError::new(&err))?;
+    }
+    medal::decompile(bytecode, decompile_options).map_err(|err| JsError::new(&err.to_string()))
+}
+
+/// Lists the instructions of every function in a Lua 5.1 or Luau chunk, `encoded` is whether
+/// Luau bytecode is encoded with the Roblox client key (203).
+#[wasm_bindgen]
+pub fn disassemble(bytecode: &[u8], encoded: Option<bool>) -> Result<String, JsError> {
+    console_error_panic_hook::set_once();
+    medal::disassemble(bytecode, flavor(bytecode, encoded.unwrap_or_default())?)
+        .map_err(|err| JsError::new(&err.to_string()))
+}
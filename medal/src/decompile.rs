@@ -0,0 +1,247 @@
+use std::{any::Any, fmt, panic};
+
+use ast::formatter::{FormatOptions, GlobalStyle};
+use pipeline::Progress;
+use restructure::Budget;
+
+/// The bytecode format passed to [`decompile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flavor {
+    #[cfg(feature = "lua51")]
+    Lua51,
+    /// Luau bytecode with opcodes multiplied by `encode_key`, 203 for Roblox and 1 otherwise
+    #[cfg(feature = "luau")]
+    Luau { encode_key: u8 },
+}
+
+impl Flavor {
+    /// Guesses the flavor of a chunk from its header. Luau bytecode is assumed not to be encoded.
+    pub fn detect(bytecode: &[u8]) -> Option<Self> {
+        match bytecode {
+            #[cfg(feature = "lua51")]
+            [0x1b, b'L', b'u', b'a', 0x51, ..] => Some(Self::Lua51),
+            // a chunk starting with 0 is a compile error message
+            #[cfg(feature = "luau")]
+            [version, ..]
+                if *version == 0 || crate::luau::BytecodeVersion::try_from(*version).is_ok() =>
+            {
+                Some(Self::Luau { encode_key: 1 })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecompileOptions {
+    pub flavor: Flavor,
+    /// Print every function in SSA form instead of decompiling it, for debugging the decompiler.
+    /// Only supported for Luau.
+    pub ssa: bool,
+    /// Start the output with comments describing the input, see
+    /// [`provenance_banner`](crate::luau::provenance_banner). Only supported for Luau.
+    pub verbose: bool,
+    /// Keep the `-- warning:` comments left where something couldn't be decompiled, e.g. functions
+    /// that failed and instructions that aren't handled
+    pub comments: bool,
+    /// Leave control flow that can't be structured as `goto` and labels instead of a state machine
+    /// loop. The output needs Lua 5.2 or later, so this is only supported for Lua 5.1.
+    pub gotos: bool,
+    /// Write branches of ifs that end in a `return`, `break` or `continue` as guard clauses instead
+    /// of nesting the other branch in an else block
+    pub guard_clauses: bool,
+    /// Normalize and order the arms of if chains that compare one local to constants, like the
+    /// dispatch loops of virtual machines in obfuscated scripts, see
+    /// [`normalize_dispatch_chains`](ast::dispatch_chains::normalize_dispatch_chains)
+    pub dispatch_chains: bool,
+    /// How globals are written, e.g. as fields of `_ENV` for a Lua 5.2 target
+    pub global_style: GlobalStyle,
+    /// Limits on structuring every function, after which the rest of it is structured with the
+    /// fallback and a warning comment is left
+    pub budget: Budget,
+}
+
+impl DecompileOptions {
+    pub fn new(flavor: Flavor) -> Self {
+        Self {
+            flavor,
+            ssa: false,
+            verbose: false,
+            comments: true,
+            gotos: false,
+            guard_clauses: false,
+            dispatch_chains: false,
+            global_style: GlobalStyle::Name,
+            budget: Budget::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecompileError {
+    /// The bytecode couldn't be deserialized
+    InvalidBytecode(String),
+    /// An option the flavor doesn't support
+    Unsupported(&'static str),
+    /// The decompiler panicked, with the panic message if there was one
+    Panicked(Option<String>),
+    /// The [`Progress`] passed to [`decompile_with_progress`] was cancelled
+    Cancelled,
+}
+
+impl fmt::Display for DecompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidBytecode(error) => write!(f, "invalid bytecode: {}", error),
+            Self::Unsupported(option) => write!(f, "{} isn't supported for this flavor", option),
+            Self::Panicked(Some(message)) => write!(f, "decompiler panicked: {}", message),
+            Self::Panicked(None) => write!(f, "decompiler panicked"),
+            Self::Cancelled => write!(f, "decompilation was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for DecompileError {}
+
+#[allow(dead_code)]
+pub(crate) fn invalid_bytecode(error: impl fmt::Display) -> DecompileError {
+    DecompileError::InvalidBytecode(format!("{:#}", error))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> Option<String> {
+    match payload.downcast::<String>() {
+        Ok(message) => Some(*message),
+        Err(payload) => payload.downcast_ref::<&str>().map(|m| m.to_string()),
+    }
+}
+
+#[allow(unused_variables)]
+fn decompile_flavor(
+    bytecode: &[u8],
+    options: &DecompileOptions,
+    progress: &Progress,
+) -> Result<String, DecompileError> {
+    let format = FormatOptions {
+        global_style: options.global_style,
+        ..Default::default()
+    };
+    match options.flavor {
+        #[cfg(feature = "lua51")]
+        Flavor::Lua51 => {
+            if options.ssa {
+                return Err(DecompileError::Unsupported("SSA output"));
+            }
+            if options.verbose {
+                return Err(DecompileError::Unsupported("verbose output"));
+            }
+            let structure = crate::lua51::StructureOptions {
+                fallback: if options.gotos {
+                    crate::lua51::Fallback::Goto
+                } else {
+                    crate::lua51::Fallback::StateMachine
+                },
+                guard_clauses: options.guard_clauses,
+                budget: options.budget,
+            };
+            let mut pipeline = crate::lua51::default_pipeline();
+            pipeline
+                .blocks
+                .set_enabled("normalize dispatch chains", options.dispatch_chains)
+                .unwrap();
+            let mut emitter = ast::emitter::DisplayEmitter { options: format };
+            crate::lua51::decompile_bytecode_with_progress(
+                bytecode,
+                structure,
+                &pipeline,
+                &mut emitter,
+                progress,
+            )
+            .map_err(invalid_bytecode)
+        }
+        #[cfg(feature = "luau")]
+        Flavor::Luau { encode_key } => {
+            if options.gotos {
+                return Err(DecompileError::Unsupported("goto"));
+            }
+            if options.ssa {
+                return crate::luau::decompile_bytecode_ssa(bytecode, encode_key)
+                    .map_err(invalid_bytecode);
+            }
+            let mut output = String::new();
+            if options.verbose {
+                output += &crate::luau::provenance_banner(bytecode, encode_key)
+                    .map_err(invalid_bytecode)?;
+            }
+            let mut pipeline = crate::luau::default_pipeline();
+            pipeline
+                .blocks
+                .set_enabled("normalize dispatch chains", options.dispatch_chains)
+                .unwrap();
+            let options = crate::luau::DecompileOptions {
+                encode_key,
+                pipeline: &pipeline,
+                progress,
+                budget: options.budget,
+                guard_clauses: options.guard_clauses,
+                emitter: ast::emitter::DisplayEmitter { options: format },
+                ..Default::default()
+            };
+            output += &crate::luau::decompile_bytecode_with(bytecode, &options)
+                .map_err(invalid_bytecode)?
+                .source;
+            Ok(output)
+        }
+    }
+}
+
+/// Decompiles a chunk to source code. Panics in the decompiler are caught and returned as
+/// [`DecompileError::Panicked`], functions that fail to decompile on their own are replaced with a
+/// warning comment instead.
+pub fn decompile(bytecode: &[u8], options: DecompileOptions) -> Result<String, DecompileError> {
+    decompile_with_progress(bytecode, options, &Progress::default())
+}
+
+/// Like [`decompile`], but the functions lifted and decompiled are reported to `progress`, and
+/// [`DecompileError::Cancelled`] is returned once its token is cancelled, e.g. when the user of a
+/// GUI aborts a decompilation that is taking long. SSA output can't be cancelled.
+pub fn decompile_with_progress(
+    bytecode: &[u8],
+    options: DecompileOptions,
+    progress: &Progress,
+) -> Result<String, DecompileError> {
+    // the progress is only read, so a panic can't leave it in a broken state
+    let progress = panic::AssertUnwindSafe(progress);
+    let output = panic::catch_unwind(|| decompile_flavor(bytecode, &options, *progress))
+        .map_err(|payload| DecompileError::Panicked(panic_message(payload)))
+        .and_then(|result| result)
+        // the lifters report cancellation as one of their errors
+        .map_err(|error| {
+            if progress.cancellation.is_cancelled() {
+                DecompileError::Cancelled
+            } else {
+                error
+            }
+        })?;
+    if options.comments {
+        return Ok(output);
+    }
+    // the same lines `luau::Decompilation` counts as warnings
+    Ok(output
+        .lines()
+        .filter(|l| !l.trim_start().starts_with("-- warning:"))
+        .flat_map(|l| [l, "\n"])
+        .collect())
+}
+
+/// Lists the instructions of every function in a chunk, with constants written inline and jump
+/// targets as labels.
+pub fn disassemble(bytecode: &[u8], flavor: Flavor) -> Result<String, DecompileError> {
+    match flavor {
+        #[cfg(feature = "lua51")]
+        Flavor::Lua51 => crate::lua51::disassemble_bytecode(bytecode).map_err(invalid_bytecode),
+        #[cfg(feature = "luau")]
+        Flavor::Luau { encode_key } => {
+            crate::luau::disassemble_bytecode(bytecode, encode_key).map_err(invalid_bytecode)
+        }
+    }
+}
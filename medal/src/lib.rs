@@ -0,0 +1,43 @@
+//! The decompiler as a single dependency. Each bytecode frontend is behind a feature of the same
+//! name, so embedders only build the ones they need:
+//!
+//! - `luau`: [`luau`], the Luau frontend
+//! - `lua51`: [`lua51`], the Lua 5.1 frontend, and [`lua51_serializer`], which compiles and
+//!   assembles Lua 5.1 chunks
+//! - `roblox`: [`roblox`], which reads the scripts of Roblox places and models, and
+//!   [`decompile_place`], which decompiles them
+//! - `verify`: [`verify`], which checks decompiled scripts against the interpreters
+//!
+//! The AST, control flow graph and structuring crates are always available, so the analyses can
+//! be used without any frontend. [`decompile`] decompiles a chunk of either flavor in a single
+//! call, [`disassemble`] lists its instructions and [`prototypes`] the functions it defines.
+
+pub use ::ast;
+pub use ::cfg;
+pub use ::restructure;
+
+pub use decompile::{
+    decompile, decompile_with_progress, disassemble, DecompileError, DecompileOptions, Flavor,
+};
+pub use pipeline::{CancellationToken, Progress, ProgressSink};
+pub use prototype::{prototypes, Prototype};
+
+mod decompile;
+mod prototype;
+
+#[cfg(feature = "lua51")]
+pub use lua51_lifter as lua51;
+#[cfg(feature = "lua51")]
+pub use lua51_serializer;
+#[cfg(feature = "luau")]
+pub use luau_lifter as luau;
+
+#[cfg(feature = "roblox")]
+mod place;
+#[cfg(feature = "roblox")]
+pub use ::roblox;
+#[cfg(feature = "roblox")]
+pub use place::{decompile_place, PlaceScript};
+
+#[cfg(feature = "verify")]
+pub mod verify;
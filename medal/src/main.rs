@@ -0,0 +1,301 @@
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::anyhow;
+use clap::{Parser, Subcommand};
+use medal::{
+    ast::formatter::GlobalStyle,
+    decompile, disassemble,
+    restructure::Budget,
+    verify::{corpus_scripts, Toolchain, Verifier},
+    DecompileOptions, Flavor,
+};
+
+#[derive(Parser, Debug)]
+#[clap(about, version, author, args_conflicts_with_subcommands = true)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// File to decompile, the bytecode format is detected from its header
+    file: Option<String>,
+    #[clap(flatten)]
+    options: Options,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Decompile Lua 5.1 bytecode
+    Lua51 {
+        file: String,
+        #[clap(flatten)]
+        options: Options,
+    },
+    /// Decompile Luau bytecode
+    Luau {
+        file: String,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+        #[clap(flatten)]
+        options: Options,
+    },
+    /// Print the instructions of every function, the bytecode format is detected from the header
+    Disasm {
+        file: String,
+        /// Luau bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+    },
+    /// Assemble a Lua 5.1 listing in the format printed by disasm into a chunk
+    Asm {
+        file: String,
+        /// Where to write the chunk
+        #[clap(short, long, default_value = "luac.out")]
+        output: String,
+    },
+    /// Decompile the scripts of a Roblox place or model (rbxl, rbxm, rbxlx or rbxmx), writing
+    /// each to a file named after its path in the tree. Scripts that hold source are written as is.
+    Place {
+        file: String,
+        /// The directory to write the scripts to
+        #[clap(short, long, default_value = ".")]
+        output: String,
+        /// Bytecode is encoded with the Roblox client key (203)
+        #[clap(short)]
+        encoded: bool,
+        /// How to write globals: by name, as fields of _ENV (env) or of getfenv() (getfenv)
+        #[clap(long, default_value = "name")]
+        global_style: GlobalStyle,
+    },
+    /// Compile, decompile and run every script in a corpus, checking that the decompiled scripts
+    /// print the same output as the originals
+    Verify(VerifyArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyArgs {
+    /// Directory of scripts to check, `.lua` files are compiled with the Lua 5.1 toolchain and
+    /// `.luau` files with the Luau one
+    #[clap(default_value = "tests/corpus")]
+    directory: String,
+    /// Lua 5.1 interpreter
+    #[clap(long, default_value = "lua5.1")]
+    lua: String,
+    /// Lua 5.1 compiler
+    #[clap(long, default_value = "luac5.1")]
+    luac: String,
+    /// Luau interpreter
+    #[clap(long, default_value = "luau")]
+    luau: String,
+    /// Luau compiler, which must support `--binary`
+    #[clap(long, default_value = "luau-compile")]
+    luau_compile: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct Options {
+    /// Write the output to this file instead of stdout
+    #[clap(short, long)]
+    output: Option<String>,
+    /// Write the control flow graph of every function to <id>.dot in this directory instead of
+    /// decompiling. Only supported for Luau.
+    #[clap(long)]
+    graph_dot: Option<String>,
+    /// Print every function in SSA form without structuring it. Only supported for Luau.
+    #[clap(long)]
+    no_structure: bool,
+    /// Start the output with comments describing the input. Only supported for Luau.
+    #[clap(short, long)]
+    verbose: bool,
+    /// Use goto for control flow that can't be structured, the output needs Lua 5.2 or later. Only
+    /// supported for Lua 5.1.
+    #[clap(long)]
+    gotos: bool,
+    /// Write branches that end in a return, break or continue as guard clauses instead of nesting
+    /// the other branch in an else block
+    #[clap(long)]
+    guard_clauses: bool,
+    /// Normalize and order the arms of if chains that compare one local to constants, like the
+    /// dispatch loops of obfuscated scripts
+    #[clap(long)]
+    dispatch_chains: bool,
+    /// How to write globals: by name, as fields of _ENV (env) or of getfenv() (getfenv)
+    #[clap(long, default_value = "name")]
+    global_style: GlobalStyle,
+    /// Stop structuring a function after this many passes over its control flow graph and leave
+    /// the rest to the state machine or goto fallback, 0 for no limit
+    #[clap(long)]
+    max_passes: Option<usize>,
+    /// Stop structuring a function after this many milliseconds, the output then depends on how
+    /// fast the machine is
+    #[clap(long)]
+    structuring_timeout: Option<u64>,
+    /// Leave functions with more blocks than this to the fallback without structuring them
+    #[clap(long)]
+    max_blocks: Option<usize>,
+}
+
+fn write_graphs(bytecode: &[u8], flavor: Flavor, directory: &str) -> anyhow::Result<()> {
+    let Flavor::Luau { encode_key } = flavor else {
+        return Err(anyhow!("control flow graphs are only supported for Luau"));
+    };
+    let directory = Path::new(directory);
+    fs::create_dir_all(directory)?;
+    for (function_id, dot) in medal::luau::render_cfgs(bytecode, encode_key)? {
+        fs::write(directory.join(format!("{}.dot", function_id)), dot)?;
+    }
+    Ok(())
+}
+
+fn decompile_place(
+    file: &str,
+    output: &str,
+    encode_key: u8,
+    global_style: GlobalStyle,
+) -> anyhow::Result<()> {
+    let mut options = DecompileOptions::new(Flavor::Luau { encode_key });
+    options.global_style = global_style;
+    let scripts = medal::decompile_place(&fs::read(file)?, options)
+        .map_err(|err| anyhow!("{}: {}", file, err))?;
+    let (mut decompiled, mut failed) = (0, 0);
+    for script in &scripts {
+        let source = match &script.source {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{}: {}", script.script.full_name(), err);
+                failed += 1;
+                continue;
+            }
+        };
+        if script.decompiled {
+            decompiled += 1;
+        }
+        let path = Path::new(output).join(&script.file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, source)?;
+    }
+    eprintln!(
+        "{} scripts: {} decompiled, {} with source, {} failed",
+        scripts.len(),
+        decompiled,
+        scripts.len() - decompiled - failed,
+        failed
+    );
+    Ok(())
+}
+
+// prints a line for every script, fails if any of them couldn't be verified or behaved
+// differently once decompiled
+fn verify_corpus(args: VerifyArgs) -> anyhow::Result<()> {
+    let scripts = corpus_scripts(Path::new(&args.directory))
+        .map_err(|err| anyhow!("couldn't read {}: {}", args.directory, err))?;
+    let verifier = Verifier::new(Toolchain {
+        lua: args.lua,
+        luac: args.luac,
+        luau: args.luau,
+        luau_compile: args.luau_compile,
+    })?;
+    let (mut passed, mut failed) = (0, 0);
+    for (script, flavor) in scripts {
+        match verifier.verify(&script, flavor) {
+            Ok(None) => {
+                passed += 1;
+                println!("ok      {}", script.display());
+            }
+            Ok(Some(difference)) => {
+                failed += 1;
+                println!("FAILED  {}: {}", script.display(), difference);
+            }
+            Err(err) => {
+                failed += 1;
+                println!("ERROR   {}: {}", script.display(), err);
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed != 0 {
+        return Err(anyhow!("{} scripts failed verification", failed));
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let (file, flavor, options) = match args.command {
+        Some(Command::Lua51 { file, options }) => (file, Some(Flavor::Lua51), options),
+        Some(Command::Luau {
+            file,
+            encoded,
+            options,
+        }) => {
+            let encode_key = if encoded { 203 } else { 1 };
+            (file, Some(Flavor::Luau { encode_key }), options)
+        }
+        Some(Command::Verify(args)) => return verify_corpus(args),
+        Some(Command::Place {
+            file,
+            output,
+            encoded,
+            global_style,
+        }) => {
+            let encode_key = if encoded { 203 } else { 1 };
+            return decompile_place(&file, &output, encode_key, global_style);
+        }
+        Some(Command::Asm { file, output }) => {
+            let prototype = medal::lua51_serializer::assemble(&fs::read_to_string(&file)?)
+                .map_err(|err| anyhow!("{}: {}", file, err))?;
+            fs::write(output, medal::lua51_serializer::serialize(&prototype))?;
+            return Ok(());
+        }
+        Some(Command::Disasm { file, encoded }) => {
+            let bytecode = fs::read(&file)?;
+            let flavor = match Flavor::detect(&bytecode) {
+                Some(Flavor::Luau { .. }) if encoded => Flavor::Luau { encode_key: 203 },
+                Some(flavor) => flavor,
+                None => return Err(anyhow!("{} isn't Lua 5.1 or Luau bytecode", file)),
+            };
+            print!("{}", disassemble(&bytecode, flavor)?);
+            return Ok(());
+        }
+        None => (
+            args.file.ok_or_else(|| anyhow!("no file to decompile"))?,
+            None,
+            args.options,
+        ),
+    };
+
+    let bytecode = fs::read(&file)?;
+    let flavor = match flavor {
+        Some(flavor) => flavor,
+        None => Flavor::detect(&bytecode)
+            .ok_or_else(|| anyhow!("{} isn't Lua 5.1 or Luau bytecode", file))?,
+    };
+
+    if let Some(directory) = options.graph_dot {
+        return write_graphs(&bytecode, flavor, &directory);
+    }
+
+    let mut decompile_options = DecompileOptions::new(flavor);
+    decompile_options.ssa = options.no_structure;
+    decompile_options.verbose = options.verbose;
+    decompile_options.gotos = options.gotos;
+    decompile_options.guard_clauses = options.guard_clauses;
+    decompile_options.dispatch_chains = options.dispatch_chains;
+    decompile_options.global_style = options.global_style;
+    decompile_options.budget = Budget {
+        max_iterations: match options.max_passes {
+            Some(0) => None,
+            Some(max) => Some(max),
+            None => Budget::default().max_iterations,
+        },
+        max_duration: options.structuring_timeout.map(Duration::from_millis),
+        max_nodes: options.max_blocks,
+    };
+    let output = decompile(&bytecode, decompile_options)?;
+    match options.output {
+        Some(path) => fs::write(path, output)?,
+        None => println!("{}", output),
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use crate::{decompile, DecompileError, DecompileOptions, Flavor};
+
+/// A script of a place or model, see [`decompile_place`].
+#[derive(Debug, Clone)]
+pub struct PlaceScript {
+    pub script: roblox::Script,
+    /// Where to write the script relative to an output directory, see [`roblox::file_paths`]
+    pub file: PathBuf,
+    /// Whether the script held bytecode rather than source
+    pub decompiled: bool,
+    pub source: Result<String, DecompileError>,
+}
+
+/// Reads the scripts of a Roblox place or model and decompiles the ones that hold Luau bytecode
+/// with `options`, whose flavor has to be Luau. The source of the other scripts is kept as is.
+pub fn decompile_place(
+    file: &[u8],
+    options: DecompileOptions,
+) -> Result<Vec<PlaceScript>, roblox::RobloxError> {
+    let scripts = roblox::read_scripts(file)?;
+    let files = roblox::file_paths(&scripts);
+    Ok(scripts
+        .into_iter()
+        .zip(files)
+        .map(|(script, file)| {
+            let decompiled = matches!(Flavor::detect(&script.source), Some(Flavor::Luau { .. }));
+            let source = if decompiled {
+                decompile(&script.source, options)
+            } else {
+                Ok(String::from_utf8_lossy(&script.source).into_owned())
+            };
+            PlaceScript {
+                script,
+                file,
+                decompiled,
+                source,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,73 @@
+use crate::{decompile::invalid_bytecode, DecompileError, Flavor};
+
+/// A function of a chunk with the closures it defines, as returned by [`prototypes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prototype {
+    /// The name the function was given in the source, only Luau bytecode records it
+    pub name: Option<String>,
+    pub line_defined: usize,
+    pub num_parameters: u8,
+    pub is_vararg: bool,
+    pub num_upvalues: u8,
+    pub num_instructions: usize,
+    /// The closures defined directly in the function, in the order of their indices
+    pub children: Vec<Prototype>,
+}
+
+#[cfg(feature = "lua51")]
+fn lua51_prototype(function: &lua51_deserializer::Function) -> Prototype {
+    Prototype {
+        name: None,
+        line_defined: function.line_defined as usize,
+        num_parameters: function.number_of_parameters,
+        is_vararg: function.is_vararg(),
+        num_upvalues: function.number_of_upvalues,
+        num_instructions: function.code.len(),
+        children: function.closures.iter().map(lua51_prototype).collect(),
+    }
+}
+
+// closures are referenced by id, a malformed chunk could have a function define one of its
+// ancestors, which is left out
+#[cfg(feature = "luau")]
+fn luau_prototype(
+    chunk: &crate::luau::Chunk,
+    function_id: usize,
+    ancestors: &mut Vec<usize>,
+) -> Prototype {
+    let function = &chunk.functions[function_id];
+    ancestors.push(function_id);
+    let mut children = Vec::new();
+    for &child in &function.functions {
+        if !ancestors.contains(&child) {
+            children.push(luau_prototype(chunk, child, ancestors));
+        }
+    }
+    ancestors.pop();
+    Prototype {
+        name: chunk.function_name(function_id),
+        line_defined: function.line_defined,
+        num_parameters: function.num_parameters,
+        is_vararg: function.is_vararg,
+        num_upvalues: function.num_upvalues,
+        num_instructions: function.instructions.len(),
+        children,
+    }
+}
+
+/// The main function of a chunk with every function nested in it, without lifting any of them.
+pub fn prototypes(bytecode: &[u8], flavor: Flavor) -> Result<Prototype, DecompileError> {
+    match flavor {
+        #[cfg(feature = "lua51")]
+        Flavor::Lua51 => {
+            let chunk = crate::lua51::parse_chunk(bytecode).map_err(invalid_bytecode)?;
+            Ok(lua51_prototype(&chunk.function))
+        }
+        #[cfg(feature = "luau")]
+        Flavor::Luau { encode_key } => {
+            let chunk =
+                crate::luau::deserialize_chunk(bytecode, encode_key).map_err(invalid_bytecode)?;
+            Ok(luau_prototype(&chunk, chunk.main, &mut Vec::new()))
+        }
+    }
+}
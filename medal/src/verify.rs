@@ -0,0 +1,326 @@
+//! Checks the decompiler against real interpreters: a script is compiled, decompiled and run
+//! again, and must print the same output as the original. Lua 5.1 scripts are also lifted,
+//! compiled back to bytecode with [`lua51_serializer::compile`] and decompiled again, which checks
+//! the lifted AST as well as its source. `medal verify` runs it over a corpus and so does `cargo
+//! test`, which skips the flavors whose toolchain isn't installed.
+
+use std::{
+    env, fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ast::{emitter::Emitter, Block};
+use lua51_serializer::{compile, serialize, CompileError, Prototype};
+
+use crate::{
+    decompile, decompile::invalid_bytecode, lua51::StructureOptions, DecompileError,
+    DecompileOptions, Flavor,
+};
+
+/// The programs used to compile and run scripts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    /// Lua 5.1 interpreter
+    pub lua: String,
+    /// Lua 5.1 compiler, called like `luac -o <output> <script>`
+    pub luac: String,
+    /// Luau interpreter
+    pub luau: String,
+    /// Luau compiler, called like `luau-compile --binary <script>`
+    pub luau_compile: String,
+}
+
+impl Default for Toolchain {
+    fn default() -> Self {
+        Self {
+            lua: "lua5.1".to_string(),
+            luac: "luac5.1".to_string(),
+            luau: "luau".to_string(),
+            luau_compile: "luau-compile".to_string(),
+        }
+    }
+}
+
+// whether a program can be run, either a path or a name to look up in PATH
+fn is_installed(program: &str) -> bool {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file();
+    }
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|directory| directory.join(program).is_file())
+    })
+}
+
+impl Toolchain {
+    /// The default toolchain with every program that's set in `MEDAL_LUA`, `MEDAL_LUAC`,
+    /// `MEDAL_LUAU` or `MEDAL_LUAU_COMPILE` replaced.
+    pub fn from_env() -> Self {
+        let mut toolchain = Self::default();
+        for (variable, program) in [
+            ("MEDAL_LUA", &mut toolchain.lua),
+            ("MEDAL_LUAC", &mut toolchain.luac),
+            ("MEDAL_LUAU", &mut toolchain.luau),
+            ("MEDAL_LUAU_COMPILE", &mut toolchain.luau_compile),
+        ] {
+            if let Ok(value) = env::var(variable) {
+                *program = value;
+            }
+        }
+        toolchain
+    }
+
+    fn interpreter(&self, flavor: Flavor) -> &str {
+        match flavor {
+            Flavor::Lua51 => &self.lua,
+            Flavor::Luau { .. } => &self.luau,
+        }
+    }
+
+    fn compiler(&self, flavor: Flavor) -> &str {
+        match flavor {
+            Flavor::Lua51 => &self.luac,
+            Flavor::Luau { .. } => &self.luau_compile,
+        }
+    }
+
+    /// Whether the interpreter and compiler of a flavor are installed.
+    pub fn is_available(&self, flavor: Flavor) -> bool {
+        is_installed(self.interpreter(flavor)) && is_installed(self.compiler(flavor))
+    }
+}
+
+#[derive(Debug)]
+pub enum VerifyError {
+    Io(io::Error),
+    /// A program of the toolchain couldn't be run or failed, with its stderr
+    Tool {
+        program: String,
+        message: String,
+    },
+    Decompile(DecompileError),
+    /// The lifted AST of a Lua 5.1 chunk couldn't be compiled back to bytecode
+    Compile(CompileError),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::Tool { program, message } => write!(f, "{} failed: {}", program, message),
+            Self::Decompile(error) => write!(f, "{}", error),
+            Self::Compile(error) => write!(f, "couldn't recompile: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl From<io::Error> for VerifyError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<DecompileError> for VerifyError {
+    fn from(error: DecompileError) -> Self {
+        Self::Decompile(error)
+    }
+}
+
+impl From<CompileError> for VerifyError {
+    fn from(error: CompileError) -> Self {
+        Self::Compile(error)
+    }
+}
+
+// compiles the decompiled main function instead of formatting it
+struct Recompiler;
+
+impl Emitter for Recompiler {
+    type Output = Result<Prototype, CompileError>;
+
+    fn emit(&mut self, block: &Block) -> Self::Output {
+        compile(block)
+    }
+}
+
+/// Decompiles a Lua 5.1 chunk and compiles the lifted AST back to a chunk, without debug info.
+pub fn recompile(bytecode: &[u8]) -> Result<Vec<u8>, VerifyError> {
+    let prototype = crate::lua51::decompile_bytecode_with_emitter(
+        bytecode,
+        StructureOptions::default(),
+        &mut Recompiler,
+    )
+    .map_err(invalid_bytecode)??;
+    Ok(serialize(&prototype))
+}
+
+// how long a script may run, a decompiled loop that doesn't terminate must not hang the check
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// the stdout of a command that must succeed within the timeout
+fn output(command: &mut Command) -> Result<Vec<u8>, VerifyError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let error = |message: String| VerifyError::Tool {
+        program: program.clone(),
+        message,
+    };
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| error(err.to_string()))?;
+    // read on other threads so a full pipe doesn't block the child
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let stdout = thread::spawn(move || {
+        let mut buffer = Vec::new();
+        stdout.read_to_end(&mut buffer).map(|_| buffer)
+    });
+    let stderr = thread::spawn(move || {
+        let mut buffer = Vec::new();
+        stderr.read_to_end(&mut buffer).map(|_| buffer)
+    });
+    let deadline = Instant::now() + TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(error(format!("timed out after {:?}", TIMEOUT)));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = stdout.join().unwrap()?;
+    let stderr = stderr.join().unwrap()?;
+    if !status.success() {
+        return Err(error(String::from_utf8_lossy(&stderr).trim().to_string()));
+    }
+    Ok(stdout)
+}
+
+// the first line the outputs differ on, if they do
+fn first_difference(expected: &[u8], actual: &[u8]) -> Option<String> {
+    let expected = String::from_utf8_lossy(expected);
+    let actual = String::from_utf8_lossy(actual);
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return None,
+            (expected, actual) if expected != actual => {
+                return Some(format!(
+                    "line {} is {:?}, expected {:?}",
+                    line,
+                    actual.unwrap_or("<end of output>"),
+                    expected.unwrap_or("<end of output>")
+                ))
+            }
+            _ => {}
+        }
+    }
+    unreachable!()
+}
+
+/// The scripts of a corpus directory in order, `.lua` files are Lua 5.1 and `.luau` files Luau.
+pub fn corpus_scripts(directory: &Path) -> io::Result<Vec<(PathBuf, Flavor)>> {
+    let mut scripts = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let flavor = match path.extension().and_then(|e| e.to_str()) {
+            Some("lua") => Flavor::Lua51,
+            Some("luau") => Flavor::Luau { encode_key: 1 },
+            _ => continue,
+        };
+        scripts.push((path, flavor));
+    }
+    scripts.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(scripts)
+}
+
+/// Verifies scripts with a toolchain. The bytecode and decompiled scripts are written to a
+/// directory of its own, which is removed when it's dropped.
+pub struct Verifier {
+    toolchain: Toolchain,
+    scratch: PathBuf,
+}
+
+impl Verifier {
+    pub fn new(toolchain: Toolchain) -> io::Result<Self> {
+        // every verifier of the process needs its own directory, e.g. for tests on other threads
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let scratch = env::temp_dir().join(format!(
+            "medal-verify-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&scratch)?;
+        Ok(Self { toolchain, scratch })
+    }
+
+    pub fn toolchain(&self) -> &Toolchain {
+        &self.toolchain
+    }
+
+    fn compile(&self, script: &Path, flavor: Flavor) -> Result<Vec<u8>, VerifyError> {
+        let compiler = self.toolchain.compiler(flavor);
+        match flavor {
+            Flavor::Lua51 => {
+                let bytecode = self.scratch.join("luac.out");
+                output(Command::new(compiler).arg("-o").arg(&bytecode).arg(script))?;
+                Ok(fs::read(bytecode)?)
+            }
+            Flavor::Luau { .. } => output(Command::new(compiler).arg("--binary").arg(script)),
+        }
+    }
+
+    // how the script decompiled from `bytecode` behaves differently from the original
+    fn run_decompiled(
+        &self,
+        script: &Path,
+        flavor: Flavor,
+        bytecode: &[u8],
+        expected: &[u8],
+    ) -> Result<Option<String>, VerifyError> {
+        let source = decompile(bytecode, DecompileOptions::new(flavor))?;
+        let decompiled = self.scratch.join(script.file_name().unwrap_or_default());
+        fs::write(&decompiled, source)?;
+        Ok(
+            match output(Command::new(self.toolchain.interpreter(flavor)).arg(&decompiled)) {
+                Ok(actual) => first_difference(expected, &actual),
+                Err(err) => Some(err.to_string()),
+            },
+        )
+    }
+
+    /// Returns how the decompiled script behaves differently from the original, if it does. Lua
+    /// 5.1 scripts that behave the same are also [recompiled](recompile), and the script
+    /// decompiled from that has to behave the same too.
+    pub fn verify(&self, script: &Path, flavor: Flavor) -> Result<Option<String>, VerifyError> {
+        let expected = output(Command::new(self.toolchain.interpreter(flavor)).arg(script))?;
+        let bytecode = self.compile(script, flavor)?;
+        let difference = self.run_decompiled(script, flavor, &bytecode, &expected)?;
+        if difference.is_some() || !matches!(flavor, Flavor::Lua51) {
+            return Ok(difference);
+        }
+        let recompiled = recompile(&bytecode)?;
+        Ok(self
+            .run_decompiled(script, flavor, &recompiled, &expected)?
+            .map(|difference| format!("after recompiling, {}", difference)))
+    }
+}
+
+impl Drop for Verifier {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.scratch);
+    }
+}
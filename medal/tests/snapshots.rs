@@ -0,0 +1,59 @@
+//! Decompiles every chunk in `tests/fixtures` and compares the output with its snapshot in
+//! `tests/snapshots`, and formats hand built trees for cases the fixtures don't cover. Changed
+//! output is reviewed with `cargo insta review`.
+
+use std::{fs, path::Path};
+
+use ast::{
+    formatter::{FormatOptions, Formatter},
+    Binary, BinaryOperation, Block, Call, CompoundAssign, Global,
+};
+use medal::{decompile, DecompileOptions, Flavor};
+
+fn decompile_fixture(path: &Path, flavor: Flavor) -> String {
+    let bytecode = fs::read(path).unwrap();
+    decompile(&bytecode, DecompileOptions::new(flavor))
+        .unwrap_or_else(|err| panic!("couldn't decompile {}: {}", path.display(), err))
+}
+
+#[test]
+fn lua51() {
+    insta::glob!("../../tests/fixtures", "lua51/*.luac", |path| {
+        insta::assert_snapshot!(decompile_fixture(path, Flavor::Lua51));
+    });
+}
+
+#[test]
+fn luau() {
+    insta::glob!("../../tests/fixtures", "luau/*.luac", |path| {
+        insta::assert_snapshot!(decompile_fixture(path, Flavor::Luau { encode_key: 1 }));
+    });
+}
+
+#[test]
+fn compound_assign_before_parenthesized_call() {
+    let block = Block(vec![
+        CompoundAssign::new(
+            Global::from("a").into(),
+            BinaryOperation::Add,
+            Global::from("f").into(),
+        )
+        .into(),
+        Call::new(
+            Binary::new(
+                Global::from("g").into(),
+                Global::from("h").into(),
+                BinaryOperation::Or,
+            )
+            .into(),
+            Vec::new(),
+        )
+        .into(),
+    ]);
+    let mut output = String::new();
+    Formatter::format(&block, &mut output, FormatOptions::default()).unwrap();
+    insta::assert_snapshot!(output, @r"
+    a += f;
+    (g or h)()
+    ");
+}
@@ -0,0 +1,54 @@
+//! Runs the corpus through `medal::verify`. Set `MEDAL_LUA`, `MEDAL_LUAC`, `MEDAL_LUAU` and
+//! `MEDAL_LUAU_COMPILE` to use other programs than `lua5.1`, `luac5.1`, `luau` and `luau-compile`,
+//! scripts of a flavor whose toolchain isn't installed are skipped. The Lua 5.1 fixtures are
+//! recompiled without any toolchain.
+
+use std::{fs, path::Path};
+
+use medal::{
+    decompile,
+    verify::{corpus_scripts, recompile, Toolchain, Verifier},
+    DecompileOptions, Flavor,
+};
+
+#[test]
+fn corpus() {
+    let corpus = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/corpus"));
+    let verifier = Verifier::new(Toolchain::from_env()).unwrap();
+    let mut failures = Vec::new();
+    for (script, flavor) in corpus_scripts(corpus).unwrap() {
+        if !verifier.toolchain().is_available(flavor) {
+            eprintln!(
+                "skipping {}, its toolchain isn't installed",
+                script.display()
+            );
+            continue;
+        }
+        match verifier.verify(&script, flavor) {
+            Ok(None) => {}
+            Ok(Some(difference)) => failures.push(format!("{}: {}", script.display(), difference)),
+            Err(err) => failures.push(format!("{}: {}", script.display(), err)),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+// the lifted fixtures have to compile, and the recompiled chunks have to lift and decompile again
+#[test]
+fn recompile_fixtures() {
+    let fixtures = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../tests/fixtures/lua51"
+    ));
+    let mut fixtures = fs::read_dir(fixtures)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    fixtures.sort();
+    for path in fixtures {
+        let recompiled = recompile(&fs::read(&path).unwrap())
+            .unwrap_or_else(|err| panic!("couldn't recompile {}: {}", path.display(), err));
+        decompile(&recompiled, DecompileOptions::new(Flavor::Lua51))
+            .unwrap_or_else(|err| panic!("couldn't decompile {}: {}", path.display(), err));
+    }
+}
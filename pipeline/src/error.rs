@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+use crate::Cancelled;
+
+/// Why the passes of a [`PassManager`](crate::PassManager) can't be put in order or didn't all
+/// run.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PipelineError {
+    #[error("a pass named `{0}` is already registered")]
+    DuplicatePass(String),
+    #[error("no pass named `{0}` is registered")]
+    UnknownPass(String),
+    #[error("pass `{pass}` depends on `{dependency}`, which isn't registered")]
+    UnknownDependency { pass: String, dependency: String },
+    #[error("pass `{0}` depends on itself through its dependencies")]
+    Cycle(String),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
+}
@@ -0,0 +1,52 @@
+//! The passes that run on every function between lifting and printing, so they can be reordered,
+//! disabled, timed or extended with passes of your own, and [`Progress`], which lets a host follow
+//! a decompilation and cancel it.
+
+mod error;
+mod manager;
+mod pass;
+mod progress;
+
+pub use error::PipelineError;
+pub use manager::PassManager;
+pub use pass::{FnPass, Pass};
+pub use progress::{CancellationToken, Cancelled, Progress, ProgressSink};
+
+use indexmap::IndexMap;
+use rustc_hash::FxHashMap;
+
+/// A function in SSA form and what the passes over it need to know about its locals.
+pub struct SsaFunction {
+    pub function: cfg::function::Function,
+    /// The group of every local, locals in a group were split from the same register
+    pub local_to_group: FxHashMap<ast::RcLocal, usize>,
+    /// The upvalue every local that is captured by a closure is grouped with
+    pub upvalue_to_group: IndexMap<ast::RcLocal, ast::RcLocal>,
+    /// Set by a pass that changed the function in a way that lets the passes do more, so they run
+    /// again
+    pub changed: bool,
+}
+
+/// The passes a decompiler runs on every function.
+#[derive(Default)]
+pub struct Pipeline {
+    /// Run on a function in SSA form over and over, until none of them set
+    /// [`SsaFunction::changed`]
+    pub ssa: PassManager<SsaFunction>,
+    /// Run on the control flow graph of a function once it is out of SSA form, before it's
+    /// structured
+    pub functions: PassManager<cfg::function::Function>,
+    /// Run on the body of a function once it's structured and its locals are declared
+    pub blocks: PassManager<ast::Block>,
+}
+
+impl Pipeline {
+    /// Checks that the passes of every manager can be put in order, so [`PassManager::run`]
+    /// won't fail and [`PassManager::run_cancellable`] only fails if it's cancelled.
+    pub fn validate(&self) -> Result<(), PipelineError> {
+        self.ssa.order()?;
+        self.functions.order()?;
+        self.blocks.order()?;
+        Ok(())
+    }
+}
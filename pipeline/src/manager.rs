@@ -0,0 +1,294 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use web_time::Instant;
+
+use crate::{CancellationToken, Pass, PipelineError};
+
+struct Entry<T> {
+    pass: Box<dyn Pass<T>>,
+    enabled: bool,
+    // the total time spent running the pass
+    elapsed: Mutex<Duration>,
+}
+
+/// Runs passes in the order they were registered in, except that a pass runs after its
+/// dependencies. Passes can be disabled, and the time spent in each pass is added up over every
+/// run, see [`PassManager::timings`].
+pub struct PassManager<T> {
+    entries: Vec<Entry<T>>,
+}
+
+impl<T> Default for PassManager<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T> PassManager<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|e| e.pass.name() == name)
+    }
+
+    fn insert(&mut self, index: usize, pass: Box<dyn Pass<T>>) -> Result<(), PipelineError> {
+        if self.position(pass.name()).is_some() {
+            return Err(PipelineError::DuplicatePass(pass.name().to_string()));
+        }
+        self.entries.insert(
+            index,
+            Entry {
+                pass,
+                enabled: true,
+                elapsed: Mutex::default(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Adds a pass after the ones already registered.
+    pub fn register(&mut self, pass: impl Pass<T> + 'static) -> Result<(), PipelineError> {
+        self.insert(self.entries.len(), Box::new(pass))
+    }
+
+    /// Adds a pass right before the pass named `before`, so it runs before it if their
+    /// dependencies allow it.
+    pub fn register_before(
+        &mut self,
+        before: &str,
+        pass: impl Pass<T> + 'static,
+    ) -> Result<(), PipelineError> {
+        let index = self
+            .position(before)
+            .ok_or_else(|| PipelineError::UnknownPass(before.to_string()))?;
+        self.insert(index, Box::new(pass))
+    }
+
+    /// Enables or disables the pass named `name`. Passes are enabled when they are registered.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), PipelineError> {
+        let index = self
+            .position(name)
+            .ok_or_else(|| PipelineError::UnknownPass(name.to_string()))?;
+        self.entries[index].enabled = enabled;
+        Ok(())
+    }
+
+    /// The names of the registered passes in the order they run in, whether or not they are
+    /// enabled.
+    pub fn order(&self) -> Result<Vec<&str>, PipelineError> {
+        Ok(self
+            .schedule()?
+            .into_iter()
+            .map(|i| self.entries[i].pass.name())
+            .collect())
+    }
+
+    // the indices of the entries in the order they run in, each is the first registered pass
+    // whose dependencies have all been scheduled
+    fn schedule(&self) -> Result<Vec<usize>, PipelineError> {
+        let dependencies = self
+            .entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .pass
+                    .dependencies()
+                    .into_iter()
+                    .map(|dependency| {
+                        self.position(dependency)
+                            .ok_or_else(|| PipelineError::UnknownDependency {
+                                pass: entry.pass.name().to_string(),
+                                dependency: dependency.to_string(),
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut scheduled = vec![false; self.entries.len()];
+        let mut schedule = Vec::with_capacity(self.entries.len());
+        while schedule.len() < self.entries.len() {
+            let next = (0..self.entries.len())
+                .find(|&i| !scheduled[i] && dependencies[i].iter().all(|&d| scheduled[d]))
+                .ok_or_else(|| {
+                    let stuck = scheduled.iter().position(|&s| !s).unwrap();
+                    PipelineError::Cycle(self.entries[stuck].pass.name().to_string())
+                })?;
+            scheduled[next] = true;
+            schedule.push(next);
+        }
+        Ok(schedule)
+    }
+
+    /// Runs the enabled passes on `ir`. A pass is skipped if it or one of its dependencies is
+    /// disabled or was skipped.
+    pub fn run(&self, ir: &mut T) -> Result<(), PipelineError> {
+        self.run_cancellable(ir, &CancellationToken::default())
+    }
+
+    /// Like [`PassManager::run`], but `cancellation` is checked before every pass, and once it's
+    /// cancelled the remaining passes don't run and [`PipelineError::Cancelled`] is returned.
+    pub fn run_cancellable(
+        &self,
+        ir: &mut T,
+        cancellation: &CancellationToken,
+    ) -> Result<(), PipelineError> {
+        self.run_observed(ir, cancellation, &mut |_, _| {})
+    }
+
+    /// Like [`PassManager::run_cancellable`], but `observe` is called with the name of every pass
+    /// that ran and `ir` after it, e.g. to record how each pass changed a function.
+    pub fn run_observed(
+        &self,
+        ir: &mut T,
+        cancellation: &CancellationToken,
+        observe: &mut dyn FnMut(&str, &T),
+    ) -> Result<(), PipelineError> {
+        let mut ran = vec![false; self.entries.len()];
+        for index in self.schedule()? {
+            cancellation.check()?;
+            let entry = &self.entries[index];
+            if !entry.enabled
+                || !entry
+                    .pass
+                    .dependencies()
+                    .into_iter()
+                    .all(|d| ran[self.position(d).unwrap()])
+            {
+                continue;
+            }
+            let start = Instant::now();
+            entry.pass.run(ir);
+            let elapsed = start.elapsed();
+            log::debug!("{} took {:?}", entry.pass.name(), elapsed);
+            *entry.elapsed.lock() += elapsed;
+            ran[index] = true;
+            observe(entry.pass.name(), ir);
+        }
+        Ok(())
+    }
+
+    /// The time spent in every pass over all runs so far, in the order they were registered in.
+    pub fn timings(&self) -> Vec<(&str, Duration)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.pass.name(), *entry.elapsed.lock()))
+            .collect()
+    }
+
+    /// Sets the time spent in every pass back to zero.
+    pub fn reset_timings(&self) {
+        for entry in &self.entries {
+            *entry.elapsed.lock() = Duration::ZERO;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FnPass;
+
+    // a pass that records that it ran
+    fn pass(name: &'static str) -> FnPass<Vec<&'static str>> {
+        FnPass::new(name, move |ran: &mut Vec<&'static str>| ran.push(name))
+    }
+
+    fn run(manager: &PassManager<Vec<&'static str>>) -> Vec<&'static str> {
+        let mut ran = Vec::new();
+        manager.run(&mut ran).unwrap();
+        ran
+    }
+
+    #[test]
+    fn registration_order() {
+        let mut manager = PassManager::new();
+        manager.register(pass("a")).unwrap();
+        manager.register(pass("c")).unwrap();
+        manager.register_before("c", pass("b")).unwrap();
+        assert_eq!(manager.order().unwrap(), ["a", "b", "c"]);
+        assert_eq!(run(&manager), ["a", "b", "c"]);
+        assert_eq!(
+            manager.register_before("d", pass("e")),
+            Err(PipelineError::UnknownPass("d".to_string()))
+        );
+    }
+
+    #[test]
+    fn dependencies_run_first() {
+        let mut manager = PassManager::new();
+        manager.register(pass("a").after("c")).unwrap();
+        manager.register(pass("b")).unwrap();
+        manager.register(pass("c")).unwrap();
+        // a dependency doesn't have to be registered first, but it runs first
+        manager.register_before("c", pass("d").after("a")).unwrap();
+        assert_eq!(manager.order().unwrap(), ["b", "c", "a", "d"]);
+        assert_eq!(run(&manager), ["b", "c", "a", "d"]);
+    }
+
+    #[test]
+    fn duplicate_pass() {
+        let mut manager = PassManager::new();
+        manager.register(pass("a")).unwrap();
+        manager.register(pass("b")).unwrap();
+        assert_eq!(
+            manager.register(pass("a")),
+            Err(PipelineError::DuplicatePass("a".to_string()))
+        );
+        assert_eq!(
+            manager.register_before("a", pass("b")),
+            Err(PipelineError::DuplicatePass("b".to_string()))
+        );
+        assert_eq!(manager.order().unwrap(), ["a", "b"]);
+    }
+
+    #[test]
+    fn unknown_dependency() {
+        let mut manager = PassManager::new();
+        manager.register(pass("a").after("b")).unwrap();
+        let error = PipelineError::UnknownDependency {
+            pass: "a".to_string(),
+            dependency: "b".to_string(),
+        };
+        assert_eq!(manager.order(), Err(error.clone()));
+        assert_eq!(manager.run(&mut Vec::new()), Err(error));
+    }
+
+    #[test]
+    fn cycle() {
+        let mut manager = PassManager::new();
+        manager.register(pass("a")).unwrap();
+        manager.register(pass("b").after("c")).unwrap();
+        manager.register(pass("c").after("b")).unwrap();
+        assert_eq!(manager.order(), Err(PipelineError::Cycle("b".to_string())));
+        let mut ran = Vec::new();
+        assert_eq!(
+            manager.run(&mut ran),
+            Err(PipelineError::Cycle("b".to_string()))
+        );
+        // nothing runs if the passes can't be put in order
+        assert!(ran.is_empty());
+    }
+
+    #[test]
+    fn disabled_dependency() {
+        let mut manager = PassManager::new();
+        manager.register(pass("a")).unwrap();
+        manager.register(pass("b").after("a")).unwrap();
+        manager.register(pass("c").after("b")).unwrap();
+        manager.register(pass("d")).unwrap();
+        manager.set_enabled("a", false).unwrap();
+        // b is skipped since a is disabled, and c since b was skipped
+        assert_eq!(run(&manager), ["d"]);
+        manager.set_enabled("a", true).unwrap();
+        assert_eq!(run(&manager), ["a", "b", "c", "d"]);
+        assert_eq!(
+            manager.set_enabled("e", false),
+            Err(PipelineError::UnknownPass("e".to_string()))
+        );
+    }
+}
@@ -0,0 +1,52 @@
+/// A transformation of a function in its control flow graph form, [`cfg::function::Function`], or
+/// its structured form, [`ast::Block`]. Passes are run by a [`PassManager`](crate::PassManager)
+/// on functions decompiled in parallel, so they can't keep state between runs without
+/// synchronizing it.
+pub trait Pass<T>: Send + Sync {
+    /// The name other passes refer to this one by, which has to be unique in its manager
+    fn name(&self) -> &str;
+
+    /// The passes that have to run before this one. The pass is skipped if any of them are.
+    fn dependencies(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn run(&self, ir: &mut T);
+}
+
+/// A pass that runs a function, for passes that don't need a type of their own.
+pub struct FnPass<T> {
+    name: String,
+    dependencies: Vec<String>,
+    run: Box<dyn Fn(&mut T) + Send + Sync>,
+}
+
+impl<T> FnPass<T> {
+    pub fn new(name: impl Into<String>, run: impl Fn(&mut T) + Send + Sync + 'static) -> Self {
+        Self {
+            name: name.into(),
+            dependencies: Vec::new(),
+            run: Box::new(run),
+        }
+    }
+
+    /// Adds a pass that has to run before this one.
+    pub fn after(mut self, dependency: impl Into<String>) -> Self {
+        self.dependencies.push(dependency.into());
+        self
+    }
+}
+
+impl<T> Pass<T> for FnPass<T> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn dependencies(&self) -> Vec<&str> {
+        self.dependencies.iter().map(String::as_str).collect()
+    }
+
+    fn run(&self, ir: &mut T) {
+        (self.run)(ir)
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use thiserror::Error;
+
+/// The error returned by a decompilation that stopped because its [`CancellationToken`] was
+/// cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("decompilation was cancelled")]
+pub struct Cancelled;
+
+/// A flag a decompilation checks between steps, e.g. between functions, passes and structuring
+/// iterations, to stop early. Clones share the flag, so a host keeps a clone to cancel the
+/// decompilation from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns [`Cancelled`] if the token has been cancelled, for use with `?`.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Told how far a decompilation has gotten, e.g. to show a progress bar. Functions are decompiled
+/// in parallel, so it's called from several threads and the calls of a stage can be out of order.
+pub trait ProgressSink: Send + Sync {
+    /// `done` of the `total` steps of `stage` have finished, e.g. 3 of the 10 functions of a chunk
+    /// for `"decompiling"`
+    fn progress(&self, stage: &str, done: usize, total: usize);
+}
+
+impl<F: Fn(&str, usize, usize) + Send + Sync> ProgressSink for F {
+    fn progress(&self, stage: &str, done: usize, total: usize) {
+        self(stage, done, total)
+    }
+}
+
+/// Where a decompilation reports its progress and the token that cancels it. The default has no
+/// sink and can only be cancelled through its token.
+#[derive(Clone, Default)]
+pub struct Progress {
+    sink: Option<Arc<dyn ProgressSink>>,
+    pub cancellation: CancellationToken,
+}
+
+impl Progress {
+    pub fn new(sink: impl ProgressSink + 'static, cancellation: CancellationToken) -> Self {
+        Self {
+            sink: Some(Arc::new(sink)),
+            cancellation,
+        }
+    }
+
+    pub fn report(&self, stage: &str, done: usize, total: usize) {
+        if let Some(sink) = &self.sink {
+            sink.progress(stage, done, total);
+        }
+    }
+
+    pub fn check(&self) -> Result<(), Cancelled> {
+        self.cancellation.check()
+    }
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("sink", &self.sink.is_some())
+            .field("cancellation", &self.cancellation)
+            .finish()
+    }
+}
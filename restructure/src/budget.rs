@@ -0,0 +1,99 @@
+use std::{fmt, time::Duration};
+
+use web_time::Instant;
+
+use crate::GraphStructurer;
+
+/// Limits on the pattern matching that structures a function. Once one is exceeded no more passes
+/// are made over the graph and what's left of it goes to the [fallback](crate::Fallback), so an
+/// adversarial graph can't keep the structurer busy forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    /// How many passes over the graph are made
+    pub max_iterations: Option<usize>,
+    /// How long passes are made for. Off by default as the output would depend on how fast the
+    /// machine is.
+    pub max_duration: Option<Duration>,
+    /// Functions with more blocks than this go to the fallback without matching any patterns
+    pub max_nodes: Option<usize>,
+}
+
+impl Budget {
+    /// A budget without limits
+    pub const UNLIMITED: Self = Self {
+        max_iterations: None,
+        max_duration: None,
+        max_nodes: None,
+    };
+}
+
+// far more passes than any function compiled from real code needs
+const DEFAULT_MAX_ITERATIONS: usize = 10_000;
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            max_iterations: Some(DEFAULT_MAX_ITERATIONS),
+            ..Self::UNLIMITED
+        }
+    }
+}
+
+/// The limit of a [`Budget`] structuring a function exceeded, displayed as the limit, e.g. `10000
+/// passes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetExceeded {
+    Iterations(usize),
+    Duration(Duration),
+    Nodes(usize),
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Iterations(max) => write!(f, "{} passes", max),
+            Self::Duration(max) => write!(f, "{:?}", max),
+            Self::Nodes(max) => write!(f, "{} blocks", max),
+        }
+    }
+}
+
+// how much of the budget has been spent so far
+#[derive(Debug, Default)]
+pub(crate) struct Spent {
+    iterations: usize,
+    deadline: Option<Instant>,
+    pub exceeded: Option<BudgetExceeded>,
+}
+
+impl GraphStructurer<'_> {
+    pub(crate) fn start_budget(&mut self) {
+        self.spent.deadline = self
+            .budget
+            .max_duration
+            .map(|duration| Instant::now() + duration);
+        let nodes = self.function.graph().node_count();
+        if let Some(max) = self.budget.max_nodes
+            && nodes > max
+        {
+            self.spent.exceeded = Some(BudgetExceeded::Nodes(max));
+        }
+    }
+
+    /// Counts a pass over the graph, returns false if there's no budget left for it.
+    pub(crate) fn spend_iteration(&mut self) -> bool {
+        if self.spent.exceeded.is_none() {
+            self.spent.iterations += 1;
+            if let Some(max) = self.budget.max_iterations
+                && self.spent.iterations > max
+            {
+                self.spent.exceeded = Some(BudgetExceeded::Iterations(max));
+            } else if let Some(deadline) = self.spent.deadline
+                && Instant::now() > deadline
+            {
+                self.spent.exceeded = self.budget.max_duration.map(BudgetExceeded::Duration);
+            }
+        }
+        self.spent.exceeded.is_none()
+    }
+}
@@ -6,10 +6,10 @@ use petgraph::visit::EdgeRef;
 use triomphe::Arc;
 use tuple::Map;
 
-use crate::GraphStructurer;
+use crate::{GraphStructurer, Pattern};
 use petgraph::{algo::dominators::Dominators, stable_graph::NodeIndex};
 
-impl GraphStructurer {
+impl GraphStructurer<'_> {
     fn simplify_if(if_stat: &mut ast::If) {
         if let Some(unary) = if_stat.condition.as_unary() {
             if unary.operation == ast::UnaryOperation::Not {
@@ -65,6 +65,45 @@ impl GraphStructurer {
         }
     }
 
+    // whether control never reaches the end of the block
+    fn exits(block: &ast::Block) -> bool {
+        match block.last() {
+            Some(
+                ast::Statement::Return(_)
+                | ast::Statement::Break(_)
+                | ast::Statement::Continue(_)
+                | ast::Statement::Goto(_),
+            ) => true,
+            Some(ast::Statement::If(r#if)) => {
+                Self::exits(&r#if.then_block.lock()) && Self::exits(&r#if.else_block.lock())
+            }
+            _ => false,
+        }
+    }
+
+    // `if a then return else b end` becomes `if a then return end b`, the shorter branch is the
+    // guard if both exit
+    fn guard_clause(if_stat: &mut ast::If) -> Option<ast::Block> {
+        let mut then_block = if_stat.then_block.lock();
+        let mut else_block = if_stat.else_block.lock();
+        if then_block.is_empty() || else_block.is_empty() {
+            return None;
+        }
+        let else_exits = Self::exits(&else_block);
+        if Self::exits(&then_block) && (!else_exits || then_block.len() <= else_block.len()) {
+            Some(std::mem::take(&mut else_block))
+        } else if else_exits {
+            let then_block =
+                std::mem::replace::<ast::Block>(&mut then_block, std::mem::take(&mut else_block));
+            if_stat.condition =
+                ast::Unary::new(if_stat.condition.clone(), ast::UnaryOperation::Not)
+                    .reduce_condition();
+            Some(then_block)
+        } else {
+            None
+        }
+    }
+
     // a -> b -> d + a -> c -> d
     // results in a -> d
     fn match_diamond_conditional(
@@ -92,11 +131,19 @@ impl GraphStructurer {
                     false
                 };
                 if (!t && then_successors.len() != 1) || (!e && else_successors.len() != 1) {
-                    return false;
+                    return self.reject(
+                        entry,
+                        Pattern::DiamondConditional,
+                        "a branch has more than one successor",
+                    );
                 }
 
                 if then_successors != else_successors {
-                    return false;
+                    return self.reject(
+                        entry,
+                        Pattern::DiamondConditional,
+                        "branches don't join at the same block",
+                    );
                 }
 
                 let mut refine = |n| {
@@ -126,22 +173,38 @@ impl GraphStructurer {
                 let then_changed = if t { refine(then_node) } else { false };
                 let else_changed = if e { refine(else_node) } else { false };
                 if !then_changed && !else_changed {
-                    return false;
+                    return self.reject(
+                        entry,
+                        Pattern::DiamondConditional,
+                        "branches don't continue the loop",
+                    );
                 }
                 if t && e {
                     assert!(then_changed && else_changed)
                 }
             } else {
-                return false;
+                return self.reject(
+                    entry,
+                    Pattern::DiamondConditional,
+                    "a branch has more than one successor",
+                );
             }
         } else if then_successors != else_successors {
-            return false;
+            return self.reject(
+                entry,
+                Pattern::DiamondConditional,
+                "branches don't join at the same block",
+            );
         }
 
         if self.function.predecessor_blocks(then_node).count() != 1
             || self.function.predecessor_blocks(else_node).count() != 1
         {
-            return false;
+            return self.reject(
+                entry,
+                Pattern::DiamondConditional,
+                "a branch has more than one predecessor",
+            );
         }
 
         let then_block = self.function.remove_block(then_node).unwrap();
@@ -154,7 +217,10 @@ impl GraphStructurer {
         if_stat.else_block = Arc::new(else_block.into());
         Self::simplify_if(if_stat);
 
-        let after = Self::expand_if(if_stat);
+        let mut after = Self::expand_if(if_stat);
+        if after.is_none() && self.guard_clauses {
+            after = Self::guard_clause(if_stat);
+        }
         if if_stat.then_block.lock().is_empty() {
             // TODO: unnecessary clone
             if_stat.condition =
@@ -177,7 +243,7 @@ impl GraphStructurer {
         }
         self.match_jump(entry, exit);
 
-        true
+        self.accept(entry, Pattern::DiamondConditional)
     }
 
     // a -> b -> c + a -> c
@@ -192,15 +258,27 @@ impl GraphStructurer {
             let then_successors = self.function.successor_blocks(then_node).collect_vec();
 
             if then_successors.len() > 1 {
-                return false;
+                return self.reject(
+                    entry,
+                    Pattern::TriangleConditional,
+                    "branch has more than one successor",
+                );
             }
 
             if self.function.predecessor_blocks(then_node).count() != 1 {
-                return false;
+                return self.reject(
+                    entry,
+                    Pattern::TriangleConditional,
+                    "branch has more than one predecessor",
+                );
             }
 
             if !then_successors.is_empty() && then_successors[0] != else_node {
-                return false;
+                return self.reject(
+                    entry,
+                    Pattern::TriangleConditional,
+                    "branch doesn't rejoin the other side of the condition",
+                );
             }
 
             let then_block = self.function.remove_block(then_node).unwrap();
@@ -224,7 +302,7 @@ impl GraphStructurer {
 
             self.match_jump(entry, Some(else_node));
 
-            true
+            self.accept(entry, Pattern::TriangleConditional)
         };
 
         _match_triangle_conditional(then_node, else_node, false)
@@ -346,7 +424,21 @@ impl GraphStructurer {
         let block = self.function.block_mut(entry).unwrap();
         if block.last_mut().unwrap().as_if_mut().is_none() {
             // for loops
-            return false;
+            return self.reject(entry, Pattern::Conditional, "block doesn't end with an if");
+        }
+        // a goto can't jump into the block of an if
+        if [then_node, else_node].into_iter().any(|n| {
+            self.function
+                .block(n)
+                .unwrap()
+                .first()
+                .is_some_and(|s| s.as_label().is_some())
+        }) {
+            return self.reject(
+                entry,
+                Pattern::Conditional,
+                "a branch is the target of a goto",
+            );
         }
 
         self.match_diamond_conditional(entry, then_node, else_node)
@@ -0,0 +1,55 @@
+use cfg::{block::BranchType, function::Function};
+use pipeline::Cancelled;
+use thiserror::Error;
+
+/// Why a function couldn't be structured, its control flow graph breaks an assumption the
+/// structurer makes or structuring was cancelled.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum StructureError {
+    #[error("function has no entry block")]
+    NoEntry,
+    #[error("block {0} has more than two successors")]
+    TooManySuccessors(usize),
+    #[error("block {0} has two successors that aren't a then and an else branch")]
+    InvalidBranches(usize),
+    #[error("block {0} has two successors but doesn't end with a condition")]
+    MissingCondition(usize),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
+}
+
+// checked before structuring so malformed input is reported instead of panicking halfway through
+pub(crate) fn validate(function: &Function) -> Result<(), StructureError> {
+    if function.entry().is_none() {
+        return Err(StructureError::NoEntry);
+    }
+    for (node, block) in function.blocks() {
+        let branch_types = function
+            .graph()
+            .edges(node)
+            .map(|e| &e.weight().branch_type)
+            .collect::<Vec<_>>();
+        match branch_types.len() {
+            0 | 1 => {}
+            2 => {
+                if !branch_types.contains(&&BranchType::Then)
+                    || !branch_types.contains(&&BranchType::Else)
+                {
+                    return Err(StructureError::InvalidBranches(node.index()));
+                }
+                if !matches!(
+                    block.last(),
+                    Some(
+                        ast::Statement::If(_)
+                            | ast::Statement::NumForNext(_)
+                            | ast::Statement::GenericForNext(_)
+                    )
+                ) {
+                    return Err(StructureError::MissingCondition(node.index()));
+                }
+            }
+            _ => return Err(StructureError::TooManySuccessors(node.index())),
+        }
+    }
+    Ok(())
+}
@@ -8,7 +8,9 @@ use petgraph::{
     Direction,
 };
 
-impl super::GraphStructurer {
+use crate::Pattern;
+
+impl super::GraphStructurer<'_> {
     // TODO: STYLE: better name
     // TODO: this is the same as in structuring.rs but w/o block params
     // maybe we can use the same function?
@@ -39,6 +41,7 @@ impl super::GraphStructurer {
                         right: vec![cond],
                         prefix: true,
                         parallel: false,
+                        span: None,
                     }
                     .into(),
                 ),
@@ -58,7 +61,7 @@ impl super::GraphStructurer {
     pub(crate) fn match_jump(&mut self, node: NodeIndex, target: Option<NodeIndex>) -> bool {
         if let Some(target) = target {
             if node == target {
-                return false;
+                return self.reject(node, Pattern::Jump, "block jumps to itself");
             }
             if !self.is_for_next(node) {
                 assert!(self.function.unconditional_edge(node).is_some());
@@ -78,7 +81,7 @@ impl super::GraphStructurer {
                         self.try_remove_unnecessary_condition(source);
                     }
                     self.function.remove_block(node);
-                    true
+                    self.accept(node, Pattern::Jump)
                 } else if self.function.predecessor_blocks(target).count() == 1
                     && !self.function.edges_to_block(node).any(|(t, _)| t == target)
                     && !self
@@ -94,7 +97,7 @@ impl super::GraphStructurer {
                         let block = self.function.remove_block(target).unwrap();
                         self.function.block_mut(node).unwrap().extend(block.0);
                         self.function.set_edges(node, edges);
-                        true
+                        self.accept(node, Pattern::Jump)
                     } else if self.function.entry() != &Some(node) && !self.is_loop_header(node) {
                         // TODO: test
                         for (source, edge) in self
@@ -111,15 +114,23 @@ impl super::GraphStructurer {
                         let mut block = self.function.remove_block(node).unwrap();
                         block.extend(std::mem::take(self.function.block_mut(target).unwrap()).0);
                         *self.function.block_mut(target).unwrap() = block;
-                        true
+                        self.accept(node, Pattern::Jump)
                     } else {
-                        false
+                        self.reject(
+                            node,
+                            Pattern::Jump,
+                            "both blocks are the entry, a loop header or a for loop",
+                        )
                     }
                 } else {
-                    false
+                    self.reject(
+                        node,
+                        Pattern::Jump,
+                        "target has more than one predecessor or is part of a cycle",
+                    )
                 }
             } else {
-                false
+                self.reject(node, Pattern::Jump, "block is a for loop")
             }
         }
         // node is terminating
@@ -154,9 +165,13 @@ impl super::GraphStructurer {
                     );
                 }
                 self.function.remove_block(node);
-                true
+                self.accept(node, Pattern::Jump)
             } else {
-                false
+                self.reject(
+                    node,
+                    Pattern::Jump,
+                    "empty exit block has a predecessor with more than one successor",
+                )
             }
         } else {
             false
@@ -1,7 +1,9 @@
 #![feature(let_chains)]
 
-use cfg::{block::BranchType, function::Function};
+use cfg::{block::BranchType, dot::GraphVisualizer, function::Function};
 use itertools::Itertools;
+use parking_lot::Mutex;
+use pipeline::CancellationToken;
 use rustc_hash::{FxHashMap, FxHashSet};
 
 use petgraph::{
@@ -9,11 +11,20 @@ use petgraph::{
     stable_graph::{EdgeIndex, NodeIndex, StableDiGraph},
     visit::*,
 };
+use triomphe::Arc;
 use tuple::Map;
 
+mod budget;
 mod conditional;
+mod error;
 mod jump;
 mod r#loop;
+mod state_machine;
+mod trace;
+
+pub use budget::{Budget, BudgetExceeded};
+pub use error::StructureError;
+pub use trace::{Decision, Pattern, StructuringTrace};
 
 // TODO: REFACTOR: move
 pub fn post_dominators<N: Default, E: Default>(
@@ -32,35 +43,79 @@ pub fn post_dominators<N: Default, E: Default>(
     res
 }
 
-struct GraphStructurer {
+/// What the structurer falls back to for control flow that can't be structured, e.g. irreducible
+/// loops.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Fallback {
+    /// A `while true do` loop that dispatches on a local, valid in every dialect
+    #[default]
+    StateMachine,
+    /// `goto` and labels for the remaining edges, which need Lua 5.2 or later
+    Goto,
+}
+
+/// How [`lift_with_options`] structures a function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StructureOptions {
+    pub fallback: Fallback,
+    /// When a branch of an if ends in a `return`, `break` or `continue`, write it as a guard clause
+    /// and the other branch after the if instead of in an else block, which reduces nesting
+    pub guard_clauses: bool,
+    pub budget: Budget,
+}
+
+struct GraphStructurer<'a> {
     pub function: Function,
     loop_headers: FxHashSet<NodeIndex>,
     label_to_node: FxHashMap<ast::Label, NodeIndex>,
+    trace: Option<StructuringTrace>,
+    visualizer: Option<&'a mut GraphVisualizer>,
+    fallback: Fallback,
+    guard_clauses: bool,
+    cancellation: Option<CancellationToken>,
+    budget: Budget,
+    spent: budget::Spent,
 }
 
-impl GraphStructurer {
+impl GraphStructurer<'_> {
     fn find_loop_headers(&mut self) {
         self.loop_headers.clear();
-        depth_first_search(
-            self.function.graph(),
-            Some(self.function.entry().unwrap()),
-            |event| {
-                if let DfsEvent::BackEdge(_, header) = event {
-                    self.loop_headers.insert(header);
-                }
-            },
-        );
+        let dominators = simple_fast(self.function.graph(), self.function.entry().unwrap());
+        // an edge is a back edge if its target dominates its source, so only the headers of
+        // natural loops are found and irreducible cycles are left to the goto fallback, which
+        // makes them reducible
+        for edge in self.function.graph().edge_references() {
+            if dominators
+                .dominators(edge.source())
+                .is_some_and(|mut d| d.contains(&edge.target()))
+            {
+                self.loop_headers.insert(edge.target());
+            }
+        }
     }
-    fn new(function: Function) -> Self {
+    fn new(function: Function, options: StructureOptions) -> Self {
         let mut this = Self {
             function,
             loop_headers: FxHashSet::default(),
             label_to_node: FxHashMap::default(),
+            trace: None,
+            visualizer: None,
+            fallback: options.fallback,
+            guard_clauses: options.guard_clauses,
+            cancellation: None,
+            budget: options.budget,
+            spent: Default::default(),
         };
         this.find_loop_headers();
         this
     }
 
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
     fn block_is_no_op(block: &ast::Block) -> bool {
         !block.iter().any(|s| s.as_comment().is_none())
     }
@@ -73,18 +128,16 @@ impl GraphStructurer {
     ) -> bool {
         let successors = self.function.successor_blocks(node).collect_vec();
 
-        // cfg::dot::render_to(&self.function, &mut std::io::stdout()).unwrap();
         if self.try_collapse_loop(node, dominators, post_dom) {
             self.find_loop_headers();
-            // println!("matched loop");
             return true;
         }
 
         if self.try_remove_unnecessary_condition(node) {
-            return true;
+            return self.accept(node, Pattern::Conditional);
         }
 
-        let changed = match successors.len() {
+        match successors.len() {
             0 => false,
             1 => {
                 // remove unnecessary jumps to allow pattern matching
@@ -100,12 +153,7 @@ impl GraphStructurer {
             }
 
             _ => unreachable!(),
-        };
-
-        //println!("after");
-        //dot::render_to(&self.function, &mut std::io::stdout()).unwrap();
-
-        changed
+        }
     }
 
     fn match_blocks(&mut self) -> bool {
@@ -117,20 +165,21 @@ impl GraphStructurer {
         let mut dominators = simple_fast(self.function.graph(), self.function.entry().unwrap());
         let mut post_dom = post_dominators(self.function.graph_mut());
 
-        // cfg::dot::render_to(&self.function, &mut std::io::stdout()).unwrap();
-
         let mut changed = false;
         while let Some(node) = dfs_postorder.next(self.function.graph()) {
-            // println!("matching {:?}", node);
+            if self.is_cancelled() {
+                return false;
+            }
+            log::trace!("matching node {}", node.index());
             let matched = self.try_match_pattern(node, &dominators, &post_dom);
             if matched {
                 dominators = simple_fast(self.function.graph(), self.function.entry().unwrap());
                 post_dom = post_dominators(self.function.graph_mut());
             }
             changed |= matched;
-            // if matched {
-            //     cfg::dot::render_to(&self.function, &mut std::io::stdout()).unwrap();
-            // }
+            if matched && let Some(visualizer) = &mut self.visualizer {
+                visualizer.snapshot(&self.function);
+            }
         }
 
         for node in self
@@ -166,6 +215,7 @@ impl GraphStructurer {
 
     fn insert_goto_for_edge(&mut self, edge: EdgeIndex) {
         let (source, target) = self.function.graph().edge_endpoints(edge).unwrap();
+        self.accept(target, Pattern::Goto);
         if self.function.graph().edge_weight(edge).unwrap().branch_type == BranchType::Unconditional
             && self.function.predecessor_blocks(target).count() == 1
         {
@@ -176,13 +226,7 @@ impl GraphStructurer {
             self.function.block_mut(source).unwrap().extend(block.0);
             self.function.set_edges(source, edges);
         } else {
-            // TODO: make label an Rc and have a global counter for block name
-            let label = ast::Label(format!("l{}", target.index()));
-            let target_block = self.function.block_mut(target).unwrap();
-            if target_block.first().and_then(|s| s.as_label()).is_none() {
-                self.label_to_node.insert(label.clone(), target);
-                target_block.insert(0, label.clone().into());
-            }
+            let label = self.label_block(target);
             let goto_block = self.function.new_block();
             self.function
                 .block_mut(goto_block)
@@ -194,6 +238,61 @@ impl GraphStructurer {
         }
     }
 
+    // the label gotos to `target` jump to, inserted at the start of its block
+    fn label_block(&mut self, target: NodeIndex) -> ast::Label {
+        // TODO: make label an Rc and have a global counter for block name
+        let label = ast::Label(format!("l{}", target.index()));
+        let target_block = self.function.block_mut(target).unwrap();
+        if target_block.first().and_then(|s| s.as_label()).is_none() {
+            self.label_to_node.insert(label.clone(), target);
+            target_block.insert(0, label.clone().into());
+        }
+        label
+    }
+
+    // replaces every edge that's left with a goto without matching any patterns, for when the
+    // budget is exceeded
+    fn insert_remaining_gotos(&mut self) {
+        // for loop headers become conditionals so the gotos can leave the loop like any other edge
+        self.lower_for_loops();
+        for node in self.function.graph().node_indices().collect_vec() {
+            let targets = match self.function.conditional_edges(node) {
+                Some((then_edge, else_edge))
+                    if self
+                        .function
+                        .block(node)
+                        .unwrap()
+                        .last()
+                        .is_some_and(|s| s.as_if().is_some()) =>
+                {
+                    vec![then_edge.target(), else_edge.target()]
+                }
+                // a generic for loop without an init can't be lowered, it's left as it is
+                Some(_) => continue,
+                None => self.function.successor_blocks(node).collect_vec(),
+            };
+            if targets.is_empty() {
+                continue;
+            }
+            let gotos = targets
+                .into_iter()
+                .map(|target| {
+                    self.accept(target, Pattern::Goto);
+                    ast::Statement::from(ast::Goto::new(self.label_block(target)))
+                })
+                .collect_vec();
+            self.function.remove_edges(node);
+            let block = self.function.block_mut(node).unwrap();
+            if let [then_goto, else_goto] = &gotos[..] {
+                let r#if = block.last_mut().unwrap().as_if_mut().unwrap();
+                r#if.then_block = Arc::new(Mutex::new(vec![then_goto.clone()].into()));
+                r#if.else_block = Arc::new(Mutex::new(vec![else_goto.clone()].into()));
+            } else {
+                block.extend(gotos);
+            }
+        }
+    }
+
     fn remove_last_return(block: ast::Block) -> ast::Block {
         if let Some(ast::Statement::Return(last_statement)) = block.last() {
             if last_statement.values.is_empty() {
@@ -206,8 +305,19 @@ impl GraphStructurer {
 
     fn collapse(&mut self) {
         loop {
-            while self.match_blocks() {}
-            if self.function.graph().node_count() == 1 {
+            // a structured graph doesn't need another pass, which could exceed the budget
+            while self.function.graph().node_count() != 1
+                && self.spend_iteration()
+                && self.match_blocks()
+            {}
+            if self.is_cancelled()
+                || self.function.graph().node_count() == 1
+                || (self.fallback == Fallback::StateMachine && self.try_collapse_state_machine())
+            {
+                break;
+            }
+            if self.spent.exceeded.is_some() {
+                self.insert_remaining_gotos();
                 break;
             }
             // last resort refinement
@@ -218,6 +328,9 @@ impl GraphStructurer {
             // to get best output
             let mut changed = false;
             for &edge in &edges {
+                if self.is_cancelled() {
+                    break;
+                }
                 // edge might have been invalidated by a previous iteration due to insert_goto_for_edge
                 // calling remove_block(target)
                 if self.function.graph().edge_weight(edge).is_none() {
@@ -240,6 +353,11 @@ impl GraphStructurer {
 
                 self.insert_goto_for_edge(edge);
                 self.find_loop_headers();
+                // the rest of the edges are left to insert_remaining_gotos
+                if !self.spend_iteration() {
+                    changed = true;
+                    break;
+                }
                 changed = self.match_blocks();
                 if changed {
                     break;
@@ -248,6 +366,9 @@ impl GraphStructurer {
 
             if !changed {
                 for edge in edges {
+                    if self.is_cancelled() {
+                        break;
+                    }
                     // edge might have been invalidated by a previous iteration due to insert_goto_for_edge
                     // calling remove_block(target)
                     if self.function.graph().edge_weight(edge).is_none() {
@@ -255,6 +376,10 @@ impl GraphStructurer {
                     }
                     self.insert_goto_for_edge(edge);
                     self.find_loop_headers();
+                    if !self.spend_iteration() {
+                        changed = true;
+                        break;
+                    }
                     changed = self.match_blocks();
                     if changed {
                         break;
@@ -267,8 +392,45 @@ impl GraphStructurer {
         }
     }
 
-    fn structure(mut self) -> ast::Block {
+    fn structure(mut self) -> (ast::Block, Option<StructuringTrace>) {
+        if let Some(visualizer) = &mut self.visualizer {
+            visualizer.snapshot(&self.function);
+        }
+        self.start_budget();
         self.collapse();
+        let exceeded = self.spent.exceeded;
+        if let Some(exceeded) = exceeded {
+            log::warn!(
+                "function {}: structuring exceeded its budget of {}, the rest was left to the fallback",
+                self.function.id,
+                exceeded
+            );
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.remaining = self
+                .function
+                .graph()
+                .node_indices()
+                .map(|n| n.index())
+                .collect();
+            trace.exceeded = exceeded;
+        }
+        let trace = self.trace.take();
+        let mut block = self.structure_blocks();
+        if let Some(exceeded) = exceeded {
+            block.insert(
+                0,
+                ast::Comment::new(format!(
+                    "warning: structuring exceeded its budget of {}, the rest was left to the fallback",
+                    exceeded
+                ))
+                .into(),
+            );
+        }
+        (block, trace)
+    }
+
+    fn structure_blocks(mut self) -> ast::Block {
         if self.function.graph().node_count() != 1 {
             let mut res_block = ast::Block::default();
             let entry = self.function.entry().unwrap();
@@ -355,6 +517,79 @@ impl GraphStructurer {
     }
 }
 
-pub fn lift(function: cfg::function::Function) -> ast::Block {
-    GraphStructurer::new(function).structure()
+// validates `function` and structures it as `options` say, `configure` sets up what the entry
+// points add on top, e.g. a trace or a visualizer
+fn structure<'a>(
+    function: cfg::function::Function,
+    options: StructureOptions,
+    configure: impl FnOnce(&mut GraphStructurer<'a>),
+) -> Result<(ast::Block, Option<StructuringTrace>), StructureError> {
+    error::validate(&function)?;
+    let mut structurer = GraphStructurer::new(function, options);
+    configure(&mut structurer);
+    Ok(structurer.structure())
+}
+
+pub fn lift(function: cfg::function::Function) -> Result<ast::Block, StructureError> {
+    lift_with_options(function, StructureOptions::default())
+}
+
+/// Like [`lift`], but what can't be structured is left to `fallback` instead of a state machine.
+pub fn lift_with_fallback(
+    function: cfg::function::Function,
+    fallback: Fallback,
+) -> Result<ast::Block, StructureError> {
+    lift_with_options(
+        function,
+        StructureOptions {
+            fallback,
+            ..Default::default()
+        },
+    )
+}
+
+/// Like [`lift`], but structured as `options` say.
+pub fn lift_with_options(
+    function: cfg::function::Function,
+    options: StructureOptions,
+) -> Result<ast::Block, StructureError> {
+    Ok(structure(function, options, |_| {})?.0)
+}
+
+/// Like [`lift_with_options`], but `cancellation` is checked before every pattern is matched and
+/// [`StructureError::Cancelled`] is returned once it's cancelled, so a function whose structuring
+/// takes long can be abandoned.
+pub fn lift_with_cancellation(
+    function: cfg::function::Function,
+    options: StructureOptions,
+    cancellation: &CancellationToken,
+) -> Result<ast::Block, StructureError> {
+    let (block, _) = structure(function, options, |structurer| {
+        structurer.cancellation = Some(cancellation.clone())
+    })?;
+    // the block is only partially structured if matching stopped early
+    cancellation.check()?;
+    Ok(block)
+}
+
+/// Like [`lift`], but also returns every structuring decision, including why patterns didn't match.
+pub fn lift_with_trace(
+    function: cfg::function::Function,
+) -> Result<(ast::Block, StructuringTrace), StructureError> {
+    let (block, trace) = structure(function, StructureOptions::default(), |structurer| {
+        structurer.trace = Some(StructuringTrace::default())
+    })?;
+    Ok((block, trace.unwrap()))
+}
+
+/// Like [`lift`], but writes a snapshot of the control flow graph with `visualizer` before
+/// structuring and after every pattern that matched.
+pub fn lift_with_snapshots(
+    function: cfg::function::Function,
+    visualizer: &mut GraphVisualizer,
+) -> Result<ast::Block, StructureError> {
+    let (block, _) = structure(function, StructureOptions::default(), |structurer| {
+        structurer.visualizer = Some(visualizer)
+    })?;
+    Ok(block)
 }
@@ -1,14 +1,64 @@
 use array_tool::vec::Intersect;
-use ast::{Reduce, SideEffects};
+use ast::{LocalRw, Reduce, SideEffects};
 use cfg::block::{BlockEdge, BranchType};
 use itertools::Itertools;
 use rustc_hash::FxHashSet;
 use tuple::Map;
 
-use crate::GraphStructurer;
+use crate::{GraphStructurer, Pattern};
 use petgraph::{algo::dominators::Dominators, stable_graph::NodeIndex, visit::EdgeRef};
 
-impl GraphStructurer {
+fn reads(block: &ast::Block, local: &ast::RcLocal) -> usize {
+    block
+        .iter()
+        .map(|statement| {
+            let nested = match statement {
+                ast::Statement::If(r#if) => {
+                    reads(&r#if.then_block.lock(), local) + reads(&r#if.else_block.lock(), local)
+                }
+                ast::Statement::While(r#while) => reads(&r#while.block.lock(), local),
+                ast::Statement::Repeat(repeat) => reads(&repeat.block.lock(), local),
+                ast::Statement::NumericFor(numeric_for) => reads(&numeric_for.block.lock(), local),
+                ast::Statement::GenericFor(generic_for) => reads(&generic_for.block.lock(), local),
+                _ => 0,
+            };
+            statement
+                .values_read()
+                .into_iter()
+                .filter(|&l| l == local)
+                .count()
+                + nested
+        })
+        .sum()
+}
+
+// lua 5.1 copies the internal counter to the loop variable at the start of every iteration,
+// use the loop variable as the counter when that copy is the only read of the internal one
+fn numeric_for(
+    for_init: ast::NumForInit,
+    num_for_next: ast::NumForNext,
+    mut body: ast::Block,
+) -> ast::NumericFor {
+    let mut counter = num_for_next.counter.0.as_local().unwrap().clone();
+    if let Some(ast::Statement::Assign(assign)) = body.first()
+        && let [ast::LValue::Local(variable)] = &assign.left[..]
+        && let [ast::RValue::Local(source)] = &assign.right[..]
+        && source == &counter
+        && reads(&body, &counter) == 1
+    {
+        counter = variable.clone();
+        body.remove(0);
+    }
+    ast::NumericFor::new(
+        for_init.counter.1,
+        for_init.limit.1,
+        for_init.step.1,
+        counter,
+        body,
+    )
+}
+
+impl GraphStructurer<'_> {
     pub(crate) fn is_loop_header(&self, node: NodeIndex) -> bool {
         self.loop_headers.contains(&node)
     }
@@ -77,14 +127,22 @@ impl GraphStructurer {
                 let then_successors = self.function.successor_blocks(then_node).collect_vec();
 
                 if then_successors.len() > 1 {
-                    return false;
+                    return self.reject(
+                        header,
+                        Pattern::ForLoop,
+                        "loop body has more than one successor",
+                    );
                 }
 
                 let (init_block, init_index) = self.find_for_init(header);
                 if then_node != else_node
                     && self.function.predecessor_blocks(then_node).count() != 1
                 {
-                    return false;
+                    return self.reject(
+                        header,
+                        Pattern::ForLoop,
+                        "loop body has more than one predecessor",
+                    );
                 }
 
                 let else_successors = self.function.successor_blocks(else_node).collect_vec();
@@ -92,7 +150,11 @@ impl GraphStructurer {
                     && !(else_successors.len() == 1 && then_successors[0] == else_successors[0])
                     && !(then_successors[0] == header && else_node == init_block)
                 {
-                    return false;
+                    return self.reject(
+                        header,
+                        Pattern::ForLoop,
+                        "loop body doesn't continue to the loop exit",
+                    );
                 }
 
                 let statement = self.function.block_mut(header).unwrap().pop().unwrap();
@@ -113,14 +175,7 @@ impl GraphStructurer {
                 let new_stat = match statement {
                     ast::Statement::NumForNext(num_for_next) => {
                         let for_init = init_ast.remove(init_index).into_num_for_init().unwrap();
-                        ast::NumericFor::new(
-                            for_init.counter.1,
-                            for_init.limit.1,
-                            for_init.step.1,
-                            num_for_next.counter.0.as_local().unwrap().clone(),
-                            body_ast,
-                        )
-                        .into()
+                        numeric_for(for_init, num_for_next, body_ast).into()
                     }
                     ast::Statement::GenericForNext(generic_for_next) => {
                         let for_init = init_ast.remove(init_index).into_generic_for_init().unwrap();
@@ -148,7 +203,7 @@ impl GraphStructurer {
                 );
 
                 self.match_jump(init_block, Some(else_node));
-                return true;
+                return self.accept(header, Pattern::ForLoop);
             }
             return false;
         }
@@ -223,14 +278,7 @@ impl GraphStructurer {
                 let new_stat = match statement {
                     ast::Statement::NumForNext(num_for_next) => {
                         let for_init = init_ast.remove(init_index).into_num_for_init().unwrap();
-                        ast::NumericFor::new(
-                            for_init.counter.1,
-                            for_init.limit.1,
-                            for_init.step.1,
-                            num_for_next.counter.0.as_local().unwrap().clone(),
-                            body_ast,
-                        )
-                        .into()
+                        numeric_for(for_init, num_for_next, body_ast).into()
                     }
                     ast::Statement::GenericForNext(generic_for_next) => {
                         let for_init = init_ast.remove(init_index).into_generic_for_init().unwrap();
@@ -266,7 +314,7 @@ impl GraphStructurer {
                 self.match_jump(init_block, next);
             }
 
-            true
+            self.accept(header, Pattern::Loop)
         } else if successors.len() == 2 {
             //if successors.iter().find(|s| self.function.successor_blocks(s).exactly_one() == Ok())
             let (mut next, mut body) = (successors[0], successors[1]);
@@ -290,7 +338,11 @@ impl GraphStructurer {
                     .count()
                     != 1
                 {
-                    return false;
+                    return self.reject(
+                        header,
+                        Pattern::Loop,
+                        "neither successor has a single predecessor",
+                    );
                 }
             }
             let continues = self
@@ -380,7 +432,12 @@ impl GraphStructurer {
                 })
                 && self.function.successor_blocks(body).exactly_one().ok() != Some(header)
             {
-                return changed;
+                return self.record(
+                    header,
+                    Pattern::Loop,
+                    changed,
+                    "loop is entered from a block that bypasses its body",
+                );
             }
 
             let next = if self.function.successor_blocks(body).exactly_one().ok() == Some(header)
@@ -461,7 +518,7 @@ impl GraphStructurer {
                         vec![(next, BlockEdge::new(BranchType::Unconditional))],
                     );
                     self.match_jump(header, Some(next));
-                    return true;
+                    return self.accept(header, Pattern::Loop);
                 } else {
                     let statements =
                         std::mem::take(&mut self.function.block_mut(header).unwrap().0);
@@ -474,14 +531,7 @@ impl GraphStructurer {
                     let new_stat = match statement {
                         ast::Statement::NumForNext(num_for_next) => {
                             let for_init = init_ast.remove(init_index).into_num_for_init().unwrap();
-                            ast::NumericFor::new(
-                                for_init.counter.1,
-                                for_init.limit.1,
-                                for_init.step.1,
-                                num_for_next.counter.0.as_local().unwrap().clone(),
-                                body_ast,
-                            )
-                            .into()
+                            numeric_for(for_init, num_for_next, body_ast).into()
                         }
                         ast::Statement::GenericForNext(generic_for_next) => {
                             let for_init =
@@ -511,10 +561,15 @@ impl GraphStructurer {
                         vec![(next, BlockEdge::new(BranchType::Unconditional))],
                     );
                     self.match_jump(init_block, Some(next));
-                    return true;
+                    return self.accept(header, Pattern::ForLoop);
                 }
             }
-            changed
+            self.record(
+                header,
+                Pattern::Loop,
+                changed,
+                "loop body doesn't end by jumping back to the header",
+            )
         } else if let Ok(&body) = successors.iter().exactly_one()
             && self
                 .function
@@ -532,9 +587,13 @@ impl GraphStructurer {
                 .unwrap()
                 .push(ast::While::new(ast::Literal::Boolean(true).into(), body_block).into());
             self.function.set_edges(header, Vec::new());
-            true
+            self.accept(header, Pattern::Loop)
         } else {
-            false
+            self.reject(
+                header,
+                Pattern::Loop,
+                "loop body doesn't end by jumping back to the header",
+            )
         }
     }
 }
@@ -0,0 +1,259 @@
+use itertools::Itertools;
+use petgraph::{stable_graph::NodeIndex, visit::EdgeRef};
+use rustc_hash::FxHashMap;
+use triomphe::Arc;
+
+use crate::{GraphStructurer, Pattern};
+
+fn set_state(state: &ast::RcLocal, value: usize) -> ast::Statement {
+    ast::Assign::new(
+        vec![state.clone().into()],
+        vec![ast::Literal::Number(value as f64).into()],
+    )
+    .into()
+}
+
+fn binary(left: ast::RValue, right: ast::RValue, operation: ast::BinaryOperation) -> ast::RValue {
+    ast::Binary::new(left, right, operation).into()
+}
+
+// lua 5.1's FORPREP subtracts the step once, so the header can add it before every test
+fn lower_num_for_init(init: ast::NumForInit) -> Vec<ast::Statement> {
+    let counter = init.counter.0.as_local().unwrap().clone();
+    let step = init.step.0.as_local().unwrap().clone();
+    vec![
+        ast::Assign::new(
+            vec![init.counter.0, init.limit.0, init.step.0],
+            vec![init.counter.1, init.limit.1, init.step.1],
+        )
+        .into(),
+        ast::Assign::new(
+            vec![counter.clone().into()],
+            vec![binary(
+                counter.into(),
+                step.into(),
+                ast::BinaryOperation::Sub,
+            )],
+        )
+        .into(),
+    ]
+}
+
+fn lower_num_for_next(next: ast::NumForNext) -> Vec<ast::Statement> {
+    let counter = next.counter.0.as_local().unwrap().clone();
+    let ascending = binary(
+        binary(
+            ast::Literal::Number(0.0).into(),
+            next.step.clone(),
+            ast::BinaryOperation::LessThan,
+        ),
+        binary(
+            counter.clone().into(),
+            next.limit.clone(),
+            ast::BinaryOperation::LessThanOrEqual,
+        ),
+        ast::BinaryOperation::And,
+    );
+    let descending = binary(
+        binary(
+            next.step.clone(),
+            ast::Literal::Number(0.0).into(),
+            ast::BinaryOperation::LessThanOrEqual,
+        ),
+        binary(
+            next.limit,
+            counter.clone().into(),
+            ast::BinaryOperation::LessThanOrEqual,
+        ),
+        ast::BinaryOperation::And,
+    );
+    vec![
+        ast::Assign::new(
+            vec![counter.clone().into()],
+            vec![binary(counter.into(), next.step, ast::BinaryOperation::Add)],
+        )
+        .into(),
+        ast::If::new(
+            binary(ascending, descending, ast::BinaryOperation::Or),
+            ast::Block::default(),
+            ast::Block::default(),
+        )
+        .into(),
+    ]
+}
+
+// `control` is the internal control local the loop's GenericForInit assigns
+fn lower_generic_for_next(next: ast::GenericForNext, control: ast::RcLocal) -> Vec<ast::Statement> {
+    let first = next.res_locals[0].as_local().unwrap().clone();
+    vec![
+        ast::Assign::new(
+            next.res_locals,
+            vec![ast::Call::new(next.generator, vec![next.state, control.clone().into()]).into()],
+        )
+        .into(),
+        ast::Assign::new(vec![control.clone().into()], vec![first.into()]).into(),
+        ast::If::new(
+            binary(
+                control.into(),
+                ast::Literal::Nil.into(),
+                ast::BinaryOperation::NotEqual,
+            ),
+            ast::Block::default(),
+            ast::Block::default(),
+        )
+        .into(),
+    ]
+}
+
+impl GraphStructurer<'_> {
+    fn generic_for_control(&self, header: NodeIndex) -> Option<ast::RcLocal> {
+        self.function
+            .predecessor_blocks(header)
+            .unique()
+            .filter_map(|p| {
+                self.function
+                    .block(p)
+                    .unwrap()
+                    .iter()
+                    .rev()
+                    .find_map(|s| s.as_generic_for_init())
+            })
+            .exactly_one()
+            .ok()
+            .map(|init| init.0.left[2].as_local().unwrap().clone())
+    }
+
+    // turns the remaining for loops into while loops made of conditionals, so their headers can
+    // be split from the loop like any other node. returns false, without changing anything, if
+    // the init of a generic for loop can't be found, since it assigns the control local
+    pub(crate) fn lower_for_loops(&mut self) -> bool {
+        let nodes = self.function.graph().node_indices().collect_vec();
+        let mut controls = FxHashMap::default();
+        for &node in &nodes {
+            if matches!(
+                self.function.block(node).unwrap().first(),
+                Some(ast::Statement::GenericForNext(_))
+            ) {
+                match self.generic_for_control(node) {
+                    Some(control) => controls.insert(node, control),
+                    None => return false,
+                };
+            }
+        }
+        for node in nodes {
+            let block = self.function.block_mut(node).unwrap();
+            for statement in std::mem::take(&mut block.0) {
+                match statement {
+                    ast::Statement::NumForInit(init) => block.extend(lower_num_for_init(init)),
+                    ast::Statement::NumForNext(next) => block.extend(lower_num_for_next(next)),
+                    ast::Statement::GenericForInit(init) => block.push(init.0.into()),
+                    ast::Statement::GenericForNext(next) => {
+                        block.extend(lower_generic_for_next(next, controls[&node].clone()))
+                    }
+                    statement => block.push(statement),
+                }
+            }
+        }
+        true
+    }
+
+    // last resort for graphs that can't be collapsed without gotos, e.g. irreducible loops.
+    // every remaining node becomes a state of a `while true do` loop that dispatches on a local.
+    // the states are checked in order, so a node that continues to a later state runs it in the
+    // same iteration, which is equivalent and saves nesting them in an if-else chain.
+    pub(crate) fn try_collapse_state_machine(&mut self) -> bool {
+        let entry = self.function.entry().unwrap();
+        let nodes = std::iter::once(entry)
+            .chain(
+                self.function
+                    .graph()
+                    .node_indices()
+                    .filter(|&n| n != entry)
+                    .sorted(),
+            )
+            .collect_vec();
+        if !self.lower_for_loops() {
+            return self.reject(entry, Pattern::StateMachine, "generic for loop has no init");
+        }
+        if let Some(&node) = nodes.iter().find(|&&n| {
+            self.function.successor_blocks(n).count() == 2
+                && self
+                    .function
+                    .block(n)
+                    .unwrap()
+                    .last()
+                    .unwrap()
+                    .as_if()
+                    .is_none()
+        }) {
+            return self.reject(
+                node,
+                Pattern::StateMachine,
+                "node has two successors but no condition",
+            );
+        }
+
+        let states = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, &n)| (n, i + 1))
+            .collect::<FxHashMap<_, _>>();
+        let state = ast::RcLocal::default();
+        let mut dispatch = ast::Block::default();
+        // the edges are removed along with the blocks
+        let successors = nodes
+            .iter()
+            .map(|&node| match self.function.conditional_edges(node) {
+                Some((then_edge, else_edge)) => vec![then_edge.target(), else_edge.target()],
+                None => self.function.successor_blocks(node).collect_vec(),
+            })
+            .collect_vec();
+        for (&node, successors) in nodes.iter().zip(successors) {
+            let mut block = self.function.remove_block(node).unwrap();
+            match successors[..] {
+                [] => {
+                    if !matches!(block.last(), Some(ast::Statement::Return(_))) {
+                        block.push(ast::Break {}.into());
+                    }
+                }
+                [target] => block.push(set_state(&state, states[&target])),
+                [then_target, else_target] => {
+                    let r#if = block.last_mut().unwrap().as_if_mut().unwrap();
+                    let then_block: ast::Block =
+                        vec![set_state(&state, states[&then_target])].into();
+                    let else_block: ast::Block =
+                        vec![set_state(&state, states[&else_target])].into();
+                    r#if.then_block = Arc::new(then_block.into());
+                    r#if.else_block = Arc::new(else_block.into());
+                }
+                _ => unreachable!(),
+            }
+            dispatch.push(
+                ast::If::new(
+                    ast::Binary::new(
+                        state.clone().into(),
+                        ast::Literal::Number(states[&node] as f64).into(),
+                        ast::BinaryOperation::Equal,
+                    )
+                    .into(),
+                    block,
+                    ast::Block::default(),
+                )
+                .into(),
+            );
+        }
+
+        let mut init = ast::Assign::new(
+            vec![state.clone().into()],
+            vec![ast::Literal::Number(1.0).into()],
+        );
+        init.prefix = true;
+        let new_entry = self.function.new_block();
+        self.function.block_mut(new_entry).unwrap().extend([
+            init.into(),
+            ast::While::new(ast::Literal::Boolean(true).into(), dispatch).into(),
+        ]);
+        self.function.set_entry(new_entry);
+        self.accept(new_entry, Pattern::StateMachine)
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+
+use itertools::Itertools;
+use petgraph::stable_graph::NodeIndex;
+
+use crate::{BudgetExceeded, GraphStructurer};
+
+/// A pattern the structurer tries to collapse a node with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    Loop,
+    ForLoop,
+    Jump,
+    Conditional,
+    DiamondConditional,
+    TriangleConditional,
+    /// Last resort, the edge to this node is replaced with a goto
+    Goto,
+    /// Tried before gotos unless they are the [fallback](crate::Fallback), the remaining nodes
+    /// become states of a loop that dispatches on a local
+    StateMachine,
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Loop => write!(f, "loop"),
+            Self::ForLoop => write!(f, "for loop"),
+            Self::Jump => write!(f, "jump"),
+            Self::Conditional => write!(f, "conditional"),
+            Self::DiamondConditional => write!(f, "diamond conditional"),
+            Self::TriangleConditional => write!(f, "triangle conditional"),
+            Self::Goto => write!(f, "goto"),
+            Self::StateMachine => write!(f, "state machine"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub node: usize,
+    pub pattern: Pattern,
+    /// Why the pattern didn't match, `None` if it did
+    pub rejected: Option<&'static str>,
+}
+
+/// Every pattern the structurer tried, in order, and the nodes it couldn't collapse.
+#[derive(Debug, Clone, Default)]
+pub struct StructuringTrace {
+    pub decisions: Vec<Decision>,
+    /// Nodes left when structuring finished, the function was fully structured if there is only one
+    pub remaining: Vec<usize>,
+    /// The limit of the [`Budget`](crate::Budget) that stopped pattern matching early, if any
+    pub exceeded: Option<BudgetExceeded>,
+}
+
+impl StructuringTrace {
+    pub fn is_structured(&self) -> bool {
+        self.remaining.len() <= 1
+    }
+}
+
+impl fmt::Display for StructuringTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for decision in &self.decisions {
+            match decision.rejected {
+                Some(reason) => writeln!(
+                    f,
+                    "node {}: {} rejected, {}",
+                    decision.node, decision.pattern, reason
+                )?,
+                None => writeln!(f, "node {}: {} matched", decision.node, decision.pattern)?,
+            }
+        }
+        if let Some(exceeded) = self.exceeded {
+            writeln!(
+                f,
+                "stopped matching after exceeding the budget of {}",
+                exceeded
+            )?;
+        }
+        if self.is_structured() {
+            return Ok(());
+        }
+        writeln!(
+            f,
+            "failed to collapse, {} nodes remain",
+            self.remaining.len()
+        )?;
+        // the reason each remaining node was last rejected for is usually what needs fixing
+        for &node in &self.remaining {
+            write!(f, "node {}:", node)?;
+            let rejections = self
+                .decisions
+                .iter()
+                .rev()
+                .filter(|d| d.node == node)
+                .filter_map(|d| Some((d.pattern, d.rejected?)))
+                .unique_by(|&(pattern, _)| pattern)
+                .collect::<Vec<_>>();
+            if rejections.is_empty() {
+                write!(f, " no pattern applies")?;
+            }
+            for (pattern, reason) in rejections {
+                write!(f, " {} rejected, {};", pattern, reason)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl GraphStructurer<'_> {
+    pub(crate) fn record(
+        &mut self,
+        node: NodeIndex,
+        pattern: Pattern,
+        matched: bool,
+        reason: &'static str,
+    ) -> bool {
+        if matched {
+            log::trace!("node {}: {} matched", node.index(), pattern);
+        } else {
+            log::trace!("node {}: {} rejected, {}", node.index(), pattern, reason);
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.decisions.push(Decision {
+                node: node.index(),
+                pattern,
+                rejected: (!matched).then_some(reason),
+            });
+        }
+        matched
+    }
+
+    pub(crate) fn accept(&mut self, node: NodeIndex, pattern: Pattern) -> bool {
+        self.record(node, pattern, true, "")
+    }
+
+    pub(crate) fn reject(
+        &mut self,
+        node: NodeIndex,
+        pattern: Pattern,
+        reason: &'static str,
+    ) -> bool {
+        self.record(node, pattern, false, reason)
+    }
+}
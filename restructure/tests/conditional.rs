@@ -0,0 +1,106 @@
+use cfg::{
+    block::{BlockEdge, BranchType},
+    function::Function,
+};
+use restructure::{lift_with_options, StructureOptions};
+
+fn call(name: &str) -> ast::Statement {
+    ast::Call::new(ast::Global::from(name).into(), Vec::new()).into()
+}
+
+fn return_one() -> ast::Statement {
+    ast::Return::new(vec![ast::Literal::Number(1.0).into()]).into()
+}
+
+// `if a then <then_statements> else <else_statements> end`, where neither branch continues
+fn branches(
+    then_statements: Vec<ast::Statement>,
+    else_statements: Vec<ast::Statement>,
+) -> Function {
+    let mut function = Function::new(0);
+    let entry = function.new_block();
+    let then_node = function.new_block();
+    let else_node = function.new_block();
+    function.set_entry(entry);
+
+    function.block_mut(entry).unwrap().push(
+        ast::If::new(
+            ast::Global::from("a").into(),
+            ast::Block::default(),
+            ast::Block::default(),
+        )
+        .into(),
+    );
+    function.set_edges(
+        entry,
+        vec![
+            (then_node, BlockEdge::new(BranchType::Then)),
+            (else_node, BlockEdge::new(BranchType::Else)),
+        ],
+    );
+    function
+        .block_mut(then_node)
+        .unwrap()
+        .extend(then_statements);
+    function
+        .block_mut(else_node)
+        .unwrap()
+        .extend(else_statements);
+    function
+}
+
+fn lift_with_guard_clauses(function: Function, guard_clauses: bool) -> ast::Block {
+    lift_with_options(
+        function,
+        StructureOptions {
+            guard_clauses,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn guard_clause() {
+    let block = lift_with_guard_clauses(
+        branches(vec![call("f"), return_one()], vec![call("g"), call("h")]),
+        true,
+    );
+    assert_eq!(block.len(), 3, "{}", block);
+    let r#if = block[0].as_if().unwrap();
+    assert_eq!(r#if.condition, ast::Global::from("a").into());
+    assert_eq!(r#if.then_block.lock().len(), 2);
+    assert!(r#if.else_block.lock().is_empty());
+    assert_eq!(block[1], call("g"));
+    assert_eq!(block[2], call("h"));
+}
+
+#[test]
+fn negated_guard_clause() {
+    let block = lift_with_guard_clauses(
+        branches(vec![call("f"), call("g")], vec![return_one()]),
+        true,
+    );
+    assert_eq!(block.len(), 3, "{}", block);
+    let r#if = block[0].as_if().unwrap();
+    assert_eq!(
+        r#if.condition,
+        ast::Unary::new(ast::Global::from("a").into(), ast::UnaryOperation::Not).into()
+    );
+    assert_eq!(*r#if.then_block.lock(), vec![return_one()].into());
+    assert!(r#if.else_block.lock().is_empty());
+    assert_eq!(block[1], call("f"));
+    assert_eq!(block[2], call("g"));
+}
+
+#[test]
+fn guard_clauses_off() {
+    let block = lift_with_guard_clauses(
+        branches(vec![call("f"), return_one()], vec![call("g"), call("h")]),
+        false,
+    );
+    assert_eq!(block.len(), 1, "{}", block);
+    let r#if = block[0].as_if().unwrap();
+    assert_eq!(r#if.then_block.lock().len(), 2);
+    assert_eq!(r#if.else_block.lock().len(), 2);
+}
@@ -0,0 +1,231 @@
+use cfg::{
+    block::{BlockEdge, BranchType},
+    function::Function,
+};
+use petgraph::stable_graph::NodeIndex;
+use restructure::{
+    lift_with_options, lift_with_trace, Budget, Fallback, Pattern, StructureOptions,
+    StructuringTrace,
+};
+
+fn local(name: &str) -> ast::RcLocal {
+    ast::RcLocal::new(ast::Local::new(Some(name.into())))
+}
+
+fn call(name: &str) -> ast::Statement {
+    ast::Call::new(ast::Global::from(name).into(), Vec::new()).into()
+}
+
+fn condition(name: &str) -> ast::Statement {
+    ast::If::new(
+        ast::Global::from(name).into(),
+        ast::Block::default(),
+        ast::Block::default(),
+    )
+    .into()
+}
+
+fn jump(function: &mut Function, node: NodeIndex, target: NodeIndex) {
+    function.set_edges(
+        node,
+        vec![(target, BlockEdge::new(BranchType::Unconditional))],
+    );
+}
+
+fn branch(function: &mut Function, node: NodeIndex, then: NodeIndex, r#else: NodeIndex) {
+    function.set_edges(
+        node,
+        vec![
+            (then, BlockEdge::new(BranchType::Then)),
+            (r#else, BlockEdge::new(BranchType::Else)),
+        ],
+    );
+}
+
+fn state_machine_matched(trace: &StructuringTrace) -> bool {
+    trace
+        .decisions
+        .iter()
+        .any(|d| d.pattern == Pattern::StateMachine && d.rejected.is_none())
+}
+
+// every goto jumps to a label that's in the output
+fn assert_gotos_resolve(output: &str) {
+    let targets = output
+        .split("goto ")
+        .skip(1)
+        .map(|s| s.split(|c: char| !c.is_alphanumeric()).next().unwrap())
+        .collect::<Vec<_>>();
+    assert!(!targets.is_empty(), "{}", output);
+    for target in targets {
+        assert_eq!(
+            output.matches(&format!("::{}::", target)).count(),
+            1,
+            "{}",
+            output
+        );
+    }
+}
+
+// the entry branches into `body` and, through the init, into `header`, so neither dominates the
+// loop they form. `header` ends with `header_statement`, with a then edge into `body` and an else
+// edge to the exit
+fn irreducible_for_loop(init: Vec<ast::Statement>, header_statement: ast::Statement) -> Function {
+    let mut function = Function::new(0);
+    let entry = function.new_block();
+    let init_block = function.new_block();
+    let header = function.new_block();
+    let body = function.new_block();
+    let exit = function.new_block();
+    function.set_entry(entry);
+
+    function.block_mut(entry).unwrap().push(condition("a"));
+    branch(&mut function, entry, init_block, body);
+    function.block_mut(init_block).unwrap().extend(init);
+    jump(&mut function, init_block, header);
+    function.block_mut(header).unwrap().push(header_statement);
+    branch(&mut function, header, body, exit);
+    function.block_mut(body).unwrap().push(call("f"));
+    jump(&mut function, body, header);
+    function.block_mut(exit).unwrap().push(call("h"));
+    function
+}
+
+// the entry branches into both `first` and `second`, which jump to each other
+fn irreducible_loop() -> Function {
+    let mut function = Function::new(0);
+    let entry = function.new_block();
+    let first = function.new_block();
+    let second = function.new_block();
+    let exit = function.new_block();
+    function.set_entry(entry);
+
+    function.block_mut(entry).unwrap().push(condition("a"));
+    branch(&mut function, entry, first, second);
+    function.block_mut(first).unwrap().push(call("f"));
+    jump(&mut function, first, second);
+    function
+        .block_mut(second)
+        .unwrap()
+        .extend([call("g"), condition("b")]);
+    branch(&mut function, second, first, exit);
+    function.block_mut(exit).unwrap().push(call("h"));
+    function
+}
+
+#[test]
+fn irreducible_loop_state_machine() {
+    let (block, trace) = lift_with_trace(irreducible_loop()).unwrap();
+    assert!(state_machine_matched(&trace), "{}", trace);
+    assert!(trace.is_structured());
+    let output = block.to_string();
+    assert!(output.contains("while true do"), "{}", output);
+    assert!(!output.contains("goto"), "{}", output);
+    for name in ["f()", "g()", "h()"] {
+        assert_eq!(output.matches(name).count(), 1, "{}", output);
+    }
+}
+
+#[test]
+fn irreducible_loop_gotos() {
+    let block = lift_with_options(
+        irreducible_loop(),
+        StructureOptions {
+            fallback: Fallback::Goto,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let output = block.to_string();
+    assert_gotos_resolve(&output);
+    for name in ["f()", "g()", "h()"] {
+        assert_eq!(output.matches(name).count(), 1, "{}", output);
+    }
+}
+
+#[test]
+fn irreducible_numeric_for_loop() {
+    let (counter, limit, step) = (local("i"), local("n"), local("s"));
+    let function = irreducible_for_loop(
+        vec![ast::NumForInit::new(counter.clone(), limit.clone(), step.clone()).into()],
+        ast::NumForNext::new(counter, limit.into(), step.into()).into(),
+    );
+
+    let (block, trace) = lift_with_trace(function).unwrap();
+    assert!(state_machine_matched(&trace), "{}", trace);
+    assert!(trace.is_structured());
+    let output = block.to_string();
+    assert!(output.contains("while true do"), "{}", output);
+    assert!(!output.contains("NumFor"), "{}", output);
+    assert!(output.contains("i = i - s"), "{}", output);
+    assert!(output.contains("i = i + s"), "{}", output);
+}
+
+#[test]
+fn irreducible_generic_for_loop() {
+    let (generator, state, control) = (local("g"), local("t"), local("c"));
+    let (key, value) = (local("k"), local("v"));
+    let function = irreducible_for_loop(
+        vec![ast::GenericForInit::new(generator.clone(), state.clone(), control).into()],
+        ast::GenericForNext::new(vec![key, value], generator.into(), state).into(),
+    );
+
+    let (block, trace) = lift_with_trace(function).unwrap();
+    assert!(state_machine_matched(&trace), "{}", trace);
+    assert!(trace.is_structured());
+    let output = block.to_string();
+    assert!(output.contains("while true do"), "{}", output);
+    assert!(!output.contains("GenericFor"), "{}", output);
+    assert!(output.contains("k, v = g(t, c)"), "{}", output);
+    assert!(output.contains("c = k"), "{}", output);
+}
+
+#[test]
+fn for_loop_without_budget() {
+    let (counter, limit, step) = (local("i"), local("n"), local("s"));
+    let mut function = Function::new(0);
+    let init = function.new_block();
+    let header = function.new_block();
+    let body = function.new_block();
+    let exit = function.new_block();
+    function.set_entry(init);
+
+    function
+        .block_mut(init)
+        .unwrap()
+        .push(ast::NumForInit::new(counter.clone(), limit.clone(), step.clone()).into());
+    jump(&mut function, init, header);
+    function
+        .block_mut(header)
+        .unwrap()
+        .push(ast::NumForNext::new(counter, limit.into(), step.into()).into());
+    branch(&mut function, header, body, exit);
+    function.block_mut(body).unwrap().push(call("f"));
+    jump(&mut function, body, header);
+    function.block_mut(exit).unwrap().push(call("g"));
+
+    let block = lift_with_options(
+        function,
+        StructureOptions {
+            fallback: Fallback::Goto,
+            budget: Budget {
+                max_iterations: Some(0),
+                ..Budget::UNLIMITED
+            },
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let output = block.to_string();
+    assert!(
+        output.contains("exceeded its budget of 0 passes"),
+        "{}",
+        output
+    );
+    assert!(!output.contains("NumFor"), "{}", output);
+    assert!(output.contains("i = i + s"), "{}", output);
+    assert_gotos_resolve(&output);
+    for name in ["f()", "g()"] {
+        assert_eq!(output.matches(name).count(), 1, "{}", output);
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use crate::{Instance, RobloxError};
+
+pub(crate) const MAGIC: &[u8] = b"<roblox!\x89\xff\x0d\x0a\x1a\x0a";
+// the magic, a version, the number of classes and instances and 8 reserved bytes
+const HEADER_LEN: usize = MAGIC.len() + 2 + 4 + 4 + 8;
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+// the type of string properties, which is also how protected strings like sources are stored
+const STRING_TYPE: u8 = 0x01;
+// lz4 can't compress data to less than about 1/255 of its length
+const LZ4_MAX_RATIO: usize = 255;
+
+// reads the fields of a chunk, which is truncated if it ends before one of them
+struct Reader<'a, 'b> {
+    chunk: &'b str,
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a, '_> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], RobloxError> {
+        if self.data.len() < len {
+            return Err(RobloxError::Truncated(self.chunk.to_string()));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, RobloxError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, RobloxError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], RobloxError> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    // integers are stored big endian with their bytes interleaved, the first byte of every
+    // integer followed by the second and so on, and zigzag encoded
+    fn interleaved_i32s(&mut self, count: usize) -> Result<Vec<i32>, RobloxError> {
+        let len = count
+            .checked_mul(4)
+            .ok_or_else(|| RobloxError::Truncated(self.chunk.to_string()))?;
+        let bytes = self.bytes(len)?;
+        Ok((0..count)
+            .map(|i| {
+                let n = u32::from_be_bytes([
+                    bytes[i],
+                    bytes[count + i],
+                    bytes[count * 2 + i],
+                    bytes[count * 3 + i],
+                ]);
+                (n >> 1) as i32 ^ -((n & 1) as i32)
+            })
+            .collect())
+    }
+
+    // referents are stored as the difference to the previous one
+    fn referents(&mut self, count: usize) -> Result<Vec<i32>, RobloxError> {
+        let mut referent = 0i32;
+        Ok(self
+            .interleaved_i32s(count)?
+            .into_iter()
+            .map(|delta| {
+                referent = referent.wrapping_add(delta);
+                referent
+            })
+            .collect())
+    }
+}
+
+fn decompress<'a>(
+    chunk: &str,
+    compressed: &'a [u8],
+    len: usize,
+) -> Result<std::borrow::Cow<'a, [u8]>, RobloxError> {
+    if compressed.starts_with(ZSTD_MAGIC) {
+        return Err(RobloxError::Zstd(chunk.to_string()));
+    }
+    lz4_flex::block::decompress(compressed, len)
+        .map(Into::into)
+        .map_err(|err| RobloxError::Decompression {
+            chunk: chunk.to_string(),
+            message: err.to_string(),
+        })
+}
+
+/// Reads the instances of a binary place or model, which is a header followed by chunks that list
+/// the instances of every class (INST), the values of a property of every instance of a class
+/// (PROP) and the parent of every instance (PRNT), ending with an END chunk. Only string
+/// properties are read.
+pub(crate) fn read(file: &[u8]) -> Result<Vec<Instance>, RobloxError> {
+    if !file.starts_with(MAGIC) {
+        return Err(RobloxError::UnknownFormat);
+    }
+    let mut rest = file
+        .get(HEADER_LEN..)
+        .ok_or_else(|| RobloxError::Truncated("header".to_string()))?;
+
+    let mut instances = Vec::<Instance>::new();
+    let mut by_referent = HashMap::new();
+    let mut classes = HashMap::<u32, Vec<usize>>::new();
+    let mut parents = Vec::new();
+    loop {
+        let mut header = Reader {
+            chunk: "chunk header",
+            data: rest,
+        };
+        let name = String::from_utf8_lossy(header.bytes(4)?).into_owned();
+        let compressed_len = header.u32()? as usize;
+        let len = header.u32()? as usize;
+        header.bytes(4)?;
+        let mut body = Reader {
+            chunk: &name,
+            data: header.data,
+        };
+        let stored = body.bytes(if compressed_len == 0 {
+            len
+        } else {
+            compressed_len
+        })?;
+        rest = body.data;
+        let data = if compressed_len == 0 {
+            stored.into()
+        } else if len > file.len().saturating_mul(LZ4_MAX_RATIO) {
+            // the output is allocated up front, so a corrupt length could exhaust memory
+            return Err(RobloxError::Decompression {
+                chunk: name,
+                message: format!("uncompressed length {} is too large for the file", len),
+            });
+        } else {
+            decompress(&name, stored, len)?
+        };
+        let mut chunk = Reader {
+            chunk: &name,
+            data: &data,
+        };
+        match name.as_str() {
+            "INST" => {
+                let class_id = chunk.u32()?;
+                let class = String::from_utf8_lossy(chunk.string()?).into_owned();
+                chunk.u8()?;
+                let count = chunk.u32()? as usize;
+                let ids = classes.entry(class_id).or_default();
+                for referent in chunk.referents(count)? {
+                    by_referent.insert(referent, instances.len());
+                    ids.push(instances.len());
+                    instances.push(Instance {
+                        class: class.clone(),
+                        ..Default::default()
+                    });
+                }
+            }
+            "PROP" => {
+                let class_id = chunk.u32()?;
+                let property = chunk.string()?;
+                if chunk.u8()? != STRING_TYPE || !matches!(property, b"Name" | b"Source") {
+                    continue;
+                }
+                let ids = classes
+                    .get(&class_id)
+                    .ok_or(RobloxError::UnknownClass(class_id))?;
+                for &id in ids {
+                    let value = chunk.string()?.to_vec();
+                    match property {
+                        b"Name" => instances[id].name = String::from_utf8_lossy(&value).into(),
+                        _ => instances[id].source = value,
+                    }
+                }
+            }
+            "PRNT" => {
+                chunk.u8()?;
+                let count = chunk.u32()? as usize;
+                let children = chunk.referents(count)?;
+                let referents = chunk.referents(count)?;
+                parents.extend(children.into_iter().zip(referents));
+            }
+            "END\0" => break,
+            _ => {}
+        }
+    }
+    for (child, parent) in parents {
+        if let (Some(&child), Some(&parent)) = (by_referent.get(&child), by_referent.get(&parent)) {
+            instances[child].parent = Some(parent);
+        }
+    }
+    Ok(instances)
+}
@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+/// Why the scripts of a place or model couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RobloxError {
+    #[error("not a binary or XML Roblox place or model")]
+    UnknownFormat,
+    #[error("file ends in the middle of a {0} chunk")]
+    Truncated(String),
+    #[error("chunk {0} is compressed with zstd, which isn't supported")]
+    Zstd(String),
+    #[error("chunk {chunk} couldn't be decompressed: {message}")]
+    Decompression { chunk: String, message: String },
+    #[error("properties refer to class {0}, which has no instances")]
+    UnknownClass(u32),
+    #[error("invalid XML: {0}")]
+    Xml(String),
+}
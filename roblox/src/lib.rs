@@ -0,0 +1,121 @@
+//! Reads the scripts out of Roblox places and models, both the binary (`.rbxl`, `.rbxm`) and XML
+//! (`.rbxlx`, `.rbxmx`) formats, so they can be decompiled one at a time. Only the properties
+//! needed to find scripts and name them are read.
+
+use std::{collections::HashSet, path::PathBuf};
+
+mod binary;
+mod error;
+mod xml;
+
+pub use error::RobloxError;
+
+/// The classes whose `Source` is a script
+pub const SCRIPT_CLASSES: &[&str] = &["Script", "LocalScript", "ModuleScript"];
+
+// an instance with the properties that are read
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Instance {
+    class: String,
+    name: String,
+    source: Vec<u8>,
+    // the index of the parent in the list of instances
+    parent: Option<usize>,
+}
+
+/// A script of a place or model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    /// `Script`, `LocalScript` or `ModuleScript`
+    pub class: String,
+    /// The names of the instances from the top of the tree down to the script, e.g.
+    /// `["ServerScriptService", "Main"]`
+    pub path: Vec<String>,
+    /// The `Source` property, which is the script's source or its bytecode
+    pub source: Vec<u8>,
+}
+
+impl Script {
+    /// The path joined with dots, the way instances are referred to in scripts
+    pub fn full_name(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+// names of instances can contain anything, so characters that can't be in a file name are
+// replaced
+fn file_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    match name.as_str() {
+        "" | "." | ".." => name.replace('.', "_") + "_",
+        _ => name,
+    }
+}
+
+/// The scripts with a non-empty `Source` in a place or model, in the order they appear in the
+/// file.
+pub fn read_scripts(file: &[u8]) -> Result<Vec<Script>, RobloxError> {
+    let instances = if file.starts_with(binary::MAGIC) {
+        binary::read(file)?
+    } else if file.starts_with(b"<roblox") {
+        xml::read(std::str::from_utf8(file).map_err(|err| RobloxError::Xml(err.to_string()))?)?
+    } else {
+        return Err(RobloxError::UnknownFormat);
+    };
+    Ok(instances
+        .iter()
+        .filter(|i| SCRIPT_CLASSES.contains(&i.class.as_str()) && !i.source.is_empty())
+        .map(|instance| {
+            let mut path = vec![instance.name.clone()];
+            let mut parent = instance.parent;
+            // a malformed file could make an instance its own ancestor
+            while let Some(index) = parent {
+                if path.len() > instances.len() {
+                    break;
+                }
+                path.push(instances[index].name.clone());
+                parent = instances[index].parent;
+            }
+            path.reverse();
+            Script {
+                class: instance.class.clone(),
+                path,
+                source: instance.source.clone(),
+            }
+        })
+        .collect())
+}
+
+/// A relative path to write every script to, named after the instances in its path, e.g.
+/// `ServerScriptService/Main.lua`. Instances can share names, so scripts that would be written to
+/// the same path are numbered.
+pub fn file_paths(scripts: &[Script]) -> Vec<PathBuf> {
+    let mut taken = HashSet::new();
+    scripts
+        .iter()
+        .map(|script| {
+            let mut dir = script
+                .path
+                .iter()
+                .map(|n| file_name(n))
+                .collect::<PathBuf>();
+            let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+            dir.pop();
+            let mut path = dir.join(format!("{}.lua", name));
+            for n in 2.. {
+                if taken.insert(path.clone()) {
+                    break;
+                }
+                path = dir.join(format!("{} {}.lua", name, n));
+            }
+            path
+        })
+        .collect()
+}
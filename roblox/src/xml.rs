@@ -0,0 +1,43 @@
+use roxmltree::{Document, Node};
+
+use crate::{Instance, RobloxError};
+
+// the text of a property, e.g. `<string name="Name">Script</string>`
+fn property<'a>(properties: Option<Node<'a, '_>>, name: &str) -> Option<&'a str> {
+    properties?
+        .children()
+        .find(|p| p.is_element() && p.attribute("name") == Some(name))
+        .and_then(|p| p.text())
+}
+
+fn visit(item: Node, parent: Option<usize>, instances: &mut Vec<Instance>) {
+    let properties = item.children().find(|c| c.has_tag_name("Properties"));
+    let id = instances.len();
+    instances.push(Instance {
+        class: item.attribute("class").unwrap_or_default().to_string(),
+        name: property(properties, "Name").unwrap_or_default().to_string(),
+        source: property(properties, "Source")
+            .unwrap_or_default()
+            .as_bytes()
+            .to_vec(),
+        parent,
+    });
+    for child in item.children().filter(|c| c.has_tag_name("Item")) {
+        visit(child, Some(id), instances);
+    }
+}
+
+/// Reads the instances of an XML place or model, where every instance is an `Item` element with
+/// its properties in a `Properties` element and its children after them.
+pub(crate) fn read(file: &str) -> Result<Vec<Instance>, RobloxError> {
+    let document = Document::parse(file).map_err(|err| RobloxError::Xml(err.to_string()))?;
+    let root = document.root_element();
+    if !root.has_tag_name("roblox") {
+        return Err(RobloxError::UnknownFormat);
+    }
+    let mut instances = Vec::new();
+    for item in root.children().filter(|c| c.has_tag_name("Item")) {
+        visit(item, None, &mut instances);
+    }
+    Ok(instances)
+}
@@ -0,0 +1,121 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use roblox::{file_paths, read_scripts, RobloxError, Script};
+
+fn fixture(name: &str) -> Vec<u8> {
+    let fixtures = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../tests/fixtures/roblox"
+    ));
+    fs::read(fixtures.join(name)).unwrap()
+}
+
+// the header of the binary fixture, without its chunks
+fn header() -> Vec<u8> {
+    let mut file = fixture("scripts.rbxm");
+    file.truncate(32);
+    file
+}
+
+fn script(class: &str, path: &[&str], source: &str) -> Script {
+    Script {
+        class: class.to_string(),
+        path: path.iter().map(|n| n.to_string()).collect(),
+        source: source.as_bytes().to_vec(),
+    }
+}
+
+// both fixtures are the same model:
+// ServerScriptService (Folder)
+//     Main (Script)
+//         Util (ModuleScript)
+//     Main (Script)
+//     Empty (LocalScript without a source)
+fn assert_scripts(scripts: &[Script]) {
+    let mut expected = vec![
+        script("Script", &["ServerScriptService", "Main"], "print(1)"),
+        script("Script", &["ServerScriptService", "Main"], "print(2)"),
+        script(
+            "ModuleScript",
+            &["ServerScriptService", "Main", "Util"],
+            "return {}",
+        ),
+    ];
+    let mut scripts = scripts.to_vec();
+    // instances are listed by class in binary files and depth first in XML files
+    scripts.sort_by(|a, b| (&a.path, &a.source).cmp(&(&b.path, &b.source)));
+    expected.sort_by(|a, b| (&a.path, &a.source).cmp(&(&b.path, &b.source)));
+    assert_eq!(scripts, expected);
+}
+
+#[test]
+fn binary() {
+    let scripts = read_scripts(&fixture("scripts.rbxm")).unwrap();
+    assert_scripts(&scripts);
+    assert_eq!(scripts[2].full_name(), "ServerScriptService.Main.Util");
+}
+
+#[test]
+fn xml() {
+    assert_scripts(&read_scripts(&fixture("scripts.rbxmx")).unwrap());
+}
+
+#[test]
+fn duplicate_names() {
+    let scripts = read_scripts(&fixture("scripts.rbxm")).unwrap();
+    assert_eq!(
+        file_paths(&scripts),
+        [
+            "ServerScriptService/Main.lua",
+            "ServerScriptService/Main 2.lua",
+            "ServerScriptService/Main/Util.lua",
+        ]
+        .map(PathBuf::from)
+    );
+}
+
+#[test]
+fn truncated() {
+    let file = fixture("scripts.rbxm");
+    for len in 0..file.len() {
+        assert!(read_scripts(&file[..len]).is_err());
+    }
+}
+
+// a chunk whose uncompressed length can't fit in the file is rejected before anything is
+// allocated for it
+#[test]
+fn uncompressed_length() {
+    let mut file = header();
+    file.extend(b"INST");
+    file.extend(1u32.to_le_bytes());
+    file.extend(u32::MAX.to_le_bytes());
+    file.extend([0; 5]);
+    assert!(matches!(
+        read_scripts(&file),
+        Err(RobloxError::Decompression { chunk, .. }) if chunk == "INST"
+    ));
+}
+
+// the number of referents is read from the file, so it can be larger than the chunk
+#[test]
+fn referent_count() {
+    let mut file = header();
+    let mut data = Vec::new();
+    data.extend(0u32.to_le_bytes());
+    data.extend(6u32.to_le_bytes());
+    data.extend(b"Folder\0");
+    data.extend(u32::MAX.to_le_bytes());
+    file.extend(b"INST");
+    file.extend(0u32.to_le_bytes());
+    file.extend((data.len() as u32).to_le_bytes());
+    file.extend(0u32.to_le_bytes());
+    file.extend(data);
+    assert_eq!(
+        read_scripts(&file),
+        Err(RobloxError::Truncated("INST".to_string()))
+    );
+}
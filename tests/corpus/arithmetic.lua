@@ -0,0 +1,18 @@
+local function gcd(a, b)
+	while b ~= 0 do
+		a, b = b, a % b
+	end
+	return a
+end
+
+local total = 0
+for i = 1, 20 do
+	if i % 3 == 0 then
+		total = total + i
+	elseif i % 5 == 0 then
+		total = total - i
+	else
+		total = total + i * 2
+	end
+end
+print(total, gcd(48, 18), 2 ^ 10, -total / 4, 7 % -3)
@@ -0,0 +1,22 @@
+local function counter(step)
+	local count = 0
+	return function()
+		count = count + step
+		return count
+	end
+end
+
+local a, b = counter(1), counter(10)
+a()
+b()
+print(a(), b(), a())
+
+local callbacks = {}
+for i = 1, 3 do
+	callbacks[i] = function()
+		return i * i
+	end
+end
+for _, callback in ipairs(callbacks) do
+	print(callback())
+end
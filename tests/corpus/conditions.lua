@@ -0,0 +1,18 @@
+local function classify(value)
+	if type(value) == "number" and value > 0 then
+		return "positive"
+	elseif type(value) == "number" and (value < 0 or value ~= value) then
+		return "negative or nan"
+	elseif not value then
+		return "falsy"
+	end
+	return "other"
+end
+
+for _, value in ipairs({ 1, -1, 0, false, "x" }) do
+	print(classify(value))
+end
+
+local x, y = nil, 5
+local z = x or y and y * 2
+print(z, x and x.field, not (y > 3 and y < 10))
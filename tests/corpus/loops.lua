@@ -0,0 +1,21 @@
+local words = {}
+local i = 0
+repeat
+	i = i + 1
+	if i % 2 == 0 then
+		words[#words + 1] = "even" .. i
+	end
+until i >= 6
+
+local n = 10
+while true do
+	n = n - 3
+	if n < 0 then
+		break
+	end
+end
+
+for index = #words, 1, -1 do
+	print(index, words[index])
+end
+print(n, table.concat(words, ","))
@@ -0,0 +1,16 @@
+local function count(v1)
+	local total = 0
+	for i = 1, v1 do
+		local x = i * 2
+		total = total + x
+	end
+	local x = total
+	return function()
+		x = x + 1
+		return x
+	end
+end
+
+local v2 = count(3)
+local v1 = v2() + v2()
+print(v1, v2())
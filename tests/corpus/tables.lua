@@ -0,0 +1,24 @@
+local Point = {}
+Point.__index = Point
+
+function Point.new(x, y)
+	return setmetatable({ x = x, y = y }, Point)
+end
+
+function Point:add(other)
+	return Point.new(self.x + other.x, self.y + other.y)
+end
+
+function Point.__tostring(point)
+	return "(" .. point.x .. ", " .. point.y .. ")"
+end
+
+local sum = Point.new(1, 2):add(Point.new(3, 4))
+print(tostring(sum))
+
+local keys = {}
+for key in pairs({ b = 1, a = 2, c = 3 }) do
+	keys[#keys + 1] = key
+end
+table.sort(keys)
+print(table.concat(keys, " "), select("#", unpack({ 1, 2, nil, 4 }, 1, 4)))
@@ -0,0 +1,16 @@
+local function pack(...)
+	return { n = select("#", ...), ... }
+end
+
+local function sum(...)
+	local total = 0
+	for _, value in ipairs({ ... }) do
+		total = total + value
+	end
+	return total, select("#", ...)
+end
+
+local packed = pack(1, nil, 3)
+print(packed.n, packed[1], packed[2], packed[3])
+print(sum(1, 2, 3, 4))
+print((sum(5, 6)))